@@ -9,6 +9,16 @@ fn main() {
     println!("cargo:rerun-if-changed=build.rs");
     println!("cargo:rerun-if-changed=src/cli.rs");
 
+    // Expose the build target and profile to `portman version`
+    println!(
+        "cargo:rustc-env=PORTMAN_TARGET={}",
+        std::env::var("TARGET").unwrap()
+    );
+    println!(
+        "cargo:rustc-env=PORTMAN_PROFILE={}",
+        std::env::var("PROFILE").unwrap()
+    );
+
     generate_completions().unwrap();
     generate_manpage().unwrap();
 }
@@ -0,0 +1,123 @@
+use crate::dependencies::Exec;
+use crate::error::{ExecError, ExecResult};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::process::Command;
+
+// Run lsof and return its output, treating lsof's exit code of 1 when no files matched as empty
+// output
+fn lsof(deps: &impl Exec, args: &[&str]) -> ExecResult<String> {
+    match deps.exec(Command::new("lsof").args(args)) {
+        Err(ExecError::Failed { code: 1, .. }) => Ok(String::new()),
+        result => result,
+    }
+}
+
+// Parse lsof's field output into the name fields of each process, keyed by process id
+fn parse_fields(output: &str) -> BTreeMap<u32, Vec<&str>> {
+    let mut processes = BTreeMap::<u32, Vec<&str>>::new();
+    let mut pid = None;
+    for line in output.lines() {
+        if let Some(value) = line.strip_prefix('p') {
+            pid = value.parse().ok();
+        } else if let (Some(name), Some(pid)) = (line.strip_prefix('n'), pid) {
+            processes.entry(pid).or_default().push(name);
+        }
+    }
+    processes
+}
+
+// Return the port of a listening socket name like "*:3000", "127.0.0.1:3000", or "[::1]:3000"
+fn parse_port(name: &str) -> Option<u16> {
+    name.rsplit_once(':')?.1.parse().ok()
+}
+
+// Return the ports that processes whose working directory is inside of the directory are
+// listening on, in ascending order
+pub fn find_listening_ports(deps: &impl Exec, directory: &Path) -> ExecResult<Vec<u16>> {
+    let listeners = lsof(deps, &["-n", "-P", "-iTCP", "-sTCP:LISTEN", "-Fpn"])?;
+    let listeners = parse_fields(&listeners);
+    if listeners.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let pids = listeners
+        .keys()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(",");
+    let cwds = lsof(deps, &["-a", "-d", "cwd", "-Fpn", "-p", &pids])?;
+    Ok(parse_fields(&cwds)
+        .into_iter()
+        .filter(|(_, names)| {
+            names
+                .iter()
+                .any(|cwd| Path::new(cwd).starts_with(directory))
+        })
+        .filter_map(|(pid, _)| listeners.get(&pid))
+        .flatten()
+        .filter_map(|name| parse_port(name))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dependencies::{ExecMock, ExecStatus};
+    use unimock::{matching, MockFn, Unimock};
+
+    #[test]
+    fn test_parse_port() {
+        assert_eq!(parse_port("*:3000"), Some(3000));
+        assert_eq!(parse_port("127.0.0.1:5173"), Some(5173));
+        assert_eq!(parse_port("[::1]:8080"), Some(8080));
+        assert_eq!(parse_port("/tmp/socket"), None);
+    }
+
+    #[test]
+    fn test_find_listening_ports() {
+        let mocked_deps = Unimock::new((
+            ExecMock
+                .each_call(matching!((command) if command.get_args().any(|arg| arg == "-iTCP")))
+                .answers(&|_, _| {
+                    Ok(ExecStatus::Success {
+                        output: String::from("p100\nf20\nn*:5173\nf21\nn[::1]:5173\np200\nf7\nn127.0.0.1:24678\np300\nf9\nn*:8080\n"),
+                    })
+                })
+                .once(),
+            ExecMock
+                .each_call(matching!((command) if command.get_args().eq(["-a", "-d", "cwd", "-Fpn", "-p", "100,200,300"])))
+                .answers(&|_, _| {
+                    Ok(ExecStatus::Success {
+                        output: String::from("p100\nfcwd\nn/projects/app\np200\nfcwd\nn/projects/app/packages/web\np300\nfcwd\nn/projects/other\n"),
+                    })
+                })
+                .once(),
+        ));
+        assert_eq!(
+            find_listening_ports(&mocked_deps, Path::new("/projects/app")).unwrap(),
+            vec![5173, 24678]
+        );
+    }
+
+    #[test]
+    fn test_find_listening_ports_none() {
+        let mocked_deps = Unimock::new(
+            ExecMock
+                .each_call(matching!(_))
+                .answers(&|_, _| {
+                    Ok(ExecStatus::Failure {
+                        output: String::new(),
+                        code: 1,
+                    })
+                })
+                .once(),
+        );
+        assert_eq!(
+            find_listening_ports(&mocked_deps, Path::new("/projects/app")).unwrap(),
+            Vec::<u16>::new()
+        );
+    }
+}
@@ -1,27 +1,161 @@
-use crate::dependencies::ChoosePort;
+//! Allocation of ports from the configured port ranges.
+
+use crate::config::{AllocationStrategy, PortFallback};
+use crate::dependencies::{CheckPortInUse, ChoosePort, EphemeralPort};
 use crate::error::{ApplicationError, Result};
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+// The number of times to ask the OS for an ephemeral port that isn't already in use by a project
+const EPHEMERAL_ATTEMPTS: usize = 10;
 
+/// Allocates ports to projects from a set of available ports, optionally reserving ranges of ports
+/// for projects with certain tags
 #[cfg_attr(test, derive(Debug))]
 pub struct PortAllocator {
+    valid_ports: HashSet<u16>,
     available_ports: HashSet<u16>,
+    tag_ranges: BTreeMap<String, (u16, u16)>,
+    fallback: PortFallback,
+    fallback_ports: HashSet<u16>,
+    pinned_ports: HashSet<u16>,
+    strategy: AllocationStrategy,
 }
 
 impl PortAllocator {
-    // Create a new port allocator that allocates from the provided available ports
+    /// Create a new port allocator that allocates from the provided available ports
     pub fn new(available_ports: impl Iterator<Item = u16>) -> Self {
+        let valid_ports: HashSet<u16> = available_ports.collect();
         Self {
-            available_ports: available_ports.collect(),
+            available_ports: valid_ports.clone(),
+            valid_ports,
+            tag_ranges: BTreeMap::new(),
+            fallback: PortFallback::default(),
+            fallback_ports: HashSet::new(),
+            pinned_ports: HashSet::new(),
+            strategy: AllocationStrategy::default(),
         }
     }
 
-    // Remove a port from the pool of available ports
+    /// Reserve port ranges for projects with specific tags
+    pub fn with_tag_ranges(self, tag_ranges: BTreeMap<String, (u16, u16)>) -> Self {
+        Self { tag_ranges, ..self }
+    }
+
+    /// Set what to do when there are no available ports left
+    pub fn with_fallback(self, fallback: PortFallback) -> Self {
+        Self { fallback, ..self }
+    }
+
+    /// Set how ports are chosen from the available ports
+    pub fn with_strategy(self, strategy: AllocationStrategy) -> Self {
+        Self { strategy, ..self }
+    }
+
+    /// Determine whether a port was allocated by the fallback from outside of the available ports
+    pub fn is_fallback(&self, port: u16) -> bool {
+        self.fallback_ports.contains(&port)
+    }
+
+    /// Attempt to reserve a port outside of the available ports that was previously allocated by the
+    /// fallback, and return true if it was reserved
+    pub fn claim_fallback(&mut self, port: u16) -> bool {
+        self.fallback == PortFallback::Ephemeral
+            && !self.valid_ports.contains(&port)
+            && self.fallback_ports.insert(port)
+    }
+
+    /// Determine whether a port is inside of the configured port ranges
+    pub fn is_valid(&self, port: u16) -> bool {
+        self.valid_ports.contains(&port)
+    }
+
+    /// Attempt to reserve a port that a project was pinned to regardless of the configured port
+    /// ranges, and return true if it was reserved
+    pub fn claim_pinned(&mut self, port: u16) -> bool {
+        if self.valid_ports.contains(&port) {
+            self.available_ports.remove(&port)
+        } else {
+            !self.fallback_ports.contains(&port) && self.pinned_ports.insert(port)
+        }
+    }
+
+    /// Determine whether a port outside of the configured port ranges was pinned to a project
+    pub fn is_pinned(&self, port: u16) -> bool {
+        self.pinned_ports.contains(&port)
+    }
+
+    /// Make a port outside of the configured port ranges available so that a project can be pinned
+    /// to it, and return true if it is now available
+    pub fn pin(&mut self, port: u16) -> bool {
+        if self.valid_ports.contains(&port) || self.fallback_ports.contains(&port) {
+            return false;
+        }
+        self.pinned_ports.insert(port) && self.available_ports.insert(port)
+    }
+
+    /// Return the tag and port range reserved for a project with the provided tags, if any
+    /// If multiple tags have ranges, the alphabetically first tag wins
+    pub fn tag_range(&self, tags: &BTreeSet<String>) -> Option<(&String, (u16, u16))> {
+        tags.iter()
+            .find_map(|tag| self.tag_ranges.get_key_value(tag))
+            .map(|(tag, range)| (tag, *range))
+    }
+
+    /// Determine whether a project with the provided tags may use a port
+    /// Projects with a tag range must use ports in that range, and other projects must not use
+    /// ports in any tag range
+    pub fn permits(&self, port: u16, tags: &BTreeSet<String>) -> bool {
+        match self.tag_range(tags) {
+            Some((_, (start, end))) => (start..=end).contains(&port),
+            None => !self
+                .tag_ranges
+                .values()
+                .any(|(start, end)| (*start..=*end).contains(&port)),
+        }
+    }
+
+    /// Determine whether a project with the provided tags may be assigned a specific port
+    pub fn is_available(&self, port: u16, tags: &BTreeSet<String>) -> bool {
+        self.available_ports.contains(&port) && self.permits(port, tags)
+    }
+
+    /// Return the lowest ports that are available to untagged projects, in ascending order
+    pub fn lowest_available(&self, count: usize) -> Vec<u16> {
+        let untagged = BTreeSet::new();
+        let mut ports = self
+            .available_ports
+            .iter()
+            .copied()
+            .filter(|port| self.permits(*port, &untagged))
+            .collect::<Vec<_>>();
+        ports.sort_unstable();
+        ports.truncate(count);
+        ports
+    }
+
+    /// Remove a port from the pool of available ports
     pub fn discard(&mut self, port: u16) {
         self.available_ports.remove(&port);
     }
 
-    // Allocate a new port, using the desired port if it is provided and is valid
-    pub fn allocate(&mut self, deps: &impl ChoosePort, desired_port: Option<u16>) -> Result<u16> {
+    /// Allocate a new port for an untagged project, using the desired port if it is provided and is
+    /// valid
+    pub fn allocate(
+        &mut self,
+        deps: &(impl CheckPortInUse + ChoosePort + EphemeralPort),
+        desired_port: Option<u16>,
+    ) -> Result<u16> {
+        self.allocate_tagged(deps, desired_port, &BTreeSet::new())
+    }
+
+    /// Allocate a new port for a project with the provided tags, using the desired port if it is
+    /// provided and is valid
+    pub fn allocate_tagged(
+        &mut self,
+        deps: &(impl CheckPortInUse + ChoosePort + EphemeralPort),
+        desired_port: Option<u16>,
+        tags: &BTreeSet<String>,
+    ) -> Result<u16> {
         let allocated_port = desired_port
             .and_then(|port| {
                 if self.available_ports.contains(&port) {
@@ -30,20 +164,53 @@ impl PortAllocator {
                     None
                 }
             })
-            .or_else(|| deps.choose_port(&self.available_ports));
+            .or_else(|| {
+                let mut permitted_ports = self
+                    .available_ports
+                    .iter()
+                    .copied()
+                    .filter(|port| self.permits(*port, tags))
+                    .collect::<HashSet<_>>();
+                // Skip ports that processes outside of portman are already using
+                loop {
+                    let port = match self.strategy {
+                        AllocationStrategy::Random => deps.choose_port(&permitted_ports)?,
+                        AllocationStrategy::Sequential => *permitted_ports.iter().min()?,
+                    };
+                    if !deps.is_port_in_use(port) {
+                        return Some(port);
+                    }
+                    permitted_ports.remove(&port);
+                }
+            });
         let Some(port) = allocated_port else {
-            return Err(ApplicationError::EmptyAllocator);
+            return self.allocate_fallback(deps);
         };
         self.available_ports.remove(&port);
         Ok(port)
     }
+
+    // Allocate a port from the fallback after all of the available ports have been allocated
+    fn allocate_fallback(&mut self, deps: &impl EphemeralPort) -> Result<u16> {
+        if self.fallback == PortFallback::None {
+            return Err(ApplicationError::EmptyAllocator);
+        }
+        for _ in 0..EPHEMERAL_ATTEMPTS {
+            let port = deps.get_ephemeral_port()?;
+            if self.claim_fallback(port) {
+                return Ok(port);
+            }
+        }
+        Err(ApplicationError::EmptyAllocator)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::dependencies::{CheckPortInUseMock, ChoosePortMock, EphemeralPortMock};
     use crate::mocks::choose_port_mock;
-    use unimock::Unimock;
+    use unimock::{matching, MockFn, Unimock};
 
     #[test]
     fn test_random_chooser() {
@@ -53,6 +220,20 @@ mod tests {
         assert!(range.contains(&allocator.allocate(&mocked_deps, None).unwrap()));
     }
 
+    #[test]
+    fn test_sequential() {
+        let mut allocator =
+            PortAllocator::new(3000..=3999).with_strategy(AllocationStrategy::Sequential);
+        allocator.discard(3000);
+        let mocked_deps = Unimock::new(
+            CheckPortInUseMock
+                .each_call(matching!(_))
+                .answers(&|_, port| port == 3001),
+        );
+        assert_eq!(allocator.allocate(&mocked_deps, None).unwrap(), 3002);
+        assert_eq!(allocator.allocate(&mocked_deps, None).unwrap(), 3003);
+    }
+
     #[test]
     fn test_discard() {
         let mut allocator = PortAllocator::new(3000..=3001);
@@ -65,6 +246,38 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_skip_in_use() {
+        let mut allocator = PortAllocator::new(3000..=3002);
+        let mocked_deps = Unimock::new((
+            ChoosePortMock
+                .each_call(matching!(_))
+                .answers(&|_, available_ports| available_ports.iter().min().copied())
+                .at_least_times(1),
+            CheckPortInUseMock
+                .each_call(matching!(_))
+                .answers(&|_, port| port != 3002),
+        ));
+        assert_eq!(allocator.allocate(&mocked_deps, None).unwrap(), 3002);
+
+        // Ports that are in use are still available to later allocations
+        assert!(allocator.is_available(3000, &BTreeSet::new()));
+    }
+
+    #[test]
+    fn test_desired_in_use() {
+        let mut allocator = PortAllocator::new(3000..=3002);
+        let mocked_deps = Unimock::new(());
+        assert_eq!(allocator.allocate(&mocked_deps, Some(3001)).unwrap(), 3001);
+    }
+
+    #[test]
+    fn test_lowest_available() {
+        let mut allocator = PortAllocator::new(3000..=3999);
+        allocator.discard(3001);
+        assert_eq!(allocator.lowest_available(3), vec![3000, 3002, 3003]);
+    }
+
     #[test]
     fn test_allocate() {
         let mut allocator = PortAllocator::new(3000..=3001);
@@ -77,6 +290,34 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_tag_ranges() {
+        let mut allocator = PortAllocator::new(3000..=3003)
+            .with_tag_ranges(BTreeMap::from([(String::from("work"), (3000, 3001))]));
+        let mocked_deps = Unimock::new(choose_port_mock());
+        let work = BTreeSet::from([String::from("work")]);
+        assert_eq!(allocator.allocate(&mocked_deps, None).unwrap(), 3002);
+        assert_eq!(
+            allocator
+                .allocate_tagged(&mocked_deps, None, &work)
+                .unwrap(),
+            3000
+        );
+        assert_eq!(
+            allocator
+                .allocate_tagged(&mocked_deps, None, &BTreeSet::from([String::from("other")]))
+                .unwrap(),
+            3003
+        );
+        assert!(matches!(
+            allocator.allocate(&mocked_deps, None),
+            Err(ApplicationError::EmptyAllocator),
+        ));
+        assert!(allocator.permits(3001, &work));
+        assert!(!allocator.permits(3002, &work));
+        assert!(!allocator.permits(3001, &BTreeSet::new()));
+    }
+
     #[test]
     fn test_desired_port() {
         let mut allocator = PortAllocator::new(3000..=3002);
@@ -89,4 +330,23 @@ mod tests {
             Err(ApplicationError::EmptyAllocator),
         ));
     }
+
+    #[test]
+    fn test_fallback_ephemeral() {
+        let mut allocator = PortAllocator::new(3000..=3000).with_fallback(PortFallback::Ephemeral);
+        let mocked_deps = Unimock::new((
+            choose_port_mock(),
+            EphemeralPortMock
+                .each_call(matching!())
+                .answers(&|_| Ok(49152))
+                .once(),
+        ));
+        assert_eq!(allocator.allocate(&mocked_deps, None).unwrap(), 3000);
+        assert!(!allocator.is_fallback(3000));
+        assert_eq!(allocator.allocate(&mocked_deps, None).unwrap(), 49152);
+        assert!(allocator.is_fallback(49152));
+        assert!(!allocator.claim_fallback(49152));
+        assert!(!allocator.claim_fallback(3000));
+        assert!(allocator.claim_fallback(49154));
+    }
 }
@@ -0,0 +1,7727 @@
+use crate::archive::Archive;
+use crate::caddy::{generate_caddyfile, reload, write_gallery, CaddyService};
+use crate::cli::{
+    Activate as ActivateSubcommand, Cli, Color, Command, CompletionKind,
+    Config as ConfigSubcommand, Domain as DomainSubcommand, Forward as ForwardSubcommand, GetField,
+    Lease as LeaseSubcommand, ListSort, Protocol, Registry as RegistrySubcommand, Repo, Service,
+    Set, Tag as TagSubcommand, Template as TemplateSubcommand,
+};
+use crate::config::{BindAddress, Config, CONFIG_VERSION};
+use crate::dependencies::{
+    Args, BuildInfo, CheckPath, CheckPortInUse, CheckWritable, ChoosePort, CurrentExe, CurrentTime,
+    DataDir, DataDirOverride, Environment, EphemeralPort, Exec, FileLock, LanAddress,
+    ListDirectory, LockFile, ProbeHttp, ProbePort, Prompt, ReadFile, ReadStdin, ResolveHost, Spawn,
+    Tty, WalkDir, WatchPath, WorkingDirectory, WriteFile,
+};
+use crate::error::{ApplicationError, CaddyError, ExecError, Result};
+use crate::health::HealthCache;
+use crate::hooks::{Hook, HookEvent};
+use crate::index::{DirectoryIndex, IndexEntry};
+use crate::registry::{
+    is_icon_url, ForwardProtocol, Lease, PortForward, Project, ProjectColor, ProjectFilter,
+    ProjectOrder, Registry, RepoUpdate, Reservation, SaveOutcome, Upstream, UpstreamProtocol,
+};
+use crate::usage::UsageLog;
+use crate::version::VersionInfo;
+use crate::watch::DaemonWatcher;
+use crate::{
+    adopt, batch, caddy, config, conflicts, devcontainer, dotenv, file_template, hooks, logs, mdns,
+    repo, scan, service, shell, template, timings, tmux, ui,
+};
+use anyhow::Context;
+use clap::Parser;
+use entrait::Impl;
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::fmt::Write as FmtWrite;
+use std::io::{ErrorKind, Write as IoWrite};
+use std::path::{Component, Path, PathBuf};
+use std::process::{self, ExitCode};
+use std::time::{Duration, UNIX_EPOCH};
+
+// Find and return a reference to the active project based on the current directory
+fn get_active_project<'registry>(
+    deps: &(impl Exec + WorkingDirectory),
+    registry: &'registry Registry,
+) -> Result<(&'registry String, &'registry Project)> {
+    registry
+        .match_cwd(deps)?
+        .ok_or(ApplicationError::NoActiveProject)
+}
+
+// Find and return a reference to the named project, or the active project if no name is provided
+fn get_project<'registry>(
+    deps: &(impl Exec + WorkingDirectory),
+    registry: &'registry Registry,
+    project_name: Option<&'registry String>,
+) -> Result<(&'registry String, &'registry Project)> {
+    project_name.map_or_else(
+        || get_active_project(deps, registry),
+        |name| {
+            registry
+                .get(name)
+                .map(|project| (name, project))
+                .ok_or_else(|| ApplicationError::NonExistentProject(name.clone()))
+        },
+    )
+}
+
+fn format_project(name: &str, project: &Project) -> String {
+    let directory = project
+        .directory
+        .as_ref()
+        .map(|directory| format!(" ({})", directory.display()))
+        .unwrap_or_default();
+    let linked_port = project
+        .linked_port
+        .map(|port| format!(" -> :{port}"))
+        .unwrap_or_default();
+    let upstream = project
+        .upstream
+        .as_ref()
+        .map(|upstream| format!(" => {}", upstream.url))
+        .unwrap_or_default();
+    format!("{name} :{}{linked_port}{upstream}{directory}", project.port)
+}
+
+// Decorate a line of the list of projects with the project's icon and color for a terminal
+// Image icons can't be shown in a terminal
+fn decorate_project_line(project: &Project, line: &str) -> String {
+    let icon = project
+        .icon
+        .as_ref()
+        .filter(|icon| !is_icon_url(icon))
+        .map(|icon| format!("{icon} "))
+        .unwrap_or_default();
+    project.color.map_or_else(
+        || format!("{icon}{line}"),
+        |color| format!("{icon}{}{line}\x1b[0m", color.ansi()),
+    )
+}
+
+// Format a project like format_project, followed by its tags and description
+// Format a project with its tags, description, and how long ago it was last used
+fn format_project_extended(
+    name: &str,
+    project: &Project,
+    last_used: Option<u64>,
+    now: u64,
+) -> String {
+    let tags = project.tags.iter().fold(String::new(), |mut tags, tag| {
+        let _ = write!(tags, " #{tag}");
+        tags
+    });
+    let description = project
+        .description
+        .as_ref()
+        .map(|description| format!(" - {description}"))
+        .unwrap_or_default();
+    let last_used = last_used
+        .map(|last_used| {
+            format!(
+                " (used {} ago)",
+                format_duration(now.saturating_sub(last_used))
+            )
+        })
+        .unwrap_or_default();
+    format!(
+        "{}{tags}{description}{last_used}",
+        format_project(name, project)
+    )
+}
+
+fn format_health_status(name: &str, project: &Project, health: &HealthCache) -> String {
+    health
+        .get(name, project)
+        .map_or_else(|| String::from("unknown"), |status| status.to_string())
+}
+
+fn format_project_status(name: &str, project: &Project, health: &HealthCache) -> String {
+    let status = format_health_status(name, project, health);
+    format!("{} [{status}]", format_project(name, project))
+}
+
+// A project and its name as they are printed in JSON output
+#[derive(Serialize)]
+struct NamedProject<'project> {
+    name: &'project str,
+    #[serde(flatten)]
+    project: &'project Project,
+    // The project's status as of the last health check, if it was requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<String>,
+}
+
+fn project_json(name: &str, project: &Project) -> Result<String> {
+    Ok(serde_json::to_string(&NamedProject {
+        name,
+        project,
+        status: None,
+    })
+    .context("Failed to serialize project")?)
+}
+
+// Format a project's port, or its port, name, directory, and linked port if extended is true
+fn format_project_details(
+    deps: &impl Tty,
+    name: &str,
+    project: &Project,
+    extended: bool,
+) -> String {
+    if !extended {
+        return format!("{}\n", project.port);
+    }
+
+    let directory = project
+        .directory
+        .as_ref()
+        .map(|directory| directory.display().to_string())
+        .unwrap_or_default();
+    let linked_port = project
+        .linked_port
+        .map(|port| port.to_string())
+        .unwrap_or_default();
+    if deps.is_tty() {
+        format!(
+            "port: {}\nname: {name}\ndirectory: {directory}\nlinked port: {linked_port}\n",
+            project.port
+        )
+    } else {
+        format!("{}\n{name}\n{directory}\n{linked_port}\n", project.port)
+    }
+}
+
+// Resolve "." and ".." components in a path without accessing the filesystem
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+fn format_repo(repo: &str, port: u16) -> String {
+    format!("{repo}: {port}")
+}
+
+// Format a number of seconds as a short duration in its largest whole unit, like "15m" or "2d"
+fn format_duration(seconds: u64) -> String {
+    match seconds {
+        0..=59 => format!("{seconds}s"),
+        60..=3599 => format!("{}m", seconds / 60),
+        3600..=86399 => format!("{}h", seconds / 3600),
+        _ => format!("{}d", seconds / 86400),
+    }
+}
+
+fn format_lease(lease: &Lease, now: u64) -> String {
+    let description = lease
+        .description
+        .as_ref()
+        .map(|description| format!(" {description}"))
+        .unwrap_or_default();
+    format!(
+        "{} (expires in {}){description}",
+        lease.port,
+        format_duration(lease.expires.saturating_sub(now))
+    )
+}
+
+fn format_reservation(reservation: &Reservation) -> String {
+    let description = reservation
+        .description
+        .as_ref()
+        .map(|description| format!(" {description}"))
+        .unwrap_or_default();
+    format!("{}{description}", reservation.port)
+}
+
+// The attributes of a project to create
+#[allow(clippy::struct_excessive_bools)]
+struct CreateOptions {
+    name: Option<String>,
+    // The project's directory, which defaults to the current directory
+    directory: Option<PathBuf>,
+    no_activate: bool,
+    port: Option<u16>,
+    // Whether the explicit port may be outside of the configured port ranges
+    force: bool,
+    linked_port: Option<u16>,
+    overwrite: bool,
+    tags: BTreeSet<String>,
+    branch: Option<String>,
+    strict: bool,
+}
+
+fn create(
+    deps: &(impl CheckPortInUse + ChoosePort + EphemeralPort + WorkingDirectory),
+    registry: &mut Registry,
+    options: CreateOptions,
+) -> Result<(String, Project, bool)> {
+    let CreateOptions {
+        name,
+        directory,
+        no_activate,
+        port,
+        force,
+        linked_port,
+        overwrite,
+        tags,
+        branch,
+        strict,
+    } = options;
+    let directory = if no_activate {
+        None
+    } else {
+        let cwd = deps.get_cwd()?;
+        Some(match directory {
+            Some(directory) => cwd.join(directory),
+            None => cwd,
+        })
+    };
+    let name = if let Some(name) = name {
+        name
+    } else {
+        let directory = directory
+            .as_ref()
+            .context("Failed to determine project directory")?;
+        let basename = directory
+            .file_name()
+            .context("Failed to extract directory basename")?;
+        let name = basename
+            .to_str()
+            .context("Failed to convert directory to string")?;
+        check_normalized_name(name, strict)?
+    };
+
+    if overwrite && registry.get(&name).is_some() {
+        if let Some(port) = linked_port {
+            registry.link(deps, &name, None, port)?;
+        }
+        if !tags.is_empty() {
+            registry.set_tags(deps, &name, tags)?;
+        }
+        let project = registry.update(&name, directory)?;
+        return Ok((name, project, true));
+    }
+
+    if let (Some(port), true) = (port, force) {
+        registry.allow_port(port)?;
+    }
+    let project = registry.create(deps, &name, port, directory, linked_port, tags, branch)?;
+    Ok((name, project, false))
+}
+
+// Create a project in the current directory like `portman create` would if auto_create is enabled
+// and the directory is the root of a git repo, and return the new project
+// The notice is printed to stderr because the shell integration parses stdout
+fn auto_create_project(
+    deps: &(impl CheckPath
+          + CheckPortInUse
+          + ChoosePort
+          + CurrentTime
+          + DataDir
+          + Environment
+          + EphemeralPort
+          + Exec
+          + ListDirectory
+          + LockFile
+          + ReadFile
+          + WorkingDirectory
+          + WriteFile),
+    options: LoadOptions<'_>,
+    strict: bool,
+) -> Result<Option<(String, Project)>> {
+    if !deps.path_exists(&deps.get_cwd()?.join(".git")) {
+        return Ok(None);
+    }
+    let config = load_config(deps)?;
+    if !config.auto_create {
+        return Ok(None);
+    }
+    let mut registry = load_registry(deps, options)?;
+    let repo = if config.defaults.no_link {
+        None
+    } else {
+        repo::get_active_repo(deps, None)
+            .ok()
+            .and_then(|repo| registry.get_repo(&repo).ok().cloned())
+    };
+    let tags: BTreeSet<String> = config.defaults.tags.iter().cloned().collect();
+    let port = repo
+        .as_ref()
+        .and_then(|repo| repo.project_port)
+        .filter(|port| registry.is_port_available(*port, &tags));
+    let (name, project, _) = create(
+        deps,
+        &mut registry,
+        CreateOptions {
+            name: repo
+                .as_ref()
+                .and_then(|repo| repo.default_project_name.clone()),
+            directory: None,
+            no_activate: false,
+            port,
+            force: false,
+            linked_port: repo.as_ref().map(|repo| repo.port),
+            overwrite: false,
+            tags,
+            branch: None,
+            strict: strict || config.strict,
+        },
+    )?;
+    save_registry(deps, &registry, options.warnings)?;
+    // Hook commands may run portman themselves, which would wait for the registry lock
+    drop(registry);
+    options.warnings.push(format!(
+        "Automatically created project {}",
+        format_project(&name, &project)
+    ));
+    run_hooks(
+        deps,
+        &[HookEvent {
+            hook: Hook::Create,
+            name: name.clone(),
+            project: project.clone(),
+        }],
+    )?;
+    Ok(Some((name, project)))
+}
+
+// Normalize a generated project name, failing in strict mode if normalization changed it
+fn check_normalized_name(name: &str, strict: bool) -> Result<String> {
+    let normalized = Registry::normalize_name(name);
+    if strict && normalized != name {
+        return Err(ApplicationError::StrictMode(format!(
+            "project name \"{name}\" would be normalized to \"{normalized}\""
+        )));
+    }
+    Ok(normalized)
+}
+
+// Fail in strict mode if a linked port is outside of the configured port ranges
+fn check_linked_port(config: &Config, port: u16, strict: bool) -> Result<()> {
+    if strict && !config.in_ranges(port) {
+        return Err(ApplicationError::StrictMode(format!(
+            "linked port {port} is outside of the configured port ranges"
+        )));
+    }
+    Ok(())
+}
+
+// The reason that a project was removed during cleanup
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum CleanupReason {
+    DeletedBranch,
+    MissingDirectory,
+    Stale,
+}
+
+// A project removed during cleanup
+#[derive(Serialize)]
+struct CleanedProject {
+    name: String,
+    #[serde(flatten)]
+    project: Project,
+    reason: CleanupReason,
+}
+
+fn cleanup(
+    deps: &(impl CheckPath + DataDir + Environment + Exec + ReadFile + WriteFile),
+    registry: &mut Registry,
+) -> Result<Vec<CleanedProject>> {
+    // Find all existing projects whose directories don't exist or a per-branch project whose
+    // branch has been deleted
+    // Projects with some directories that still exist stop being activated in the missing ones
+    let mut missing_directories = vec![];
+    let removed_projects = registry
+        .iter_projects()
+        .filter_map(|(name, project)| {
+            project.directory.as_ref()?;
+            let (existing, missing): (Vec<_>, Vec<_>) = project
+                .activation_directories()
+                .partition(|directory| deps.path_exists(directory));
+            let Some(directory) = existing.first() else {
+                return Some((name.clone(), CleanupReason::MissingDirectory));
+            };
+            missing_directories.extend(
+                missing
+                    .into_iter()
+                    .map(|directory| (name.clone(), directory.clone())),
+            );
+            let branch = project.branch.as_ref()?;
+            deps.exec(
+                process::Command::new("git")
+                    .args(["rev-parse", "--verify", "--quiet"])
+                    .arg(format!("refs/heads/{branch}"))
+                    .current_dir(directory),
+            )
+            .is_err()
+            .then(|| (name.clone(), CleanupReason::DeletedBranch))
+        })
+        .collect::<Vec<_>>();
+    for (name, directory) in missing_directories {
+        registry.remove_directory(&name, &directory)?;
+    }
+    let reasons = removed_projects
+        .iter()
+        .map(|(_, reason)| *reason)
+        .collect::<Vec<_>>();
+    let deleted_projects =
+        registry.delete_many(removed_projects.into_iter().map(|(name, _)| name).collect())?;
+    Ok(deleted_projects
+        .into_iter()
+        .zip(reasons)
+        .map(|((name, project), reason)| CleanedProject {
+            name,
+            project,
+            reason,
+        })
+        .collect())
+}
+
+// Return the names of the projects that haven't been used and whose directory hasn't been modified
+// in max_age seconds, along with how many seconds ago they were last active
+// Projects without a directory that have never been used are never considered stale because their
+// age is unknown
+fn find_stale_projects(
+    deps: &(impl CurrentTime + DataDir + ReadFile + WatchPath),
+    registry: &Registry,
+    max_age: u64,
+) -> Result<Vec<(String, u64)>> {
+    let usage = UsageLog::load(deps)?;
+    let now = deps.get_current_time();
+    Ok(registry
+        .iter_projects()
+        .filter_map(|(name, project)| {
+            let modified = project
+                .directory
+                .as_ref()
+                .and_then(|directory| deps.get_modified_time(directory))
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map(|modified| modified.as_secs());
+            let last_active = usage.last_used(name).max(modified)?;
+            let age = now.saturating_sub(last_active);
+            (age >= max_age).then(|| (name.clone(), age))
+        })
+        .collect())
+}
+
+// Return the path to the config file and a flag indicating whether the location was customized with
+// the PORTMAN_CONFIG environment variable
+fn get_config_path(deps: &(impl DataDir + Environment)) -> Result<(PathBuf, bool)> {
+    let config_env = deps.read_var("PORTMAN_CONFIG").ok();
+    match config_env {
+        Some(config_path) => Ok((PathBuf::from(config_path), true)),
+        None => Ok((deps.get_data_dir()?.join("config.toml"), false)),
+    }
+}
+
+fn load_config(deps: &(impl ChoosePort + DataDir + Environment + ReadFile)) -> Result<Config> {
+    let _timer = timings::start("load config");
+    let (config_path, custom_path) = get_config_path(deps)?;
+    match Config::load(deps, &config_path)? {
+        Some(config) => Ok(config),
+        None if custom_path => Err(ApplicationError::MissingCustomConfig(config_path)),
+        None => Ok(Config::default()),
+    }
+}
+
+// Lock the registry so that other portman processes can't modify it until the lock is dropped
+// Wait up to `wait` for another process to release the lock
+fn lock_registry(deps: &(impl DataDir + LockFile), wait: Duration) -> Result<FileLock> {
+    let lock_path = Registry::lock_path(deps)?;
+    deps.lock_file(&lock_path, wait)?
+        .ok_or(ApplicationError::RegistryLocked(lock_path))
+}
+
+// Warnings that commands report instead of printing them to stderr themselves, so that the caller
+// of run_and_suggest decides where they go
+#[derive(Default)]
+struct Warnings(RefCell<Vec<String>>);
+
+impl Warnings {
+    // Commands like ui and daemon load the registry repeatedly, so only report each warning once
+    fn push(&self, warning: String) {
+        let mut warnings = self.0.borrow_mut();
+        if !warnings.contains(&warning) {
+            warnings.push(warning);
+        }
+    }
+
+    // Remove and return the warnings reported so far
+    fn take(&self) -> Vec<String> {
+        self.0.take()
+    }
+}
+
+// The global flags that affect every command that loads the registry
+#[derive(Clone, Copy)]
+struct LoadOptions<'warnings> {
+    // Don't warn about project ports that were reassigned
+    quiet: bool,
+    // Save changes without reloading caddy
+    no_reload: bool,
+    // How long to wait for another portman process to release the registry lock
+    wait: Duration,
+    // Where to report warnings about the registry
+    warnings: &'warnings Warnings,
+}
+
+fn load_registry(
+    deps: &(impl CheckPortInUse
+          + ChoosePort
+          + CurrentTime
+          + DataDir
+          + Environment
+          + EphemeralPort
+          + ListDirectory
+          + LockFile
+          + ReadFile),
+    options: LoadOptions<'_>,
+) -> Result<Registry> {
+    let config = load_config(deps)?;
+    // Lock the registry before reading it so that changes from other processes can't be lost
+    let lock = lock_registry(deps, options.wait)?;
+    let timer = timings::start("load registry");
+    let registry = Registry::load(deps, &config)?
+        .with_auto_reload(config.auto_reload && !options.no_reload)
+        .with_lock(lock);
+    drop(timer);
+    if !options.quiet {
+        for reassignment in registry.reassignments() {
+            options.warnings.push(reassignment.to_string());
+        }
+    }
+    if registry.modified_externally() {
+        options.warnings.push(String::from("The registry was modified outside of portman. Run `portman registry accept` to accept the changes or `portman registry restore` to restore the last version that portman saved."));
+    }
+    Ok(registry)
+}
+
+// Save the registry, reporting when caddy wasn't reloaded because it isn't installed
+fn save_registry(
+    deps: &(impl CurrentTime + DataDir + Environment + Exec + ReadFile + WriteFile),
+    registry: &Registry,
+    warnings: &Warnings,
+) -> Result<SaveOutcome> {
+    let outcome = registry.save(deps)?;
+    if outcome == SaveOutcome::CaddyMissing {
+        warnings.push(String::from(
+            "Caddy is not installed, so the changes were saved without reloading caddy",
+        ));
+    }
+    Ok(outcome)
+}
+
+// Record that a project was used
+// Failures are only warnings because tracking usage shouldn't prevent the project from being used
+fn record_usage(
+    deps: &(impl CurrentTime + DataDir + ReadFile + WriteFile),
+    warnings: &Warnings,
+    name: &str,
+) {
+    let result = UsageLog::load(deps).and_then(|mut usage| {
+        if usage.record(name, deps.get_current_time()) {
+            usage.save(deps)
+        } else {
+            Ok(())
+        }
+    });
+    if let Err(err) = result {
+        warnings.push(format!("Failed to record that {name} was used: {err}"));
+    }
+}
+
+// Probe every project's port, then cache the results and update the gallery
+fn check_health(
+    deps: &(impl CurrentTime + DataDir + ProbeHttp + ProbePort + ReadFile + WriteFile),
+    registry: &Registry,
+) -> Result<HealthCache> {
+    let mut health = HealthCache::load(deps)?;
+    health.probe(deps, registry);
+    health.save(deps)?;
+    write_gallery(deps, registry, &health)?;
+    Ok(health)
+}
+
+// Describe the Caddyfile changes that a dry run would have made
+fn dry_run_report(deps: &(impl DataDir + ReadFile), registry: &Registry) -> Result<String> {
+    let diff = caddy::diff(deps, registry).map_err(ApplicationError::Caddy)?;
+    Ok(if diff.is_empty() {
+        String::from("Dry run: no changes were saved, and the Caddyfile would not change\n")
+    } else {
+        format!("Dry run: no changes were saved, and the Caddyfile would change:\n{diff}")
+    })
+}
+
+// Determine whether a project's name or display name mentions a port, like api-8080
+fn mentions_port(name: &str, project: &Project, port: u16) -> bool {
+    let port = port.to_string();
+    std::iter::once(name)
+        .chain(project.display_name.as_deref())
+        .any(|name| {
+            name.split(|char: char| !char.is_ascii_digit())
+                .any(|number| number == port)
+        })
+}
+
+// Propose the unlinked project to link a port to, preferring projects whose name mentions the port
+// and then the project whose port is closest to it
+fn propose_link(registry: &Registry, port: u16) -> Option<String> {
+    registry
+        .iter_projects()
+        .filter(|(_, project)| project.linked_port.is_none())
+        .min_by_key(|(name, project)| {
+            (
+                !mentions_port(name, project, port),
+                project.port.abs_diff(port),
+                *name,
+            )
+        })
+        .map(|(name, _)| name.clone())
+}
+
+// Interactively link each port in a range to a project, proposing a project for each port
+// Return a line describing the outcome for each port
+fn link_range(
+    deps: &(impl CheckPortInUse + ChoosePort + EphemeralPort + Prompt),
+    registry: &mut Registry,
+    start: u16,
+    end: u16,
+) -> Result<Vec<String>> {
+    let mut results = vec![];
+    for port in start..=end {
+        if let Some((name, _)) = registry
+            .iter_projects()
+            .find(|(_, project)| project.iter_linked_ports().any(|linked| linked == port))
+        {
+            results.push(format!("Port {port} is already linked to project {name}"));
+            continue;
+        }
+
+        let proposal = propose_link(registry, port);
+        let mut message = proposal.as_ref().map_or_else(
+            || format!("Link port {port} to project? (a project name to link, Enter to skip) "),
+            |name| format!("Link port {port} to project [{name}]? (Enter to accept, a project name to link another, - to skip) "),
+        );
+        // Ask again instead of discarding the earlier answers if the project doesn't exist
+        let project_name = loop {
+            let answer = deps.prompt(&message)?;
+            let project_name = match answer.as_str() {
+                "-" => None,
+                "" => proposal.clone(),
+                name => Some(name.to_owned()),
+            };
+            match project_name {
+                Some(name) if registry.get(&name).is_none() => {
+                    message = format!(
+                        "Project {name} does not exist. Link port {port} to project? (a project name to link, - to skip) "
+                    );
+                }
+                project_name => break project_name,
+            }
+        };
+        match project_name {
+            Some(name) => {
+                registry.link(deps, &name, None, port)?;
+                results.push(format!("Linked port {port} to project {name}"));
+            }
+            None => results.push(format!("Skipped port {port}")),
+        }
+    }
+    Ok(results)
+}
+
+// Interactively create a project for each project directory under a root directory that doesn't
+// already belong to a project, proposing a name based on the directory's name
+// Return a line describing the outcome for each directory
+fn scan_projects(
+    deps: &(impl CheckPortInUse + ChoosePort + EphemeralPort + Prompt + WalkDir),
+    registry: &mut Registry,
+    root: &Path,
+    depth: usize,
+    accept_all: bool,
+) -> Result<Vec<String>> {
+    let mut results = vec![];
+    for directory in scan::find_projects(&deps.walk_dir(root, depth)?) {
+        if let Some((name, _)) = registry.iter_projects().find(|(_, project)| {
+            project
+                .activation_directories()
+                .any(|existing| existing == &directory)
+        }) {
+            results.push(format!(
+                "\"{}\" already belongs to project {name}",
+                directory.display()
+            ));
+            continue;
+        }
+
+        let proposal = directory
+            .file_name()
+            .map(|name| Registry::normalize_name(&name.to_string_lossy()))
+            .filter(|name| !name.is_empty() && registry.get(name).is_none());
+        let answer = if accept_all {
+            String::new()
+        } else {
+            let message = proposal.as_ref().map_or_else(
+                || format!("Create project for \"{}\"? (a project name to create, Enter to skip) ", directory.display()),
+                |name| format!("Create project [{name}] for \"{}\"? (Enter to accept, a project name to use another, - to skip) ", directory.display()),
+            );
+            deps.prompt(&message)?
+        };
+        let project_name = match answer.as_str() {
+            "-" => None,
+            "" => proposal,
+            name => Some(name.to_owned()),
+        };
+        match project_name {
+            Some(name) => {
+                registry.create(
+                    deps,
+                    &name,
+                    None,
+                    Some(directory.clone()),
+                    None,
+                    BTreeSet::new(),
+                    None,
+                )?;
+                results.push(format!(
+                    "Created project {name} for \"{}\"",
+                    directory.display()
+                ));
+            }
+            None => results.push(format!("Skipped \"{}\"", directory.display())),
+        }
+    }
+    Ok(results)
+}
+
+// Make sure that no project ports or linked ports are reserved in the config or below 1024, where
+// they can collide with system services or caddy itself
+fn check_reserved_ports(config: &Config, registry: &Registry) -> Result<()> {
+    let reserved_ports = registry
+        .iter_projects()
+        .flat_map(|(name, project)| {
+            std::iter::once(project.port)
+                .chain(project.ports.values().copied())
+                .chain(project.iter_linked_ports())
+                .filter(|port| *port < 1024 || config.reserved.contains(port))
+                .map(|port| (name.clone(), port))
+        })
+        .collect::<Vec<_>>();
+    if reserved_ports.is_empty() {
+        Ok(())
+    } else {
+        Err(ApplicationError::ReservedPorts(reserved_ports))
+    }
+}
+
+// Run diagnostics on the portman installation and return each check's description and result
+// Checks that depend on the config or the registry have no result if those can't be loaded
+fn diagnose(
+    deps: &(impl CheckPortInUse
+          + CheckWritable
+          + ChoosePort
+          + CurrentTime
+          + DataDir
+          + Environment
+          + EphemeralPort
+          + Exec
+          + ListDirectory
+          + LockFile
+          + ProbePort
+          + ReadFile),
+    options: LoadOptions<'_>,
+) -> Vec<(&'static str, Option<Result<()>>)> {
+    let data_dir_result = deps
+        .get_data_dir()
+        .map_err(ApplicationError::from)
+        .and_then(|data_dir| {
+            deps.check_writable(&data_dir)
+                .map_err(|io_err| ApplicationError::UnwritableDataDir(data_dir, io_err))
+        });
+    let config = load_config(deps);
+    let caddyfile = config
+        .as_ref()
+        .ok()
+        .and_then(|config| config.caddyfile.clone());
+    let (config_result, registry_result, ports_result) = match config {
+        Err(err) => (Err(err), None, None),
+        Ok(config) => match load_registry(deps, options) {
+            Err(err) => (Ok(()), Some(Err(err)), None),
+            Ok(registry) => (
+                Ok(()),
+                Some(Ok(())),
+                Some(check_reserved_ports(&config, &registry)),
+            ),
+        },
+    };
+    vec![
+        (
+            "caddy is installed",
+            Some(caddy::check_installed(deps).map_err(ApplicationError::Caddy)),
+        ),
+        (
+            "caddy is running",
+            Some(caddy::check_running(deps).map_err(ApplicationError::Caddy)),
+        ),
+        (
+            "the Caddyfile imports the portman Caddyfile",
+            Some(caddy::check_import(deps, caddyfile.as_deref()).map_err(ApplicationError::Caddy)),
+        ),
+        ("the data directory is writable", Some(data_dir_result)),
+        ("the config is valid", Some(config_result)),
+        ("the registry can be loaded", registry_result),
+        (
+            "project ports don't use reserved or system ports",
+            ports_result,
+        ),
+    ]
+}
+
+// Return the projects as they are shown in the interactive UI
+fn ui_rows(deps: &impl ProbePort, registry: &Registry) -> Vec<ui::ProjectRow> {
+    registry
+        .iter_projects()
+        .map(|(name, project)| ui::ProjectRow {
+            name: name.clone(),
+            port: project.port,
+            linked_port: project.linked_port,
+            directory: project.directory.clone(),
+            tags: project.tags.iter().cloned().collect(),
+            up: deps.probe_port(project.port),
+        })
+        .collect()
+}
+
+// Open a URL in the default browser
+fn open_url(deps: &impl Exec, url: &str) -> Result<()> {
+    let mut command = if cfg!(target_os = "macos") {
+        process::Command::new("open")
+    } else if cfg!(windows) {
+        let mut command = process::Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else {
+        process::Command::new("xdg-open")
+    };
+    deps.exec(command.arg(url))
+        .map_err(ApplicationError::BrowserCommand)?;
+    Ok(())
+}
+
+// Render the configured templates into a project's directory, if it has one
+// Return the path of each rendered file and whether it changed
+fn render_templates(
+    deps: &(impl DataDir + Environment + ReadFile + WriteFile),
+    config: &Config,
+    name: &str,
+    project: &Project,
+) -> Result<Vec<(PathBuf, bool)>> {
+    let Some(directory) = project.directory.as_ref() else {
+        return Ok(vec![]);
+    };
+    let (config_path, _) = get_config_path(deps)?;
+    config
+        .templates
+        .iter()
+        .map(|(destination, template)| {
+            let template_path = Config::resolve_path(deps, &config_path, template)?;
+            let destination = directory.join(destination);
+            let changed = file_template::sync(deps, &template_path, &destination, name, project)?;
+            Ok((destination, changed))
+        })
+        .collect()
+}
+
+// Render templates for new projects and run the configured hooks for project changes after the
+// registry has been saved
+fn run_hooks(
+    deps: &(impl ChoosePort + DataDir + Environment + Exec + ReadFile + WriteFile),
+    events: &[HookEvent],
+) -> Result<()> {
+    if events.is_empty() {
+        return Ok(());
+    }
+    let config = load_config(deps)?;
+    for event in events {
+        // Render templates first so that the create hook can use the rendered files
+        if matches!(event.hook, Hook::Create) {
+            render_templates(deps, &config, &event.name, &event.project)?;
+        }
+        hooks::run(deps, &config.hooks, event).map_err(ApplicationError::HookCommand)?;
+    }
+    Ok(())
+}
+
+// Run a command in the directory of every project that has one of the tags, or every project if
+// no tags are provided
+// Return the combined output, the number of projects that the command ran in, and the names of the
+// projects where the command failed
+// Run a command with a project's port and environment variables set, optionally in a specific
+// directory, and fail with the command's exit code if it fails
+fn run_with_project(
+    deps: &impl Spawn,
+    name: &str,
+    project: &Project,
+    command: &[String],
+    directory: Option<&Path>,
+) -> Result<()> {
+    let mut child = process::Command::new(&command[0]);
+    child
+        .args(&command[1..])
+        .envs(dotenv::variables(name, project));
+    if project.linked_port.is_none() {
+        child.env_remove("PORTMAN_LINKED_PORT");
+    }
+    if let Some(directory) = directory {
+        child.current_dir(directory);
+    }
+    let code = deps
+        .spawn(&mut child)
+        .map_err(ApplicationError::RunCommand)?;
+    if code != 0 {
+        return Err(ApplicationError::RunCommandExit(code));
+    }
+    Ok(())
+}
+
+fn exec_all(
+    deps: &impl Exec,
+    registry: &Registry,
+    tags: &[String],
+    command: &[String],
+) -> (String, usize, Vec<String>) {
+    let mut output = String::new();
+    let mut total = 0;
+    let mut failed = vec![];
+    let projects = registry.iter_projects().filter(|(_, project)| {
+        tags.is_empty() || tags.iter().any(|tag| project.tags.contains(tag))
+    });
+    for (name, project) in projects {
+        let Some(directory) = project.directory.as_ref() else {
+            continue;
+        };
+        total += 1;
+        writeln!(output, "==> {name} ({})", directory.display()).unwrap();
+        let mut project_command = process::Command::new(&command[0]);
+        project_command
+            .args(&command[1..])
+            .current_dir(directory)
+            .env("PORT", project.port.to_string())
+            .env("PORTMAN_PROJECT", name);
+        match project.linked_port {
+            Some(linked_port) => {
+                project_command.env("PORTMAN_LINKED_PORT", linked_port.to_string())
+            }
+            None => project_command.env_remove("PORTMAN_LINKED_PORT"),
+        };
+        let command_output = match deps.exec(&mut project_command) {
+            Ok(command_output) => command_output,
+            Err(err) => {
+                failed.push(name.clone());
+                err.to_string()
+            }
+        };
+        output += &command_output;
+        if !command_output.is_empty() && !command_output.ends_with('\n') {
+            output.push('\n');
+        }
+    }
+    (output, total, failed)
+}
+
+// Delete the projects whose directories no longer exist and reload caddy if the registry changed
+// Return the directories of the remaining projects to watch and the names of the projects
+fn sync_daemon(
+    deps: &(impl CheckPath
+          + CheckPortInUse
+          + ChoosePort
+          + CurrentTime
+          + DataDir
+          + Environment
+          + EphemeralPort
+          + Exec
+          + ListDirectory
+          + LockFile
+          + ReadFile
+          + WriteFile),
+    options: LoadOptions<'_>,
+) -> Result<(Vec<PathBuf>, BTreeSet<String>)> {
+    let mut registry = load_registry(deps, options)?;
+    let deleted_projects = cleanup(deps, &mut registry)?;
+    let outcome = save_registry(deps, &registry, options.warnings)?;
+    for CleanedProject { name, project, .. } in deleted_projects {
+        println!("Deleted project {}", format_project(&name, &project));
+    }
+    // Saving the registry already took care of caddy if it changed
+    let handled_caddy = matches!(outcome, SaveOutcome::Reloaded | SaveOutcome::CaddyMissing);
+    if !handled_caddy && caddy::sync(deps, &registry).map_err(ApplicationError::Caddy)? {
+        println!("Reloaded caddy after the registry changed");
+    }
+    let directories = registry
+        .iter_projects()
+        .filter_map(|(_, project)| project.directory.clone())
+        .collect();
+    Ok((directories, mdns::project_names(&registry)))
+}
+
+// Keep the registry and caddy in sync with the filesystem and check the projects' health every
+// `interval` seconds until the process is stopped, advertising the projects over mDNS if an
+// advertiser is provided
+// Errors are printed instead of returned so that a single failure doesn't stop the daemon
+fn run_daemon(
+    deps: &(impl CheckPath
+          + CheckPortInUse
+          + ChoosePort
+          + CurrentTime
+          + DataDir
+          + Environment
+          + EphemeralPort
+          + Exec
+          + LanAddress
+          + ListDirectory
+          + LockFile
+          + ProbeHttp
+          + ProbePort
+          + ReadFile
+          + WatchPath
+          + WriteFile),
+    options: LoadOptions<'_>,
+    mut advertiser: Option<mdns::Advertiser>,
+    interval: u64,
+) -> ! {
+    let mut watcher = DaemonWatcher::default();
+    let mut elapsed = interval;
+    let (mut directories, mut names) = match sync_daemon(deps, options) {
+        Ok(state) => state,
+        Err(err) => {
+            eprintln!("{err}");
+            (vec![], BTreeSet::new())
+        }
+    };
+    loop {
+        if elapsed >= interval {
+            elapsed = 0;
+            if let Err(err) =
+                load_registry(deps, options).and_then(|registry| check_health(deps, &registry))
+            {
+                eprintln!("{err}");
+            }
+        }
+        match watcher.poll(deps, &directories) {
+            Ok(true) => match sync_daemon(deps, options) {
+                Ok(state) => (directories, names) = state,
+                Err(err) => eprintln!("{err}"),
+            },
+            Ok(false) => {}
+            Err(err) => eprintln!("{err}"),
+        }
+        // The advertiser also follows changes to the machine's network address
+        if let Some(Err(err)) = advertiser
+            .as_mut()
+            .map(|advertiser| advertiser.sync(deps, &names))
+        {
+            // The responder will keep failing, so stop advertising instead of repeating the error
+            eprintln!("{err}\nStopped advertising projects over mDNS");
+            advertiser = None;
+        }
+        // The daemon never returns, so print warnings as they are reported
+        for warning in options.warnings.take() {
+            eprintln!("Warning: {warning}");
+        }
+        std::thread::sleep(Duration::from_secs(1));
+        elapsed += 1;
+    }
+}
+
+// Apply a command that modifies the registry without saving it, returning its output
+#[allow(clippy::too_many_lines)]
+fn apply(
+    deps: &(impl BuildInfo
+          + CheckPath
+          + CheckPortInUse
+          + ChoosePort
+          + CurrentExe
+          + CurrentTime
+          + DataDir
+          + Environment
+          + EphemeralPort
+          + Exec
+          + ListDirectory
+          + ProbePort
+          + Prompt
+          + ReadFile
+          + ResolveHost
+          + Tty
+          + WriteFile
+          + WorkingDirectory),
+    registry: &mut Registry,
+    command: Command,
+    strict: bool,
+    events: &mut Vec<HookEvent>,
+) -> Result<String> {
+    let mut output = String::new();
+    match command {
+        Command::Create {
+            project_name,
+            name_template,
+            directory,
+            port,
+            force,
+            link,
+            no_link,
+            no_activate,
+            overwrite,
+            tags,
+            ports,
+            description,
+            env,
+            per_branch,
+            upstream,
+            headers,
+            wildcard,
+            match_repo,
+            remote,
+        } => {
+            // Per-branch projects are named after their repo and branch by default
+            let name_template = name_template.or_else(|| {
+                (per_branch && project_name.is_none()).then(|| String::from("{repo}-{branch}"))
+            });
+            let branch = if per_branch {
+                Some(template::branch_name(deps)?)
+            } else {
+                None
+            };
+            let config = load_config(deps)?;
+            let strict = strict || config.strict;
+            let project_name = match name_template {
+                Some(template) => Some(check_normalized_name(
+                    &template::render_name_template(deps, &template)?,
+                    strict,
+                )?),
+                None => project_name,
+            };
+            let repo = if no_link || config.defaults.no_link {
+                None
+            } else {
+                repo::get_active_repo(deps, remote.as_deref())
+                    .ok()
+                    .and_then(|repo| registry.get_repo(&repo).ok().cloned())
+            };
+            let linked_port = link.or_else(|| repo.as_ref().map(|repo| repo.port));
+            if let Some(port) = linked_port {
+                check_linked_port(&config, port, strict)?;
+            }
+            // Projects in a repo with linked ports link them to the project's named ports
+            let repo_linked_ports = repo
+                .as_ref()
+                .map(|repo| repo.linked_ports.clone())
+                .unwrap_or_default();
+            for port in repo_linked_ports.values() {
+                check_linked_port(&config, *port, strict)?;
+            }
+            let tags: BTreeSet<String> = if tags.is_empty() {
+                config.defaults.tags.iter().cloned().collect()
+            } else {
+                tags.into_iter().collect()
+            };
+            // Projects in a repo with a project port are assigned it by default, unless another
+            // project in the repo already claimed it
+            let port = port.or_else(|| {
+                repo.as_ref()
+                    .and_then(|repo| repo.project_port)
+                    .filter(|port| registry.is_port_available(*port, &tags))
+            });
+            // Projects in a repo with a default project name are named after it by default
+            let project_name =
+                project_name.or_else(|| repo.and_then(|repo| repo.default_project_name));
+            let (name, project, updated) = create(
+                deps,
+                registry,
+                CreateOptions {
+                    name: project_name,
+                    directory,
+                    no_activate,
+                    port,
+                    force,
+                    linked_port,
+                    overwrite,
+                    tags,
+                    branch,
+                    strict,
+                },
+            )?;
+            // A port that is already in use before the project has ever run belongs to another
+            // process
+            if strict && deps.probe_port(project.port) {
+                return Err(ApplicationError::StrictMode(format!(
+                    "port {} is already in use by another process",
+                    project.port
+                )));
+            }
+            let project = match upstream {
+                Some(url) => registry.set_upstream(
+                    &name,
+                    Some(Upstream {
+                        url,
+                        headers: headers.into_iter().collect(),
+                    }),
+                )?,
+                None => project,
+            };
+            let project = match description {
+                Some(description) => registry.set_description(&name, Some(description))?,
+                None => project,
+            };
+            let project = if env.is_empty() {
+                project
+            } else {
+                registry.extend_env(&name, env)?
+            };
+            let project = if ports.is_empty() {
+                project
+            } else {
+                registry.add_named_ports(deps, &name, ports)?
+            };
+            let project = if repo_linked_ports.is_empty() {
+                project
+            } else {
+                registry.add_named_ports(
+                    deps,
+                    &name,
+                    repo_linked_ports.keys().cloned().collect(),
+                )?;
+                for (port_name, port) in &repo_linked_ports {
+                    registry.link(deps, &name, Some(port_name), *port)?;
+                }
+                registry
+                    .get(&name)
+                    .cloned()
+                    .ok_or_else(|| ApplicationError::NonExistentProject(name.clone()))?
+            };
+            let project = if wildcard {
+                registry.set_wildcard(&name, true)?
+            } else {
+                project
+            };
+            let project = if match_repo {
+                let url = repo::get_active_repo(deps, remote.as_deref())?;
+                registry.set_repo_match(&name, Some(url))?
+            } else {
+                project
+            };
+
+            if !updated {
+                events.push(HookEvent {
+                    hook: Hook::Create,
+                    name: name.clone(),
+                    project: project.clone(),
+                });
+            }
+
+            if deps.is_tty() {
+                writeln!(
+                    output,
+                    "{} project {}",
+                    if updated { "Updated" } else { "Created" },
+                    format_project(&name, &project)
+                )
+                .unwrap();
+                // Some resolvers don't resolve *.localhost, and other TLDs need to be configured,
+                // which leaves the project mysteriously unreachable by its hostname
+                let hostname = registry.hostname(&name);
+                if !deps.resolves_to_loopback(&hostname) {
+                    let tld = registry.tld();
+                    writeln!(output, "Warning: {hostname} does not resolve to this machine, so the project isn't reachable by its hostname.\nTry adding \"127.0.0.1 {hostname}\" to /etc/hosts or configuring dnsmasq with \"address=/{tld}/127.0.0.1\" to resolve every *.{tld} hostname.").unwrap();
+                }
+            } else {
+                // Only print the port if stdout isn't a TTY for easier scripting
+                writeln!(output, "{}", project.port).unwrap();
+            }
+        }
+
+        Command::Set(subcommand) => {
+            let (project_name, project) = match subcommand {
+                Set::DisplayName {
+                    display_name,
+                    project_name,
+                } => {
+                    let project_name = match project_name {
+                        Some(name) => name,
+                        None => get_active_project(deps, registry)?.0.clone(),
+                    };
+                    let display_name = Some(display_name).filter(|name| !name.is_empty());
+                    let project = registry.set_display_name(&project_name, display_name)?;
+                    (project_name, project)
+                }
+                Set::Bind { bind, project_name } => {
+                    let project_name = match project_name {
+                        Some(name) => name,
+                        None => get_active_project(deps, registry)?.0.clone(),
+                    };
+                    let bind = Some(bind)
+                        .filter(|bind| !bind.is_empty())
+                        .map(BindAddress::try_from)
+                        .transpose()?;
+                    let project = registry.set_bind(&project_name, bind)?;
+                    (project_name, project)
+                }
+                Set::Hmr {
+                    enabled,
+                    project_name,
+                } => {
+                    let project_name = match project_name {
+                        Some(name) => name,
+                        None => get_active_project(deps, registry)?.0.clone(),
+                    };
+                    let project = registry.set_hmr(&project_name, enabled)?;
+                    (project_name, project)
+                }
+                Set::Protocol {
+                    protocol,
+                    project_name,
+                } => {
+                    let project_name = match project_name {
+                        Some(name) => name,
+                        None => get_active_project(deps, registry)?.0.clone(),
+                    };
+                    let upstream_protocol = match protocol {
+                        Protocol::Http => UpstreamProtocol::Http,
+                        Protocol::H2c => UpstreamProtocol::H2c,
+                    };
+                    let project =
+                        registry.set_upstream_protocol(&project_name, upstream_protocol)?;
+                    (project_name, project)
+                }
+                Set::Wildcard {
+                    enabled,
+                    project_name,
+                } => {
+                    let project_name = match project_name {
+                        Some(name) => name,
+                        None => get_active_project(deps, registry)?.0.clone(),
+                    };
+                    let project = registry.set_wildcard(&project_name, enabled)?;
+                    (project_name, project)
+                }
+                Set::HealthPath {
+                    health_path,
+                    project_name,
+                } => {
+                    let project_name = match project_name {
+                        Some(name) => name,
+                        None => get_active_project(deps, registry)?.0.clone(),
+                    };
+                    let health_path = Some(health_path).filter(|path| !path.is_empty());
+                    let project = registry.set_health_path(&project_name, health_path)?;
+                    (project_name, project)
+                }
+                Set::CaddyExtra {
+                    file,
+                    clear: _,
+                    project_name,
+                } => {
+                    let project_name = match project_name {
+                        Some(name) => name,
+                        None => get_active_project(deps, registry)?.0.clone(),
+                    };
+                    let caddy_extra = match file {
+                        Some(file) => {
+                            let path = deps.get_cwd()?.join(file);
+                            let caddy_extra = deps.read_file(&path)?.with_context(|| {
+                                format!("Caddyfile snippet \"{}\" does not exist", path.display())
+                            })?;
+                            Some(caddy_extra).filter(|snippet| !snippet.trim().is_empty())
+                        }
+                        None => None,
+                    };
+                    // Saving the registry refuses to save directives that caddy would reject
+                    let project = registry.set_caddy_extra(&project_name, caddy_extra)?;
+                    (project_name, project)
+                }
+                Set::Icon { icon, project_name } => {
+                    let project_name = match project_name {
+                        Some(name) => name,
+                        None => get_active_project(deps, registry)?.0.clone(),
+                    };
+                    let icon = Some(icon).filter(|icon| !icon.is_empty());
+                    let project = registry.set_icon(&project_name, icon)?;
+                    (project_name, project)
+                }
+                Set::Color {
+                    color,
+                    project_name,
+                } => {
+                    let project_name = match project_name {
+                        Some(name) => name,
+                        None => get_active_project(deps, registry)?.0.clone(),
+                    };
+                    let color = match color {
+                        Color::Red => Some(ProjectColor::Red),
+                        Color::Yellow => Some(ProjectColor::Yellow),
+                        Color::Green => Some(ProjectColor::Green),
+                        Color::Cyan => Some(ProjectColor::Cyan),
+                        Color::Blue => Some(ProjectColor::Blue),
+                        Color::Magenta => Some(ProjectColor::Magenta),
+                        Color::None => None,
+                    };
+                    let project = registry.set_color(&project_name, color)?;
+                    (project_name, project)
+                }
+            };
+            writeln!(
+                output,
+                "Updated project {}",
+                format_project(&project_name, &project)
+            )
+            .unwrap();
+        }
+
+        Command::Delete { project_name } => {
+            let project_name = match project_name {
+                Some(name) => name,
+                None => get_active_project(deps, registry)?.0.clone(),
+            };
+            let project = registry.delete(&project_name)?;
+            writeln!(
+                output,
+                "Deleted project {}",
+                format_project(&project_name, &project),
+            )
+            .unwrap();
+            events.push(HookEvent {
+                hook: Hook::Delete,
+                name: project_name,
+                project,
+            });
+        }
+
+        Command::Link {
+            port,
+            project_name,
+            port_name,
+            no_save,
+            remote,
+            ..
+        } => {
+            let save_repo = port.is_some() && project_name.is_none() && !no_save;
+            let project_name = match project_name {
+                Some(name) => name,
+                None => get_active_project(deps, registry)?.0.clone(),
+            };
+            let port = match port {
+                Some(name) => name,
+                None => registry.get_repo_port(&repo::get_active_repo(deps, remote.as_deref())?)?,
+            };
+            let config = load_config(deps)?;
+            check_linked_port(&config, port, strict || config.strict)?;
+            registry.link(deps, &project_name, port_name.as_deref(), port)?;
+            match port_name.as_ref() {
+                Some(port_name) => writeln!(
+                    output,
+                    "Linked port {port} to the {port_name} port of project {project_name}"
+                ),
+                None => writeln!(output, "Linked port {port} to project {project_name}"),
+            }
+            .unwrap();
+            if let Some(project) = registry.get(&project_name) {
+                events.push(HookEvent {
+                    hook: Hook::Link,
+                    name: project_name.clone(),
+                    project: project.clone(),
+                });
+            }
+            if save_repo {
+                if let Ok(repo) = repo::get_active_repo(deps, remote.as_deref()) {
+                    // The repo must already exist to remember a named port's linked port
+                    if let Some(port_name) = port_name {
+                        if registry
+                            .set_repo_metadata(
+                                &repo,
+                                RepoUpdate {
+                                    linked_ports: vec![(port_name.clone(), port)],
+                                    ..Default::default()
+                                },
+                            )
+                            .is_ok()
+                        {
+                            writeln!(
+                                output,
+                                "Saved default {port_name} port {port} for repo {repo}"
+                            )
+                            .unwrap();
+                        }
+                    } else {
+                        writeln!(output, "Saved default port {port} for repo {repo}").unwrap();
+                        registry.set_repo_port(repo, port);
+                    }
+                }
+            }
+        }
+
+        Command::Unlink { port } => {
+            let unlinked_port = registry.unlink(port);
+            match unlinked_port {
+                Some(project_name) => {
+                    writeln!(output, "Unlinked port {port} from project {project_name}").unwrap();
+                }
+                None => writeln!(output, "Port {port} was not linked to a project").unwrap(),
+            };
+        }
+
+        _ => return Err(ApplicationError::UnbatchableCommand),
+    }
+    Ok(output)
+}
+
+#[allow(clippy::too_many_lines)]
+fn run(
+    deps: &(impl BuildInfo
+          + CheckPath
+          + CheckPortInUse
+          + CheckWritable
+          + ChoosePort
+          + CurrentExe
+          + CurrentTime
+          + DataDir
+          + Environment
+          + EphemeralPort
+          + Exec
+          + LanAddress
+          + ListDirectory
+          + LockFile
+          + ProbeHttp
+          + ProbePort
+          + Prompt
+          + ReadFile
+          + ReadStdin
+          + ResolveHost
+          + Spawn
+          + Tty
+          + WalkDir
+          + WatchPath
+          + WriteFile
+          + WorkingDirectory),
+    command: Command,
+    strict: bool,
+    json: bool,
+    dry_run: bool,
+    options: LoadOptions<'_>,
+) -> Result<String> {
+    let mut output = String::new();
+    match command {
+        Command::Init { shell, var_prefix } => {
+            output += &shell::init_script(&shell, var_prefix.as_deref());
+        }
+
+        Command::Config(subcommand) => match subcommand {
+            ConfigSubcommand::Show => {
+                let config_path = get_config_path(deps)?.0;
+                let config = load_config(deps)?;
+                let data_dir = deps.get_data_dir()?;
+                let registry_path = Registry::store_path(deps)?;
+                let caddyfile_path = caddy::import_path(deps)?;
+                let gallery_path = caddy::gallery_www_path(deps)?;
+                if json {
+                    let report = serde_json::json!({
+                        "data_dir": data_dir,
+                        "config_path": config_path,
+                        "registry_path": registry_path,
+                        "caddyfile_path": caddyfile_path,
+                        "gallery_path": gallery_path,
+                        "config": config,
+                    });
+                    writeln!(output, "{report}").unwrap();
+                } else {
+                    writeln!(
+                        output,
+                        "Data directory: {}\nConfig path: {}\nRegistry path: {}\nCaddyfile path: {}\nGallery path: {}\nConfiguration:\n--------------\n{config}",
+                        data_dir.display(),
+                        config_path.display(),
+                        registry_path.display(),
+                        caddyfile_path.display(),
+                        gallery_path.display()
+                    )
+                    .unwrap();
+                }
+            }
+            ConfigSubcommand::Edit => {
+                let config_path = get_config_path(deps)?.0;
+                let editor = deps.read_var("EDITOR")?;
+                writeln!(
+                    output,
+                    "Opening \"{}\" with \"{editor}\"",
+                    config_path.display()
+                )
+                .unwrap();
+                deps.exec(process::Command::new(editor).arg(config_path))
+                    .map_err(ApplicationError::EditorCommand)?;
+            }
+            ConfigSubcommand::Migrate => {
+                let config_path = get_config_path(deps)?.0;
+                let migrated = deps
+                    .read_file(&config_path)?
+                    .map(|contents| config::migrate(&contents))
+                    .transpose()
+                    .map_err(ApplicationError::InvalidConfig)?
+                    .flatten();
+                if let Some(contents) = migrated {
+                    deps.write_file(&config_path, &contents)?;
+                    writeln!(
+                        output,
+                        "Upgraded \"{}\" to config version {CONFIG_VERSION}",
+                        config_path.display()
+                    )
+                    .unwrap();
+                } else {
+                    writeln!(
+                        output,
+                        "\"{}\" is already up to date",
+                        config_path.display()
+                    )
+                    .unwrap();
+                }
+            }
+            ConfigSubcommand::Validate { path } => {
+                let (config_path, custom_path) = match path {
+                    Some(path) => (normalize_path(&deps.get_cwd()?.join(path)), true),
+                    None => get_config_path(deps)?,
+                };
+                match Config::load(deps, &config_path)? {
+                    Some(_) => {
+                        writeln!(output, "\"{}\" is valid", config_path.display()).unwrap();
+                    }
+                    None if custom_path => {
+                        return Err(ApplicationError::MissingCustomConfig(config_path))
+                    }
+                    None => {
+                        writeln!(
+                            output,
+                            "\"{}\" does not exist, so the default configuration is used",
+                            config_path.display()
+                        )
+                        .unwrap();
+                    }
+                }
+            }
+            ConfigSubcommand::Set { key, value } => {
+                let config_path = get_config_path(deps)?.0;
+                let contents = deps.read_file(&config_path)?.unwrap_or_default();
+                let updated = config::set_value(&contents, &key, &value)
+                    .map_err(ApplicationError::InvalidConfig)?;
+                // Refuse to save changes that would make the config invalid
+                Config::parse(deps, &config_path, &updated)?;
+                deps.write_file(&config_path, &updated)?;
+                writeln!(output, "Set {key} in \"{}\"", config_path.display()).unwrap();
+            }
+        },
+
+        Command::Get {
+            shell_fast: true, ..
+        } => {
+            let index = match deps.read_file(&DirectoryIndex::path(deps)?)? {
+                Some(contents) => DirectoryIndex::from_toml(&contents)?,
+                // Fall back to the registry if the index hasn't been written yet
+                None => DirectoryIndex::from_registry(&load_registry(deps, options)?),
+            };
+            let directory = deps.get_cwd()?;
+            let auto_created;
+            let entry = index
+                .get(&directory, || template::branch_name(deps).ok())
+                .or_else(|| {
+                    // Only run git if at least one project is activated by its repo
+                    index
+                        .has_repos()
+                        .then(|| repo::get_checkout_repo(deps, &directory))
+                        .flatten()
+                        .and_then(|url| index.get_repo(&url))
+                });
+            let entry = if let Some(entry) = entry {
+                entry
+            } else {
+                let (name, project) = auto_create_project(deps, options, strict)?
+                    .ok_or(ApplicationError::NoActiveProject)?;
+                auto_created = IndexEntry::new(&name, &project);
+                &auto_created
+            };
+            record_usage(deps, options.warnings, &entry.name);
+            let linked_port = entry
+                .linked_port
+                .map(|port| port.to_string())
+                .unwrap_or_default();
+            write!(
+                output,
+                "{}\n{}\n{}\n{linked_port}\n",
+                entry.port,
+                entry.name,
+                directory.display()
+            )
+            .unwrap();
+            // The remaining lines are the project's environment variables
+            for (key, value) in &entry.env {
+                writeln!(output, "{key}={value}").unwrap();
+            }
+        }
+
+        Command::Get {
+            project_name,
+            extended,
+            port_name,
+            field,
+            url,
+            ..
+        } => {
+            let mut registry = load_registry(deps, options)?;
+            let name = match project_name {
+                Some(name) => name,
+                None => {
+                    if let Some((name, _)) = registry.match_cwd(deps)? {
+                        name.clone()
+                    } else {
+                        drop(registry);
+                        let (name, _) = auto_create_project(deps, options, strict)?
+                            .ok_or(ApplicationError::NoActiveProject)?;
+                        registry = load_registry(deps, options)?;
+                        name
+                    }
+                }
+            };
+            let project = registry
+                .get(&name)
+                .ok_or_else(|| ApplicationError::NonExistentProject(name.clone()))?;
+            let name = &name;
+            record_usage(deps, options.warnings, name);
+            if url {
+                let url = match port_name {
+                    Some(port_name) => {
+                        if !project.ports.contains_key(&port_name) {
+                            return Err(ApplicationError::NonExistentPortName(
+                                name.clone(),
+                                port_name,
+                            ));
+                        }
+                        registry.port_url(name, &port_name)
+                    }
+                    None => registry.project_url(name, project),
+                };
+                writeln!(output, "{url}").unwrap();
+            } else if let Some(field) = field {
+                let value = match field {
+                    GetField::Port => project.port.to_string(),
+                    GetField::Name => name.clone(),
+                    GetField::Directory => project
+                        .directory
+                        .as_ref()
+                        .ok_or_else(|| ApplicationError::NoProjectDirectory(name.clone()))?
+                        .display()
+                        .to_string(),
+                    GetField::LinkedPort => project
+                        .linked_port
+                        .ok_or_else(|| ApplicationError::NoLinkedPort(name.clone()))?
+                        .to_string(),
+                };
+                writeln!(output, "{value}").unwrap();
+            } else if let Some(port_name) = port_name {
+                let port = project.ports.get(&port_name).ok_or_else(|| {
+                    ApplicationError::NonExistentPortName(name.clone(), port_name.clone())
+                })?;
+                writeln!(output, "{port}").unwrap();
+            } else if json {
+                writeln!(output, "{}", project_json(name, project)?).unwrap();
+            } else {
+                output += &format_project_details(deps, name, project, extended);
+            }
+        }
+
+        Command::Resolve { path, extended } => {
+            let registry = load_registry(deps, options)?;
+            let path = normalize_path(&deps.get_cwd()?.join(path));
+            let (name, project) = path
+                .ancestors()
+                .find_map(|directory| {
+                    registry.match_directory(directory, || {
+                        template::branch_name_in(deps, directory).ok()
+                    })
+                })
+                .ok_or_else(|| ApplicationError::UnresolvedPath(path.clone()))?;
+            if json {
+                writeln!(output, "{}", project_json(name, project)?).unwrap();
+            } else {
+                output += &format_project_details(deps, name, project, extended);
+            }
+        }
+
+        Command::Which => {
+            let registry = load_registry(deps, options)?;
+            let directory = deps.get_cwd()?;
+            writeln!(output, "Directory: {}", directory.display()).unwrap();
+            let mut branch = None;
+            let active = registry
+                .match_directory(&directory, || {
+                    branch = template::branch_name(deps).ok();
+                    branch.clone()
+                })
+                .or_else(|| registry.match_checkout(deps, &directory));
+            match (active, branch) {
+                (Some((name, project)), _) => {
+                    let reason = if !project
+                        .activation_directories()
+                        .any(|existing| existing == &directory)
+                    {
+                        format!(
+                            "this directory is a checkout of its repo {}",
+                            project.repo.as_deref().unwrap_or_default()
+                        )
+                    } else if let Some(branch) = &project.branch {
+                        format!("its directory and branch {branch} match")
+                    } else {
+                        String::from("its directory matches")
+                    };
+                    writeln!(
+                        output,
+                        "Project: {name} (port {}), because {reason}",
+                        project.port
+                    )
+                    .unwrap();
+                }
+                (None, Some(branch)) => writeln!(
+                    output,
+                    "Project: none, because no project has this directory and branch {branch}"
+                )
+                .unwrap(),
+                (None, None) => {
+                    writeln!(
+                        output,
+                        "Project: none, because no project has this directory"
+                    )
+                    .unwrap();
+                }
+            }
+            if active.is_none() {
+                // The shell integration only activates projects in their exact directory
+                let parent = directory.ancestors().skip(1).find_map(|ancestor| {
+                    registry
+                        .match_directory(ancestor, || template::branch_name_in(deps, ancestor).ok())
+                        .map(|(name, _)| (name, ancestor))
+                });
+                if let Some((name, ancestor)) = parent {
+                    writeln!(
+                        output,
+                        "Parent project: {name} in {}, which is only active in its own directory",
+                        ancestor.display()
+                    )
+                    .unwrap();
+                }
+            }
+            match repo::get_active_repo(deps, None) {
+                Ok(url) => match registry.get_repo(&url) {
+                    Ok(entry) => {
+                        writeln!(output, "Repo: {url} (default port {})", entry.port).unwrap();
+                    }
+                    Err(_) => writeln!(output, "Repo: {url} (no default port)").unwrap(),
+                },
+                Err(_) => writeln!(output, "Repo: none").unwrap(),
+            }
+            match active {
+                Some((name, project)) => {
+                    writeln!(output, "Environment:").unwrap();
+                    for (key, value) in dotenv::variables(name, project) {
+                        writeln!(output, "  {key}={value}").unwrap();
+                    }
+                }
+                None => writeln!(output, "Environment: none").unwrap(),
+            }
+        }
+
+        command @ (Command::Create { .. }
+        | Command::Set(_)
+        | Command::Delete { .. }
+        | Command::Link { range: None, .. }
+        | Command::Unlink { .. }) => {
+            let mut registry = load_registry(deps, options)?.with_dry_run(dry_run);
+            let mut events = vec![];
+            output += &apply(deps, &mut registry, command, strict, &mut events)?;
+            save_registry(deps, &registry, options.warnings)?;
+            if dry_run {
+                output += &dry_run_report(deps, &registry)?;
+            } else {
+                // Hook commands may run portman themselves, which would wait for the registry lock
+                drop(registry);
+                run_hooks(deps, &events)?;
+            }
+        }
+
+        Command::Adopt {
+            project_name,
+            port,
+            force,
+        } => {
+            let directory = deps.get_cwd()?;
+            let ports = adopt::find_listening_ports(deps, &directory)
+                .map_err(ApplicationError::LsofCommand)?;
+            let port = match (port, ports.as_slice()) {
+                (Some(port), ports) if ports.contains(&port) => port,
+                (None, [port]) => *port,
+                (None, []) | (Some(_), _) => {
+                    return Err(ApplicationError::NoListeningPort(directory))
+                }
+                (None, ports) => {
+                    return Err(ApplicationError::AmbiguousListeningPorts(ports.to_vec()))
+                }
+            };
+            let name = if let Some(name) = project_name {
+                name
+            } else {
+                let basename = directory
+                    .file_name()
+                    .and_then(|basename| basename.to_str())
+                    .context("Failed to extract directory basename")?;
+                check_normalized_name(basename, strict || load_config(deps)?.strict)?
+            };
+            let mut registry = load_registry(deps, options)?.with_dry_run(dry_run);
+            let project = registry.adopt(deps, &name, port, Some(directory), force)?;
+            writeln!(
+                output,
+                "Adopted project {}",
+                format_project(&name, &project)
+            )
+            .unwrap();
+            save_registry(deps, &registry, options.warnings)?;
+            if dry_run {
+                output += &dry_run_report(deps, &registry)?;
+            } else {
+                drop(registry);
+                run_hooks(
+                    deps,
+                    &[HookEvent {
+                        hook: Hook::Create,
+                        name,
+                        project,
+                    }],
+                )?;
+            }
+        }
+
+        Command::Batch { file } => {
+            let input = match file {
+                Some(path) => deps
+                    .read_file(&path)?
+                    .with_context(|| format!("Batch file \"{}\" does not exist", path.display()))?,
+                None => deps.read_stdin()?,
+            };
+            let commands = batch::parse(&input)?;
+            let mut registry = load_registry(deps, options)?.with_dry_run(dry_run);
+            let mut events = vec![];
+            for (line_number, cli) in commands {
+                output += &apply(
+                    deps,
+                    &mut registry,
+                    cli.command,
+                    strict || cli.strict,
+                    &mut events,
+                )
+                .map_err(|err| ApplicationError::BatchCommand(line_number, Box::new(err)))?;
+            }
+            // Nothing is saved unless every command succeeded
+            save_registry(deps, &registry, options.warnings)?;
+            if dry_run {
+                output += &dry_run_report(deps, &registry)?;
+            } else {
+                drop(registry);
+                run_hooks(deps, &events)?;
+            }
+        }
+
+        Command::Archive {
+            project_name,
+            reserve_port,
+        } => {
+            let mut registry = load_registry(deps, options)?;
+            let project_name = match project_name {
+                Some(name) => name,
+                None => get_active_project(deps, &registry)?.0.clone(),
+            };
+            let project = registry.archive(&project_name, reserve_port)?;
+            // Save the archive first so that the project isn't lost if saving the registry fails
+            let mut archive = Archive::load(deps)?;
+            archive.insert(project_name.clone(), project.clone());
+            archive.save(deps)?;
+            save_registry(deps, &registry, options.warnings)?;
+            writeln!(
+                output,
+                "Archived project {}",
+                format_project(&project_name, &project),
+            )
+            .unwrap();
+        }
+
+        Command::Unarchive { project_name } => {
+            let mut registry = load_registry(deps, options)?;
+            let mut archive = Archive::load(deps)?;
+            let project = archive.remove(&project_name)?;
+            let project = registry.unarchive(deps, &project_name, project)?;
+            save_registry(deps, &registry, options.warnings)?;
+            archive.save(deps)?;
+            writeln!(
+                output,
+                "Unarchived project {}",
+                format_project(&project_name, &project),
+            )
+            .unwrap();
+        }
+
+        Command::Cleanup { stale, yes } => {
+            let mut registry = load_registry(deps, options)?.with_dry_run(dry_run);
+            let mut deleted_projects = cleanup(deps, &mut registry)?;
+            if let Some(max_age) = stale {
+                let stale_projects = find_stale_projects(deps, &registry, max_age)?;
+                let confirmed = if stale_projects.is_empty() || yes || json || !deps.is_tty() {
+                    true
+                } else {
+                    let list = stale_projects
+                        .iter()
+                        .filter_map(|(name, age)| {
+                            let project = registry.get(name)?;
+                            Some(format!(
+                                "  {} (inactive for {})\n",
+                                format_project(name, project),
+                                format_duration(*age)
+                            ))
+                        })
+                        .collect::<String>();
+                    let answer = deps.prompt(&format!(
+                        "These projects haven't been used in {}:\n{list}Delete them? [y/N] ",
+                        format_duration(max_age)
+                    ))?;
+                    matches!(answer.to_lowercase().as_str(), "y" | "yes")
+                };
+                if confirmed {
+                    let names = stale_projects.into_iter().map(|(name, _)| name).collect();
+                    deleted_projects.extend(registry.delete_many(names)?.into_iter().map(
+                        |(name, project)| CleanedProject {
+                            name,
+                            project,
+                            reason: CleanupReason::Stale,
+                        },
+                    ));
+                }
+            }
+            save_registry(deps, &registry, options.warnings)?;
+            if json {
+                let report = serde_json::json!({ "removed": deleted_projects });
+                writeln!(output, "{report}").unwrap();
+            } else {
+                writeln!(
+                    output,
+                    "Deleted {}",
+                    match deleted_projects.len() {
+                        1 => String::from("1 project"),
+                        count => format!("{count} projects"),
+                    }
+                )
+                .unwrap();
+                for CleanedProject { name, project, .. } in deleted_projects {
+                    writeln!(output, "{}", format_project(&name, &project)).unwrap();
+                }
+                if dry_run {
+                    output += &dry_run_report(deps, &registry)?;
+                }
+            }
+        }
+
+        Command::List {
+            status,
+            archived,
+            reservations,
+            extended,
+            sort,
+            linked_only,
+            no_directory,
+            filter,
+        } => {
+            let filter = ProjectFilter {
+                pattern: filter,
+                linked_only,
+                no_directory,
+            };
+            let order = match sort {
+                ListSort::Name => ProjectOrder::Name,
+                ListSort::Port => ProjectOrder::Port,
+                ListSort::Directory => ProjectOrder::Directory,
+            };
+            // Usage is only shown in the extended format
+            let (usage, now) = if extended && !json {
+                (UsageLog::load(deps)?, deps.get_current_time())
+            } else {
+                (UsageLog::default(), 0)
+            };
+            if reservations {
+                let registry = load_registry(deps, options)?;
+                save_registry(deps, &registry, options.warnings)?;
+                if json {
+                    let reservations = registry.iter_reservations().collect::<Vec<_>>();
+                    writeln!(
+                        output,
+                        "{}",
+                        serde_json::json!({ "reservations": reservations })
+                    )
+                    .unwrap();
+                } else {
+                    for reservation in registry.iter_reservations() {
+                        writeln!(output, "{}", format_reservation(reservation)).unwrap();
+                    }
+                }
+            } else if archived {
+                let archive = Archive::load(deps)?;
+                let mut projects = archive
+                    .iter_projects()
+                    .filter(|(name, project)| filter.matches(name, project))
+                    .collect::<Vec<_>>();
+                order.sort(&mut projects);
+                if json {
+                    let projects = projects
+                        .into_iter()
+                        .map(|(name, project)| NamedProject {
+                            name,
+                            project,
+                            status: None,
+                        })
+                        .collect::<Vec<_>>();
+                    writeln!(output, "{}", serde_json::json!({ "projects": projects })).unwrap();
+                } else {
+                    for (name, project) in projects {
+                        let line = if extended {
+                            format_project_extended(name, project, usage.last_used(name), now)
+                        } else {
+                            format_project(name, project)
+                        };
+                        writeln!(output, "{line}").unwrap();
+                    }
+                }
+            } else {
+                let registry = load_registry(deps, options)?;
+                save_registry(deps, &registry, options.warnings)?;
+                let health = if status {
+                    Some(HealthCache::load(deps)?)
+                } else {
+                    None
+                };
+                let projects = registry.sorted_projects(&filter, order);
+                if json {
+                    let projects = projects
+                        .into_iter()
+                        .map(|(name, project)| NamedProject {
+                            name,
+                            project,
+                            status: health
+                                .as_ref()
+                                .map(|health| format_health_status(name, project, health)),
+                        })
+                        .collect::<Vec<_>>();
+                    writeln!(output, "{}", serde_json::json!({ "projects": projects })).unwrap();
+                } else {
+                    let decorate = deps.is_tty();
+                    for (name, project) in projects {
+                        let line = match (health.as_ref(), extended) {
+                            (Some(health), false) => format_project_status(name, project, health),
+                            (Some(health), true) => format!(
+                                "{} [{}]",
+                                format_project_extended(name, project, usage.last_used(name), now),
+                                format_health_status(name, project, health)
+                            ),
+                            (None, false) => format_project(name, project),
+                            (None, true) => {
+                                format_project_extended(name, project, usage.last_used(name), now)
+                            }
+                        };
+                        let line = if decorate {
+                            decorate_project_line(project, &line)
+                        } else {
+                            line
+                        };
+                        writeln!(output, "{line}").unwrap();
+                    }
+                }
+            }
+        }
+
+        Command::Status { project_name } => {
+            let registry = load_registry(deps, options)?;
+            if let Some(name) = project_name {
+                let project = registry
+                    .get(&name)
+                    .ok_or_else(|| ApplicationError::NonExistentProject(name.clone()))?;
+                let mut health = HealthCache::load(deps)?;
+                let status = health.probe_project(deps, &name, project);
+                health.save(deps)?;
+                write_gallery(deps, &registry, &health)?;
+                writeln!(output, "{} [{status}]", format_project(&name, project)).unwrap();
+            } else {
+                let health = check_health(deps, &registry)?;
+                for (name, project) in registry.iter_projects() {
+                    writeln!(output, "{}", format_project_status(name, project, &health)).unwrap();
+                }
+            }
+        }
+
+        Command::Logs {
+            project_name,
+            follow,
+            lines,
+        } => {
+            let registry = load_registry(deps, options)?;
+            if !registry.access_logs() {
+                return Err(ApplicationError::AccessLogsDisabled);
+            }
+            let name = match project_name {
+                Some(name) => {
+                    if registry.get(&name).is_none() {
+                        return Err(ApplicationError::NonExistentProject(name));
+                    }
+                    name
+                }
+                None => get_active_project(deps, &registry)?.0.clone(),
+            };
+            // Release the registry lock because following the log never finishes
+            drop(registry);
+            for line in logs::tail(deps, &name, lines)? {
+                if json {
+                    writeln!(output, "{line}").unwrap();
+                } else {
+                    writeln!(output, "{}", logs::format_entry(&line)).unwrap();
+                }
+            }
+            if follow {
+                print!("{output}");
+                logs::follow(deps, &name, json);
+            }
+        }
+
+        Command::Daemon { mdns, interval } => {
+            let advertiser = if mdns {
+                // caddy has to serve the .local hostnames for the advertisements to be useful
+                if !load_config(deps)?.mdns {
+                    return Err(ApplicationError::MdnsDisabled);
+                }
+                let responder =
+                    mdns::Responder::current().ok_or(ApplicationError::MdnsUnsupported)?;
+                Some(mdns::Advertiser::new(responder))
+            } else {
+                None
+            };
+            run_daemon(deps, options, advertiser, interval)
+        }
+
+        Command::Ui => {
+            ui::run(
+                || Ok(ui_rows(deps, &load_registry(deps, options)?)),
+                |action| {
+                    let args = match action {
+                        ui::Action::Create(name) => {
+                            let mut args = vec![String::from("create")];
+                            if !name.is_empty() {
+                                args.push(name);
+                            }
+                            args
+                        }
+                        ui::Action::Delete(name) => vec![String::from("delete"), name],
+                        ui::Action::Link(name, port) => {
+                            vec![String::from("link"), port.to_string(), name]
+                        }
+                        ui::Action::Open(name) => {
+                            let url = load_registry(deps, options)?.url(&name);
+                            open_url(deps, &url)?;
+                            return Ok(format!("Opened {url}"));
+                        }
+                    };
+                    // Run the action like the equivalent command so that it saves the registry,
+                    // reloads caddy, and runs hooks
+                    let cli =
+                        Cli::try_parse_from(std::iter::once(String::from("portman")).chain(args))
+                            .context("Failed to parse command")?;
+                    let output = run(deps, cli.command, strict, false, false, options)?;
+                    Ok(output.lines().next().unwrap_or_default().to_owned())
+                },
+            )?;
+        }
+
+        Command::Health { watch, interval } => {
+            if watch {
+                run_daemon(deps, options, None, interval);
+            }
+            let registry = load_registry(deps, options)?;
+            let health = check_health(deps, &registry)?;
+            for (name, project) in registry.iter_projects() {
+                writeln!(output, "{}", format_project_status(name, project, &health)).unwrap();
+            }
+        }
+
+        Command::Link {
+            range: Some((start, end)),
+            ..
+        } => {
+            if !deps.is_tty() {
+                return Err(ApplicationError::NotInteractive("portman link --range"));
+            }
+            let mut registry = load_registry(deps, options)?;
+            for line in link_range(deps, &mut registry, start, end)? {
+                writeln!(output, "{line}").unwrap();
+            }
+            save_registry(deps, &registry, options.warnings)?;
+        }
+
+        Command::Scan { root, depth, yes } => {
+            let mut registry = load_registry(deps, options)?;
+            let root = normalize_path(&deps.get_cwd()?.join(root));
+            for line in scan_projects(deps, &mut registry, &root, depth, yes)? {
+                writeln!(output, "{line}").unwrap();
+            }
+            save_registry(deps, &registry, options.warnings)?;
+        }
+
+        Command::Handoff { project_name, to } => {
+            let mut registry = load_registry(deps, options)?;
+            let project_name = match project_name {
+                Some(name) => name,
+                None => get_active_project(deps, &registry)?.0.clone(),
+            };
+            let (old_port, project) = registry.handoff(deps, &project_name, to)?;
+            save_registry(deps, &registry, options.warnings)?;
+            if deps.is_tty() {
+                writeln!(
+                    output,
+                    "Moved project {project_name} from port {old_port} to port {}\nPort {old_port} stays linked to the project until you run `portman unlink {old_port}`",
+                    project.port
+                )
+                .unwrap();
+            } else {
+                // Only print the ports if stdout isn't a TTY for easier scripting
+                writeln!(output, "{old_port}\n{}", project.port).unwrap();
+            }
+        }
+
+        Command::Registry(subcommand) => {
+            let config = load_config(deps)?;
+            match subcommand {
+                RegistrySubcommand::Accept => {
+                    let _lock = lock_registry(deps, options.wait)?;
+                    Registry::accept_changes(deps, config.layout, config.backups)?;
+                    writeln!(output, "Accepted changes to the registry").unwrap();
+                }
+                RegistrySubcommand::Restore { index } => {
+                    let _lock = lock_registry(deps, options.wait)?;
+                    Registry::restore_backup(deps, config.layout, index)?;
+                    writeln!(output, "Restored the registry from backup {index}").unwrap();
+                }
+            }
+            // Apply the accepted or restored registry
+            let registry = load_registry(deps, options)?;
+            registry.write_index(deps)?;
+            if registry.auto_reload() {
+                reload(deps, &registry).map_err(ApplicationError::Caddy)?;
+            }
+        }
+
+        Command::Repo(subcommand) => match subcommand {
+            Repo::Delete { repo } => {
+                let mut registry = load_registry(deps, options)?.with_dry_run(dry_run);
+                let port = registry.delete_repo(&repo)?;
+                writeln!(output, "Deleted repo {}", format_repo(&repo, port)).unwrap();
+                save_registry(deps, &registry, options.warnings)?;
+                if dry_run {
+                    output += &dry_run_report(deps, &registry)?;
+                }
+            }
+
+            Repo::List => {
+                let registry = load_registry(deps, options)?;
+                if json {
+                    let repos = registry
+                        .iter_repos()
+                        .map(|(repo, entry)| {
+                            serde_json::json!({
+                                "repo": repo,
+                                "port": entry.port,
+                                "project_port": entry.project_port,
+                                "linked_ports": entry.linked_ports,
+                                "description": entry.description,
+                                "default_project_name": entry.default_project_name,
+                            })
+                        })
+                        .collect::<Vec<_>>();
+                    writeln!(output, "{}", serde_json::json!({ "repos": repos })).unwrap();
+                } else {
+                    for (repo, entry) in registry.iter_repos() {
+                        writeln!(output, "{}", format_repo(repo, entry.port)).unwrap();
+                    }
+                }
+            }
+
+            Repo::Set {
+                repo,
+                port,
+                project_port,
+                linked_ports,
+                description,
+                default_project_name,
+            } => {
+                let mut registry = load_registry(deps, options)?;
+                let entry = registry.set_repo_metadata(
+                    &repo,
+                    RepoUpdate {
+                        port,
+                        project_port,
+                        linked_ports,
+                        description,
+                        default_project_name,
+                    },
+                )?;
+                save_registry(deps, &registry, options.warnings)?;
+                writeln!(output, "Updated repo {}", format_repo(&repo, entry.port)).unwrap();
+            }
+        },
+
+        Command::Reserve { port, description } => {
+            let mut registry = load_registry(deps, options)?;
+            let reservation = registry.reserve(port, description)?;
+            save_registry(deps, &registry, options.warnings)?;
+            writeln!(output, "Reserved port {}", format_reservation(&reservation)).unwrap();
+        }
+
+        Command::Unreserve { port } => {
+            let mut registry = load_registry(deps, options)?;
+            registry.unreserve(port)?;
+            save_registry(deps, &registry, options.warnings)?;
+            writeln!(output, "Unreserved port {port}").unwrap();
+        }
+
+        Command::Lease(subcommand) => match subcommand {
+            LeaseSubcommand::Acquire { ttl, description } => {
+                let mut registry = load_registry(deps, options)?;
+                let now = deps.get_current_time();
+                let lease = registry.acquire_lease(deps, now.saturating_add(ttl), description)?;
+                save_registry(deps, &registry, options.warnings)?;
+                if deps.is_tty() {
+                    writeln!(output, "Leased port {}", format_lease(&lease, now)).unwrap();
+                } else {
+                    // Only print the port if stdout isn't a TTY for easier scripting
+                    writeln!(output, "{}", lease.port).unwrap();
+                }
+            }
+
+            LeaseSubcommand::Release { port } => {
+                let mut registry = load_registry(deps, options)?;
+                registry.release_lease(port)?;
+                save_registry(deps, &registry, options.warnings)?;
+                writeln!(output, "Released port {port}").unwrap();
+            }
+
+            LeaseSubcommand::List => {
+                let registry = load_registry(deps, options)?;
+                // Save to forget the leases that expired
+                save_registry(deps, &registry, options.warnings)?;
+                let now = deps.get_current_time();
+                for lease in registry.iter_leases() {
+                    writeln!(output, "{}", format_lease(lease, now)).unwrap();
+                }
+            }
+        },
+
+        Command::Domain(subcommand) => {
+            let mut registry = load_registry(deps, options)?;
+            match subcommand {
+                DomainSubcommand::Add {
+                    project_name,
+                    domain,
+                } => {
+                    registry.add_domain(&project_name, domain.clone())?;
+                    writeln!(output, "Added domain {domain} to project {project_name}").unwrap();
+                }
+                DomainSubcommand::Remove {
+                    project_name,
+                    domain,
+                } => {
+                    registry.remove_domain(&project_name, &domain)?;
+                    writeln!(
+                        output,
+                        "Removed domain {domain} from project {project_name}"
+                    )
+                    .unwrap();
+                }
+            }
+            save_registry(deps, &registry, options.warnings)?;
+        }
+
+        Command::Forward(subcommand) => {
+            let mut registry = load_registry(deps, options)?;
+            match subcommand {
+                ForwardSubcommand::Add {
+                    project_name,
+                    listen_port,
+                    port_name,
+                    udp,
+                } => {
+                    let forward = PortForward {
+                        listen_port,
+                        port_name,
+                        protocol: if udp {
+                            ForwardProtocol::Udp
+                        } else {
+                            ForwardProtocol::Tcp
+                        },
+                    };
+                    let project = registry.add_forward(&project_name, forward.clone())?;
+                    let target = project.forward_target(&forward).unwrap_or(project.port);
+                    writeln!(
+                        output,
+                        "Forwarded {} port {listen_port} to project {project_name} :{target}",
+                        forward.protocol
+                    )
+                    .unwrap();
+                }
+                ForwardSubcommand::Remove {
+                    project_name,
+                    listen_port,
+                    udp,
+                } => {
+                    let protocol = if udp {
+                        ForwardProtocol::Udp
+                    } else {
+                        ForwardProtocol::Tcp
+                    };
+                    registry.remove_forward(&project_name, listen_port, protocol)?;
+                    writeln!(
+                        output,
+                        "Stopped forwarding {protocol} port {listen_port} to project {project_name}"
+                    )
+                    .unwrap();
+                }
+            }
+            save_registry(deps, &registry, options.warnings)?;
+        }
+
+        Command::Mv {
+            project_name,
+            directory,
+        } => {
+            let mut registry = load_registry(deps, options)?.with_dry_run(dry_run);
+            let cwd = deps.get_cwd()?;
+            let directory =
+                normalize_path(&directory.map_or_else(|| cwd.clone(), |path| cwd.join(path)));
+            let previous = registry
+                .get(&project_name)
+                .and_then(|project| project.directory.clone());
+            registry.move_directory(&project_name, directory.clone())?;
+            if previous.as_ref() == Some(&directory) {
+                writeln!(
+                    output,
+                    "Project {project_name} is already in \"{}\"",
+                    directory.display()
+                )
+                .unwrap();
+            } else {
+                writeln!(
+                    output,
+                    "Moved project {project_name} to \"{}\"",
+                    directory.display()
+                )
+                .unwrap();
+            }
+            save_registry(deps, &registry, options.warnings)?;
+            if dry_run {
+                output += &dry_run_report(deps, &registry)?;
+            }
+        }
+
+        Command::Activate(subcommand) => {
+            let mut registry = load_registry(deps, options)?;
+            let cwd = deps.get_cwd()?;
+            match subcommand {
+                ActivateSubcommand::Add { path, project_name } => {
+                    let project_name = match project_name {
+                        Some(name) => name,
+                        None => get_active_project(deps, &registry)?.0.clone(),
+                    };
+                    let directory = normalize_path(&cwd.join(path));
+                    registry.add_directory(&project_name, directory.clone())?;
+                    writeln!(
+                        output,
+                        "Activated project {project_name} in \"{}\"",
+                        directory.display()
+                    )
+                    .unwrap();
+                }
+                ActivateSubcommand::Remove { path, project_name } => {
+                    let project_name = match project_name {
+                        Some(name) => name,
+                        None => get_active_project(deps, &registry)?.0.clone(),
+                    };
+                    let directory = normalize_path(&cwd.join(path));
+                    registry.remove_directory(&project_name, &directory)?;
+                    writeln!(
+                        output,
+                        "Stopped activating project {project_name} in \"{}\"",
+                        directory.display()
+                    )
+                    .unwrap();
+                }
+            }
+            save_registry(deps, &registry, options.warnings)?;
+        }
+
+        Command::Tag(subcommand) => {
+            let mut registry = load_registry(deps, options)?;
+            match subcommand {
+                TagSubcommand::Add { tag, project_name } => {
+                    let project_name = match project_name {
+                        Some(name) => name,
+                        None => get_active_project(deps, &registry)?.0.clone(),
+                    };
+                    let project = registry.add_tag(deps, &project_name, tag.clone())?;
+                    writeln!(
+                        output,
+                        "Added tag {tag} to project {}",
+                        format_project(&project_name, &project)
+                    )
+                    .unwrap();
+                }
+                TagSubcommand::Remove { tag, project_name } => {
+                    let project_name = match project_name {
+                        Some(name) => name,
+                        None => get_active_project(deps, &registry)?.0.clone(),
+                    };
+                    let project = registry.remove_tag(deps, &project_name, &tag)?;
+                    writeln!(
+                        output,
+                        "Removed tag {tag} from project {}",
+                        format_project(&project_name, &project)
+                    )
+                    .unwrap();
+                }
+            }
+            save_registry(deps, &registry, options.warnings)?;
+        }
+
+        Command::Template(TemplateSubcommand::Render { project_name }) => {
+            let config = load_config(deps)?;
+            if config.templates.is_empty() {
+                return Err(ApplicationError::NoTemplates);
+            }
+            let registry = load_registry(deps, options)?;
+            let (name, project) = get_project(deps, &registry, project_name.as_ref())?;
+            if project.directory.is_none() {
+                return Err(ApplicationError::NoProjectDirectory(name.clone()));
+            }
+            for (path, changed) in render_templates(deps, &config, name, project)? {
+                if changed {
+                    writeln!(output, "Rendered \"{}\"", path.display()).unwrap();
+                } else {
+                    writeln!(output, "\"{}\" is already up to date", path.display()).unwrap();
+                }
+            }
+        }
+
+        Command::Devcontainer { project_name } => {
+            let registry = load_registry(deps, options)?;
+            let (name, project) = get_project(deps, &registry, project_name.as_ref())?;
+            let directory = project
+                .directory
+                .as_ref()
+                .ok_or_else(|| ApplicationError::NoProjectDirectory(name.clone()))?;
+            let path = devcontainer::config_path(directory);
+            if devcontainer::sync(deps, directory, name, project.port)? {
+                writeln!(
+                    output,
+                    "Updated \"{}\" to forward port {}",
+                    path.display(),
+                    project.port
+                )
+                .unwrap();
+            } else {
+                writeln!(
+                    output,
+                    "\"{}\" already forwards port {}",
+                    path.display(),
+                    project.port
+                )
+                .unwrap();
+            }
+        }
+
+        Command::Env { project_name, file } => {
+            let registry = load_registry(deps, options)?;
+            let (name, project) = get_project(deps, &registry, project_name.as_ref())?;
+            let variables = dotenv::variables(name, project);
+            match file {
+                Some(path) => {
+                    let path = deps.get_cwd()?.join(path);
+                    if dotenv::sync(deps, &path, &variables)? {
+                        writeln!(output, "Updated \"{}\"", path.display()).unwrap();
+                    } else {
+                        writeln!(output, "\"{}\" is already up to date", path.display()).unwrap();
+                    }
+                }
+                None => output += &dotenv::format(&variables),
+            }
+        }
+
+        Command::Service(subcommand) => match subcommand {
+            Service::Install => {
+                let platform =
+                    service::Platform::current().ok_or(ApplicationError::UnsupportedPlatform)?;
+                let service_path = service::install(deps, platform)?;
+                writeln!(
+                    output,
+                    "Installed and started the service at \"{}\"",
+                    service_path.display()
+                )
+                .unwrap();
+            }
+        },
+
+        Command::Tmux { set_environment } => {
+            let registry = load_registry(deps, options)?;
+            let active_project = registry.match_cwd(deps)?;
+            if set_environment {
+                tmux::set_environment(deps, active_project)
+                    .map_err(ApplicationError::TmuxCommand)?;
+            }
+            if let Some((name, project)) = active_project {
+                writeln!(output, "{}", tmux::format_segment(name, project)).unwrap();
+            }
+        }
+
+        Command::Run { command } => {
+            let mut registry = load_registry(deps, options)?;
+            // The command may run portman itself, which would wait for the registry lock
+            registry.unlock();
+            let (name, project) = registry
+                .match_cwd(deps)?
+                .ok_or(ApplicationError::NoActiveProject)?;
+            record_usage(deps, options.warnings, name);
+            run_with_project(deps, name, project, &command, None)?;
+        }
+
+        Command::Exec {
+            project_name,
+            command,
+        } => {
+            let mut registry = load_registry(deps, options)?;
+            // The command may run portman itself, which would wait for the registry lock
+            registry.unlock();
+            let project = registry
+                .get(&project_name)
+                .ok_or_else(|| ApplicationError::NonExistentProject(project_name.clone()))?;
+            run_with_project(
+                deps,
+                &project_name,
+                project,
+                &command,
+                project.directory.as_deref(),
+            )?;
+        }
+
+        Command::ExecAll { tags, command } => {
+            let mut registry = load_registry(deps, options)?;
+            // The commands may run portman themselves, which would wait for the registry lock
+            registry.unlock();
+            let (exec_output, total, failed) = exec_all(deps, &registry, &tags, &command);
+            if !failed.is_empty() {
+                return Err(ApplicationError::ExecAllFailed {
+                    output: exec_output,
+                    failed,
+                    total,
+                });
+            }
+            output += &exec_output;
+        }
+
+        Command::Complete { kind } => {
+            let registry = load_registry(deps, options)?;
+            let candidates = match kind {
+                CompletionKind::LinkedPorts => registry
+                    .iter_projects()
+                    .flat_map(|(_, project)| project.iter_linked_ports())
+                    .collect::<BTreeSet<_>>()
+                    .into_iter()
+                    .map(|port| port.to_string())
+                    .collect::<Vec<_>>(),
+                CompletionKind::LinkPorts => {
+                    // Suggest the active repo's port first, followed by a few free ports
+                    let repo_port = repo::get_active_repo(deps, None)
+                        .ok()
+                        .and_then(|repo| registry.get_repo_port(&repo).ok());
+                    repo_port
+                        .into_iter()
+                        .chain(
+                            registry
+                                .free_ports(5)
+                                .into_iter()
+                                .filter(|port| Some(*port) != repo_port),
+                        )
+                        .map(|port| port.to_string())
+                        .collect()
+                }
+                CompletionKind::Projects => registry
+                    .iter_projects()
+                    .map(|(name, _)| name.clone())
+                    .collect(),
+                CompletionKind::Repos => registry
+                    .iter_repos()
+                    .map(|(repo, _)| repo.clone())
+                    .collect(),
+            };
+            for candidate in candidates {
+                writeln!(output, "{candidate}").unwrap();
+            }
+        }
+
+        Command::Verify => {
+            let registry = load_registry(deps, options)?;
+            save_registry(deps, &registry, options.warnings)?;
+            let violations = registry
+                .iter_misplaced_projects()
+                .map(|(name, project)| {
+                    let project_str = format_project(name, project);
+                    match registry.tag_range(project) {
+                        Some((tag, (start, end))) => {
+                            format!("{project_str} is outside the {tag} port range {start}-{end}")
+                        }
+                        None => format!("{project_str} is in a port range reserved for a tag"),
+                    }
+                })
+                .collect::<Vec<_>>();
+            if !violations.is_empty() {
+                return Err(ApplicationError::MisplacedPorts(violations.join("\n")));
+            }
+            writeln!(output, "All project ports are in their tag port ranges").unwrap();
+        }
+
+        Command::Conflicts => {
+            let config = load_config(deps)?;
+            let registry = load_registry(deps, options)?;
+            let conflicts = conflicts::find(deps, &config, &registry)?;
+            if json {
+                writeln!(
+                    output,
+                    "{}",
+                    serde_json::to_string(&conflicts).context("Failed to serialize conflicts")?
+                )
+                .unwrap();
+            } else if conflicts.is_empty() {
+                writeln!(output, "No port conflicts found").unwrap();
+            } else {
+                return Err(ApplicationError::ConflictsFound {
+                    output: conflicts::format_table(&conflicts),
+                    count: conflicts.len(),
+                });
+            }
+        }
+
+        Command::Caddyfile => {
+            let registry = load_registry(deps, options)?;
+            write!(output, "{}", generate_caddyfile(deps, &registry)?).unwrap();
+        }
+
+        Command::ReloadCaddy => {
+            let registry = load_registry(deps, options)?;
+            caddy::check_port_conflicts(deps, &registry).map_err(ApplicationError::Caddy)?;
+            reload(deps, &registry).map_err(ApplicationError::Caddy)?;
+            writeln!(output, "Successfully reloaded caddy").unwrap();
+        }
+
+        Command::Doctor => {
+            let checks = diagnose(deps, options);
+            let mut failed = 0;
+            for (description, result) in &checks {
+                match result {
+                    Some(Ok(())) => writeln!(output, "[pass] {description}").unwrap(),
+                    Some(Err(err)) => {
+                        failed += 1;
+                        // Caddy errors are wrapped for reloading, but the checks don't reload caddy
+                        let message = match err {
+                            ApplicationError::Caddy(err) => err.to_string(),
+                            err => err.to_string(),
+                        };
+                        writeln!(output, "[fail] {description}").unwrap();
+                        let context = SuggestionContext {
+                            caddy_service: caddy::service(deps),
+                            ..SuggestionContext::default()
+                        };
+                        let suggestion = suggest(err, &context);
+                        for line in message.lines().chain(suggestion.lines()) {
+                            writeln!(output, "       {line}").unwrap();
+                        }
+                    }
+                    None => writeln!(output, "[skip] {description}").unwrap(),
+                }
+            }
+            if failed > 0 {
+                return Err(ApplicationError::DiagnosticsFailed {
+                    output,
+                    failed,
+                    total: checks.len(),
+                });
+            }
+            writeln!(output, "All checks passed").unwrap();
+        }
+
+        Command::Version => {
+            let info = VersionInfo {
+                build: deps.get_build_info(),
+                data_dir: deps.get_data_dir()?,
+                config_path: get_config_path(deps)?.0,
+                caddy_version: caddy::version(deps),
+            };
+            if json {
+                writeln!(
+                    output,
+                    "{}",
+                    serde_json::to_string(&info).context("Failed to serialize version info")?
+                )
+                .unwrap();
+            } else {
+                write!(output, "{info}").unwrap();
+            }
+        }
+    };
+
+    Ok(output)
+}
+
+// Details about the command that failed that determine which suggestions apply to its error
+#[derive(Default)]
+struct SuggestionContext {
+    has_create_project_name: Option<bool>,
+    linking_project: bool,
+    deleting_repo: bool,
+    caddy_service: CaddyService,
+}
+
+// Return suggestions for how to fix an error, one per line
+#[allow(clippy::too_many_lines)]
+fn suggest(err: &ApplicationError, context: &SuggestionContext) -> String {
+    let mut output = String::new();
+
+    match err {
+        ApplicationError::AccessLogsDisabled => {
+            output += "Try running `portman config edit` and setting `access_logs = true`.\n";
+        }
+        ApplicationError::AmbiguousListeningPorts(_) => {
+            output += "Try running the command again with --port to choose which port to adopt.\n";
+        }
+        ApplicationError::BatchCommand(line_number, _) => {
+            writeln!(
+                output,
+                "Try fixing the command on line {line_number} and running the batch again."
+            )
+            .unwrap();
+        }
+        ApplicationError::Caddy(CaddyError::Exec(ExecError::IO { io_err, .. }))
+            if io_err.kind() == ErrorKind::NotFound =>
+        {
+            output += match context.caddy_service {
+                CaddyService::Homebrew => "Try running `brew install caddy` or making sure that caddy is in your PATH.\n",
+                CaddyService::Systemd => "Try installing caddy with your package manager, like `sudo apt install caddy`, or making sure that caddy is in your PATH.\n",
+                CaddyService::Windows => "Try running `scoop install caddy` or making sure that caddy is in your PATH.\n",
+            };
+        }
+        ApplicationError::Caddy(
+            CaddyError::Exec(ExecError::Failed { code: 1, .. }) | CaddyError::NotRunning,
+        ) => {
+            output += match context.caddy_service {
+                CaddyService::Homebrew => "Try running `brew services start caddy` to make sure that caddy is running.\n",
+                CaddyService::Systemd => "Try running `sudo systemctl start caddy` to make sure that caddy is running.\n",
+                CaddyService::Windows => "Try running `caddy start --config` with the path of your Caddyfile to make sure that caddy is running.\n",
+            };
+        }
+        ApplicationError::Caddy(CaddyError::InvalidCaddyfile(_)) => {
+            output += "Try fixing the directives in the Caddyfile snippet or running `portman set caddy-extra --clear` to remove them.\n";
+        }
+        ApplicationError::Caddy(CaddyError::MissingImport(_)) => {
+            output += "Try running `portman reload-caddy` to add the import.\n";
+        }
+        ApplicationError::Caddy(CaddyError::PortConflicts(conflicts)) => {
+            writeln!(
+                output,
+                "Try stopping the processes using those ports or running `portman unlink {}`.",
+                conflicts[0].1
+            )
+            .unwrap();
+        }
+        ApplicationError::Caddy(CaddyError::UnknownCaddyfile) => {
+            output += "Try running `portman config edit` and setting `caddyfile` to the path of the Caddyfile that caddy loads.\n";
+        }
+        ApplicationError::DuplicateDirectory(name, _) => {
+            writeln!(output, "Try running the command in a different directory, providing the --no-activate flag, or running `portman delete {name}` and rerunning the command.").unwrap();
+        }
+        ApplicationError::DuplicateDomain(_, owner) => {
+            writeln!(output, "Try running `portman domain remove {owner} <domain>` to remove the domain from that project first.").unwrap();
+        }
+        ApplicationError::DuplicateForward(port, _) => {
+            writeln!(output, "Try forwarding a different port or running `portman forward remove <project> {port}` to stop the other project from forwarding it.").unwrap();
+        }
+        ApplicationError::DuplicateProject(_) => {
+            if let Some(has_project_name) = context.has_create_project_name {
+                if has_project_name {
+                    output +=
+                        "Try providing the --overwrite flag to modify the existing project.\n";
+                } else {
+                    output += "Try manually providing a project name.\n";
+                }
+            }
+        }
+        ApplicationError::DuplicateRepoMatch(name, _) => {
+            writeln!(
+                output,
+                "Try running `portman delete {name}` or creating the project without --match-repo."
+            )
+            .unwrap();
+        }
+        ApplicationError::DuplicateReservation(_) | ApplicationError::NonExistentReservation(_) => {
+            output +=
+                "Try running `portman list --reservations` to see which ports are reserved.\n";
+        }
+        ApplicationError::EditorCommand(ExecError::IO { io_err, .. })
+            if io_err.kind() == ErrorKind::NotFound =>
+        {
+            output += "Try setting the $EDITOR environment variable to a valid command like vi or nano.\n";
+        }
+        ApplicationError::EmptyAllocator => {
+            output += "Try running `portman config edit` to edit the config file and modify the `ranges` field to allow more ports.\n";
+        }
+        ApplicationError::GitCommand(_) => {
+            output += "Try running `portman link` in a directory with a git repo or providing an explicit port.\n";
+        }
+        ApplicationError::HookCommand(_) => {
+            output += "Try running `portman config edit` to fix the hook command. The registry changes were already saved.\n";
+        }
+        ApplicationError::InvalidBindAddress(_) => {
+            output += "Try a bind address like \"loopback\", \"all\", or \"192.168.1.5\".\n";
+        }
+        ApplicationError::InvalidConfig(_) => {
+            output += "Try running `portman config edit` to edit the config file and correct the error.\n";
+        }
+        ApplicationError::InvalidDomain(_, _) => {
+            output += "Try a hostname like \"api.myapp.test\" or \"myapp.dev.localhost\".\n";
+        }
+        ApplicationError::InvalidHealthPath(_) => {
+            output += "Try providing a path that starts with /, like /healthz, or an empty string to only check that the port accepts connections.\n";
+        }
+        ApplicationError::InvalidNameTemplate(_, _) => {
+            output += "Try a template that only uses the {repo}, {branch}, and {dir} variables, like \"{repo}-{branch}\".\n";
+        }
+        ApplicationError::InvalidProjectName(_, _) => {
+            if context.has_create_project_name == Some(false) {
+                output += "Try manually providing a project name.\n";
+            }
+        }
+        ApplicationError::InvalidUpstream(_, _) => {
+            output += "Try providing a URL with only a scheme, host, and port, like https://example.com:8443.\n";
+        }
+        ApplicationError::LsofCommand(_) => {
+            output += "Try installing lsof or running `portman create --port <port>` to assign the port manually.\n";
+        }
+        ApplicationError::MdnsDisabled => {
+            output += "Try running `portman config edit` and setting `mdns = true`.\n";
+        }
+        ApplicationError::MdnsUnsupported => {
+            output += "Try running `portman daemon` without --mdns.\n";
+        }
+        ApplicationError::MisplacedPorts(_) => {
+            output += "Try running `portman config edit` to adjust the tag port ranges or recreating the projects with `portman delete` and `portman create`.\n";
+        }
+        ApplicationError::MissingCustomConfig(path) => {
+            writeln!(output, "Try creating a config file at \"{}\" or unsetting the $PORTMAN_CONFIG environment variable.", path.display()).unwrap();
+        }
+        ApplicationError::NoActiveProject => {
+            output+= "Try running the command again in a directory containing a project or providing an explicit project name.\n";
+        }
+        ApplicationError::NoLinkedPort(name) => {
+            writeln!(
+                output,
+                "Try running `portman link <port> {name}` to link a port to the project."
+            )
+            .unwrap();
+        }
+        ApplicationError::NoListeningPort(_) => {
+            output += "Try starting the project's dev server from this directory before adopting its port.\n";
+        }
+        ApplicationError::NoProjectDirectory(name) => {
+            writeln!(output, "Try running `portman create {name} --overwrite` in the project's directory to associate it with that directory.").unwrap();
+        }
+        ApplicationError::NoTemplates => {
+            output += "Try running `portman config edit` and adding templates to the `[templates]` section.\n";
+        }
+        ApplicationError::NotInteractive(_) => {
+            output +=
+                "Try running the command again in a terminal so that it can ask for answers.\n";
+        }
+        ApplicationError::NonExistentArchivedProject(_) => {
+            output += "Try running `portman list --archived` to see which projects are archived.\n";
+        }
+        ApplicationError::NonExistentDirectory(name, _) => {
+            writeln!(
+                output,
+                "Try running `portman get {name} --json` to see which directories activate the project."
+            )
+            .unwrap();
+        }
+        ApplicationError::NonExistentDomain(name, _) => {
+            writeln!(
+                output,
+                "Try running `portman get {name} --json` to see which domains the project has."
+            )
+            .unwrap();
+        }
+        ApplicationError::NonExistentForward(name, _) => {
+            writeln!(
+                output,
+                "Try running `portman get {name} --json` to see which ports the project forwards."
+            )
+            .unwrap();
+        }
+        ApplicationError::NonExistentLease(_) => {
+            output += "Try running `portman lease list` to see which ports are leased.\n";
+        }
+        ApplicationError::NonExistentPortName(name, _) => {
+            writeln!(
+                output,
+                "Try running `portman get {name} --json` to see which ports the project has."
+            )
+            .unwrap();
+        }
+        ApplicationError::NonExistentProject(_) => {
+            output += "Try providing a different project name.\n";
+        }
+        ApplicationError::NonExistentRepo(_) => {
+            if context.linking_project {
+                output += "Try providing an explicit port.\n";
+            }
+            if context.deleting_repo {
+                output += "Try running `portman repo list` to see which repos exist.\n";
+            }
+        }
+        ApplicationError::NonExistentTag(name, _) => {
+            writeln!(
+                output,
+                "Try running `portman get {name} --json` to see which tags the project has."
+            )
+            .unwrap();
+        }
+        ApplicationError::OutOfRangePort(_) => {
+            output += "Try a port in the configured ranges, or run the command again with --force to use a port outside of them.\n";
+        }
+        ApplicationError::RegistryLocked(_) => {
+            output += "Try running the command again after the other portman process finishes, or providing --wait with a longer timeout in seconds.\n";
+        }
+        ApplicationError::ReservedPortInUse(_, name) => {
+            writeln!(
+                output,
+                "Try reserving a different port or running `portman delete {name}` first."
+            )
+            .unwrap();
+        }
+        ApplicationError::ReservedPorts(_) => {
+            output += "Try running `portman config edit` to move the `ranges` field above port 1024 or remove the ports from the `reserved` field, or unlink the ports with `portman unlink`.\n";
+        }
+        ApplicationError::RunCommand(ExecError::IO { io_err, .. })
+            if io_err.kind() == ErrorKind::NotFound =>
+        {
+            output += "Try making sure that the command is installed and in your PATH.\n";
+        }
+        ApplicationError::ServiceCommand(_) => {
+            output += "Try making sure that the service file is valid and that the service manager is running.\n";
+        }
+        ApplicationError::StrictMode(_) => {
+            output += "Try running the command again without --strict, or set `strict = false` in the config file.\n";
+        }
+        ApplicationError::TmuxCommand(_) => {
+            output += "Try running the command again inside of a tmux session.\n";
+        }
+        ApplicationError::UnavailablePort(_) => {
+            output += "Try a port that isn't already assigned to another project, or omit --port to allocate one automatically.\n";
+        }
+        ApplicationError::UnbatchableCommand => {
+            output += "Try running the command on its own outside of the batch.\n";
+        }
+        ApplicationError::UnresolvedPath(_) => {
+            output += "Try running `portman list` to see which directory each project is in.\n";
+        }
+        ApplicationError::UnwritableDataDir(_, _) => {
+            output += "Try fixing the permissions of the data directory or choosing a different one with --data-dir.\n";
+        }
+        _ => {}
+    };
+
+    output
+}
+
+enum RunStatus {
+    Success,
+    Failure,
+    // Exit with a specific code without printing anything
+    Exit(u8),
+}
+
+fn run_and_suggest(
+    deps: &(impl Args
+          + BuildInfo
+          + CheckPath
+          + CheckPortInUse
+          + CheckWritable
+          + ChoosePort
+          + CurrentExe
+          + CurrentTime
+          + DataDir
+          + Environment
+          + EphemeralPort
+          + Exec
+          + LanAddress
+          + ListDirectory
+          + LockFile
+          + ProbeHttp
+          + ProbePort
+          + Prompt
+          + ReadFile
+          + ReadStdin
+          + ResolveHost
+          + Spawn
+          + Tty
+          + WalkDir
+          + WatchPath
+          + WriteFile
+          + WorkingDirectory),
+) -> (RunStatus, String, Vec<String>) {
+    let cli = Cli::parse_from(deps.get_args());
+
+    let mut context = SuggestionContext {
+        has_create_project_name: if let Command::Create {
+            ref project_name,
+            ref name_template,
+            per_branch,
+            ..
+        } = cli.command
+        {
+            Some(project_name.is_some() || name_template.is_some() || per_branch)
+        } else {
+            None
+        },
+        linking_project: matches!(cli.command, Command::Link { .. }),
+        deleting_repo: matches!(cli.command, Command::Repo(Repo::Delete { .. })),
+        caddy_service: CaddyService::default(),
+    };
+
+    if cli.timings {
+        timings::enable();
+    }
+    let timer = timings::start("total");
+    let warnings = Warnings::default();
+    let options = LoadOptions {
+        quiet: cli.quiet,
+        no_reload: cli.no_reload,
+        wait: Duration::from_secs(cli.wait),
+        warnings: &warnings,
+    };
+    let result = match cli.data_dir {
+        Some(data_dir) => run(
+            &DataDirOverride::new(deps, data_dir),
+            cli.command,
+            cli.strict,
+            cli.json,
+            cli.dry_run,
+            options,
+        ),
+        None => run(
+            deps,
+            cli.command,
+            cli.strict,
+            cli.json,
+            cli.dry_run,
+            options,
+        ),
+    };
+    drop(timer);
+    if let Some(report) = timings::report() {
+        eprint!("{report}");
+    }
+
+    let err = match result {
+        // The command already printed its own output, so only propagate its exit code
+        Err(ApplicationError::RunCommandExit(code)) => {
+            return (
+                RunStatus::Exit(u8::try_from(code).unwrap_or(1)),
+                String::new(),
+                warnings.take(),
+            );
+        }
+        Err(err) => err,
+        Ok(output) => {
+            return (RunStatus::Success, output, warnings.take());
+        }
+    };
+
+    if let ApplicationError::Caddy(_) = err {
+        context.caddy_service = caddy::service(deps);
+    }
+    let output = format!("{err}\n{}", suggest(&err, &context));
+    (RunStatus::Failure, output, warnings.take())
+}
+
+// Run the portman command line interface with the process's arguments and return its exit code
+pub fn run_cli() -> ExitCode {
+    let deps = Impl::new(());
+    let (status, output, _) = run_and_suggest(&deps);
+    match status {
+        RunStatus::Success => {
+            std::io::stdout().write_all(output.as_bytes()).unwrap();
+            ExitCode::SUCCESS
+        }
+        RunStatus::Failure => {
+            std::io::stderr().write_all(output.as_bytes()).unwrap();
+            ExitCode::FAILURE
+        }
+        RunStatus::Exit(code) => ExitCode::from(code),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dependencies::{
+        BuildInfoMock, CheckPathMock, CheckWritableMock, ChoosePortMock, CurrentExeMock,
+        EnvironmentMock, ExecMock, ExecStatus, LockFileMock, ProbePortMock, PromptMock,
+        ReadFileMock, SpawnMock, WalkDirMock, WatchPathMock, WriteFileMock,
+    };
+    use crate::mocks::{
+        args_mock, choose_port_mock, current_time_mock, cwd_mock, data_dir_mock, exec_git_mock,
+        exec_mock, get_mocked_registry, lock_registry_mock, read_registry_mock, read_stdin_mock,
+        read_usage_mock, read_var_mock, resolve_host_mock, tty_mock, write_caddyfile_mock,
+        write_checksum_mock, write_file_mock, write_index_mock, write_registry_mock,
+    };
+    use anyhow::bail;
+    use std::io::Error;
+    use unimock::{matching, Clause, MockFn, Unimock};
+
+    fn exec_git_no_repo_mock() -> impl Clause {
+        ExecMock
+            .each_call(matching!((command) if command.get_program() == "git"))
+            .answers(&|_, _| {
+                Ok(ExecStatus::Failure {
+                    output: String::from("No repo\n"),
+                    code: 1,
+                })
+            })
+            .at_least_times(1)
+    }
+
+    fn read_file_mock() -> impl Clause {
+        ReadFileMock
+            .each_call(matching!((path) if path == &PathBuf::from("/data/config.toml") || path == &PathBuf::from("/homebrew/etc/Caddyfile") || path == &PathBuf::from("/data/health.toml") || path == &PathBuf::from("/data/usage.toml")))
+            .answers(&|_, _| Err(Error::from(std::io::ErrorKind::NotFound)))
+            .at_least_times(1)
+    }
+
+    fn build_info_mock() -> impl Clause {
+        BuildInfoMock
+            .each_call(matching!())
+            .answers(&|_| crate::version::Build {
+                version: "1.0.0",
+                target: "aarch64-apple-darwin",
+                profile: "release",
+                static_binary: false,
+                platform: String::from("macos-aarch64"),
+            })
+            .once()
+    }
+
+    fn exec_caddy_version_mock() -> impl Clause {
+        ExecMock
+            .each_call(matching!((command) if command.get_program() == "caddy" && command.get_args().eq(["version"])))
+            .answers(&|_, _| {
+                Ok(ExecStatus::Success {
+                    output: String::from("v2.7.6 h1:w0NymbG2m9PcvKWsrXO6EEkY9Ru4FJK8uQbYcev1p3A=\n"),
+                })
+            })
+            .once()
+    }
+
+    fn readonly_mocks() -> impl Clause {
+        (
+            data_dir_mock(),
+            lock_registry_mock(),
+            read_registry_mock(None),
+            read_file_mock(),
+            read_var_mock(),
+        )
+    }
+
+    fn record_usage_mock() -> impl Clause {
+        (
+            current_time_mock(1000),
+            WriteFileMock
+                .each_call(matching!((path, contents) if path == &PathBuf::from("/data/usage.toml") && contents.contains(" = 1000")))
+                .answers(&|_, _, _| Ok(()))
+                .once(),
+        )
+    }
+
+    fn readwrite_mocks() -> impl Clause {
+        (
+            readonly_mocks(),
+            exec_mock(),
+            write_caddyfile_mock(),
+            write_checksum_mock(),
+            write_index_mock(),
+        )
+    }
+
+    #[test]
+    fn test_format_project_simple() {
+        assert_eq!(
+            format_project(
+                "app1",
+                &Project {
+                    port: 3001,
+                    ..Default::default()
+                },
+            ),
+            String::from("app1 :3001"),
+        );
+    }
+
+    #[test]
+    fn test_ui_rows() {
+        let mocked_deps = Unimock::new(
+            ProbePortMock
+                .each_call(matching!(_))
+                .answers(&|_, port| port == 3003)
+                .n_times(3),
+        );
+        let registry = get_mocked_registry().unwrap();
+        let rows = ui_rows(&mocked_deps, &registry);
+        assert_eq!(
+            rows.iter()
+                .map(|row| (row.name.as_str(), row.linked_port, row.up))
+                .collect::<Vec<_>>(),
+            vec![
+                ("app1", None, false),
+                ("app2", Some(3000), false),
+                ("app3", None, true),
+            ]
+        );
+        assert_eq!(registry.url("app1"), "https://app1.localhost");
+    }
+
+    #[test]
+    fn test_format_project_complex() {
+        assert_eq!(
+            format_project(
+                "app1",
+                &Project {
+                    port: 3001,
+                    directory: Some(PathBuf::from("/projects/app1")),
+                    linked_port: Some(3000),
+                    ..Default::default()
+                },
+            ),
+            String::from("app1 :3001 -> :3000 (/projects/app1)"),
+        );
+    }
+
+    #[test]
+    fn test_config_init_bash() {
+        let mocked_deps = Unimock::new(args_mock("portman init bash"));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert!(!output.is_empty());
+    }
+
+    #[test]
+    fn test_config_init_fish() {
+        let mocked_deps = Unimock::new(args_mock("portman init fish"));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert!(!output.is_empty());
+    }
+
+    #[test]
+    fn test_config_init_nu() {
+        let mocked_deps = Unimock::new(args_mock("portman init nu"));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert!(output.contains("def --env __portman_sync_env"));
+    }
+
+    #[test]
+    fn test_config_init_powershell() {
+        let mocked_deps = Unimock::new(args_mock("portman init powershell"));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert!(output.contains("function global:prompt"));
+    }
+
+    #[test]
+    fn test_config_init_var_prefix() {
+        let mocked_deps = Unimock::new(args_mock("portman init bash --var-prefix MYAPP"));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert!(output.contains("export MYAPP_PORT MYAPP_PROJECT\n"));
+    }
+
+    #[test]
+    fn test_config_init_invalid_var_prefix() {
+        let mocked_deps = Unimock::new(args_mock("portman init bash --var-prefix 1APP"));
+
+        let err = Cli::try_parse_from(mocked_deps.get_args()).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ValueValidation);
+    }
+
+    #[test]
+    fn test_config_init_zsh() {
+        let mocked_deps = Unimock::new(args_mock("portman init zsh"));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert!(!output.is_empty());
+    }
+
+    #[test]
+    fn test_config_edit() {
+        let mocked_deps = Unimock::new((
+            args_mock("portman config edit"),
+            data_dir_mock(),
+            exec_mock(),
+            read_var_mock(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Opening \"/data/config.toml\" with \"editor\"\n");
+    }
+
+    #[test]
+    fn test_config_edit_no_editor_env() {
+        let mocked_deps = Unimock::new((
+            args_mock("portman config edit"),
+            data_dir_mock(),
+            EnvironmentMock
+                .each_call(matching!("PORTMAN_CONFIG"))
+                .answers(&|_, _| bail!("Failed"))
+                .once(),
+            EnvironmentMock
+                .each_call(matching!("EDITOR"))
+                .answers(&|_, _| bail!("Failed"))
+                .once(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Failed\n");
+    }
+
+    #[test]
+    fn test_config_edit_editor_not_found() {
+        let mocked_deps = Unimock::new((
+            args_mock("portman config edit"),
+            data_dir_mock(),
+            read_var_mock(),
+            ExecMock
+                .each_call(matching!((command) if command.get_program() == "editor"))
+                .answers(&|_, _| Err(Error::from(std::io::ErrorKind::NotFound)))
+                .once(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r#"Editor command could not be run:
+
+Command "editor /data/config.toml" failed to run:
+entity not found
+Try setting the $EDITOR environment variable to a valid command like vi or nano.
+"#
+        );
+    }
+
+    #[test]
+    fn test_config_edit_editor_failed() {
+        let mocked_deps = Unimock::new((
+            args_mock("portman config edit"),
+            data_dir_mock(),
+            read_var_mock(),
+            ExecMock
+                .each_call(matching!((command) if command.get_program() == "editor"))
+                .answers(&|_, _| {
+                    Ok(ExecStatus::Failure {
+                        output: String::from("Invalid config\n"),
+                        code: 1,
+                    })
+                })
+                .once(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r#"Editor command could not be run:
+
+Command "editor /data/config.toml" failed with exit code 1 and output:
+Invalid config
+
+"#
+        );
+    }
+
+    #[test]
+    fn test_config_show() {
+        let mocked_deps = Unimock::new((
+            args_mock("portman config show"),
+            data_dir_mock(),
+            read_file_mock(),
+            read_var_mock(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r"Data directory: /data
+Config path: /data/config.toml
+Registry path: /data/registry.toml
+Caddyfile path: /data/Caddyfile
+Gallery path: /data/gallery_www
+Configuration:
+--------------
+Allowed port ranges: 3000-3999
+"
+        );
+    }
+
+    #[test]
+    fn test_config_show_json() {
+        let mocked_deps = Unimock::new((
+            args_mock("portman config show --json"),
+            data_dir_mock(),
+            read_file_mock(),
+            read_var_mock(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "{\"data_dir\":\"/data\",\"config_path\":\"/data/config.toml\",\"registry_path\":\"/data/registry.toml\",\"caddyfile_path\":\"/data/Caddyfile\",\"gallery_path\":\"/data/gallery_www\",\"config\":{\"version\":2,\"ranges\":[[3000,3999]],\"reserved\":[],\"tag_ranges\":{},\"layout\":\"single\",\"backups\":0,\"fallback\":\"none\",\"strategy\":\"random\",\"bind\":\"loopback\",\"gallery_hostname\":\"localhost\",\"tld\":\"localhost\",\"tls_mode\":\"internal\",\"streaming\":false,\"access_logs\":false,\"mdns\":false,\"auto_reload\":true,\"require_caddy\":true,\"auto_create\":false,\"strict\":false,\"hooks\":{},\"templates\":{},\"defaults\":{\"no_link\":false},\"project_overrides\":{}}}\n"
+        );
+    }
+
+    #[test]
+    fn test_config_migrate() {
+        let mocked_deps = Unimock::new((
+            args_mock("portman config migrate"),
+            data_dir_mock(),
+            read_var_mock(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/config.toml")))
+                .answers(&|_, _| Ok(include_str!("fixtures/custom_config.toml").to_owned()))
+                .once(),
+            WriteFileMock
+                .each_call(matching!((path, contents) if path == &PathBuf::from("/data/config.toml") && contents.starts_with("# `version` is the config format version") && contents.ends_with("version = 2\n\nranges = [[2000, 2199], [4100, 4199]]\nreserved = [2002, 4004]\n")))
+                .answers(&|_, _, _| Ok(()))
+                .once(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Upgraded \"/data/config.toml\" to config version 2\n"
+        );
+    }
+
+    #[test]
+    fn test_config_validate() {
+        let mocked_deps = Unimock::new((
+            args_mock("portman config validate config.toml"),
+            cwd_mock("app1"),
+            ReadFileMock
+                .each_call(
+                    matching!((path) if path == &PathBuf::from("/projects/app1/config.toml")),
+                )
+                .answers(&|_, _| Ok(include_str!("fixtures/custom_config.toml").to_owned()))
+                .once(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "\"/projects/app1/config.toml\" is valid\n");
+    }
+
+    #[test]
+    fn test_config_validate_invalid() {
+        let mocked_deps = Unimock::new((
+            args_mock("portman config validate"),
+            data_dir_mock(),
+            read_var_mock(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/config.toml")))
+                .answers(&|_, _| Ok(String::from("ranges = []\n")))
+                .once(),
+        ));
+
+        let (status, output, _) = run_and_suggest(&mocked_deps);
+        assert!(matches!(status, RunStatus::Failure));
+        assert_eq!(
+            output,
+            "Configuration is invalid:\n\nValidation error: port ranges must not be empty\n\nTry running `portman config edit` to edit the config file and correct the error.\n"
+        );
+    }
+
+    #[test]
+    fn test_config_validate_missing() {
+        let mocked_deps = Unimock::new((
+            args_mock("portman config validate /missing.toml"),
+            cwd_mock("app1"),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/missing.toml")))
+                .answers(&|_, _| Err(std::io::Error::from(std::io::ErrorKind::NotFound)))
+                .once(),
+        ));
+
+        let (status, output, _) = run_and_suggest(&mocked_deps);
+        assert!(matches!(status, RunStatus::Failure));
+        assert!(output.starts_with("Custom config file at \"/missing.toml\" does not exist\n"));
+    }
+
+    #[test]
+    fn test_config_set() {
+        let mocked_deps = Unimock::new((
+            args_mock("portman config set reserved [2002,4004,4005]"),
+            data_dir_mock(),
+            read_var_mock(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/config.toml")))
+                .answers(&|_, _| Ok(include_str!("fixtures/custom_config.toml").to_owned()))
+                .once(),
+            WriteFileMock
+                .each_call(matching!((path, contents) if path == &PathBuf::from("/data/config.toml") && contents == &String::from("ranges = [[2000, 2199], [4100, 4199]]\nreserved = [2002,4004,4005]\n")))
+                .answers(&|_, _, _| Ok(()))
+                .once(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Set reserved in \"/data/config.toml\"\n");
+    }
+
+    #[test]
+    fn test_config_set_invalid() {
+        let mocked_deps = Unimock::new((
+            args_mock("portman config set ranges []"),
+            data_dir_mock(),
+            read_var_mock(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/config.toml")))
+                .answers(&|_, _| Ok(include_str!("fixtures/custom_config.toml").to_owned()))
+                .once(),
+        ));
+
+        let (status, output, _) = run_and_suggest(&mocked_deps);
+        assert!(matches!(status, RunStatus::Failure));
+        assert!(output.starts_with(
+            "Configuration is invalid:\n\nValidation error: port ranges must not be empty\n"
+        ));
+    }
+
+    #[test]
+    fn test_config_show_custom_config() {
+        let mocked_deps = Unimock::new((
+            args_mock("portman config show"),
+            data_dir_mock(),
+            read_var_mock(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/config.toml")))
+                .answers(&|_, _| Ok(include_str!("fixtures/custom_config.toml").to_owned()))
+                .once(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r"Data directory: /data
+Config path: /data/config.toml
+Registry path: /data/registry.toml
+Caddyfile path: /data/Caddyfile
+Gallery path: /data/gallery_www
+Configuration:
+--------------
+Allowed port ranges: 2000-2199 & 4100-4199
+Reserved ports: 2002, 4004
+"
+        );
+    }
+
+    #[test]
+    fn test_config_show_custom_path() {
+        let mocked_deps = Unimock::new((
+            args_mock("portman config show"),
+            data_dir_mock(),
+            EnvironmentMock
+                .each_call(matching!("PORTMAN_CONFIG"))
+                .answers(&|_, _| Ok("/data/custom_config.toml".to_owned()))
+                .at_least_times(1),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/custom_config.toml")))
+                .answers(&|_, _| Ok(include_str!("fixtures/custom_config.toml").to_owned()))
+                .once(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r"Data directory: /data
+Config path: /data/custom_config.toml
+Registry path: /data/registry.toml
+Caddyfile path: /data/Caddyfile
+Gallery path: /data/gallery_www
+Configuration:
+--------------
+Allowed port ranges: 2000-2199 & 4100-4199
+Reserved ports: 2002, 4004
+"
+        );
+    }
+
+    #[test]
+    fn test_config_show_custom_path_not_found() {
+        let mocked_deps = Unimock::new((
+            args_mock("portman config show"),
+            EnvironmentMock
+                .each_call(matching!("PORTMAN_CONFIG"))
+                .answers(&|_, _| Ok("/data/custom_config.toml".to_owned()))
+                .at_least_times(1),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/custom_config.toml")))
+                .answers(&|_, _| Err(Error::from(std::io::ErrorKind::NotFound)))
+                .once(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r#"Custom config file at "/data/custom_config.toml" does not exist
+Try creating a config file at "/data/custom_config.toml" or unsetting the $PORTMAN_CONFIG environment variable.
+"#
+        );
+    }
+
+    #[test]
+    fn test_get() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman get"),
+            cwd_mock("app3"),
+            record_usage_mock(),
+        ));
+
+        let (_, output, warnings) = run_and_suggest(&mocked_deps);
+        assert_eq!(output, "3003\n");
+        assert_eq!(warnings, Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_get_record_usage_failure() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman get"),
+            cwd_mock("app3"),
+            current_time_mock(1000),
+            WriteFileMock
+                .each_call(matching!((path, _) if path == &PathBuf::from("/data/usage.toml")))
+                .answers(&|_, _, _| bail!("Disk full"))
+                .once(),
+        ));
+
+        let (status, output, warnings) = run_and_suggest(&mocked_deps);
+        assert!(matches!(status, RunStatus::Success));
+        assert_eq!(output, "3003\n");
+        assert_eq!(
+            warnings,
+            vec![String::from(
+                "Failed to record that app3 was used: Failed to save usage log"
+            )]
+        );
+    }
+
+    #[test]
+    fn test_get_name() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman get app2"),
+            record_usage_mock(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "3002\n");
+    }
+
+    #[test]
+    fn test_get_name_non_existent() {
+        let mocked_deps = Unimock::new((readonly_mocks(), args_mock("portman get project")));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r"Project project does not exist
+Try providing a different project name.
+"
+        );
+    }
+
+    #[test]
+    fn test_get_port_name() {
+        let mocked_deps = Unimock::new((
+            lock_registry_mock(),
+            data_dir_mock(),
+            read_registry_mock(Some(
+                "projects.app1 = { port = 3001, ports = { api = 3002 } }",
+            )),
+            read_file_mock(),
+            read_var_mock(),
+            args_mock("portman get app1 --port-name api"),
+            record_usage_mock(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "3002\n");
+    }
+
+    #[test]
+    fn test_get_url() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman get app1 --url"),
+            record_usage_mock(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "https://app1.localhost\n");
+    }
+
+    #[test]
+    fn test_get_url_linked() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman get app2 --url"),
+            record_usage_mock(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "http://localhost:3000\n");
+    }
+
+    #[test]
+    fn test_get_url_port_name() {
+        let mocked_deps = Unimock::new((
+            lock_registry_mock(),
+            data_dir_mock(),
+            read_registry_mock(Some(
+                "projects.app1 = { port = 3001, ports = { api = 3002 } }",
+            )),
+            read_file_mock(),
+            read_var_mock(),
+            args_mock("portman get app1 --port-name api --url"),
+            record_usage_mock(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "https://api.app1.localhost\n");
+    }
+
+    #[test]
+    fn test_get_field() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman get app3 --field directory"),
+            record_usage_mock(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "/projects/app3\n");
+    }
+
+    #[test]
+    fn test_get_field_linked_port() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman get app2 --field linked-port"),
+            record_usage_mock(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "3000\n");
+    }
+
+    #[test]
+    fn test_get_field_empty() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman get app1 --field linked-port"),
+            record_usage_mock(),
+        ));
+
+        let (status, output, _) = run_and_suggest(&mocked_deps);
+        assert!(matches!(status, RunStatus::Failure));
+        assert_eq!(
+            output,
+            r"Project app1 is not linked to a port
+Try running `portman link <port> app1` to link a port to the project.
+"
+        );
+    }
+
+    #[test]
+    fn test_get_port_name_non_existent() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman get app1 --port-name api"),
+            record_usage_mock(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r"Project app1 does not have a port named api
+Try running `portman get app1 --json` to see which ports the project has.
+"
+        );
+    }
+
+    #[test]
+    fn test_get_shell_fast() {
+        let mocked_deps = Unimock::new((
+            args_mock("portman get --shell-fast"),
+            data_dir_mock(),
+            read_usage_mock(),
+            record_usage_mock(),
+            cwd_mock("app1"),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/index.toml")))
+                .answers(&|_, _| {
+                    Ok(String::from(
+                        "[[\"/projects/app1\"]]\nname = \"app1\"\nport = 3001\nlinked_port = 3000\n",
+                    ))
+                })
+                .once(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "3001\napp1\n/projects/app1\n3000\n");
+    }
+
+    #[test]
+    fn test_get_shell_fast_auto_create() {
+        let mocked_deps = Unimock::new((
+            args_mock("portman get --shell-fast"),
+            cwd_mock("project"),
+            CheckPathMock
+                .each_call(matching!((path) if path == &PathBuf::from("/projects/project/.git")))
+                .returns(true)
+                .once(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/index.toml")))
+                .answers(&|_, _| Ok(String::new()))
+                .once(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/config.toml")))
+                .answers(&|_, _| Ok(String::from("auto_create = true\n")))
+                .at_least_times(1),
+            // Registered before the caddy mocks so that it matches the usage timestamp
+            record_usage_mock(),
+            readwrite_mocks(),
+            choose_port_mock(),
+            exec_git_mock("project"),
+            write_registry_mock(include_str!("snapshots/create_unknown_repo.toml")),
+        ));
+
+        let (_, output, warnings) = run_and_suggest(&mocked_deps);
+        assert_eq!(output, "3004\nproject\n/projects/project\n\n");
+        assert_eq!(
+            warnings,
+            vec![String::from(
+                "Automatically created project project :3004 (/projects/project)"
+            )]
+        );
+    }
+
+    #[test]
+    fn test_get_shell_fast_no_auto_create() {
+        let mocked_deps = Unimock::new((
+            args_mock("portman get --shell-fast"),
+            cwd_mock("project"),
+            CheckPathMock
+                .each_call(matching!((path) if path == &PathBuf::from("/projects/project/.git")))
+                .returns(true)
+                .once(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/index.toml")))
+                .answers(&|_, _| Ok(String::new()))
+                .once(),
+            data_dir_mock(),
+            read_file_mock(),
+            read_var_mock(),
+        ));
+
+        let (status, output, warnings) = run_and_suggest(&mocked_deps);
+        assert!(matches!(status, RunStatus::Failure));
+        assert!(output.starts_with("The current directory does not contain a project\n"));
+        assert_eq!(warnings, Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_get_shell_fast_repo() {
+        let mocked_deps = Unimock::new((
+            args_mock("portman get --shell-fast"),
+            data_dir_mock(),
+            read_usage_mock(),
+            record_usage_mock(),
+            cwd_mock("clone"),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/index.toml")))
+                .answers(&|_, _| {
+                    Ok(String::from(
+                        "[[\"/projects/app1\"]]\nname = \"app1\"\nport = 3001\n\n[repos.\"github.com/user/app1\"]\nname = \"app1\"\nport = 3001\n",
+                    ))
+                })
+                .once(),
+            ExecMock
+                .each_call(matching!((command) if command.get_args().eq(["rev-parse", "--show-toplevel"])))
+                .answers(&|_, _| {
+                    Ok(ExecStatus::Success {
+                        output: String::from("/projects/clone\n"),
+                    })
+                })
+                .once(),
+            ExecMock
+                .each_call(matching!((command) if command.get_args().eq(["remote", "get-url", "origin"])))
+                .answers(&|_, _| {
+                    Ok(ExecStatus::Success {
+                        output: String::from("git@github.com:user/app1.git\n"),
+                    })
+                })
+                .once(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "3001\napp1\n/projects/clone\n\n");
+    }
+
+    #[test]
+    fn test_get_shell_fast_env() {
+        let mocked_deps = Unimock::new((
+            args_mock("portman get --shell-fast"),
+            data_dir_mock(),
+            read_usage_mock(),
+            record_usage_mock(),
+            cwd_mock("app1"),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/index.toml")))
+                .answers(&|_, _| {
+                    Ok(String::from(
+                        "[[\"/projects/app1\"]]\nname = \"app1\"\nport = 3001\n\n[\"/projects/app1\".env]\nAPI_URL = \"http://api\"\nDEBUG = \"1\"\n",
+                    ))
+                })
+                .once(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "3001\napp1\n/projects/app1\n\nAPI_URL=http://api\nDEBUG=1\n"
+        );
+    }
+
+    #[test]
+    fn test_get_shell_fast_no_index() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman get --shell-fast"),
+            cwd_mock("app3"),
+            record_usage_mock(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/index.toml")))
+                .answers(&|_, _| Err(Error::from(std::io::ErrorKind::NotFound)))
+                .once(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "3003\napp3\n/projects/app3\n\n");
+    }
+
+    #[test]
+    fn test_normalize_path() {
+        assert_eq!(
+            normalize_path(Path::new("/projects/app1/./../app3/src")),
+            PathBuf::from("/projects/app3/src")
+        );
+    }
+
+    #[test]
+    fn test_resolve() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman resolve ../app3/src/main.ts"),
+            cwd_mock("app1"),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "3003\n");
+    }
+
+    #[test]
+    fn test_resolve_extended() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman resolve /projects/app3 --extended"),
+            cwd_mock("app1"),
+            tty_mock(false),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "3003\napp3\n/projects/app3\n\n");
+    }
+
+    #[test]
+    fn test_resolve_unresolved() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman resolve /projects"),
+            cwd_mock("app3"),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "No project is active in \"/projects\" or any of its parent directories\nTry running `portman list` to see which directory each project is in.\n"
+        );
+    }
+
+    #[test]
+    fn test_which() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman which"),
+            cwd_mock("app3"),
+            exec_git_mock("app3"),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r"Directory: /projects/app3
+Project: app3 (port 3003), because its directory matches
+Repo: https://github.com/user/app3.git (default port 3004)
+Environment:
+  PORT=3003
+  PORTMAN_PROJECT=app3
+"
+        );
+    }
+
+    #[test]
+    fn test_which_parent() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman which"),
+            cwd_mock("app3/src"),
+            exec_git_no_repo_mock(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r"Directory: /projects/app3/src
+Project: none, because no project has this directory
+Parent project: app3 in /projects/app3, which is only active in its own directory
+Repo: none
+Environment: none
+"
+        );
+    }
+
+    #[test]
+    fn test_get_json() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman get app2 --json"),
+            record_usage_mock(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "{\"name\":\"app2\",\"port\":3002,\"directory\":null,\"linked_port\":3000,\"display_name\":null,\"icon\":null,\"description\":null,\"upstream\":null,\"bind\":null,\"branch\":null}\n"
+        );
+    }
+
+    #[test]
+    fn test_get_extended() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman get --extended"),
+            record_usage_mock(),
+            cwd_mock("app3"),
+            tty_mock(true),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "port: 3003\nname: app3\ndirectory: /projects/app3\nlinked port: \n"
+        );
+    }
+
+    #[test]
+    fn test_get_extended_not_tty() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman get --extended"),
+            record_usage_mock(),
+            cwd_mock("app3"),
+            tty_mock(false),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r"3003
+app3
+/projects/app3
+
+"
+        );
+    }
+
+    #[test]
+    fn test_create() {
+        let mocked_deps = Unimock::new((
+            resolve_host_mock(true),
+            readwrite_mocks(),
+            args_mock("portman create"),
+            choose_port_mock(),
+            cwd_mock("project"),
+            exec_git_mock("project"),
+            tty_mock(true),
+            write_registry_mock(include_str!("snapshots/create.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Created project project :3004 (/projects/project)\n"
+        );
+    }
+
+    #[test]
+    fn test_create_templates() {
+        let mocked_deps = Unimock::new((
+            templates_mocks(),
+            exec_mock(),
+            resolve_host_mock(true),
+            write_caddyfile_mock(),
+            write_checksum_mock(),
+            write_index_mock(),
+            args_mock("portman create"),
+            choose_port_mock(),
+            cwd_mock("project"),
+            exec_git_mock("project"),
+            tty_mock(true),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/projects/project/.env.local")))
+                .answers(&|_, _| Err(Error::from(std::io::ErrorKind::NotFound)))
+                .once(),
+            WriteFileMock
+                .each_call(matching!((path, contents) if path == &PathBuf::from("/projects/project/.env.local") && contents == &String::from("PORT=3004\nNAME=project\n")))
+                .answers(&|_, _, _| Ok(()))
+                .once(),
+            write_registry_mock(include_str!("snapshots/create.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Created project project :3004 (/projects/project)\n"
+        );
+    }
+
+    #[test]
+    fn test_create_not_tty() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman create"),
+            choose_port_mock(),
+            cwd_mock("project"),
+            exec_git_mock("project"),
+            tty_mock(false),
+            write_registry_mock(include_str!("snapshots/create.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "3004\n");
+    }
+
+    #[test]
+    fn test_create_duplicate_directory() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman create project"),
+            cwd_mock("app3"),
+            exec_git_mock("project"),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r#"Project app3 already uses the directory "/projects/app3"
+Try running the command in a different directory, providing the --no-activate flag, or running `portman delete app3` and rerunning the command.
+"#
+        );
+    }
+
+    #[test]
+    fn test_create_duplicate_project() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman create"),
+            cwd_mock("app3"),
+            exec_git_mock("app3"),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r"A project already has the name app3
+Try manually providing a project name.
+"
+        );
+    }
+
+    #[test]
+    fn test_create_duplicate_project_explicit_name() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman create app3"),
+            cwd_mock("app3"),
+            exec_git_mock("app3"),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r"A project already has the name app3
+Try providing the --overwrite flag to modify the existing project.
+"
+        );
+    }
+
+    #[test]
+    fn test_create_name_template() {
+        let mocked_deps = Unimock::new((
+            resolve_host_mock(true),
+            readwrite_mocks(),
+            args_mock("portman create --name-template {repo}-{branch} --no-link"),
+            choose_port_mock(),
+            cwd_mock("project"),
+            ExecMock
+                .each_call(matching!((command) if command.get_program() == "git" && command.get_args().eq(["branch", "--show-current"])))
+                .answers(&|_, _| {
+                    Ok(ExecStatus::Success {
+                        output: String::from("Feature_Login\n"),
+                    })
+                })
+                .once(),
+            exec_git_mock("project"),
+            tty_mock(true),
+            write_file_mock(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Created project project-feature-login :3004 (/projects/project)\n"
+        );
+    }
+
+    #[test]
+    fn test_create_full_specification() {
+        let mocked_deps = Unimock::new((
+            resolve_host_mock(true),
+            readwrite_mocks(),
+            args_mock("portman create api --directory /projects/api --port 3500 --link 3010 --tags work,backend --description API --env API_URL=http://localhost:3500"),
+            cwd_mock("project"),
+            exec_git_mock("project"),
+            tty_mock(true),
+            write_registry_mock(include_str!("snapshots/create_full_specification.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Created project api :3500 -> :3010 (/projects/api)\n"
+        );
+    }
+
+    #[test]
+    fn test_create_out_of_range_port() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman create --no-link --port 8080"),
+            cwd_mock("project"),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Port 8080 is outside of the configured port ranges\nTry a port in the configured ranges, or run the command again with --force to use a port outside of them.\n"
+        );
+    }
+
+    #[test]
+    fn test_create_force_port() {
+        let mocked_deps = Unimock::new((
+            resolve_host_mock(true),
+            readwrite_mocks(),
+            args_mock("portman create --no-link --port 8080 --force"),
+            cwd_mock("project"),
+            tty_mock(true),
+            write_registry_mock(include_str!("snapshots/create_force.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Created project project :8080 (/projects/project)\n"
+        );
+    }
+
+    #[test]
+    fn test_create_unavailable_port() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman create app4 --no-link --port 3001"),
+            cwd_mock("project"),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Port 3001 is not available\nTry a port that isn't already assigned to another project, or omit --port to allocate one automatically.\n"
+        );
+    }
+
+    #[test]
+    fn test_create_strict_normalized_name() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman create --strict --no-link"),
+            cwd_mock("My_Project"),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r#"Strict mode violation: project name "My_Project" would be normalized to "my-project"
+Try running the command again without --strict, or set `strict = false` in the config file.
+"#
+        );
+    }
+
+    #[test]
+    fn test_create_strict_port_in_use() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman create --strict --no-link"),
+            choose_port_mock(),
+            cwd_mock("project"),
+            ProbePortMock
+                .each_call(matching!(3004))
+                .answers(&|_, _| true)
+                .once(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r"Strict mode violation: port 3004 is already in use by another process
+Try running the command again without --strict, or set `strict = false` in the config file.
+"
+        );
+    }
+
+    #[test]
+    fn test_create_per_branch() {
+        let mocked_deps = Unimock::new((
+            resolve_host_mock(true),
+            readwrite_mocks(),
+            args_mock("portman create --per-branch --no-link"),
+            choose_port_mock(),
+            cwd_mock("project"),
+            ExecMock
+                .each_call(matching!((command) if command.get_program() == "git" && command.get_args().eq(["branch", "--show-current"])))
+                .answers(&|_, _| {
+                    Ok(ExecStatus::Success {
+                        output: String::from("Feature_Login\n"),
+                    })
+                })
+                .n_times(2),
+            exec_git_mock("project"),
+            tty_mock(true),
+            write_registry_mock(include_str!("snapshots/create_per_branch.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Created project project-feature-login :3004 (/projects/project)\n"
+        );
+    }
+
+    #[test]
+    fn test_create_link() {
+        let mocked_deps = Unimock::new((
+            resolve_host_mock(true),
+            readwrite_mocks(),
+            args_mock("portman create"),
+            choose_port_mock(),
+            cwd_mock("project"),
+            exec_git_mock("app3"),
+            tty_mock(true),
+            write_registry_mock(include_str!("snapshots/create_link.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Created project project :3005 -> :3004 (/projects/project)\n"
+        );
+    }
+
+    #[test]
+    fn test_create_repo_project_port() {
+        let mocked_deps = Unimock::new((
+            lock_registry_mock(),
+            data_dir_mock(),
+            read_registry_mock(Some(
+                "projects = {}\n\n[repos.\"https://github.com/user/project.git\"]\nport = 3000\nproject_port = 3500\n",
+            )),
+            read_file_mock(),
+            read_var_mock(),
+            exec_mock(),
+            resolve_host_mock(true),
+            write_caddyfile_mock(),
+            write_checksum_mock(),
+            write_index_mock(),
+            args_mock("portman create"),
+            cwd_mock("project"),
+            exec_git_mock("project"),
+            tty_mock(true),
+            write_registry_mock(include_str!("snapshots/create_repo_project_port.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Created project project :3500 -> :3000 (/projects/project)\n"
+        );
+    }
+
+    #[test]
+    fn test_create_repo_linked_ports() {
+        let mocked_deps = Unimock::new((
+            lock_registry_mock(),
+            data_dir_mock(),
+            read_registry_mock(Some(
+                "projects = {}\n\n[repos.\"https://github.com/user/project.git\"]\nport = 3000\n\n[repos.\"https://github.com/user/project.git\".linked_ports]\napi = 8000\n",
+            )),
+            read_file_mock(),
+            read_var_mock(),
+            exec_mock(),
+            resolve_host_mock(true),
+            write_caddyfile_mock(),
+            write_checksum_mock(),
+            write_index_mock(),
+            args_mock("portman create"),
+            choose_port_mock(),
+            cwd_mock("project"),
+            exec_git_mock("project"),
+            tty_mock(true),
+            write_registry_mock(include_str!("snapshots/create_repo_linked_ports.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Created project project :3001 -> :3000 (/projects/project)\n"
+        );
+    }
+
+    #[test]
+    fn test_create_no_repo() {
+        let mocked_deps = Unimock::new((
+            resolve_host_mock(true),
+            readwrite_mocks(),
+            args_mock("portman create"),
+            choose_port_mock(),
+            cwd_mock("project"),
+            exec_git_no_repo_mock(),
+            tty_mock(true),
+            write_registry_mock(include_str!("snapshots/create_no_repo.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Created project project :3004 (/projects/project)\n"
+        );
+    }
+
+    #[test]
+    fn test_create_unknown_repo() {
+        let mocked_deps = Unimock::new((
+            resolve_host_mock(true),
+            readwrite_mocks(),
+            args_mock("portman create"),
+            choose_port_mock(),
+            cwd_mock("project"),
+            exec_git_mock("project"),
+            tty_mock(true),
+            write_registry_mock(include_str!("snapshots/create_unknown_repo.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Created project project :3004 (/projects/project)\n"
+        );
+    }
+
+    #[test]
+    fn test_create_match_repo() {
+        let mocked_deps = Unimock::new((
+            resolve_host_mock(true),
+            readwrite_mocks(),
+            args_mock("portman create --match-repo"),
+            choose_port_mock(),
+            cwd_mock("project"),
+            exec_git_mock("project"),
+            tty_mock(true),
+            write_registry_mock(include_str!("snapshots/create_match_repo.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Created project project :3004 (/projects/project)\n"
+        );
+    }
+
+    #[test]
+    fn test_create_caddy_failed() {
+        let mocked_deps = Unimock::new((
+            resolve_host_mock(true),
+            readonly_mocks(),
+            args_mock("portman create"),
+            choose_port_mock(),
+            cwd_mock("project"),
+            exec_git_mock("project"),
+            ExecMock
+                .each_call(matching!((command) if command.get_program() == "caddy"))
+                .answers(&|_, _| {
+                    Ok(ExecStatus::Failure {
+                        output: String::from("caddy is not running\n"),
+                        code: 1,
+                    })
+                })
+                .once(),
+            tty_mock(true),
+            write_file_mock(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r#"Error reloading caddy:
+Command "caddy reload --adapter caddyfile --config /homebrew/etc/Caddyfile" failed with exit code 1 and output:
+caddy is not running
+
+Try running `brew services start caddy` to make sure that caddy is running.
+"#
+        );
+    }
+
+    #[test]
+    fn test_create_caddy_not_found() {
+        let mocked_deps = Unimock::new((
+            resolve_host_mock(true),
+            readonly_mocks(),
+            args_mock("portman create"),
+            choose_port_mock(),
+            cwd_mock("project"),
+            exec_git_mock("project"),
+            ExecMock
+                .each_call(matching!((command) if command.get_program() == "caddy"))
+                .answers(&|_, _| Err(Error::from(std::io::ErrorKind::NotFound)))
+                .once(),
+            tty_mock(true),
+            write_file_mock(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r#"Error reloading caddy:
+Command "caddy reload --adapter caddyfile --config /homebrew/etc/Caddyfile" failed to run:
+entity not found
+Try running `brew install caddy` or making sure that caddy is in your PATH.
+"#
+        );
+    }
+
+    #[test]
+    fn test_create_caddy_not_required() {
+        let mocked_deps = Unimock::new((
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/config.toml")))
+                .answers(&|_, _| Ok(String::from("require_caddy = false\n")))
+                .at_least_times(1),
+            readonly_mocks(),
+            args_mock("portman create"),
+            choose_port_mock(),
+            cwd_mock("project"),
+            exec_git_mock("project"),
+            ExecMock
+                .each_call(matching!((command) if command.get_program() == "caddy"))
+                .answers(&|_, _| Err(Error::from(std::io::ErrorKind::NotFound)))
+                .once(),
+            resolve_host_mock(true),
+            tty_mock(true),
+            write_file_mock(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Created project project :3004 (/projects/project)\n"
+        );
+    }
+
+    #[test]
+    fn test_create_empty_allocator() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman create"),
+            ChoosePortMock.each_call(matching!(_)).returns(None).once(),
+            cwd_mock("project"),
+            exec_git_mock("project"),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r"All available ports have been allocated already
+Try running `portman config edit` to edit the config file and modify the `ranges` field to allow more ports.
+"
+        );
+    }
+
+    #[test]
+    fn test_create_invalid_config() {
+        let mocked_deps = Unimock::new((
+            args_mock("portman create"),
+            data_dir_mock(),
+            read_var_mock(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/config.toml")))
+                .answers(&|_, _| Ok(include_str!("fixtures/invalid_config.toml").to_owned()))
+                .once(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r"Configuration is invalid:
+
+Validation error: port ranges must not be empty
+
+Try running `portman config edit` to edit the config file and correct the error.
+"
+        );
+    }
+
+    #[test]
+    fn test_create_invalid_name() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman create"),
+            cwd_mock("-"),
+            exec_git_mock("project"),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r#"Project name "" is invalid: must not be empty
+Try manually providing a project name.
+"#
+        );
+    }
+
+    #[test]
+    fn test_create_no_link() {
+        let mocked_deps = Unimock::new((
+            resolve_host_mock(true),
+            readwrite_mocks(),
+            args_mock("portman create --no-link"),
+            choose_port_mock(),
+            cwd_mock("project"),
+            tty_mock(true),
+            write_registry_mock(include_str!("snapshots/create_no_link.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Created project project :3004 (/projects/project)\n"
+        );
+    }
+
+    #[test]
+    fn test_create_name() {
+        let mocked_deps = Unimock::new((
+            resolve_host_mock(true),
+            readwrite_mocks(),
+            args_mock("portman create project"),
+            choose_port_mock(),
+            cwd_mock("project"),
+            exec_git_mock("project"),
+            tty_mock(true),
+            write_registry_mock(include_str!("snapshots/create_name.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Created project project :3004 (/projects/project)\n"
+        );
+    }
+
+    #[test]
+    fn test_create_no_activate() {
+        let mocked_deps = Unimock::new((
+            resolve_host_mock(true),
+            readwrite_mocks(),
+            args_mock("portman create project --no-activate"),
+            choose_port_mock(),
+            exec_git_mock("project"),
+            tty_mock(true),
+            write_registry_mock(include_str!("snapshots/create_no_activate.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Created project project :3004\n");
+    }
+
+    #[test]
+    fn test_create_caddy_not_installed() {
+        let mocked_deps = Unimock::new((
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/config.toml")))
+                .answers(&|_, _| Ok(String::from("require_caddy = false\n")))
+                .at_least_times(1),
+            readonly_mocks(),
+            resolve_host_mock(true),
+            args_mock("portman create project --no-activate"),
+            choose_port_mock(),
+            exec_git_mock("project"),
+            ExecMock
+                .each_call(matching!((command) if command.get_program() == "caddy"))
+                .answers(&|_, _| Err(Error::from(std::io::ErrorKind::NotFound)))
+                .once(),
+            tty_mock(true),
+            write_caddyfile_mock(),
+            write_checksum_mock(),
+            write_index_mock(),
+            write_registry_mock(include_str!("snapshots/create_no_activate.toml")),
+        ));
+
+        let (status, output, warnings) = run_and_suggest(&mocked_deps);
+        assert!(matches!(status, RunStatus::Success));
+        assert_eq!(output, "Created project project :3004\n");
+        assert_eq!(
+            warnings,
+            vec![String::from(
+                "Caddy is not installed, so the changes were saved without reloading caddy"
+            )]
+        );
+    }
+
+    #[test]
+    fn test_create_ports() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman create project --no-activate --ports web,api"),
+            choose_port_mock(),
+            exec_git_mock("project"),
+            tty_mock(false),
+            write_registry_mock(include_str!("snapshots/create_ports.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "3004\n");
+    }
+
+    #[test]
+    fn test_create_unresolvable_hostname() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            exec_mock(),
+            resolve_host_mock(false),
+            write_caddyfile_mock(),
+            write_checksum_mock(),
+            write_index_mock(),
+            args_mock("portman create project --no-activate"),
+            choose_port_mock(),
+            exec_git_mock("project"),
+            tty_mock(true),
+            write_registry_mock(include_str!("snapshots/create_no_activate.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r#"Created project project :3004
+Warning: project.localhost does not resolve to this machine, so the project isn't reachable by its hostname.
+Try adding "127.0.0.1 project.localhost" to /etc/hosts or configuring dnsmasq with "address=/localhost/127.0.0.1" to resolve every *.localhost hostname.
+"#
+        );
+    }
+
+    #[test]
+    fn test_create_no_activate_no_link() {
+        let mocked_deps = Unimock::new(args_mock("portman create project --no-activate --no-link"));
+
+        let err = Cli::try_parse_from(mocked_deps.get_args()).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+    }
+
+    #[test]
+    fn test_create_overwrite() {
+        let mocked_deps = Unimock::new((
+            resolve_host_mock(true),
+            readwrite_mocks(),
+            args_mock("portman create app3 --overwrite"),
+            cwd_mock("project"),
+            exec_git_mock("project"),
+            tty_mock(true),
+            write_registry_mock(include_str!("snapshots/create_overwrite.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Updated project app3 :3003 (/projects/project)\n");
+    }
+
+    #[test]
+    fn test_create_overwrite_link() {
+        let mocked_deps = Unimock::new((
+            resolve_host_mock(true),
+            readwrite_mocks(),
+            args_mock("portman create app3 --overwrite"),
+            cwd_mock("project"),
+            exec_git_mock("app3"),
+            tty_mock(true),
+            write_registry_mock(include_str!("snapshots/create_overwrite_link.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Updated project app3 :3003 -> :3004 (/projects/project)\n"
+        );
+    }
+
+    #[test]
+    fn test_create_no_activate_no_name() {
+        let mocked_deps = Unimock::new(args_mock("portman create --no-activate"));
+
+        let err = Cli::try_parse_from(mocked_deps.get_args()).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::MissingRequiredArgument);
+    }
+
+    #[test]
+    fn test_batch() {
+        let mocked_deps = Unimock::new((
+            resolve_host_mock(true),
+            readwrite_mocks(),
+            args_mock("portman batch"),
+            read_stdin_mock(
+                "# Set up the project\ncreate project --no-activate\n\nlink 3010 project\n",
+            ),
+            choose_port_mock(),
+            exec_git_mock("project"),
+            tty_mock(true),
+            write_registry_mock(include_str!("snapshots/batch.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Created project project :3004\nLinked port 3010 to project project\n"
+        );
+    }
+
+    #[test]
+    fn test_batch_rollback() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman batch"),
+            read_stdin_mock("create project --no-activate\ndelete app4\n"),
+            choose_port_mock(),
+            exec_git_mock("project"),
+            resolve_host_mock(true),
+            tty_mock(true),
+        ));
+
+        let (status, output, _) = run_and_suggest(&mocked_deps);
+        assert!(matches!(status, RunStatus::Failure));
+        assert!(output.starts_with("Command on line 2 of the batch failed"));
+    }
+
+    #[test]
+    fn test_batch_unbatchable() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman batch"),
+            read_stdin_mock("list\n"),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert!(output
+            .contains("Only create, set, delete, link, and unlink commands can be run in a batch"));
+    }
+
+    #[test]
+    fn test_set_display_name() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman set display-name App app1"),
+            write_registry_mock(include_str!("snapshots/set_display_name.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Updated project app1 :3001\n");
+    }
+
+    #[test]
+    fn test_set_color() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman set color magenta app1"),
+            write_registry_mock(include_str!("snapshots/set_color.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Updated project app1 :3001\n");
+    }
+
+    #[test]
+    fn test_set_bind() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman set bind all app1"),
+            write_registry_mock(include_str!("snapshots/set_bind.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Updated project app1 :3001\n");
+    }
+
+    #[test]
+    fn test_set_hmr() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman set hmr true app1"),
+            write_registry_mock(include_str!("snapshots/set_hmr.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Updated project app1 :3001\n");
+    }
+
+    #[test]
+    fn test_set_caddy_extra() {
+        let mocked_deps = Unimock::new((
+            ReadFileMock
+                .each_call(
+                    matching!((path) if path == &PathBuf::from("/projects/app1/snippet.caddy")),
+                )
+                .answers(&|_, _| Ok(String::from("header X-Frame-Options DENY\n")))
+                .once(),
+            readwrite_mocks(),
+            args_mock("portman set caddy-extra --file snippet.caddy app1"),
+            cwd_mock("app1"),
+            write_registry_mock(include_str!("snapshots/set_caddy_extra.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Updated project app1 :3001\n");
+    }
+
+    #[test]
+    fn test_set_caddy_extra_invalid() {
+        let mocked_deps = Unimock::new((
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/projects/app1/snippet.caddy")))
+                .answers(&|_, _| Ok(String::from("invalid_directive\n")))
+                .once(),
+            ExecMock
+                .each_call(matching!((command) if command.get_program() == "caddy" && command.get_args().next() == Some(std::ffi::OsStr::new("validate"))))
+                .answers(&|_, _| {
+                    Ok(ExecStatus::Failure {
+                        output: String::from("Error: unrecognized directive: invalid_directive\n"),
+                        code: 1,
+                    })
+                })
+                .once(),
+            WriteFileMock
+                .each_call(matching!((path, contents) if path == &PathBuf::from("/data/Caddyfile.validate") && contents.contains("\tinvalid_directive\n")))
+                .answers(&|_, _, _| Ok(()))
+                .once(),
+            readonly_mocks(),
+            args_mock("portman set caddy-extra --file snippet.caddy app1"),
+            cwd_mock("app1"),
+        ));
+
+        let (status, output, _) = run_and_suggest(&mocked_deps);
+        assert!(matches!(status, RunStatus::Failure));
+        assert!(output.contains("unrecognized directive: invalid_directive"));
+        assert!(output.ends_with("Try fixing the directives in the Caddyfile snippet or running `portman set caddy-extra --clear` to remove them.\n"));
+    }
+
+    #[test]
+    fn test_set_wildcard() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman set wildcard true app1"),
+            write_registry_mock(include_str!("snapshots/set_wildcard.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Updated project app1 :3001\n");
+    }
+
+    #[test]
+    fn test_set_protocol() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman set protocol h2c app1"),
+            write_registry_mock(include_str!("snapshots/set_protocol.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Updated project app1 :3001\n");
+    }
+
+    #[test]
+    fn test_delete() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman delete"),
+            cwd_mock("app3"),
+            write_registry_mock(include_str!("snapshots/delete.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Deleted project app3 :3003 (/projects/app3)\n");
+    }
+
+    #[test]
+    fn test_delete_no_reload() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman delete --no-reload"),
+            cwd_mock("app3"),
+            write_checksum_mock(),
+            write_index_mock(),
+            write_registry_mock(include_str!("snapshots/delete.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Deleted project app3 :3003 (/projects/app3)\n");
+    }
+
+    #[test]
+    fn test_delete_no_active() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman delete"),
+            cwd_mock("app2"),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r"The current directory does not contain a project
+Try running the command again in a directory containing a project or providing an explicit project name.
+"
+        );
+    }
+
+    #[test]
+    fn test_delete_name() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman delete app3"),
+            write_registry_mock(include_str!("snapshots/delete_name.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Deleted project app3 :3003 (/projects/app3)\n");
+    }
+
+    #[test]
+    fn test_delete_dry_run() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman --dry-run delete app1"),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/Caddyfile")))
+                .answers(&|_, _| Ok(String::from(include_str!("snapshots/Caddyfile"))))
+                .once(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Deleted project app1 :3001
+Dry run: no changes were saved, and the Caddyfile would change:
+-app1.localhost {
+-\tbind 127.0.0.1 [::1]
+-\treverse_proxy localhost:3001
+-}
+-
+"
+        );
+    }
+
+    #[test]
+    fn test_delete_hook() {
+        let mocked_deps = Unimock::new((
+            lock_registry_mock(),
+            args_mock("portman delete app3"),
+            data_dir_mock(),
+            read_registry_mock(None),
+            read_var_mock(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/config.toml")))
+                .answers(&|_, _| Ok(String::from("[hooks]\non_delete = \"./remove-host.sh\"\n")))
+                .at_least_times(1),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/homebrew/etc/Caddyfile") || path == &PathBuf::from("/data/health.toml") || path == &PathBuf::from("/data/usage.toml")))
+                .answers(&|_, _| Err(Error::from(std::io::ErrorKind::NotFound))),
+            exec_mock(),
+            ExecMock
+                .each_call(matching!((command) if command.get_program() == "sh" && command.get_args().eq(["-c", "./remove-host.sh"]) && command.get_envs().any(|(name, value)| name == "PORTMAN_PROJECT" && value == Some(std::ffi::OsStr::new("app3")))))
+                .answers(&|_, _| Ok(ExecStatus::Success { output: String::new() }))
+                .once(),
+            write_file_mock(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Deleted project app3 :3003 (/projects/app3)\n");
+    }
+
+    #[test]
+    fn test_archive() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman archive app3 --reserve-port"),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/archive.toml")))
+                .answers(&|_, _| Err(Error::from(std::io::ErrorKind::NotFound)))
+                .once(),
+            WriteFileMock
+                .each_call(matching!((path, contents) if path == &PathBuf::from("/data/archive.toml") && contents == &String::from("[projects.app3]\nport = 3003\ndirectory = \"/projects/app3\"\n")))
+                .answers(&|_, _, _| Ok(()))
+                .once(),
+            write_registry_mock(include_str!("snapshots/archive.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Archived project app3 :3003 (/projects/app3)\n");
+    }
+
+    #[test]
+    fn test_unarchive() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman unarchive app4"),
+            choose_port_mock(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/archive.toml")))
+                .answers(&|_, _| {
+                    Ok(String::from(
+                        "[projects.app4]\nport = 3001\ndirectory = \"/projects/app4\"\n",
+                    ))
+                })
+                .once(),
+            WriteFileMock
+                .each_call(matching!((path, contents) if path == &PathBuf::from("/data/archive.toml") && !contents.contains("app4")))
+                .answers(&|_, _, _| Ok(()))
+                .once(),
+            write_registry_mock(include_str!("snapshots/unarchive.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Unarchived project app4 :3004 (/projects/app4)\n");
+    }
+
+    #[test]
+    fn test_unarchive_nonexistent() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman unarchive app4"),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/archive.toml")))
+                .answers(&|_, _| Err(Error::from(std::io::ErrorKind::NotFound)))
+                .once(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Archived project app4 does not exist\nTry running `portman list --archived` to see which projects are archived.\n"
+        );
+    }
+
+    #[test]
+    fn test_cleanup_single() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman cleanup"),
+            write_registry_mock(include_str!("snapshots/cleanup_single.toml")),
+            CheckPathMock
+                .each_call(matching!((path) if path == &PathBuf::from("/projects/app3")))
+                .returns(false)
+                .once(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r"Deleted 1 project
+app3 :3003 (/projects/app3)
+"
+        );
+    }
+
+    #[test]
+    fn test_registry_load_matches_load_registry() {
+        let mocked_deps = Unimock::new((
+            data_dir_mock(),
+            lock_registry_mock(),
+            read_var_mock(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/config.toml")))
+                .answers(&|_, _| {
+                    Ok(String::from(
+                        "bind = \"all\"\ntld = \"test\"\ntls_mode = \"off\"\nstreaming = true\naccess_logs = true\nmdns = true\ngallery_hostname = \"ports\"\n",
+                    ))
+                })
+                .at_least_times(1),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/registry.toml")))
+                .answers(&|_, _| Ok(String::from(include_str!("fixtures/registry.toml"))))
+                .n_times(2),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/registry.toml.checksum")))
+                .answers(&|_, _| Err(Error::from(std::io::ErrorKind::NotFound)))
+                .n_times(2),
+        ));
+
+        let options = LoadOptions {
+            quiet: false,
+            no_reload: false,
+            wait: Duration::ZERO,
+            warnings: &Warnings::default(),
+        };
+        let cli_registry = load_registry(&mocked_deps, options).unwrap();
+        let config = load_config(&mocked_deps).unwrap();
+        let library_registry = Registry::load(&mocked_deps, &config).unwrap();
+        let caddyfile = caddy::generate_caddyfile(&mocked_deps, &cli_registry).unwrap();
+        assert!(caddyfile.contains("http://app1.test"));
+        assert_eq!(
+            caddyfile,
+            caddy::generate_caddyfile(&mocked_deps, &library_registry).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_sync_daemon() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            write_registry_mock(include_str!("snapshots/cleanup_single.toml")),
+            CheckPathMock
+                .each_call(matching!((path) if path == &PathBuf::from("/projects/app3")))
+                .returns(false)
+                .once(),
+        ));
+
+        let options = LoadOptions {
+            quiet: false,
+            no_reload: false,
+            wait: Duration::ZERO,
+            warnings: &Warnings::default(),
+        };
+        let (directories, names) = sync_daemon(&mocked_deps, options).unwrap();
+        assert_eq!(directories, Vec::<PathBuf>::new());
+        assert_eq!(
+            names,
+            BTreeSet::from([String::from("app1"), String::from("app2")])
+        );
+    }
+
+    #[test]
+    fn test_daemon_mdns_disabled() {
+        let mocked_deps = Unimock::new((
+            args_mock("portman daemon --mdns"),
+            data_dir_mock(),
+            read_file_mock(),
+            read_var_mock(),
+        ));
+
+        let (status, output, _) = run_and_suggest(&mocked_deps);
+        assert!(matches!(status, RunStatus::Failure));
+        assert_eq!(
+            output,
+            "mDNS is disabled\nTry running `portman config edit` and setting `mdns = true`.\n"
+        );
+    }
+
+    #[test]
+    fn test_cleanup_json() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman cleanup --json"),
+            write_registry_mock(include_str!("snapshots/cleanup_single.toml")),
+            CheckPathMock
+                .each_call(matching!((path) if path == &PathBuf::from("/projects/app3")))
+                .returns(false)
+                .once(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "{\"removed\":[{\"name\":\"app3\",\"port\":3003,\"directory\":\"/projects/app3\",\"linked_port\":null,\"display_name\":null,\"icon\":null,\"description\":null,\"upstream\":null,\"bind\":null,\"branch\":null,\"reason\":\"missing_directory\"}]}\n"
+        );
+    }
+
+    #[test]
+    fn test_cleanup_deleted_branch() {
+        let mocked_deps = Unimock::new((
+            choose_port_mock(),
+            CheckPathMock
+                .each_call(matching!((path) if path == &PathBuf::from("/projects/app3")))
+                .returns(true)
+                .n_times(2),
+            ExecMock
+                .each_call(matching!((command) if command.get_program() == "git" && command.get_args().eq(["rev-parse", "--verify", "--quiet", "refs/heads/feature"])))
+                .answers(&|_, _| {
+                    Ok(ExecStatus::Failure {
+                        output: String::new(),
+                        code: 1,
+                    })
+                })
+                .once(),
+        ));
+        let mut registry = get_mocked_registry().unwrap();
+        registry
+            .create(
+                &mocked_deps,
+                "app3-feature",
+                None,
+                Some(PathBuf::from("/projects/app3")),
+                None,
+                BTreeSet::new(),
+                Some(String::from("feature")),
+            )
+            .unwrap();
+
+        let removed = cleanup(&mocked_deps, &mut registry).unwrap();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].name, "app3-feature");
+        assert!(matches!(removed[0].reason, CleanupReason::DeletedBranch));
+    }
+
+    #[test]
+    fn test_cleanup_none() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman cleanup"),
+            CheckPathMock
+                .each_call(matching!((path) if path == &PathBuf::from("/projects/app3")))
+                .returns(true)
+                .once(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Deleted 0 projects\n");
+    }
+
+    fn stale_mocks() -> impl Clause {
+        (
+            current_time_mock(3_000_000),
+            CheckPathMock
+                .each_call(matching!((path) if path == &PathBuf::from("/projects/app3")))
+                .returns(true)
+                .once(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/usage.toml")))
+                .answers(&|_, _| Ok(String::from("[projects]\napp1 = 100\napp2 = 2999000\n")))
+                .at_least_times(1),
+            WatchPathMock
+                .each_call(matching!((path) if path == &PathBuf::from("/projects/app3")))
+                .returns(Some(
+                    std::time::SystemTime::UNIX_EPOCH + Duration::from_secs(2_900_000),
+                ))
+                .once(),
+        )
+    }
+
+    #[test]
+    fn test_cleanup_stale() {
+        let mocked_deps = Unimock::new((
+            stale_mocks(),
+            readwrite_mocks(),
+            args_mock("portman cleanup --stale 30d"),
+            tty_mock(false),
+            write_registry_mock(include_str!("snapshots/cleanup_stale.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Deleted 1 project\napp1 :3001\n");
+    }
+
+    #[test]
+    fn test_cleanup_stale_declined() {
+        let mocked_deps = Unimock::new((
+            stale_mocks(),
+            readonly_mocks(),
+            args_mock("portman cleanup --stale 30d"),
+            tty_mock(true),
+            PromptMock
+                .each_call(matching!("These projects haven't been used in 30d:\n  app1 :3001 (inactive for 34d)\nDelete them? [y/N] "))
+                .answers(&|_, _| Ok(String::from("n")))
+                .once(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Deleted 0 projects\n");
+    }
+
+    #[test]
+    fn test_data_dir_flag() {
+        for args in [
+            "portman --data-dir /sandbox list",
+            "portman list --data-dir /sandbox",
+        ] {
+            let mocked_deps = Unimock::new(args_mock(args));
+
+            let cli = Cli::try_parse_from(mocked_deps.get_args()).unwrap();
+            assert_eq!(cli.data_dir, Some(PathBuf::from("/sandbox")));
+            assert!(matches!(cli.command, Command::List { .. }));
+        }
+    }
+
+    #[test]
+    fn test_data_dir_flag_reads_and_writes() {
+        // DataDirMock is deliberately absent, so consulting the default data directory would fail
+        let mocked_deps = Unimock::new((
+            resolve_host_mock(true),
+            read_var_mock(),
+            exec_mock(),
+            args_mock("portman --data-dir /sandbox create project --no-activate"),
+            choose_port_mock(),
+            exec_git_mock("project"),
+            tty_mock(true),
+            LockFileMock
+                .each_call(matching!((path, _) if path == &PathBuf::from("/sandbox/registry.toml.lock")))
+                .answers(&|_, _, _| Ok(Some(FileLock::default())))
+                .once(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/sandbox/registry.toml")))
+                .answers(&|_, _| Ok(String::from(include_str!("fixtures/registry.toml"))))
+                .once(),
+            ReadFileMock
+                .each_call(matching!((path) if path.starts_with("/sandbox") || path == &PathBuf::from("/homebrew/etc/Caddyfile")))
+                .answers(&|_, _| Err(Error::from(std::io::ErrorKind::NotFound)))
+                .at_least_times(1),
+            WriteFileMock
+                .each_call(matching!((path, contents) if path == &PathBuf::from("/sandbox/registry.toml") && *contents == include_str!("snapshots/create_no_activate.toml")))
+                .answers(&|_, _, _| Ok(()))
+                .once(),
+            WriteFileMock
+                .each_call(matching!((path, _) if path.starts_with("/sandbox") || path == &PathBuf::from("/homebrew/etc/Caddyfile")))
+                .answers(&|_, _, _| Ok(()))
+                .at_least_times(1),
+            current_time_mock(1000),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Created project project :3004\n");
+    }
+
+    #[test]
+    fn test_list() {
+        let mocked_deps =
+            Unimock::new((readonly_mocks(), args_mock("portman list"), tty_mock(false)));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r"app1 :3001
+app2 :3002 -> :3000
+app3 :3003 (/projects/app3)
+"
+        );
+    }
+
+    #[test]
+    fn test_list_sort_directory() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman list --sort directory"),
+            tty_mock(false),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r"app3 :3003 (/projects/app3)
+app1 :3001
+app2 :3002 -> :3000
+"
+        );
+    }
+
+    #[test]
+    fn test_list_filter() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman list --filter app? --no-directory --linked-only"),
+            tty_mock(false),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "app2 :3002 -> :3000\n");
+    }
+
+    #[test]
+    fn test_list_extended() {
+        let mocked_deps = Unimock::new((
+            lock_registry_mock(),
+            args_mock("portman list --extended"),
+            data_dir_mock(),
+            read_registry_mock(Some(
+                "[projects.app1]\nport = 3001\ntags = ['backend', 'work']\ndescription = 'API server'\n\n[projects.app2]\nport = 3002",
+            )),
+            read_var_mock(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/config.toml")))
+                .answers(&|_, _| Err(Error::from(std::io::ErrorKind::NotFound)))
+                .once(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/usage.toml")))
+                .answers(&|_, _| Ok(String::from("[projects]\napp1 = 1000\n")))
+                .once(),
+            current_time_mock(8200),
+            tty_mock(false),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "app1 :3001 #backend #work - API server (used 2h ago)\napp2 :3002\n"
+        );
+    }
+
+    #[test]
+    fn test_list_decorated() {
+        let mocked_deps = Unimock::new((
+            lock_registry_mock(),
+            args_mock("portman list"),
+            data_dir_mock(),
+            read_registry_mock(Some(
+                "[projects.app1]\nport = 3001\nicon = '🚀'\ncolor = 'blue'\n\n[projects.app2]\nport = 3002\nicon = 'https://example.com/icon.png'\n",
+            )),
+            read_file_mock(),
+            read_var_mock(),
+            tty_mock(true),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "🚀 \x1b[34mapp1 :3001\x1b[0m\napp2 :3002\n");
+    }
+
+    #[test]
+    fn test_list_json() {
+        let mocked_deps = Unimock::new((readonly_mocks(), args_mock("portman --json list")));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "{\"projects\":[{\"name\":\"app1\",\"port\":3001,\"directory\":null,\"linked_port\":null,\"display_name\":null,\"icon\":null,\"description\":null,\"upstream\":null,\"bind\":null,\"branch\":null},{\"name\":\"app2\",\"port\":3002,\"directory\":null,\"linked_port\":3000,\"display_name\":null,\"icon\":null,\"description\":null,\"upstream\":null,\"bind\":null,\"branch\":null},{\"name\":\"app3\",\"port\":3003,\"directory\":\"/projects/app3\",\"linked_port\":null,\"display_name\":null,\"icon\":null,\"description\":null,\"upstream\":null,\"bind\":null,\"branch\":null}]}\n"
+        );
+    }
+
+    #[test]
+    fn test_list_status() {
+        let mocked_deps = Unimock::new((
+            lock_registry_mock(),
+            args_mock("portman list --status"),
+            data_dir_mock(),
+            read_registry_mock(None),
+            read_var_mock(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/config.toml")))
+                .answers(&|_, _| Err(Error::from(std::io::ErrorKind::NotFound)))
+                .once(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/health.toml")))
+                .answers(&|_, _| {
+                    Ok(String::from(
+                        "[projects.app1]\nport = 3001\nstatus = 'up'\n\n[projects.app2]\nport = 3002\nstatus = 'down'",
+                    ))
+                })
+                .once(),
+            tty_mock(false),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r"app1 :3001 [up]
+app2 :3002 -> :3000 [down]
+app3 :3003 (/projects/app3) [unknown]
+"
+        );
+    }
+
+    #[test]
+    fn test_health() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman health"),
+            current_time_mock(1000),
+            ProbePortMock
+                .each_call(matching!(_))
+                .answers(&|_, port| port != 3003)
+                .n_times(3),
+            WriteFileMock
+                .each_call(matching!((path, _) if path == &PathBuf::from("/data/health.toml") || path == &PathBuf::from("/data/gallery_www/index.html")))
+                .answers(&|_, _, _| Ok(()))
+                .n_times(2),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r"app1 :3001 [up]
+app2 :3002 -> :3000 [up]
+app3 :3003 (/projects/app3) [down]
+"
+        );
+    }
+
+    #[test]
+    fn test_logs() {
+        let mocked_deps = Unimock::new((
+            lock_registry_mock(),
+            args_mock("portman logs app1 --lines 1"),
+            data_dir_mock(),
+            read_registry_mock(None),
+            read_var_mock(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/config.toml")))
+                .answers(&|_, _| Ok(String::from("access_logs = true\n")))
+                .once(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/logs/app1.log")))
+                .answers(&|_, _| {
+                    Ok(String::from(concat!(
+                        r#"{"ts":1792108800,"request":{"method":"GET","host":"app1.localhost","uri":"/"},"duration":0.002,"status":200}"#,
+                        "\n",
+                        r#"{"ts":1792108801,"request":{"method":"POST","host":"app1.localhost","uri":"/api"},"duration":0.0504,"status":201}"#,
+                        "\n",
+                    )))
+                })
+                .once(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "[2026-10-16 00:00:01] POST app1.localhost/api 201 (50ms)\n"
+        );
+    }
+
+    #[test]
+    fn test_logs_disabled() {
+        let mocked_deps = Unimock::new((readonly_mocks(), args_mock("portman logs app1")));
+
+        let (status, output, _) = run_and_suggest(&mocked_deps);
+        assert!(matches!(status, RunStatus::Failure));
+        assert_eq!(
+            output,
+            "Access logs are disabled\nTry running `portman config edit` and setting `access_logs = true`.\n"
+        );
+    }
+
+    #[test]
+    fn test_status_project() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman status app3"),
+            ProbePortMock
+                .each_call(matching!(3003))
+                .returns(false)
+                .once(),
+            WriteFileMock
+                .each_call(matching!((path, contents) if path == &PathBuf::from("/data/health.toml") && contents.contains("[projects.app3]") || path == &PathBuf::from("/data/gallery_www/index.html")))
+                .answers(&|_, _, _| Ok(()))
+                .n_times(2),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "app3 :3003 (/projects/app3) [down]\n");
+    }
+
+    #[test]
+    fn test_set_health_path_invalid() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman set health-path healthz app1"),
+        ));
+
+        let (status, output, _) = run_and_suggest(&mocked_deps);
+        assert!(matches!(status, RunStatus::Failure));
+        assert_eq!(
+            output,
+            "Health path \"healthz\" is invalid: it must start with /\nTry providing a path that starts with /, like /healthz, or an empty string to only check that the port accepts connections.\n"
+        );
+    }
+
+    #[test]
+    fn test_link() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman link"),
+            cwd_mock("app3"),
+            exec_git_mock("app3"),
+            write_registry_mock(include_str!("snapshots/link.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Linked port 3004 to project app3\n");
+    }
+
+    #[test]
+    fn test_link_remote() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman link --remote upstream"),
+            cwd_mock("app3"),
+            ExecMock
+                .each_call(matching!((command) if command.get_program() == "git" && command.get_args().eq(["remote", "get-url", "upstream"])))
+                .answers(&|_, _| {
+                    Ok(ExecStatus::Success {
+                        output: String::from("git@github.com:user/app3.git\n"),
+                    })
+                })
+                .once(),
+            write_registry_mock(include_str!("snapshots/link.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Linked port 3004 to project app3\n");
+    }
+
+    #[test]
+    fn test_link_no_repo() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman link"),
+            cwd_mock("app3"),
+            exec_git_no_repo_mock(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r#"Git command could not be run:
+
+Command "git remote get-url origin" failed with exit code 1 and output:
+No repo
+
+Try running `portman link` in a directory with a git repo or providing an explicit port.
+"#
+        );
+    }
+
+    #[test]
+    fn test_link_unknown_repo() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman link"),
+            cwd_mock("app3"),
+            exec_git_mock("project"),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r"Repo https://github.com/user/project.git does not exist
+Try providing an explicit port.
+"
+        );
+    }
+
+    #[test]
+    fn test_link_strict_outside_ranges() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman link 8080 app3 --strict"),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r"Strict mode violation: linked port 8080 is outside of the configured port ranges
+Try running the command again without --strict, or set `strict = false` in the config file.
+"
+        );
+    }
+
+    #[test]
+    fn test_link_no_save() {
+        let mocked_deps = Unimock::new(args_mock("portman link --no-save"));
+
+        let err = Cli::try_parse_from(mocked_deps.get_args()).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::MissingRequiredArgument);
+    }
+
+    #[test]
+    fn test_link_port() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman link 3005"),
+            cwd_mock("app3"),
+            exec_git_mock("app3"),
+            write_registry_mock(include_str!("snapshots/link_port.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r"Linked port 3005 to project app3
+Saved default port 3005 for repo https://github.com/user/app3.git
+"
+        );
+    }
+
+    #[test]
+    fn test_link_port_no_repo() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman link 3005"),
+            cwd_mock("app3"),
+            exec_git_no_repo_mock(),
+            write_registry_mock(include_str!("snapshots/link_port_no_repo.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Linked port 3005 to project app3\n");
+    }
+
+    #[test]
+    fn test_link_port_no_save() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman link 3005 --no-save"),
+            cwd_mock("app3"),
+            write_registry_mock(include_str!("snapshots/link_port_no_save.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Linked port 3005 to project app3\n");
+    }
+
+    #[test]
+    fn test_link_port_and_project() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman link 3005 app3"),
+            write_registry_mock(include_str!("snapshots/link_port_and_project.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Linked port 3005 to project app3\n");
+    }
+
+    #[test]
+    fn test_link_nonexistent_port_name() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman link 8000 app2 --port-name api"),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r"Project app2 does not have a port named api
+Try running `portman get app2 --json` to see which ports the project has.
+"
+        );
+    }
+
+    #[test]
+    fn test_link_port_and_project_no_save() {
+        let mocked_deps = Unimock::new(args_mock("portman link 3005 app3 --no-save"));
+
+        let err = Cli::try_parse_from(mocked_deps.get_args()).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+    }
+
+    #[test]
+    fn test_link_range() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman link --range 3000-3002"),
+            choose_port_mock(),
+            tty_mock(true),
+            PromptMock
+                .each_call(matching!((message) if message.starts_with("Link port 3001 to project [app1]?")))
+                .answers(&|_, _| Ok(String::new()))
+                .once(),
+            PromptMock
+                .each_call(matching!((message) if message.starts_with("Link port 3002 to project [app3]?")))
+                .answers(&|_, _| Ok(String::from("app3")))
+                .once(),
+            write_registry_mock(include_str!("snapshots/link_range.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Port 3000 is already linked to project app2\nLinked port 3001 to project app1\nLinked port 3002 to project app3\n"
+        );
+    }
+
+    #[test]
+    fn test_link_range_reprompts() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman link --range 3001-3002"),
+            choose_port_mock(),
+            tty_mock(true),
+            PromptMock
+                .each_call(matching!((message) if message.starts_with("Link port 3001 to project [app1]?")))
+                .answers(&|_, _| Ok(String::from("app4")))
+                .once(),
+            PromptMock
+                .each_call(matching!((message) if message.starts_with("Project app4 does not exist. Link port 3001 to project?")))
+                .answers(&|_, _| Ok(String::from("app1")))
+                .once(),
+            PromptMock
+                .each_call(matching!((message) if message.starts_with("Link port 3002 to project [app3]?")))
+                .answers(&|_, _| Ok(String::from("app3")))
+                .once(),
+            write_registry_mock(include_str!("snapshots/link_range.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Linked port 3001 to project app1\nLinked port 3002 to project app3\n"
+        );
+    }
+
+    #[test]
+    fn test_link_range_end_of_input() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman link --range 3001-3002"),
+            tty_mock(true),
+            PromptMock
+                .each_call(matching!(_))
+                .answers(&|_, _| bail!("Input ended before an answer was provided"))
+                .once(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Input ended before an answer was provided\n");
+    }
+
+    #[test]
+    fn test_link_range_not_tty() {
+        let mocked_deps =
+            Unimock::new((args_mock("portman link --range 3001-3002"), tty_mock(false)));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "portman link --range must be run in an interactive terminal\nTry running the command again in a terminal so that it can ask for answers.\n"
+        );
+    }
+
+    #[test]
+    fn test_propose_link() {
+        let mut registry = get_mocked_registry().unwrap();
+        assert_eq!(propose_link(&registry, 3000).unwrap(), "app1");
+        // app1's port is closest to 3000, but app3 mentions the port in its display name
+        registry
+            .set_display_name("app3", Some(String::from("Legacy 3000")))
+            .unwrap();
+        assert_eq!(propose_link(&registry, 3000).unwrap(), "app3");
+    }
+
+    #[test]
+    fn test_scan() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman scan .."),
+            choose_port_mock(),
+            cwd_mock("app1"),
+            WalkDirMock
+                .each_call(matching!((root, 3) if root == &PathBuf::from("/projects")))
+                .answers(&|_, _, _| {
+                    Ok(vec![
+                        PathBuf::from("/projects/app3/.git"),
+                        PathBuf::from("/projects/blog/package.json"),
+                        PathBuf::from("/projects/api/Cargo.toml"),
+                        PathBuf::from("/projects/notes"),
+                    ])
+                })
+                .once(),
+            PromptMock
+                .each_call(matching!((message) if message.starts_with("Create project [api] for \"/projects/api\"?")))
+                .answers(&|_, _| Ok(String::new()))
+                .once(),
+            PromptMock
+                .each_call(matching!((message) if message.starts_with("Create project [blog] for \"/projects/blog\"?")))
+                .answers(&|_, _| Ok(String::from("-")))
+                .once(),
+            write_registry_mock(include_str!("snapshots/scan.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Created project api for \"/projects/api\"\n\"/projects/app3\" already belongs to project app3\nSkipped \"/projects/blog\"\n"
+        );
+    }
+
+    #[test]
+    fn test_link_range_invalid() {
+        let mocked_deps = Unimock::new(args_mock("portman link --range 3010-3000"));
+
+        let err = Cli::try_parse_from(mocked_deps.get_args()).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ValueValidation);
+    }
+
+    #[test]
+    fn test_handoff() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman handoff app1"),
+            choose_port_mock(),
+            tty_mock(true),
+            write_registry_mock(include_str!("snapshots/handoff.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Moved project app1 from port 3001 to port 3004\nPort 3001 stays linked to the project until you run `portman unlink 3001`\n"
+        );
+    }
+
+    #[test]
+    fn test_handoff_to_non_tty() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman handoff app1 --to 3010"),
+            tty_mock(false),
+            write_file_mock(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "3001\n3010\n");
+    }
+
+    #[test]
+    fn test_unlink() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman unlink 3000"),
+            write_registry_mock(include_str!("snapshots/unlink.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Unlinked port 3000 from project app2\n");
+    }
+
+    #[test]
+    fn test_unlink_not_linked() {
+        let mocked_deps = Unimock::new((readonly_mocks(), args_mock("portman unlink 3005")));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Port 3005 was not linked to a project\n");
+    }
+
+    #[test]
+    fn test_registry_locked() {
+        let mocked_deps = Unimock::new((
+            args_mock("portman list --wait 10"),
+            data_dir_mock(),
+            read_var_mock(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/config.toml")))
+                .answers(&|_, _| Err(Error::from(std::io::ErrorKind::NotFound)))
+                .once(),
+            LockFileMock
+                .each_call(matching!((_, timeout) if timeout == &Duration::from_secs(10)))
+                .answers(&|_, _, _| Ok(None))
+                .once(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r#"The registry is locked by another portman process (lock file "/data/registry.toml.lock")
+Try running the command again after the other portman process finishes, or providing --wait with a longer timeout in seconds.
+"#
+        );
+    }
+
+    fn reassignment_mocks(args: &str) -> impl Clause {
+        (
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/config.toml")))
+                .answers(&|_, _| Ok(String::from("ranges = [[4000, 4999]]\n")))
+                .once(),
+            readwrite_mocks(),
+            args_mock(args),
+            choose_port_mock(),
+            tty_mock(false),
+            WriteFileMock
+                .each_call(matching!((path, _) if path == &PathBuf::from("/data/registry.toml")))
+                .answers(&|_, _, _| Ok(()))
+                .once(),
+        )
+    }
+
+    #[test]
+    fn test_reassignment_warnings() {
+        let mocked_deps = Unimock::new(reassignment_mocks("portman list"));
+
+        let (_, output, warnings) = run_and_suggest(&mocked_deps);
+        assert_eq!(
+            output,
+            "app1 :4000\napp2 :4001 -> :3000\napp3 :4002 (/projects/app3)\n"
+        );
+        assert_eq!(
+            warnings,
+            vec![
+                String::from("app1 moved from 3001 to 4000"),
+                String::from("app2 moved from 3002 to 4001"),
+                String::from("app3 moved from 3003 to 4002"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reassignment_warnings_quiet() {
+        let mocked_deps = Unimock::new(reassignment_mocks("portman list --quiet"));
+
+        let warnings = run_and_suggest(&mocked_deps).2;
+        assert_eq!(warnings, Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_registry_modified_externally() {
+        let mocked_deps = Unimock::new((
+            args_mock("portman list"),
+            data_dir_mock(),
+            lock_registry_mock(),
+            read_file_mock(),
+            read_var_mock(),
+            tty_mock(false),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/registry.toml")))
+                .answers(&|_, _| Ok(String::from(include_str!("fixtures/registry.toml"))))
+                .once(),
+            ReadFileMock
+                .each_call(
+                    matching!((path) if path == &PathBuf::from("/data/registry.toml.checksum")),
+                )
+                .answers(&|_, _| Ok(String::from("0000000000000000\n")))
+                .once(),
+        ));
+
+        let (status, _, warnings) = run_and_suggest(&mocked_deps);
+        assert!(matches!(status, RunStatus::Success));
+        assert_eq!(
+            warnings,
+            vec![String::from("The registry was modified outside of portman. Run `portman registry accept` to accept the changes or `portman registry restore` to restore the last version that portman saved.")]
+        );
+    }
+
+    #[test]
+    fn test_registry_accept() {
+        let mocked_deps = Unimock::new((
+            args_mock("portman registry accept"),
+            data_dir_mock(),
+            lock_registry_mock(),
+            read_file_mock(),
+            read_var_mock(),
+            exec_mock(),
+            write_caddyfile_mock(),
+            write_index_mock(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/registry.toml")))
+                .answers(&|_, _| Ok(String::from(include_str!("fixtures/registry.toml"))))
+                .n_times(2),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/registry.toml.checksum")))
+                .answers(&|_, _| Err(Error::from(std::io::ErrorKind::NotFound)))
+                .once(),
+            WriteFileMock
+                .each_call(matching!((path, contents) if path == &PathBuf::from("/data/registry.toml.1") && contents == &String::from(include_str!("fixtures/registry.toml"))))
+                .answers(&|_, _, _| Ok(()))
+                .once(),
+            WriteFileMock
+                .each_call(matching!((path, _) if path == &PathBuf::from("/data/registry.toml.checksum")))
+                .answers(&|_, _, _| Ok(()))
+                .once(),
+        ));
+
+        let (_, output, warnings) = run_and_suggest(&mocked_deps);
+        assert_eq!(output, "Accepted changes to the registry\n");
+        assert_eq!(warnings, Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_registry_restore() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman registry restore"),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/registry.toml.1")))
+                .answers(&|_, _| Ok(String::from(include_str!("fixtures/registry.toml"))))
+                .once(),
+            write_registry_mock(include_str!("fixtures/registry.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Restored the registry from backup 1\n");
+    }
+
+    #[test]
+    fn test_registry_restore_index() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman registry restore --index 2"),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/registry.toml.2")))
+                .answers(&|_, _| Ok(String::from(include_str!("fixtures/registry.toml"))))
+                .once(),
+            write_registry_mock(include_str!("fixtures/registry.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Restored the registry from backup 2\n");
+    }
+
+    #[test]
+    fn test_registry_restore_no_backup() {
+        let mocked_deps = Unimock::new((
+            lock_registry_mock(),
+            data_dir_mock(),
+            read_file_mock(),
+            read_var_mock(),
+            args_mock("portman registry restore --index 3"),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/registry.toml.3")))
+                .answers(&|_, _| Err(Error::from(std::io::ErrorKind::NotFound)))
+                .once(),
+        ));
+
+        let (status, output, _) = run_and_suggest(&mocked_deps);
+        assert!(matches!(status, RunStatus::Failure));
+        assert_eq!(
+            output,
+            "No registry backup exists at \"/data/registry.toml.3\"\n"
+        );
+    }
+
+    fn exec_lsof_mock(listeners: &'static str) -> impl Clause {
+        (
+            ExecMock
+                .each_call(matching!((command) if command.get_program() == "lsof" && command.get_args().any(|arg| arg == "-iTCP")))
+                .answers_arc(std::sync::Arc::new(move |_, _| {
+                    Ok(ExecStatus::Success {
+                        output: String::from(listeners),
+                    })
+                }))
+                .once(),
+            ExecMock
+                .each_call(matching!((command) if command.get_program() == "lsof" && command.get_args().any(|arg| arg == "cwd")))
+                .answers(&|_, _| {
+                    Ok(ExecStatus::Success {
+                        output: String::from("p100\nfcwd\nn/projects/app4\n"),
+                    })
+                })
+                .once(),
+        )
+    }
+
+    #[test]
+    fn test_adopt() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman adopt --force"),
+            cwd_mock("app4"),
+            exec_lsof_mock("p100\nf20\nn*:8080\n"),
+            write_registry_mock(include_str!("snapshots/adopt.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Adopted project app4 :8080 (/projects/app4)\n");
+    }
+
+    #[test]
+    fn test_adopt_out_of_range() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman adopt"),
+            cwd_mock("app4"),
+            exec_lsof_mock("p100\nf20\nn*:8080\n"),
+        ));
+
+        let (status, output, _) = run_and_suggest(&mocked_deps);
+        assert!(matches!(status, RunStatus::Failure));
+        assert_eq!(
+            output,
+            "Port 8080 is outside of the configured port ranges
+Try a port in the configured ranges, or run the command again with --force to use a port outside of them.
+"
+        );
+    }
+
+    #[test]
+    fn test_adopt_ambiguous() {
+        let mocked_deps = Unimock::new((
+            args_mock("portman adopt"),
+            cwd_mock("app4"),
+            exec_lsof_mock("p100\nf20\nn*:3500\nf21\nn127.0.0.1:24678\n"),
+        ));
+
+        let (status, output, _) = run_and_suggest(&mocked_deps);
+        assert!(matches!(status, RunStatus::Failure));
+        assert_eq!(
+            output,
+            "Multiple ports are being listened on by processes running in this directory: 3500, 24678
+Try running the command again with --port to choose which port to adopt.
+"
+        );
+    }
+
+    #[test]
+    fn test_reserve() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman reserve 5432 --description postgres"),
+            write_registry_mock(include_str!("snapshots/reserve.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Reserved port 5432 postgres\n");
+    }
+
+    #[test]
+    fn test_reserve_in_use() {
+        let mocked_deps = Unimock::new((readonly_mocks(), args_mock("portman reserve 3000")));
+
+        let (status, output, _) = run_and_suggest(&mocked_deps);
+        assert!(matches!(status, RunStatus::Failure));
+        assert_eq!(
+            output,
+            "Port 3000 is already used by project app2\nTry reserving a different port or running `portman delete app2` first.\n"
+        );
+    }
+
+    #[test]
+    fn test_unreserve_nonexistent() {
+        let mocked_deps = Unimock::new((readonly_mocks(), args_mock("portman unreserve 5432")));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Port 5432 is not reserved\nTry running `portman list --reservations` to see which ports are reserved.\n"
+        );
+    }
+
+    #[test]
+    fn test_list_reservations() {
+        let mocked_deps = Unimock::new((
+            lock_registry_mock(),
+            args_mock("portman list --reservations"),
+            data_dir_mock(),
+            read_registry_mock(Some(
+                "projects = {}\nreservations = [{ port = 5432, description = 'postgres' }, { port = 6379 }]",
+            )),
+            read_var_mock(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/config.toml")))
+                .answers(&|_, _| Err(Error::from(std::io::ErrorKind::NotFound)))
+                .once(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "5432 postgres\n6379\n");
+    }
+
+    #[test]
+    fn test_tag_add() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman tag add work app1"),
+            write_registry_mock(include_str!("snapshots/tag_add.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Added tag work to project app1 :3001\n");
+    }
+
+    #[test]
+    fn test_tag_remove_nonexistent() {
+        let mocked_deps =
+            Unimock::new((readonly_mocks(), args_mock("portman tag remove work app1")));
+
+        let (status, output, _) = run_and_suggest(&mocked_deps);
+        assert!(matches!(status, RunStatus::Failure));
+        assert_eq!(
+            output,
+            "Project app1 does not have the tag work\nTry running `portman get app1 --json` to see which tags the project has.\n"
+        );
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(30), "30s");
+        assert_eq!(format_duration(15 * 60), "15m");
+        assert_eq!(format_duration(90 * 60), "1h");
+        assert_eq!(format_duration(2 * 24 * 60 * 60), "2d");
+    }
+
+    #[test]
+    fn test_lease_acquire() {
+        let mocked_deps = Unimock::new((
+            current_time_mock(1000),
+            readwrite_mocks(),
+            args_mock("portman lease acquire --ttl 30m --description tests"),
+            choose_port_mock(),
+            tty_mock(true),
+            write_registry_mock(include_str!("snapshots/lease_acquire.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Leased port 3004 (expires in 30m) tests\n");
+    }
+
+    #[test]
+    fn test_lease_acquire_non_tty() {
+        let mocked_deps = Unimock::new((
+            current_time_mock(1000),
+            readwrite_mocks(),
+            args_mock("portman lease acquire"),
+            choose_port_mock(),
+            tty_mock(false),
+            write_file_mock(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "3004\n");
+    }
+
+    #[test]
+    fn test_lease_acquire_invalid_ttl() {
+        let mocked_deps = Unimock::new(args_mock("portman lease acquire --ttl 0m"));
+
+        let err = Cli::try_parse_from(mocked_deps.get_args()).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ValueValidation);
+    }
+
+    #[test]
+    fn test_lease_release_nonexistent() {
+        let mocked_deps = Unimock::new((readonly_mocks(), args_mock("portman lease release 3005")));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Port 3005 is not leased\nTry running `portman lease list` to see which ports are leased.\n"
+        );
+    }
+
+    #[test]
+    fn test_activate_add() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman activate add ../app3-review app3"),
+            cwd_mock("app3"),
+            write_registry_mock(include_str!("snapshots/activate_add.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Activated project app3 in \"/projects/app3-review\"\n"
+        );
+    }
+
+    #[test]
+    fn test_mv() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman mv app3 ../app3-moved"),
+            cwd_mock("app3"),
+            write_registry_mock(include_str!("snapshots/mv.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Moved project app3 to \"/projects/app3-moved\"\n");
+    }
+
+    #[test]
+    fn test_mv_unchanged() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman mv app3"),
+            cwd_mock("app3"),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Project app3 is already in \"/projects/app3\"\n");
+    }
+
+    #[test]
+    fn test_mv_duplicate() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman mv app1"),
+            cwd_mock("app3"),
+        ));
+
+        let (status, output, _) = run_and_suggest(&mocked_deps);
+        assert!(matches!(status, RunStatus::Failure));
+        assert!(output.starts_with("Project app3 already uses the directory \"/projects/app3\"\n"));
+    }
+
+    #[test]
+    fn test_activate_remove_nonexistent() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman activate remove /projects/app3-review app3"),
+            cwd_mock("app3"),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Project app3 is not activated in the directory \"/projects/app3-review\"\nTry running `portman get app3 --json` to see which directories activate the project.\n"
+        );
+    }
+
+    #[test]
+    fn test_domain_add() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman domain add app1 app1.test"),
+            write_registry_mock(include_str!("snapshots/domain_add.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Added domain app1.test to project app1\n");
+    }
+
+    #[test]
+    fn test_domain_remove_nonexistent() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman domain remove app1 app1.test"),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Project app1 does not have the domain app1.test\nTry running `portman get app1 --json` to see which domains the project has.\n"
+        );
+    }
+
+    #[test]
+    fn test_forward_add() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman forward add app1 5432"),
+            write_registry_mock(include_str!("snapshots/forward_add.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Forwarded tcp port 5432 to project app1 :3001\n");
+    }
+
+    #[test]
+    fn test_forward_remove_nonexistent() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman forward remove app1 5432 --udp"),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Project app1 does not forward port 5432\nTry running `portman get app1 --json` to see which ports the project forwards.\n"
+        );
+    }
+
+    #[test]
+    fn test_repo_delete() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman repo delete https://github.com/user/app3.git"),
+            write_registry_mock(include_str!("snapshots/repo_delete.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Deleted repo https://github.com/user/app3.git: 3004\n"
+        );
+    }
+
+    #[test]
+    fn test_repo_delete_non_existent() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman repo delete https://github.com/user/project.git"),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r"Repo https://github.com/user/project.git does not exist
+Try running `portman repo list` to see which repos exist.
+"
+        );
+    }
+
+    #[test]
+    fn test_repo_delete_list() {
+        let mocked_deps = Unimock::new((readonly_mocks(), args_mock("portman repo list")));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "https://github.com/user/app3.git: 3004\n");
+    }
+
+    #[test]
+    fn test_repo_list_json() {
+        let mocked_deps = Unimock::new((readonly_mocks(), args_mock("portman repo list --json")));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "{\"repos\":[{\"repo\":\"https://github.com/user/app3.git\",\"port\":3004,\"project_port\":null,\"linked_ports\":{},\"description\":null,\"default_project_name\":null}]}\n"
+        );
+    }
+
+    #[test]
+    fn test_repo_set() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman repo set https://github.com/user/app3.git --description App_3"),
+            write_registry_mock(include_str!("snapshots/repo_set.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Updated repo https://github.com/user/app3.git: 3004\n"
+        );
+    }
+
+    #[test]
+    fn test_repo_set_ports() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman repo set https://github.com/user/app3.git --port 3010 --project-port 3500 --name app"),
+            write_registry_mock(include_str!("snapshots/repo_set_ports.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Updated repo https://github.com/user/app3.git: 3010\n"
+        );
+    }
+
+    #[test]
+    fn test_repo_set_linked_ports() {
+        let mocked_deps = Unimock::new((
+            readwrite_mocks(),
+            args_mock("portman repo set https://github.com/user/app3.git --link-port api=8000 --link-port web=3005"),
+            write_registry_mock(include_str!("snapshots/repo_set_linked_ports.toml")),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Updated repo https://github.com/user/app3.git: 3004\n"
+        );
+    }
+
+    #[test]
+    fn test_devcontainer() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman devcontainer"),
+            cwd_mock("app3"),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/projects/app3/.devcontainer/devcontainer.json")))
+                .answers(&|_, _| Ok(String::from("{}")))
+                .once(),
+            WriteFileMock
+                .each_call(matching!((path, _) if path == &PathBuf::from("/projects/app3/.devcontainer/devcontainer.json")))
+                .answers(&|_, _, _| Ok(()))
+                .once(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Updated \"/projects/app3/.devcontainer/devcontainer.json\" to forward port 3003\n"
+        );
+    }
+
+    #[test]
+    fn test_devcontainer_no_directory() {
+        let mocked_deps = Unimock::new((readonly_mocks(), args_mock("portman devcontainer app1")));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r"Project app1 does not have a directory
+Try running `portman create app1 --overwrite` in the project's directory to associate it with that directory.
+"
+        );
+    }
+
+    #[test]
+    fn test_env() {
+        let mocked_deps = Unimock::new((readonly_mocks(), args_mock("portman env app2")));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "PORT=3002\nPORTMAN_PROJECT=app2\nPORTMAN_LINKED_PORT=3000\n"
+        );
+    }
+
+    #[test]
+    fn test_env_file() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman env --file .env.local"),
+            cwd_mock("app3"),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/projects/app3/.env.local")))
+                .answers(&|_, _| Ok(String::from("DEBUG=1\nPORT=3000\n")))
+                .once(),
+            WriteFileMock
+                .each_call(matching!((path, contents) if path == &PathBuf::from("/projects/app3/.env.local") && contents == &String::from("DEBUG=1\nPORT=3003\nPORTMAN_PROJECT=app3\n")))
+                .answers(&|_, _, _| Ok(()))
+                .once(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Updated \"/projects/app3/.env.local\"\n");
+    }
+
+    fn templates_mocks() -> impl Clause {
+        (
+            data_dir_mock(),
+            lock_registry_mock(),
+            read_registry_mock(None),
+            read_var_mock(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/config.toml")))
+                .answers(&|_, _| Ok(String::from("[templates]\n'.env.local' = 'env.template'\n")))
+                .at_least_times(1),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/env.template")))
+                .answers(&|_, _| Ok(String::from("PORT={{port}}\nNAME={{name}}\n")))
+                .once(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/homebrew/etc/Caddyfile") || path == &PathBuf::from("/data/health.toml") || path == &PathBuf::from("/data/usage.toml")))
+                .answers(&|_, _| Err(Error::from(std::io::ErrorKind::NotFound))),
+        )
+    }
+
+    #[test]
+    fn test_template_render() {
+        let mocked_deps = Unimock::new((
+            templates_mocks(),
+            args_mock("portman template render app3"),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/projects/app3/.env.local")))
+                .answers(&|_, _| Err(Error::from(std::io::ErrorKind::NotFound)))
+                .once(),
+            WriteFileMock
+                .each_call(matching!((path, contents) if path == &PathBuf::from("/projects/app3/.env.local") && contents == &String::from("PORT=3003\nNAME=app3\n")))
+                .answers(&|_, _, _| Ok(()))
+                .once(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Rendered \"/projects/app3/.env.local\"\n");
+    }
+
+    #[test]
+    fn test_template_render_unchanged() {
+        let mocked_deps = Unimock::new((
+            templates_mocks(),
+            args_mock("portman template render"),
+            cwd_mock("app3"),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/projects/app3/.env.local")))
+                .answers(&|_, _| Ok(String::from("PORT=3003\nNAME=app3\n")))
+                .once(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "\"/projects/app3/.env.local\" is already up to date\n"
+        );
+    }
+
+    #[test]
+    fn test_template_render_no_templates() {
+        let mocked_deps = Unimock::new((
+            data_dir_mock(),
+            read_file_mock(),
+            read_var_mock(),
+            args_mock("portman template render app3"),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r"No templates are configured
+Try running `portman config edit` and adding templates to the `[templates]` section.
+"
+        );
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_service_install() {
+        let mocked_deps = Unimock::new((
+            args_mock("portman service install"),
+            CurrentExeMock
+                .each_call(matching!())
+                .answers(&|_| Ok(PathBuf::from("/bin/portman")))
+                .once(),
+            EnvironmentMock.stub(|each| {
+                each.call(matching!("HOME"))
+                    .answers(&|_, _| Ok(String::from("/home/user")));
+                each.call(matching!("PATH"))
+                    .answers(&|_, _| Ok(String::from("/usr/bin:/bin")));
+                each.call(matching!("PORTMAN_DATA_DIR"))
+                    .answers(&|_, _| Ok(String::from("/data/100%")));
+                each.call(matching!(_)).answers(&|_, _| bail!("Failed"));
+            }),
+            WriteFileMock
+                .each_call(matching!((path, contents) if path == &PathBuf::from("/home/user/.config/systemd/user/portman.service") && contents.contains("\nEnvironment=\"PORTMAN_DATA_DIR=/data/100%%\"\n")))
+                .answers(&|_, _, _| Ok(()))
+                .once(),
+            ExecMock
+                .each_call(matching!((command) if command.get_program() == "systemctl" && command.get_args().eq(["--user", "daemon-reload"])))
+                .answers(&|_, _| {
+                    Ok(ExecStatus::Success {
+                        output: String::new(),
+                    })
+                })
+                .once(),
+            ExecMock
+                .each_call(matching!((command) if command.get_program() == "systemctl" && command.get_args().eq(["--user", "enable", "portman.service"])))
+                .answers(&|_, _| {
+                    Ok(ExecStatus::Success {
+                        output: String::new(),
+                    })
+                })
+                .once(),
+            ExecMock
+                .each_call(matching!((command) if command.get_program() == "systemctl" && command.get_args().eq(["--user", "restart", "portman.service"])))
+                .answers(&|_, _| {
+                    Ok(ExecStatus::Success {
+                        output: String::new(),
+                    })
+                })
+                .once(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "Installed and started the service at \"/home/user/.config/systemd/user/portman.service\"\n"
+        );
+    }
+
+    #[test]
+    fn test_tmux() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman tmux"),
+            cwd_mock("app3"),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "app3:3003\n");
+    }
+
+    #[test]
+    fn test_tmux_set_environment() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman tmux --set-environment"),
+            cwd_mock("project"),
+            ExecMock
+                .each_call(matching!((command) if command.get_program() == "tmux" && command.get_args().any(|arg| arg == "-u")))
+                .answers(&|_, _| Ok(ExecStatus::Success { output: String::new() }))
+                .n_times(3),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "");
+    }
+
+    #[test]
+    fn test_doctor() {
+        let mocked_deps = Unimock::new((
+            lock_registry_mock(),
+            args_mock("portman doctor"),
+            data_dir_mock(),
+            read_registry_mock(None),
+            read_var_mock(),
+            exec_caddy_version_mock(),
+            CheckWritableMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data")))
+                .answers(&|_, _| Ok(()))
+                .once(),
+            ProbePortMock
+                .each_call(matching!(2019))
+                .answers(&|_, _| true)
+                .once(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/homebrew/etc/Caddyfile")))
+                .answers(&|_, _| Ok(String::from("import \"/data/Caddyfile\"\n")))
+                .once(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/config.toml")))
+                .answers(&|_, _| Err(Error::from(std::io::ErrorKind::NotFound)))
+                .at_least_times(1),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r"[pass] caddy is installed
+[pass] caddy is running
+[pass] the Caddyfile imports the portman Caddyfile
+[pass] the data directory is writable
+[pass] the config is valid
+[pass] the registry can be loaded
+[pass] project ports don't use reserved or system ports
+All checks passed
+"
+        );
+    }
+
+    #[test]
+    fn test_doctor_failures() {
+        let mocked_deps = Unimock::new((
+            lock_registry_mock(),
+            args_mock("portman doctor"),
+            data_dir_mock(),
+            read_registry_mock(None),
+            read_var_mock(),
+            ExecMock
+                .each_call(matching!((command) if command.get_program() == "caddy"))
+                .answers(&|_, _| Err(Error::from(std::io::ErrorKind::NotFound)))
+                .once(),
+            CheckWritableMock
+                .each_call(matching!(_))
+                .answers(&|_, _| Ok(()))
+                .once(),
+            ProbePortMock
+                .each_call(matching!(2019))
+                .answers(&|_, _| false)
+                .once(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/homebrew/etc/Caddyfile")))
+                .answers(&|_, _| Err(Error::from(std::io::ErrorKind::NotFound)))
+                .once(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/config.toml")))
+                .answers(&|_, _| Ok(String::from("reserved = [3000]\n")))
+                .at_least_times(1),
+        ));
+
+        let (status, output, _) = run_and_suggest(&mocked_deps);
+        assert!(matches!(status, RunStatus::Failure));
+        assert_eq!(
+            output,
+            r#"[fail] caddy is installed
+       Command "caddy version" failed to run:
+       entity not found
+       Try running `brew install caddy` or making sure that caddy is in your PATH.
+[fail] caddy is running
+       Caddy is not running
+       Try running `brew services start caddy` to make sure that caddy is running.
+[fail] the Caddyfile imports the portman Caddyfile
+       The Caddyfile at "/homebrew/etc/Caddyfile" does not import the portman Caddyfile
+       Try running `portman reload-caddy` to add the import.
+[pass] the data directory is writable
+[pass] the config is valid
+[pass] the registry can be loaded
+[fail] project ports don't use reserved or system ports
+       Some projects use reserved or system ports:
+         3000 (used by project app2)
+       Try running `portman config edit` to move the `ranges` field above port 1024 or remove the ports from the `reserved` field, or unlink the ports with `portman unlink`.
+4 of 7 checks failed
+"#
+        );
+    }
+
+    #[test]
+    fn test_doctor_invalid_config() {
+        let mocked_deps = Unimock::new((
+            args_mock("portman doctor"),
+            data_dir_mock(),
+            read_var_mock(),
+            exec_caddy_version_mock(),
+            CheckWritableMock
+                .each_call(matching!(_))
+                .answers(&|_, _| Ok(()))
+                .once(),
+            ProbePortMock
+                .each_call(matching!(2019))
+                .answers(&|_, _| true)
+                .once(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/homebrew/etc/Caddyfile")))
+                .answers(&|_, _| Ok(String::from("import \"/data/Caddyfile\"\n")))
+                .once(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/config.toml")))
+                .answers(&|_, _| Ok(String::from("ranges = [\n")))
+                .once(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert!(output.contains("[fail] the config is valid\n"));
+        assert!(output.contains("[skip] the registry can be loaded\n"));
+        assert!(output.contains("[skip] project ports don't use reserved or system ports\n"));
+        assert!(output.ends_with("1 of 7 checks failed\n"));
+    }
+
+    #[test]
+    fn test_version() {
+        let mocked_deps = Unimock::new((
+            args_mock("portman version"),
+            build_info_mock(),
+            data_dir_mock(),
+            exec_caddy_version_mock(),
+            read_var_mock(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r"portman 1.0.0
+build: aarch64-apple-darwin (release)
+platform: macos-aarch64
+data directory: /data
+config file: /data/config.toml
+caddy: v2.7.6
+"
+        );
+    }
+
+    #[test]
+    fn test_version_json_caddy_not_found() {
+        let mocked_deps = Unimock::new((
+            args_mock("portman version --json"),
+            build_info_mock(),
+            data_dir_mock(),
+            ExecMock
+                .each_call(matching!((command) if command.get_program() == "caddy"))
+                .answers(&|_, _| Err(Error::from(std::io::ErrorKind::NotFound)))
+                .once(),
+            read_var_mock(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "{\"version\":\"1.0.0\",\"target\":\"aarch64-apple-darwin\",\"profile\":\"release\",\"static_binary\":false,\"platform\":\"macos-aarch64\",\"data_dir\":\"/data\",\"config_path\":\"/data/config.toml\",\"caddy_version\":null}\n"
+        );
+    }
+
+    #[test]
+    fn test_run() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman run npm run dev --host"),
+            record_usage_mock(),
+            cwd_mock("app3"),
+            SpawnMock
+                .each_call(matching!((command) if command.get_program() == "npm" && command.get_args().eq(["run", "dev", "--host"]) && command.get_envs().any(|(name, value)| name == "PORT" && value == Some(std::ffi::OsStr::new("3003"))) && command.get_envs().any(|(name, value)| name == "PORTMAN_PROJECT" && value == Some(std::ffi::OsStr::new("app3")))))
+                .answers(&|_, _| Ok(0))
+                .once(),
+        ));
+
+        let (status, output, _) = run_and_suggest(&mocked_deps);
+        assert!(matches!(status, RunStatus::Success));
+        assert_eq!(output, "");
+    }
+
+    #[test]
+    fn test_run_exit_code() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman run npm test"),
+            cwd_mock("app3"),
+            record_usage_mock(),
+            SpawnMock
+                .each_call(matching!(_))
+                .answers(&|_, _| Ok(3))
+                .once(),
+        ));
+
+        let (status, output, _) = run_and_suggest(&mocked_deps);
+        assert!(matches!(status, RunStatus::Exit(3)));
+        assert_eq!(output, "");
+    }
+
+    #[test]
+    fn test_run_no_active_project() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman run npm test"),
+            cwd_mock("project"),
+        ));
+
+        let (status, output, _) = run_and_suggest(&mocked_deps);
+        assert!(matches!(status, RunStatus::Failure));
+        assert!(output.starts_with("The current directory does not contain a project\n"));
+    }
+
+    #[test]
+    fn test_exec() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman exec app3 -- npm run dev"),
+            SpawnMock
+                .each_call(matching!((command) if command.get_program() == "npm" && command.get_args().eq(["run", "dev"]) && command.get_current_dir() == Some(std::path::Path::new("/projects/app3")) && command.get_envs().any(|(name, value)| name == "PORT" && value == Some(std::ffi::OsStr::new("3003"))) && command.get_envs().any(|(name, value)| name == "PORTMAN_PROJECT" && value == Some(std::ffi::OsStr::new("app3")))))
+                .answers(&|_, _| Ok(0))
+                .once(),
+        ));
+
+        let (status, output, _) = run_and_suggest(&mocked_deps);
+        assert!(matches!(status, RunStatus::Success));
+        assert_eq!(output, "");
+    }
+
+    #[test]
+    fn test_exec_nonexistent() {
+        let mocked_deps = Unimock::new((readonly_mocks(), args_mock("portman exec app4 -- ls")));
+
+        let (status, output, _) = run_and_suggest(&mocked_deps);
+        assert!(matches!(status, RunStatus::Failure));
+        assert!(output.starts_with("Project app4 does not exist\n"));
+    }
+
+    #[test]
+    fn test_exec_all() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman exec-all -- git pull"),
+            ExecMock
+                .each_call(matching!((command) if command.get_program() == "git" && command.get_current_dir() == Some(std::path::Path::new("/projects/app3")) && command.get_envs().any(|(name, value)| name == "PORT" && value == Some(std::ffi::OsStr::new("3003")))))
+                .answers(&|_, _| {
+                    Ok(ExecStatus::Success {
+                        output: String::from("Already up to date."),
+                    })
+                })
+                .once(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "==> app3 (/projects/app3)\nAlready up to date.\n");
+    }
+
+    #[test]
+    fn test_exec_all_tag() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman exec-all --tag work -- git pull"),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "");
+    }
+
+    #[test]
+    fn test_exec_all_failed() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman exec-all -- git pull"),
+            ExecMock
+                .each_call(matching!((command) if command.get_program() == "git"))
+                .answers(&|_, _| {
+                    Ok(ExecStatus::Failure {
+                        output: String::from("Not a git repo\n"),
+                        code: 128,
+                    })
+                })
+                .once(),
+        ));
+
+        let (status, output, _) = run_and_suggest(&mocked_deps);
+        assert!(matches!(status, RunStatus::Failure));
+        assert_eq!(
+            output,
+            r#"==> app3 (/projects/app3)
+Command "git pull" failed with exit code 128 and output:
+Not a git repo
+Command failed in 1 of 1 projects: app3
+"#
+        );
+    }
+
+    #[test]
+    fn test_complete_linked_ports() {
+        let mocked_deps =
+            Unimock::new((readonly_mocks(), args_mock("portman complete linked-ports")));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "3000\n");
+    }
+
+    #[test]
+    fn test_complete_link_ports() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman complete link-ports"),
+            exec_git_mock("app3"),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "3004\n3005\n3006\n3007\n3008\n");
+    }
+
+    #[test]
+    fn test_complete_projects() {
+        let mocked_deps = Unimock::new((readonly_mocks(), args_mock("portman complete projects")));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "app1\napp2\napp3\n");
+    }
+
+    #[test]
+    fn test_complete_repos() {
+        let mocked_deps = Unimock::new((readonly_mocks(), args_mock("portman complete repos")));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "https://github.com/user/app3.git\n");
+    }
+
+    #[test]
+    fn test_verify() {
+        let mocked_deps = Unimock::new((readonly_mocks(), args_mock("portman verify")));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "All project ports are in their tag port ranges\n");
+    }
+
+    #[test]
+    fn test_verify_misplaced() {
+        let mocked_deps = Unimock::new((
+            lock_registry_mock(),
+            args_mock("portman verify"),
+            data_dir_mock(),
+            read_registry_mock(None),
+            read_var_mock(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/config.toml")))
+                .answers(&|_, _| Ok(String::from("[tag_ranges]\nwork = [3002, 3099]\n")))
+                .once(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r"Some project ports are not in their tag port ranges:
+app2 :3002 -> :3000 is in a port range reserved for a tag
+app3 :3003 (/projects/app3) is in a port range reserved for a tag
+Try running `portman config edit` to adjust the tag port ranges or recreating the projects with `portman delete` and `portman create`.
+"
+        );
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let mocked_deps = Unimock::new((
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/Caddyfile")))
+                .answers(&|_, _| Ok(String::from(include_str!("snapshots/Caddyfile"))))
+                .once(),
+            readonly_mocks(),
+            args_mock("portman conflicts"),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "No port conflicts found\n");
+    }
+
+    #[test]
+    fn test_conflicts_found() {
+        let mocked_deps = Unimock::new((
+            lock_registry_mock(),
+            args_mock("portman conflicts"),
+            data_dir_mock(),
+            read_registry_mock(None),
+            read_var_mock(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/config.toml")))
+                .answers(&|_, _| Ok(String::from("reserved = [3000]\n")))
+                .at_least_times(1),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/Caddyfile")))
+                .answers(&|_, _| Err(Error::from(std::io::ErrorKind::NotFound)))
+                .once(),
+            ProbePortMock
+                .each_call(matching!(3000))
+                .answers(&|_, _| true)
+                .once(),
+        ));
+
+        let (status, output, _) = run_and_suggest(&mocked_deps);
+        assert!(matches!(status, RunStatus::Failure));
+        assert_eq!(
+            output,
+            r"PROJECT  PORT  ROLE         CONFLICT                            SUGGESTION
+app2     3000  linked port  reserved in the config              Run `portman unlink 3000` and link the project to a different port
+app2     3000  linked port  another process is listening on it  Stop the other process or run `portman unlink 3000` and link the project to a different port
+Port conflicts found: 2
+"
+        );
+    }
+
+    #[test]
+    fn test_conflicts_json() {
+        let mocked_deps = Unimock::new((
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/Caddyfile")))
+                .answers(&|_, _| Ok(String::new()))
+                .once(),
+            ProbePortMock
+                .each_call(matching!(3000))
+                .answers(&|_, _| true)
+                .once(),
+            readonly_mocks(),
+            args_mock("portman conflicts --json"),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            "[{\"project\":\"app2\",\"port\":3000,\"role\":\"linked port\",\"conflict\":\"another process is listening on it\",\"suggestion\":\"Stop the other process or run `portman unlink 3000` and link the project to a different port\"}]\n"
+        );
+    }
+
+    #[test]
+    fn test_caddyfile() {
+        let mocked_deps = Unimock::new((readonly_mocks(), args_mock("portman caddyfile")));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, include_str!("snapshots/Caddyfile"));
+    }
+
+    #[test]
+    fn test_reload_caddy() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman reload-caddy"),
+            exec_mock(),
+            write_file_mock(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/Caddyfile")))
+                .answers(&|_, _| Ok(String::from(include_str!("snapshots/Caddyfile"))))
+                .once(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(output, "Successfully reloaded caddy\n");
+    }
+
+    #[test]
+    fn test_reload_caddy_port_conflict() {
+        let mocked_deps = Unimock::new((
+            readonly_mocks(),
+            args_mock("portman reload-caddy"),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/Caddyfile")))
+                .answers(&|_, _| Err(Error::from(std::io::ErrorKind::NotFound)))
+                .once(),
+            ProbePortMock
+                .each_call(matching!(3000))
+                .answers(&|_, _| true)
+                .once(),
+        ));
+
+        let output = run_and_suggest(&mocked_deps).1;
+        assert_eq!(
+            output,
+            r"Error reloading caddy:
+Caddy cannot listen on linked ports that other processes are already using:
+  3000 (linked to project app2)
+Try stopping the processes using those ports or running `portman unlink 3000`.
+"
+        );
+    }
+}
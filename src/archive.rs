@@ -0,0 +1,113 @@
+use crate::dependencies::{DataDir, ReadFile, WriteFile};
+use crate::error::{ApplicationError, Result};
+use crate::registry::Project;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+// Projects that have been moved out of the registry so that they don't clutter the list and gallery
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(test, derive(Debug))]
+pub struct Archive {
+    #[serde(default)]
+    projects: BTreeMap<String, Project>,
+}
+
+impl Archive {
+    // Return the path to the archive file
+    fn path(deps: &impl DataDir) -> Result<PathBuf> {
+        Ok(deps.get_data_dir()?.join("archive.toml"))
+    }
+
+    // Load the archive from the data directory
+    // A missing archive is treated as empty
+    pub fn load(deps: &(impl DataDir + ReadFile)) -> Result<Self> {
+        let path = Self::path(deps)?;
+        Ok(deps
+            .read_file(&path)
+            .context("Failed to load archive")?
+            .map(|archive_str| {
+                toml::from_str(&archive_str).with_context(|| {
+                    format!("Failed to deserialize archive at \"{}\"", path.display())
+                })
+            })
+            .transpose()?
+            .unwrap_or_default())
+    }
+
+    // Save the archive to the data directory
+    pub fn save(&self, deps: &(impl DataDir + WriteFile)) -> Result<()> {
+        let archive_str = toml::to_string(self).context("Failed to serialize archive")?;
+        deps.write_file(&Self::path(deps)?, &archive_str)
+            .context("Failed to save archive")?;
+        Ok(())
+    }
+
+    // Add a project to the archive
+    pub fn insert(&mut self, name: String, project: Project) {
+        self.projects.insert(name, project);
+    }
+
+    // Remove a project from the archive and return it
+    pub fn remove(&mut self, name: &str) -> Result<Project> {
+        self.projects
+            .remove(name)
+            .ok_or_else(|| ApplicationError::NonExistentArchivedProject(name.to_owned()))
+    }
+
+    // Iterate over all archived projects with their names
+    pub fn iter_projects(&self) -> impl Iterator<Item = (&String, &Project)> {
+        self.projects.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dependencies::{ReadFileMock, WriteFileMock};
+    use crate::mocks::data_dir_mock;
+    use unimock::{matching, MockFn, Unimock};
+
+    #[test]
+    fn test_load_missing() {
+        let mocked_deps = Unimock::new((
+            data_dir_mock(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/archive.toml")))
+                .answers(&|_, _| Err(std::io::Error::from(std::io::ErrorKind::NotFound)))
+                .once(),
+        ));
+        let archive = Archive::load(&mocked_deps).unwrap();
+        assert_eq!(archive.iter_projects().count(), 0);
+    }
+
+    #[test]
+    fn test_save() {
+        let mocked_deps = Unimock::new((
+            data_dir_mock(),
+            WriteFileMock
+                .each_call(matching!((path, contents) if path == &PathBuf::from("/data/archive.toml") && contents == &String::from("[projects.app1]\nport = 3001\n")))
+                .answers(&|_, _, _| Ok(()))
+                .once(),
+        ));
+        let mut archive = Archive::default();
+        archive.insert(
+            String::from("app1"),
+            Project {
+                port: 3001,
+                ..Default::default()
+            },
+        );
+        archive.save(&mocked_deps).unwrap();
+    }
+
+    #[test]
+    fn test_remove_nonexistent() {
+        let mut archive = Archive::default();
+        assert!(matches!(
+            archive.remove("app1"),
+            Err(ApplicationError::NonExistentArchivedProject(_))
+        ));
+    }
+}
@@ -0,0 +1,111 @@
+use crate::cli::Cli;
+use crate::error::{ApplicationError, Result};
+use anyhow::anyhow;
+use clap::Parser;
+
+// Split a command line into words, respecting single quotes, double quotes, and backslash escapes
+fn split_words(line: &str) -> std::result::Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote: Option<char> = None;
+    let mut chars = line.chars();
+    while let Some(char) = chars.next() {
+        match (quote, char) {
+            (Some(open), char) if char == open => quote = None,
+            (None | Some('"'), '\\') => {
+                let escaped = chars
+                    .next()
+                    .ok_or_else(|| String::from("line must not end with a backslash"))?;
+                word.get_or_insert_with(String::new).push(escaped);
+            }
+            (None, '\'' | '"') => {
+                quote = Some(char);
+                word.get_or_insert_with(String::new);
+            }
+            (None, char) if char.is_whitespace() => words.extend(word.take()),
+            (_, char) => word.get_or_insert_with(String::new).push(char),
+        }
+    }
+    if let Some(open) = quote {
+        return Err(format!("unterminated {open} quote"));
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+// Parse each non-empty, non-comment line of a batch into a command, along with its line number
+// Every line is parsed before any of them are run so that syntax errors don't leave the batch
+// partially applied
+pub fn parse(input: &str) -> Result<Vec<(usize, Cli)>> {
+    input
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line_number, line)| {
+            split_words(line)
+                .and_then(|words| {
+                    Cli::try_parse_from(std::iter::once(String::from("portman")).chain(words))
+                        .map_err(|err| err.to_string())
+                })
+                .map(|cli| (line_number, cli))
+                .map_err(|err| {
+                    ApplicationError::BatchCommand(
+                        line_number,
+                        Box::new(ApplicationError::Other(anyhow!(err))),
+                    )
+                })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Command;
+
+    #[test]
+    fn test_split_words() {
+        assert_eq!(
+            split_words(r#"create api  --description "Public API" --env 'A=b c' x\ y"#).unwrap(),
+            vec![
+                "create",
+                "api",
+                "--description",
+                "Public API",
+                "--env",
+                "A=b c",
+                "x y"
+            ]
+        );
+        assert_eq!(split_words("set icon ''").unwrap(), vec!["set", "icon", ""]);
+    }
+
+    #[test]
+    fn test_split_words_unterminated() {
+        assert!(split_words("create 'api").is_err());
+        assert!(split_words("create api\\").is_err());
+    }
+
+    #[test]
+    fn test_parse() {
+        let commands =
+            parse("# Provision the API\n\ncreate api --no-activate\nunlink 3000\n").unwrap();
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0].0, 3);
+        assert!(matches!(commands[0].1.command, Command::Create { .. }));
+        assert_eq!(commands[1].0, 4);
+        assert!(matches!(
+            commands[1].1.command,
+            Command::Unlink { port: 3000 }
+        ));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(matches!(
+            parse("create api --no-activate\nfrobnicate\n"),
+            Err(ApplicationError::BatchCommand(2, _))
+        ));
+    }
+}
@@ -1,27 +1,198 @@
-use crate::dependencies::{DataDir, Environment, Exec, ReadFile, WriteFile};
-use crate::error::{CaddyError, CaddyResult};
-use crate::registry::Registry;
+use crate::config::{BindAddress, TlsMode};
+use crate::dependencies::{
+    CurrentTime, DataDir, Environment, Exec, ProbePort, ReadFile, WriteFile,
+};
+use crate::error::{CaddyError, CaddyResult, ExecError};
+use crate::health::HealthCache;
+use crate::logs;
+use crate::mdns;
+use crate::registry::{
+    checksum, is_icon_url, ForwardProtocol, PortForward, Project, Registry, UpstreamProtocol,
+};
+use crate::timings;
+use crate::usage::UsageLog;
 use anyhow::Result;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashSet};
 use std::fmt::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+// The port that caddy's admin API listens on
+const ADMIN_PORT: u16 = 2019;
+
+// The Caddyfile that the systemd service from Linux distributions' caddy packages loads
+const SYSTEMD_CADDYFILE: &str = "/etc/caddy/Caddyfile";
+
+// How caddy was installed, which determines how it should be installed and started
+#[derive(Clone, Copy, Default)]
+#[cfg_attr(test, derive(Debug, Eq, PartialEq))]
+pub enum CaddyService {
+    #[default]
+    Homebrew,
+    Systemd,
+    Windows,
+}
+
+// Guess how caddy was installed on this machine
+pub fn service(deps: &impl Environment) -> CaddyService {
+    if deps.read_var("HOMEBREW_PREFIX").is_ok() {
+        CaddyService::Homebrew
+    } else if cfg!(windows) {
+        CaddyService::Windows
+    } else {
+        CaddyService::Systemd
+    }
+}
 
 // Return the path the portman Caddyfile import
-fn import_path(deps: &impl DataDir) -> Result<PathBuf> {
+pub fn import_path(deps: &impl DataDir) -> Result<PathBuf> {
     Ok(deps.get_data_dir()?.join("Caddyfile"))
 }
 
+// Return the path to the root Caddyfile that caddy loads, which is the configured Caddyfile, the
+// Homebrew Caddyfile, or the systemd service's Caddyfile if it exists, in that order
+fn root_caddyfile_path(
+    deps: &(impl Environment + ReadFile),
+    caddyfile: Option<&Path>,
+) -> CaddyResult<PathBuf> {
+    if let Some(caddyfile) = caddyfile {
+        return Ok(caddyfile.to_path_buf());
+    }
+    if let Ok(prefix) = deps.read_var("HOMEBREW_PREFIX") {
+        return Ok(PathBuf::from(prefix).join("etc").join("Caddyfile"));
+    }
+    let systemd_caddyfile = PathBuf::from(SYSTEMD_CADDYFILE);
+    if deps.read_file(&systemd_caddyfile)?.is_some() {
+        return Ok(systemd_caddyfile);
+    }
+    Err(CaddyError::UnknownCaddyfile)
+}
+
+// Return the command that makes caddy load the root Caddyfile
+// caddy's systemd service is reloaded through systemctl while it is running so that systemd
+// stays in charge of the caddy process
+fn reload_command(deps: &impl Exec, caddyfile_path: &Path) -> std::process::Command {
+    let systemd_active = caddyfile_path == Path::new(SYSTEMD_CADDYFILE)
+        && deps
+            .exec(std::process::Command::new("systemctl").args(["is-active", "--quiet", "caddy"]))
+            .is_ok();
+    if systemd_active {
+        let mut command = std::process::Command::new("systemctl");
+        command.args(["reload", "caddy"]);
+        command
+    } else {
+        let mut command = std::process::Command::new("caddy");
+        command
+            .args(["reload", "--adapter", "caddyfile", "--config"])
+            .arg(caddyfile_path);
+        command
+    }
+}
+
+// Return the path to the checksum of the Caddyfile that caddy was last reloaded with
+fn checksum_path(deps: &impl DataDir) -> Result<PathBuf> {
+    Ok(deps.get_data_dir()?.join("Caddyfile.checksum"))
+}
+
 // Return the path the gallery www directory
-fn gallery_www_path(deps: &impl DataDir) -> Result<PathBuf> {
+pub fn gallery_www_path(deps: &impl DataDir) -> Result<PathBuf> {
     Ok(deps.get_data_dir()?.join("gallery_www"))
 }
 
+// Escape a string for use in an HTML document
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// The template for the gallery page, whose {{placeholders}} are filled in with the projects
+const GALLERY_TEMPLATE: &str = include_str!("gallery.html");
+
+// Return the tag filter buttons for every tag that a project in the registry has
+fn generate_tag_filters(registry: &Registry) -> String {
+    let tags = registry
+        .iter_projects()
+        .flat_map(|(_, project)| project.tags.iter())
+        .collect::<BTreeSet<_>>();
+    if tags.is_empty() {
+        return String::new();
+    }
+    let buttons = tags.into_iter().fold(String::new(), |mut output, tag| {
+        let tag = escape_html(tag);
+        let _ = write!(
+            output,
+            "\n        <button class=\"tag\" type=\"button\" data-tag=\"{tag}\" aria-pressed=\"false\">{tag}</button>"
+        );
+        output
+    });
+    format!("\n      <div class=\"tags\">{buttons}\n      </div>")
+}
+
 // Return the generated gallery
-fn generate_gallery_index(registry: &Registry) -> String {
+fn generate_gallery_index(registry: &Registry, health: &HealthCache, usage: &UsageLog) -> String {
     let project_count = registry.iter_projects().count();
+    let scheme = match registry.tls_mode() {
+        TlsMode::Internal => "https",
+        TlsMode::Off => "http",
+    };
     let projects = registry
         .iter_projects()
         .fold(String::new(), |mut output, (name, project)| {
             let port = project.port;
+            let icon = project
+                .icon
+                .as_ref()
+                .map(|icon| {
+                    if is_icon_url(icon) {
+                        format!("<img class=\"icon\" src=\"{}\" alt=\"\" /> ", escape_html(icon))
+                    } else {
+                        format!("{} ", escape_html(icon))
+                    }
+                })
+                .unwrap_or_default();
+            // The color accents the project's card
+            let style = project
+                .color
+                .map(|color| format!(" style=\"--accent: {}\"", color.css()))
+                .unwrap_or_default();
+            let project_hostname = registry.hostname(name);
+            let (title, hostname) = project.display_name.as_ref().map_or_else(
+                || (name.clone(), String::new()),
+                |display_name| {
+                    (
+                        escape_html(display_name),
+                        format!("\n          <p class=\"monospace\">{project_hostname}</p>"),
+                    )
+                },
+            );
+            let description = project
+                .description
+                .as_ref()
+                .map(|description| format!("\n          <p>{}</p>", escape_html(description)))
+                .unwrap_or_default();
+            let tag_list = project
+                .tags
+                .iter()
+                .map(|tag| escape_html(tag))
+                .collect::<Vec<_>>();
+            let tags = if tag_list.is_empty() {
+                String::new()
+            } else {
+                format!(
+                    "\n          <p>Tags: <strong>{}</strong></p>",
+                    tag_list.join(", ")
+                )
+            };
+            let data_tags = tag_list.join(",");
+            let linked_port = project
+                .linked_port
+                .map(|linked_port| {
+                    format!("\n          <p>Linked port: <strong>{linked_port}</strong></p>")
+                })
+                .unwrap_or_default();
             let directory = project
                 .directory
                 .as_ref()
@@ -32,107 +203,306 @@ fn generate_gallery_index(registry: &Registry) -> String {
                     )
                 })
                 .unwrap_or_default();
+            let status = health
+                .get(name, project)
+                .map(|status| {
+                    format!("\n          <p>Status: <strong class=\"status-{status}\">{status}</strong></p>")
+                })
+                .unwrap_or_default();
+            // A project that was found up counts as used even if it wasn't activated
+            let last_used = usage.last_used(name).max(health.last_up(name));
+            let data_last_used = last_used
+                .map(|timestamp| timestamp.to_string())
+                .unwrap_or_default();
+            let last_used = last_used
+                .map(|timestamp| {
+                    format!("\n          <p>Last used: <strong><time data-timestamp=\"{timestamp}\"></time></strong></p>")
+                })
+                .unwrap_or_default();
             let _ = write!(
                 output,
                 r#"
-        <a class="project" href="https://{name}.localhost">
-          <h2>{name}</h2>
-          <p>Port: <strong>{port}</strong></p>{directory}
+        <a class="project" href="{scheme}://{project_hostname}" data-name="{name}" data-port="{port}" data-tags="{data_tags}" data-last-used="{data_last_used}"{style}>
+          <h2>{icon}{title}</h2>{hostname}{description}{tags}
+          <p>Port: <strong>{port}</strong></p>{linked_port}{status}{last_used}{directory}
         </a>"#,
             );
             output
         });
+    GALLERY_TEMPLATE
+        .replace("{{project_count}}", &project_count.to_string())
+        .replace("{{tag_filters}}", &generate_tag_filters(registry))
+        .replace("{{projects}}", &projects)
+}
+
+// Return the bind directive that restricts the addresses caddy listens on for a site
+fn generate_bind(bind: &BindAddress) -> String {
+    match bind {
+        BindAddress::Loopback => String::from("\tbind 127.0.0.1 [::1]\n"),
+        BindAddress::All => String::new(),
+        BindAddress::Address(address) => format!("\tbind {address}\n"),
+    }
+}
+
+// Return the reverse_proxy directive that forwards requests to a project's local port or remote
+// upstream
+fn generate_reverse_proxy(project: &Project, streaming: bool) -> String {
+    let mut subdirectives = String::new();
+    let target = project.upstream.as_ref().map_or_else(
+        || format!("localhost:{}", project.port),
+        |upstream| {
+            subdirectives += "\n\t\theader_up Host {upstream_hostport}";
+            for (name, value) in &upstream.headers {
+                let _ = write!(
+                    subdirectives,
+                    "\n\t\theader_up {name} \"{}\"",
+                    value.replace('\\', "\\\\").replace('"', "\\\"")
+                );
+            }
+            upstream.url.clone()
+        },
+    );
+    let mut transport = String::new();
+    if project.upstream_protocol == UpstreamProtocol::H2c {
+        transport += "\n\t\t\tversions h2c 2";
+    }
+    if streaming {
+        // Stream responses immediately and keep websockets open across caddy reloads so that hot
+        // module replacement, server-sent events, and long polling connections aren't dropped
+        subdirectives += "\n\t\tflush_interval -1\n\t\tstream_close_delay 1h";
+        transport += "\n\t\t\tdial_timeout 30s\n\t\t\tresponse_header_timeout 0";
+    }
+    if !transport.is_empty() {
+        let _ = write!(subdirectives, "\n\t\ttransport http {{{transport}\n\t\t}}");
+    }
+    if subdirectives.is_empty() {
+        format!("\treverse_proxy {target}\n")
+    } else {
+        format!("\treverse_proxy {target} {{{subdirectives}\n\t}}\n")
+    }
+}
+
+// Escape a string for use in the HTML body of a Caddyfile respond directive, which is delimited by
+// backticks and would otherwise replace {placeholders}
+fn escape_respond_html(value: &str) -> String {
+    escape_html(value)
+        .replace('{', "&#123;")
+        .replace('}', "&#125;")
+        .replace('`', "&#96;")
+}
+
+// Return the handle_errors directive that serves a fallback page titled with the project's display
+// name and icon when caddy can't reach the project, or an empty string if the project has neither
+fn generate_fallback(name: &str, project: &Project) -> String {
+    if project.display_name.is_none() && project.icon.is_none() {
+        return String::new();
+    }
+    let title = escape_respond_html(project.display_name.as_deref().unwrap_or(name));
+    let (favicon, heading_icon) = project.icon.as_deref().map_or_else(
+        || (String::new(), String::new()),
+        |icon| {
+            let icon = escape_respond_html(icon);
+            if is_icon_url(&icon) {
+                (
+                    format!("<link rel=\"icon\" href=\"{icon}\" />"),
+                    format!("<img src=\"{icon}\" alt=\"\" height=\"32\" /> "),
+                )
+            } else {
+                // Render emoji favicons as SVG text
+                (
+                    format!("<link rel=\"icon\" href=\"data:image/svg+xml,<svg xmlns='http://www.w3.org/2000/svg' viewBox='0 0 100 100'><text y='.9em' font-size='90'>{icon}</text></svg>\" />"),
+                    format!("{icon} "),
+                )
+            }
+        },
+    );
+    let page = format!(
+        "<!DOCTYPE html><html lang=\"en\"><head><meta charset=\"utf-8\" /><title>{title}</title>{favicon}</head><body style=\"font-family: system-ui, sans-serif; text-align: center; padding: 4em 1em\"><h1>{heading_icon}{title}</h1><p>{title} isn't responding on port {}. Start its server and reload this page.</p></body></html>",
+        project.port
+    );
     format!(
-        r#"<!DOCTYPE html>
-<html lang="en">
-  <head>
-    <style>
-      .container {{
-        font-family: Arial, Helvetica, sans-serif;
-      }}
-
-      .container h1 {{
-        text-align: center;
-        margin-bottom: 1em;
-      }}
-
-      .gallery {{
-        flex-wrap: wrap;
-        justify-content: center;
-        gap: 2em;
-        margin: auto 3em;
-        display: flex;
-      }}
-
-      .project {{
-        width: 22em;
-        color: #222;
-        background-color: #eee;
-        border-radius: 1.5em;
-        padding: 1em 2em;
-        text-decoration: none;
-        overflow: scroll;
-      }}
-
-      .project:hover {{
-        background-color: #ddd;
-      }}
-
-      .project:active {{
-        background-color: #ccc;
-      }}
-
-      .project h1 {{
-        text-align: center;
-        border-bottom: 1px solid #444;
-        padding-bottom: 0.5em;
-      }}
-
-      .monospace {{
-        font-family: Courier New, Courier, monospace;
-        font-size: 0.8em;
-      }}
-    </style>
-    <meta charset="utf-8" />
-    <title>portman Localhost Projects</title>
-  </head>
-  <body>
-    <div class="container">
-      <h1>portman projects ({project_count})</h1>
-      <div class="gallery">{projects}
-      </div>
-    </div>
-  </body>
-</html>
-"#,
+        "\thandle_errors {{\n\t\theader Content-Type \"text/html; charset=utf-8\"\n\t\trespond `{page}` {{err.status_code}}\n\t}}\n"
     )
 }
 
+// Return the addresses that caddy's layer4 app listens on for a forwarded port
+fn generate_forward_addresses(bind: &BindAddress, forward: &PortForward) -> String {
+    let network = match forward.protocol {
+        ForwardProtocol::Tcp => "",
+        ForwardProtocol::Udp => "udp/",
+    };
+    let port = forward.listen_port;
+    match bind {
+        BindAddress::Loopback => format!("{network}127.0.0.1:{port} {network}[::1]:{port}"),
+        BindAddress::All => format!("{network}:{port}"),
+        BindAddress::Address(address) if address.contains(':') && !address.starts_with('[') => {
+            format!("{network}[{address}]:{port}")
+        }
+        BindAddress::Address(address) => format!("{network}{address}:{port}"),
+    }
+}
+
+// Return the global options block that configures the layer4 app from the caddy-l4 plugin to
+// forward raw TCP and UDP ports to projects, or an empty string if no ports are forwarded so that
+// caddy doesn't need the plugin
+fn generate_forwards(registry: &Registry) -> String {
+    let servers = registry
+        .iter_projects()
+        .flat_map(|(_, project)| {
+            project.forwards.iter().filter_map(move |forward| {
+                let target = project.forward_target(forward)?;
+                let network = match forward.protocol {
+                    ForwardProtocol::Tcp => "",
+                    ForwardProtocol::Udp => "udp/",
+                };
+                Some(format!(
+                    "\t\t{} {{\n\t\t\troute {{\n\t\t\t\tproxy {network}localhost:{target}\n\t\t\t}}\n\t\t}}\n",
+                    generate_forward_addresses(registry.bind_for(project), forward)
+                ))
+            })
+        })
+        .collect::<String>();
+    if servers.is_empty() {
+        return String::new();
+    }
+    format!("{{\n\tlayer4 {{\n{servers}\t}}\n}}\n\n")
+}
+
+// Return a project's extra Caddyfile directives indented to fit inside of a site block
+fn generate_extra(project: &Project) -> String {
+    project
+        .caddy_extra
+        .as_deref()
+        .unwrap_or_default()
+        .lines()
+        .map(|line| {
+            if line.trim().is_empty() {
+                String::from("\n")
+            } else {
+                format!("\t{line}\n")
+            }
+        })
+        .collect()
+}
+
+// Return the log directive that writes the requests to a project's sites to its access log
+fn generate_log(log_dir: &Path, name: &str) -> String {
+    format!(
+        "\tlog {{\n\t\toutput file \"{}\" {{\n\t\t\troll_size 10MiB\n\t\t\troll_keep 1\n\t\t}}\n\t}}\n",
+        logs::log_file(log_dir, name).display()
+    )
+}
+
+// Return the address of a site that caddy serves at a hostname along with the tls directive that it
+// needs, if any
+fn generate_site(hostname: &str, tls_mode: TlsMode) -> (String, &'static str) {
+    match tls_mode {
+        // Caddy automatically uses its internal certificate authority for .localhost hostnames, but
+        // it would try to obtain publicly-trusted certificates for other hostnames
+        TlsMode::Internal if hostname == "localhost" || hostname.ends_with(".localhost") => {
+            (hostname.to_owned(), "")
+        }
+        TlsMode::Internal => (hostname.to_owned(), "\ttls internal\n"),
+        TlsMode::Off => (format!("http://{hostname}"), ""),
+    }
+}
+
 // Return the Caddyfile as a string
 pub fn generate_caddyfile(deps: &impl DataDir, registry: &Registry) -> Result<String> {
+    let tls_mode = registry.tls_mode();
+    let log_dir = if registry.access_logs() {
+        Some(logs::log_dir(deps)?)
+    } else {
+        None
+    };
     let projects = registry
         .iter_projects()
         .fold(String::new(), |mut output, (name, project)| {
-            let _ = write!(
-                output,
-                "\n{name}.localhost {{\n\treverse_proxy localhost:{}\n}}\n",
-                project.port
+            let bind = generate_bind(registry.bind_for(project));
+            let streaming = registry.streams(project);
+            let log = log_dir
+                .as_ref()
+                .map(|log_dir| generate_log(log_dir, name))
+                .unwrap_or_default();
+            let directives = format!(
+                "{bind}{}{}{}{log}",
+                generate_extra(project),
+                generate_reverse_proxy(project, streaming),
+                generate_fallback(name, project)
             );
+            let (site, tls) = generate_site(&registry.hostname(name), tls_mode);
+            let _ = write!(output, "\n{site} {{\n{tls}{directives}}}\n");
+            if project.wildcard {
+                // Caddy prefers exact hostnames, so named ports still take precedence
+                let (site, tls) =
+                    generate_site(&format!("*.{}", registry.hostname(name)), tls_mode);
+                let _ = write!(output, "\n{site} {{\n{tls}{directives}}}\n");
+            }
+            for domain in &project.domains {
+                let (site, tls) = generate_site(domain, tls_mode);
+                let _ = write!(output, "\n{site} {{\n{tls}{directives}}}\n");
+            }
+            for (port_name, port) in &project.ports {
+                // Named ports are always served locally, even if the project has an upstream
+                let named_project = Project {
+                    port: *port,
+                    upstream: None,
+                    ..project.clone()
+                };
+                let (site, tls) = generate_site(
+                    &format!("{port_name}.{}", registry.hostname(name)),
+                    tls_mode,
+                );
+                let named_directives = format!(
+                    "{bind}{}{log}",
+                    generate_reverse_proxy(&named_project, streaming)
+                );
+                let _ = write!(output, "\n{site} {{\n{tls}{named_directives}}}\n");
+                if let Some(linked_port) = project.linked_ports.get(port_name) {
+                    let _ = write!(
+                        output,
+                        "\nhttp://localhost:{linked_port} {{\n{named_directives}}}\n"
+                    );
+                }
+            }
             if let Some(linked_port) = project.linked_port {
                 let _ = write!(
                     output,
-                    "\nhttp://localhost:{linked_port} {{\n\treverse_proxy localhost:{}\n}}\n",
-                    project.port
+                    "\nhttp://localhost:{linked_port} {{\n{directives}}}\n"
+                );
+            }
+            if registry.mdns() {
+                // Other devices don't trust caddy's local certificate authority
+                let _ = write!(
+                    output,
+                    "\nhttp://{} {{\n{directives}}}\n",
+                    mdns::hostname(name)
                 );
             }
             output
         });
+    let (gallery_site, gallery_tls) = generate_site(registry.gallery_hostname(), tls_mode);
     Ok(format!(
-        "localhost {{\n\tfile_server {{\n\t\troot \"{}\"\n\t}}\n}}\n{projects}",
+        "{}{gallery_site} {{\n{gallery_tls}{}\trewrite /portman/api/projects /projects.json\n\tfile_server {{\n\t\troot \"{}\"\n\t}}\n}}\n{projects}",
+        generate_forwards(registry),
+        generate_bind(registry.default_bind()),
         gallery_www_path(deps)?.display()
     ))
 }
 
+// Make sure that caddy accepts a generated Caddyfile by validating a copy of it in the data directory
+fn validate(deps: &(impl DataDir + Exec + WriteFile), caddyfile: &str) -> CaddyResult<()> {
+    let validate_path = deps.get_data_dir()?.join("Caddyfile.validate");
+    deps.write_file(&validate_path, caddyfile)?;
+    deps.exec(
+        std::process::Command::new("caddy")
+            .args(["validate", "--adapter", "caddyfile", "--config"])
+            .arg(&validate_path),
+    )
+    .map_err(CaddyError::InvalidCaddyfile)?;
+    Ok(())
+}
+
 // Ensure that the root caddyfile contains the import to the portman caddyfile
 // The inner option will be None if no updates are necessary
 fn update_import(
@@ -148,48 +518,364 @@ fn update_import(
     })
 }
 
+// Return the metrics about the registry in the Prometheus text format
+fn generate_metrics(registry: &Registry, last_reload: u64) -> String {
+    let projects = registry.iter_projects().collect::<Vec<_>>();
+    let allocated_ports = projects
+        .iter()
+        .map(|(_, project)| 1 + project.ports.len())
+        .sum::<usize>();
+    let linked_ports = projects
+        .iter()
+        .map(|(_, project)| project.iter_linked_ports().count())
+        .sum::<usize>();
+    let mut output = String::new();
+    for (metric, help, value) in [
+        (
+            "portman_projects",
+            "The number of projects in the registry",
+            projects.len().to_string(),
+        ),
+        (
+            "portman_allocated_ports",
+            "The number of ports allocated to projects, including named ports",
+            allocated_ports.to_string(),
+        ),
+        (
+            "portman_linked_ports",
+            "The number of ports linked to projects",
+            linked_ports.to_string(),
+        ),
+        (
+            "portman_last_reload_timestamp_seconds",
+            "When portman last reloaded caddy, in seconds since the Unix epoch",
+            last_reload.to_string(),
+        ),
+    ] {
+        let _ = write!(
+            output,
+            "# HELP {metric} {help}\n# TYPE {metric} gauge\n{metric} {value}\n"
+        );
+    }
+    output += "# HELP portman_project_port The port allocated to each project\n# TYPE portman_project_port gauge\n";
+    for (name, project) in &projects {
+        let _ = writeln!(
+            output,
+            "portman_project_port{{project=\"{name}\"}} {}",
+            project.port
+        );
+    }
+    output += "# HELP portman_project_linked_port The port linked to each linked project\n# TYPE portman_project_linked_port gauge\n";
+    for (name, project) in &projects {
+        if let Some(linked_port) = project.linked_port {
+            let _ = writeln!(
+                output,
+                "portman_project_linked_port{{project=\"{name}\"}} {linked_port}"
+            );
+        }
+    }
+    output
+}
+
+// Regenerate the metrics file that the gallery serves
+fn write_metrics(
+    deps: &(impl CurrentTime + DataDir + WriteFile),
+    registry: &Registry,
+) -> Result<()> {
+    deps.write_file(
+        &gallery_www_path(deps)?.join("metrics"),
+        &generate_metrics(registry, deps.get_current_time()),
+    )
+}
+
+// A project in the state file that the gallery serves to other local tools
+#[derive(Serialize)]
+struct ApiProject<'project> {
+    name: &'project str,
+    url: String,
+    #[serde(flatten)]
+    project: &'project Project,
+}
+
+// Return the JSON state file that lists every project in the registry
+fn generate_projects_json(registry: &Registry) -> Result<String> {
+    let projects = registry
+        .iter_projects()
+        .map(|(name, project)| ApiProject {
+            name,
+            url: registry.url(name),
+            project,
+        })
+        .collect::<Vec<_>>();
+    Ok(serde_json::to_string(
+        &serde_json::json!({ "projects": projects }),
+    )?)
+}
+
+// Regenerate the JSON state file that the gallery serves at /portman/api/projects
+fn write_projects_json(deps: &(impl DataDir + WriteFile), registry: &Registry) -> Result<()> {
+    deps.write_file(
+        &gallery_www_path(deps)?.join("projects.json"),
+        &generate_projects_json(registry)?,
+    )
+}
+
+// Regenerate the gallery file with the provided port registry and project statuses
+pub fn write_gallery(
+    deps: &(impl DataDir + ReadFile + WriteFile),
+    registry: &Registry,
+    health: &HealthCache,
+) -> Result<()> {
+    let usage = UsageLog::load(deps)?;
+    let gallery_index_path = gallery_www_path(deps)?.join(PathBuf::from("index.html"));
+    deps.write_file(
+        &gallery_index_path,
+        generate_gallery_index(registry, health, &usage).as_str(),
+    )
+}
+
+// Return the linked ports that caddy is already listening on according to the last Caddyfile that
+// portman generated
+fn served_linked_ports(caddyfile: &str) -> HashSet<u16> {
+    caddyfile
+        .lines()
+        .filter_map(|line| {
+            line.strip_prefix("http://localhost:")?
+                .strip_suffix(" {")?
+                .parse()
+                .ok()
+        })
+        .collect()
+}
+
+// Make sure that no other process is using a linked port that caddy needs to listen on, because
+// caddy fails to reload with an opaque error if it can't listen on a port
+pub fn check_port_conflicts(
+    deps: &(impl DataDir + ProbePort + ReadFile),
+    registry: &Registry,
+) -> CaddyResult<()> {
+    // Caddy itself is listening on the linked ports that it is already serving
+    let served_ports = deps
+        .read_file(&import_path(deps)?)?
+        .map(|caddyfile| served_linked_ports(&caddyfile))
+        .unwrap_or_default();
+    let conflicts = registry
+        .iter_projects()
+        .flat_map(|(name, project)| {
+            project
+                .iter_linked_ports()
+                .filter(|port| !served_ports.contains(port) && deps.probe_port(*port))
+                .map(|port| (name.clone(), port))
+        })
+        .collect::<Vec<_>>();
+    if conflicts.is_empty() {
+        Ok(())
+    } else {
+        Err(CaddyError::PortConflicts(conflicts))
+    }
+}
+
 // Reload the caddy service with the provided port registry
 pub fn reload(
-    deps: &(impl DataDir + Environment + Exec + ReadFile + WriteFile),
+    deps: &(impl CurrentTime + DataDir + Environment + Exec + ReadFile + WriteFile),
+    registry: &Registry,
+) -> CaddyResult<()> {
+    // Dry runs preview the Caddyfile changes with diff instead
+    if registry.is_dry_run() {
+        return Ok(());
+    }
+
+    let caddyfile = prepare(deps, registry)?;
+    reload_prepared(deps, registry, &caddyfile, true)
+}
+
+// Generate the Caddyfile for the registry and make sure that caddy accepts it
+// Preparing the Caddyfile separately from reloading caddy lets callers refuse to save changes that
+// caddy would reject without generating or validating the Caddyfile a second time
+pub fn prepare(
+    deps: &(impl DataDir + Exec + WriteFile),
     registry: &Registry,
+) -> CaddyResult<String> {
+    let timer = timings::start("generate Caddyfile");
+    let caddyfile = generate_caddyfile(deps, registry)?;
+    drop(timer);
+    // Extra directives could make the Caddyfile invalid and forwarded ports require a caddy plugin,
+    // so make sure that caddy accepts it before replacing the working Caddyfile
+    if registry
+        .iter_projects()
+        .any(|(_, project)| project.caddy_extra.is_some() || !project.forwards.is_empty())
+    {
+        let _timer = timings::start("validate Caddyfile");
+        validate(deps, &caddyfile)?;
+    }
+    Ok(caddyfile)
+}
+
+// Write a Caddyfile from prepare and reload the caddy service with it
+// Unless force is true, caddy isn't reloaded if it was already reloaded with the same Caddyfile
+pub fn reload_prepared(
+    deps: &(impl CurrentTime + DataDir + Environment + Exec + ReadFile + WriteFile),
+    registry: &Registry,
+    caddyfile: &str,
+    force: bool,
 ) -> CaddyResult<()> {
-    // Determine the caddyfile path
-    let import_path = import_path(deps)?;
-    deps.write_file(&import_path, &generate_caddyfile(deps, registry)?)?;
+    deps.write_file(&import_path(deps)?, caddyfile)?;
 
     // Read the existing caddyfile so that we can update it as necessary
-    let caddyfile_path = PathBuf::from(deps.read_var("HOMEBREW_PREFIX")?)
-        .join("etc")
-        .join("Caddyfile");
+    let caddyfile_path = root_caddyfile_path(deps, registry.caddyfile())?;
     let existing_caddyfile = deps.read_file(&caddyfile_path)?;
-    if let Some(caddyfile_contents) = update_import(deps, existing_caddyfile)? {
-        deps.write_file(&caddyfile_path, &caddyfile_contents)?;
-    }
+    let updated_import = match update_import(deps, existing_caddyfile)? {
+        Some(caddyfile_contents) => {
+            deps.write_file(&caddyfile_path, &caddyfile_contents)?;
+            true
+        }
+        None => false,
+    };
 
-    // Update the gallery file
-    let gallery_index_path = gallery_www_path(deps)?.join(PathBuf::from("index.html"));
-    deps.write_file(
-        &gallery_index_path,
-        generate_gallery_index(registry).as_str(),
-    )?;
+    // Update the gallery and projects files, which can change even when the Caddyfile doesn't
+    write_gallery(deps, registry, &HealthCache::load(deps)?)?;
+    write_projects_json(deps, registry)?;
+
+    // Registry changes that don't affect the Caddyfile, like descriptions, don't need a reload
+    let checksum_path = checksum_path(deps)?;
+    let caddyfile_checksum = checksum(caddyfile);
+    if !force
+        && !updated_import
+        && deps
+            .read_file(&checksum_path)?
+            .is_some_and(|existing| existing.trim() == caddyfile_checksum)
+    {
+        return Ok(());
+    }
 
     // Reload the caddy config using the new Caddyfile
-    deps.exec(
-        std::process::Command::new("caddy")
-            .args(["reload", "--adapter", "caddyfile", "--config"])
-            .arg(caddyfile_path),
+    write_metrics(deps, registry)?;
+    let timer = timings::start("reload caddy");
+    deps.exec(&mut reload_command(deps, &caddyfile_path))
+        .map_err(CaddyError::Exec)?;
+    drop(timer);
+    deps.write_file(&checksum_path, &caddyfile_checksum)?;
+
+    Ok(())
+}
+
+// Return a diff of the lines that differ between two strings, prefixing removed lines with "-" and
+// added lines with "+"
+fn diff_lines(old: &str, new: &str) -> String {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+    // lengths[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+    let mut lengths = vec![vec![0_usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut output = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || lengths[i][j + 1] >= lengths[i + 1][j]) {
+            let _ = writeln!(output, "+{}", new[j]);
+            j += 1;
+        } else {
+            let _ = writeln!(output, "-{}", old[i]);
+            i += 1;
+        }
+    }
+    output
+}
+
+// Return the changes that reloading caddy would make to the portman Caddyfile as a line diff
+pub fn diff(deps: &(impl DataDir + ReadFile), registry: &Registry) -> CaddyResult<String> {
+    let existing_caddyfile = deps.read_file(&import_path(deps)?)?.unwrap_or_default();
+    Ok(diff_lines(
+        &existing_caddyfile,
+        &generate_caddyfile(deps, registry)?,
+    ))
+}
+
+// Reload the caddy service if the Caddyfile generated from the registry differs from the last one
+// that was written, and return true if caddy was reloaded
+pub fn sync(
+    deps: &(impl CurrentTime + DataDir + Environment + Exec + ReadFile + WriteFile),
+    registry: &Registry,
+) -> CaddyResult<bool> {
+    if !registry.auto_reload() {
+        return Ok(false);
+    }
+    let existing_caddyfile = deps.read_file(&import_path(deps)?)?;
+    if existing_caddyfile.as_ref() == Some(&generate_caddyfile(deps, registry)?) {
+        return Ok(false);
+    }
+    reload(deps, registry)?;
+    Ok(true)
+}
+
+// Return the version of the installed caddy binary, or None if caddy could not be run
+pub fn version(deps: &impl Exec) -> Option<String> {
+    deps.exec(std::process::Command::new("caddy").arg("version"))
+        .ok()
+        .and_then(|output| output.split_whitespace().next().map(String::from))
+}
+
+// Make sure that the caddy binary can be run
+pub fn check_installed(deps: &impl Exec) -> CaddyResult<()> {
+    deps.exec(std::process::Command::new("caddy").arg("version"))?;
+    Ok(())
+}
+
+// Determine whether an error happened because the caddy binary isn't installed
+pub fn is_not_installed(err: &CaddyError) -> bool {
+    matches!(
+        err,
+        CaddyError::Exec(ExecError::IO { io_err, .. })
+            | CaddyError::InvalidCaddyfile(ExecError::IO { io_err, .. })
+            if io_err.kind() == std::io::ErrorKind::NotFound
     )
-    .map_err(CaddyError::Exec)?;
+}
+
+// Make sure that caddy is running by checking whether its admin API is listening
+pub fn check_running(deps: &impl ProbePort) -> CaddyResult<()> {
+    if deps.probe_port(ADMIN_PORT) {
+        Ok(())
+    } else {
+        Err(CaddyError::NotRunning)
+    }
+}
 
+// Make sure that the root Caddyfile imports the portman Caddyfile
+pub fn check_import(
+    deps: &(impl DataDir + Environment + ReadFile),
+    caddyfile: Option<&Path>,
+) -> CaddyResult<()> {
+    let caddyfile_path = root_caddyfile_path(deps, caddyfile)?;
+    let existing_caddyfile = deps.read_file(&caddyfile_path)?;
+    if update_import(deps, existing_caddyfile)?.is_some() {
+        return Err(CaddyError::MissingImport(caddyfile_path));
+    }
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use unimock::Unimock;
+    use unimock::{matching, Clause, MockFn, Unimock};
 
     use super::*;
-    use crate::mocks::{data_dir_mock, get_mocked_registry};
+    use crate::dependencies::{EnvironmentMock, ExecMock, ExecStatus, ReadFileMock, WriteFileMock};
+    use crate::mocks::{
+        choose_port_mock, data_dir_mock, exec_mock, get_mocked_registry, read_health_mock,
+        read_usage_mock, read_var_mock, write_file_mock,
+    };
+    use crate::registry::{ProjectColor, Upstream};
+    use std::collections::BTreeSet;
 
     #[test]
     fn test_caddyfile() {
@@ -201,6 +887,379 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_caddyfile_upstream() {
+        let mut registry = get_mocked_registry().unwrap();
+        registry
+            .set_upstream(
+                "app1",
+                Some(Upstream {
+                    url: String::from("https://staging.example.com"),
+                    headers: std::iter::once((String::from("X-Token"), String::from("a\"b")))
+                        .collect(),
+                }),
+            )
+            .unwrap();
+        let deps = Unimock::new(data_dir_mock());
+        assert!(generate_caddyfile(&deps, &registry).unwrap().contains(
+            "\napp1.localhost {\n\tbind 127.0.0.1 [::1]\n\treverse_proxy https://staging.example.com {\n\t\theader_up Host {upstream_hostport}\n\t\theader_up X-Token \"a\\\"b\"\n\t}\n}\n"
+        ));
+    }
+
+    #[test]
+    fn test_caddyfile_fallback() {
+        let mut registry = get_mocked_registry().unwrap();
+        registry
+            .set_display_name("app1", Some(String::from("App {1}")))
+            .unwrap();
+        registry.set_icon("app1", Some(String::from("🚀"))).unwrap();
+        registry
+            .set_icon("app2", Some(String::from("https://example.com/icon.png")))
+            .unwrap();
+        let deps = Unimock::new(data_dir_mock());
+        let caddyfile = generate_caddyfile(&deps, &registry).unwrap();
+        assert!(caddyfile.contains("\napp1.localhost {\n\tbind 127.0.0.1 [::1]\n\treverse_proxy localhost:3001\n\thandle_errors {\n\t\theader Content-Type \"text/html; charset=utf-8\"\n\t\trespond `<!DOCTYPE html><html lang=\"en\"><head><meta charset=\"utf-8\" /><title>App &#123;1&#125;</title><link rel=\"icon\" href=\"data:image/svg+xml,<svg xmlns='http://www.w3.org/2000/svg' viewBox='0 0 100 100'><text y='.9em' font-size='90'>🚀</text></svg>\" /></head><body style=\"font-family: system-ui, sans-serif; text-align: center; padding: 4em 1em\"><h1>🚀 App &#123;1&#125;</h1><p>App &#123;1&#125; isn't responding on port 3001. Start its server and reload this page.</p></body></html>` {err.status_code}\n\t}\n}\n"));
+        assert!(caddyfile.contains(
+            "<title>app2</title><link rel=\"icon\" href=\"https://example.com/icon.png\" />"
+        ));
+        assert!(caddyfile.contains(
+            "\napp3.localhost {\n\tbind 127.0.0.1 [::1]\n\treverse_proxy localhost:3003\n}\n"
+        ));
+    }
+
+    #[test]
+    fn test_caddyfile_h2c() {
+        let mut registry = get_mocked_registry().unwrap();
+        registry
+            .set_upstream_protocol("app1", UpstreamProtocol::H2c)
+            .unwrap();
+        let deps = Unimock::new(data_dir_mock());
+        assert!(generate_caddyfile(&deps, &registry).unwrap().contains(
+            "\napp1.localhost {\n\tbind 127.0.0.1 [::1]\n\treverse_proxy localhost:3001 {\n\t\ttransport http {\n\t\t\tversions h2c 2\n\t\t}\n\t}\n}\n"
+        ));
+    }
+
+    #[test]
+    fn test_caddyfile_hmr() {
+        let mut registry = get_mocked_registry().unwrap();
+        registry.set_hmr("app1", true).unwrap();
+        registry
+            .set_upstream_protocol("app1", UpstreamProtocol::H2c)
+            .unwrap();
+        let deps = Unimock::new(data_dir_mock());
+        assert!(generate_caddyfile(&deps, &registry).unwrap().contains(
+            "\treverse_proxy localhost:3001 {\n\t\tflush_interval -1\n\t\tstream_close_delay 1h\n\t\ttransport http {\n\t\t\tversions h2c 2\n\t\t\tdial_timeout 30s\n\t\t\tresponse_header_timeout 0\n\t\t}\n\t}\n"
+        ));
+    }
+
+    #[test]
+    fn test_caddyfile_extra() {
+        let mut registry = get_mocked_registry().unwrap();
+        registry
+            .add_domain("app2", String::from("app2.test"))
+            .unwrap();
+        registry
+            .set_caddy_extra(
+                "app2",
+                Some(String::from("header X-Frame-Options DENY\n\nencode gzip\n")),
+            )
+            .unwrap();
+        let deps = Unimock::new(data_dir_mock());
+        let caddyfile = generate_caddyfile(&deps, &registry).unwrap();
+        let extra = "\tbind 127.0.0.1 [::1]\n\theader X-Frame-Options DENY\n\n\tencode gzip\n\treverse_proxy localhost:3002\n";
+        assert!(caddyfile.contains(&format!("\napp2.localhost {{\n{extra}}}\n")));
+        assert!(caddyfile.contains(&format!("\napp2.test {{\n\ttls internal\n{extra}}}\n")));
+        assert!(caddyfile.contains(&format!("\nhttp://localhost:3000 {{\n{extra}}}\n")));
+    }
+
+    #[test]
+    fn test_caddyfile_forwards() {
+        let mut registry = get_mocked_registry().unwrap();
+        registry
+            .add_forward(
+                "app1",
+                PortForward {
+                    listen_port: 5432,
+                    port_name: None,
+                    protocol: ForwardProtocol::Tcp,
+                },
+            )
+            .unwrap();
+        registry
+            .add_forward(
+                "app3",
+                PortForward {
+                    listen_port: 5353,
+                    port_name: None,
+                    protocol: ForwardProtocol::Udp,
+                },
+            )
+            .unwrap();
+        let deps = Unimock::new(data_dir_mock());
+        let caddyfile = generate_caddyfile(&deps, &registry).unwrap();
+        assert!(caddyfile.starts_with("{\n\tlayer4 {\n\t\t127.0.0.1:5432 [::1]:5432 {\n\t\t\troute {\n\t\t\t\tproxy localhost:3001\n\t\t\t}\n\t\t}\n\t\tudp/127.0.0.1:5353 udp/[::1]:5353 {\n\t\t\troute {\n\t\t\t\tproxy udp/localhost:3003\n\t\t\t}\n\t\t}\n\t}\n}\n\nlocalhost {\n"));
+    }
+
+    #[test]
+    fn test_generate_forward_addresses() {
+        let forward = PortForward {
+            listen_port: 5432,
+            port_name: None,
+            protocol: ForwardProtocol::Tcp,
+        };
+        assert_eq!(
+            generate_forward_addresses(&BindAddress::All, &forward),
+            ":5432"
+        );
+        assert_eq!(
+            generate_forward_addresses(&BindAddress::Address(String::from("::1")), &forward),
+            "[::1]:5432"
+        );
+        assert_eq!(
+            generate_forward_addresses(
+                &BindAddress::Address(String::from("192.168.1.10")),
+                &PortForward {
+                    protocol: ForwardProtocol::Udp,
+                    ..forward
+                }
+            ),
+            "udp/192.168.1.10:5432"
+        );
+    }
+
+    #[test]
+    fn test_caddyfile_mdns() {
+        let registry = get_mocked_registry()
+            .unwrap()
+            .with_mdns(true)
+            .with_bind(BindAddress::All);
+        let deps = Unimock::new(data_dir_mock());
+        let caddyfile = generate_caddyfile(&deps, &registry).unwrap();
+        assert!(caddyfile.contains("\napp1.localhost {\n\treverse_proxy localhost:3001\n}\n"));
+        assert!(caddyfile.contains("\nhttp://app1.local {\n\treverse_proxy localhost:3001\n}\n"));
+        assert!(caddyfile.contains("\nhttp://app2.local {\n\treverse_proxy localhost:3002\n}\n"));
+    }
+
+    #[test]
+    fn test_caddyfile_streaming() {
+        let registry = get_mocked_registry().unwrap().with_streaming(true);
+        let deps = Unimock::new(data_dir_mock());
+        let caddyfile = generate_caddyfile(&deps, &registry).unwrap();
+        assert!(caddyfile.contains(
+            "\treverse_proxy localhost:3001 {\n\t\tflush_interval -1\n\t\tstream_close_delay 1h\n\t\ttransport http {\n\t\t\tdial_timeout 30s\n\t\t\tresponse_header_timeout 0\n\t\t}\n\t}\n"
+        ));
+        assert!(!caddyfile.contains("\treverse_proxy localhost:3002\n"));
+    }
+
+    #[test]
+    fn test_caddyfile_access_logs() {
+        let registry = get_mocked_registry().unwrap().with_access_logs(true);
+        let deps = Unimock::new(data_dir_mock());
+        let caddyfile = generate_caddyfile(&deps, &registry).unwrap();
+        let log = "\tlog {\n\t\toutput file \"/data/logs/app2.log\" {\n\t\t\troll_size 10MiB\n\t\t\troll_keep 1\n\t\t}\n\t}\n";
+        assert!(caddyfile.contains(&format!(
+            "\napp2.localhost {{\n\tbind 127.0.0.1 [::1]\n\treverse_proxy localhost:3002\n{log}}}\n"
+        )));
+        assert!(caddyfile.contains(&format!(
+            "\nhttp://localhost:3000 {{\n\tbind 127.0.0.1 [::1]\n\treverse_proxy localhost:3002\n{log}}}\n"
+        )));
+    }
+
+    #[test]
+    fn test_caddyfile_domains() {
+        let mut registry = get_mocked_registry().unwrap();
+        registry
+            .add_domain("app1", String::from("app1.dev.localhost"))
+            .unwrap();
+        registry
+            .add_domain("app1", String::from("api.myapp.test"))
+            .unwrap();
+        let deps = Unimock::new(data_dir_mock());
+        let caddyfile = generate_caddyfile(&deps, &registry).unwrap();
+        assert!(caddyfile.contains(
+            "\napi.myapp.test {\n\ttls internal\n\tbind 127.0.0.1 [::1]\n\treverse_proxy localhost:3001\n}\n"
+        ));
+        assert!(caddyfile.contains(
+            "\napp1.dev.localhost {\n\tbind 127.0.0.1 [::1]\n\treverse_proxy localhost:3001\n}\n"
+        ));
+    }
+
+    #[test]
+    fn test_caddyfile_named_ports() {
+        let mut registry = get_mocked_registry().unwrap();
+        registry
+            .add_named_ports(
+                &Unimock::new(choose_port_mock()),
+                "app1",
+                vec![String::from("api"), String::from("web")],
+            )
+            .unwrap();
+        let deps = Unimock::new(data_dir_mock());
+        assert!(generate_caddyfile(&deps, &registry).unwrap().contains(
+            "\napp1.localhost {\n\tbind 127.0.0.1 [::1]\n\treverse_proxy localhost:3001\n}\n\napi.app1.localhost {\n\tbind 127.0.0.1 [::1]\n\treverse_proxy localhost:3004\n}\n\nweb.app1.localhost {\n\tbind 127.0.0.1 [::1]\n\treverse_proxy localhost:3005\n}\n"
+        ));
+    }
+
+    #[test]
+    fn test_caddyfile_named_linked_port() {
+        let mut registry = get_mocked_registry().unwrap();
+        let deps = Unimock::new(choose_port_mock());
+        registry
+            .add_named_ports(&deps, "app1", vec![String::from("api")])
+            .unwrap();
+        registry.link(&deps, "app1", Some("api"), 8000).unwrap();
+        let deps = Unimock::new(data_dir_mock());
+        assert!(generate_caddyfile(&deps, &registry).unwrap().contains(
+            "\napi.app1.localhost {\n\tbind 127.0.0.1 [::1]\n\treverse_proxy localhost:3004\n}\n\nhttp://localhost:8000 {\n\tbind 127.0.0.1 [::1]\n\treverse_proxy localhost:3004\n}\n"
+        ));
+    }
+
+    #[test]
+    fn test_served_linked_ports() {
+        assert_eq!(
+            served_linked_ports(include_str!("snapshots/Caddyfile")),
+            HashSet::from([3000])
+        );
+    }
+
+    #[test]
+    fn test_caddyfile_gallery_hostname() {
+        let registry = get_mocked_registry()
+            .unwrap()
+            .with_gallery_hostname(String::from("portman.localhost"));
+        let deps = Unimock::new(data_dir_mock());
+        assert!(generate_caddyfile(&deps, &registry)
+            .unwrap()
+            .starts_with("portman.localhost {\n"));
+    }
+
+    #[test]
+    fn test_caddyfile_wildcard() {
+        let mut registry = get_mocked_registry().unwrap();
+        registry.set_wildcard("app1", true).unwrap();
+        let deps = Unimock::new(data_dir_mock());
+        let caddyfile = generate_caddyfile(&deps, &registry).unwrap();
+        assert!(caddyfile.contains(
+            "\napp1.localhost {\n\tbind 127.0.0.1 [::1]\n\treverse_proxy localhost:3001\n}\n\n*.app1.localhost {\n\tbind 127.0.0.1 [::1]\n\treverse_proxy localhost:3001\n}\n"
+        ));
+        assert!(!caddyfile.contains("*.app2.localhost"));
+    }
+
+    #[test]
+    fn test_caddyfile_tld() {
+        let registry = get_mocked_registry()
+            .unwrap()
+            .with_tld(String::from("test"));
+        let deps = Unimock::new(data_dir_mock());
+        let caddyfile = generate_caddyfile(&deps, &registry).unwrap();
+        assert!(caddyfile.starts_with("localhost {\n\tbind"));
+        assert!(caddyfile.contains(
+            "\napp1.test {\n\ttls internal\n\tbind 127.0.0.1 [::1]\n\treverse_proxy localhost:3001\n}\n"
+        ));
+    }
+
+    #[test]
+    fn test_caddyfile_tls_off() {
+        let registry = get_mocked_registry()
+            .unwrap()
+            .with_tld(String::from("test"))
+            .with_tls_mode(TlsMode::Off);
+        let deps = Unimock::new(data_dir_mock());
+        let caddyfile = generate_caddyfile(&deps, &registry).unwrap();
+        assert!(caddyfile.starts_with("http://localhost {\n\tbind"));
+        assert!(caddyfile.contains(
+            "\nhttp://app1.test {\n\tbind 127.0.0.1 [::1]\n\treverse_proxy localhost:3001\n}\n"
+        ));
+        assert!(!caddyfile.contains("tls internal"));
+    }
+
+    #[test]
+    fn test_caddyfile_bind() {
+        let mut registry = get_mocked_registry().unwrap().with_bind(BindAddress::All);
+        registry
+            .set_bind(
+                "app1",
+                Some(BindAddress::Address(String::from("192.168.1.5"))),
+            )
+            .unwrap();
+        let deps = Unimock::new(data_dir_mock());
+        let caddyfile = generate_caddyfile(&deps, &registry).unwrap();
+        assert!(caddyfile.starts_with(
+            "localhost {\n\trewrite /portman/api/projects /projects.json\n\tfile_server"
+        ));
+        assert!(caddyfile.contains(
+            "\napp1.localhost {\n\tbind 192.168.1.5\n\treverse_proxy localhost:3001\n}\n"
+        ));
+        assert!(caddyfile.contains("\napp2.localhost {\n\treverse_proxy localhost:3002\n}\n"));
+    }
+
+    #[test]
+    fn test_diff_lines() {
+        assert_eq!(diff_lines("a\nb\nc\n", "a\nc\nd\n"), "-b\n+d\n");
+        assert_eq!(diff_lines("a\n", "a\n"), "");
+        assert_eq!(diff_lines("", "a\n"), "+a\n");
+    }
+
+    #[test]
+    fn test_sync_unchanged() {
+        let registry = get_mocked_registry().unwrap();
+        let deps = Unimock::new((
+            data_dir_mock(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/Caddyfile")))
+                .answers(&|_, _| Ok(include_str!("snapshots/Caddyfile").to_owned()))
+                .once(),
+        ));
+        assert!(!sync(&deps, &registry).unwrap());
+    }
+
+    fn read_checksum_mocks(checksum: String) -> impl Clause {
+        (
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/homebrew/etc/Caddyfile")))
+                .answers(&|_, _| Ok(String::from("import \"/data/Caddyfile\"\n")))
+                .once(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/Caddyfile.checksum")))
+                .answers_arc(std::sync::Arc::new(move |_, _| Ok(checksum.clone())))
+                .once(),
+        )
+    }
+
+    #[test]
+    fn test_reload_prepared_unchanged() {
+        let registry = get_mocked_registry().unwrap();
+        let caddyfile = include_str!("snapshots/Caddyfile");
+        let deps = Unimock::new((
+            data_dir_mock(),
+            read_var_mock(),
+            read_health_mock(),
+            read_usage_mock(),
+            read_checksum_mocks(checksum(caddyfile)),
+            // caddy isn't reloaded, so the metrics aren't written
+            WriteFileMock
+                .each_call(matching!(_))
+                .answers(&|_, _, _| Ok(()))
+                .at_least_times(1),
+        ));
+        reload_prepared(&deps, &registry, caddyfile, false).unwrap();
+    }
+
+    #[test]
+    fn test_reload_prepared_changed() {
+        let registry = get_mocked_registry().unwrap();
+        let deps = Unimock::new((
+            data_dir_mock(),
+            read_var_mock(),
+            read_health_mock(),
+            read_usage_mock(),
+            read_checksum_mocks(String::from("0000000000000000")),
+            write_file_mock(),
+            exec_mock(),
+        ));
+        reload_prepared(&deps, &registry, include_str!("snapshots/Caddyfile"), false).unwrap();
+    }
+
     #[test]
     fn test_update_import_no_existing() {
         let deps = Unimock::new(data_dir_mock());
@@ -210,6 +1269,101 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_root_caddyfile_path() {
+        let deps = Unimock::new(read_var_mock());
+        assert_eq!(
+            root_caddyfile_path(&deps, None).unwrap(),
+            PathBuf::from("/homebrew/etc/Caddyfile")
+        );
+        assert_eq!(
+            root_caddyfile_path(&deps, Some(Path::new("/etc/caddy/Caddyfile"))).unwrap(),
+            PathBuf::from("/etc/caddy/Caddyfile")
+        );
+    }
+
+    fn no_homebrew_mock() -> impl Clause {
+        EnvironmentMock
+            .each_call(matching!("HOMEBREW_PREFIX"))
+            .answers(&|_, _| anyhow::bail!("Failed"))
+            .once()
+    }
+
+    #[test]
+    fn test_root_caddyfile_path_systemd() {
+        let deps = Unimock::new((
+            no_homebrew_mock(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/etc/caddy/Caddyfile")))
+                .answers(&|_, _| Ok(String::new()))
+                .once(),
+        ));
+        assert_eq!(
+            root_caddyfile_path(&deps, None).unwrap(),
+            PathBuf::from("/etc/caddy/Caddyfile")
+        );
+    }
+
+    #[test]
+    fn test_root_caddyfile_path_unknown() {
+        let deps = Unimock::new((
+            no_homebrew_mock(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/etc/caddy/Caddyfile")))
+                .answers(&|_, _| Err(std::io::Error::from(std::io::ErrorKind::NotFound)))
+                .once(),
+        ));
+        assert!(matches!(
+            root_caddyfile_path(&deps, None),
+            Err(CaddyError::UnknownCaddyfile)
+        ));
+    }
+
+    #[test]
+    fn test_reload_command_systemd() {
+        let deps = Unimock::new(
+            ExecMock
+                .each_call(matching!((command) if command.get_program() == "systemctl" && command.get_args().eq(["is-active", "--quiet", "caddy"])))
+                .answers(&|_, _| Ok(ExecStatus::Success { output: String::new() }))
+                .once(),
+        );
+        let command = reload_command(&deps, Path::new("/etc/caddy/Caddyfile"));
+        assert_eq!(command.get_program(), "systemctl");
+        assert!(command.get_args().eq(["reload", "caddy"]));
+    }
+
+    #[test]
+    fn test_reload_command_systemd_inactive() {
+        let deps = Unimock::new(
+            ExecMock
+                .each_call(matching!((command) if command.get_program() == "systemctl"))
+                .answers(&|_, _| {
+                    Ok(ExecStatus::Failure {
+                        output: String::new(),
+                        code: 3,
+                    })
+                })
+                .once(),
+        );
+        let command = reload_command(&deps, Path::new("/etc/caddy/Caddyfile"));
+        assert_eq!(command.get_program(), "caddy");
+        assert!(command.get_args().eq([
+            "reload",
+            "--adapter",
+            "caddyfile",
+            "--config",
+            "/etc/caddy/Caddyfile"
+        ]));
+    }
+
+    #[test]
+    fn test_service() {
+        let deps = Unimock::new(read_var_mock());
+        assert_eq!(service(&deps), CaddyService::Homebrew);
+        let deps = Unimock::new(no_homebrew_mock());
+        assert_eq!(service(&deps), CaddyService::Systemd);
+    }
+
     #[test]
     fn test_update_import_already_present() {
         let deps = Unimock::new(data_dir_mock());
@@ -232,12 +1386,130 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_generate_projects_json() {
+        let registry = get_mocked_registry().unwrap();
+        assert_eq!(
+            generate_projects_json(&registry).unwrap(),
+            r#"{"projects":[{"name":"app1","url":"https://app1.localhost","port":3001,"directory":null,"linked_port":null,"display_name":null,"icon":null,"description":null,"upstream":null,"bind":null,"branch":null},{"name":"app2","url":"https://app2.localhost","port":3002,"directory":null,"linked_port":3000,"display_name":null,"icon":null,"description":null,"upstream":null,"bind":null,"branch":null},{"name":"app3","url":"https://app3.localhost","port":3003,"directory":"/projects/app3","linked_port":null,"display_name":null,"icon":null,"description":null,"upstream":null,"bind":null,"branch":null}]}"#
+        );
+    }
+
+    #[test]
+    fn test_generate_metrics() {
+        let registry = get_mocked_registry().unwrap();
+        assert_eq!(
+            generate_metrics(&registry, 1000),
+            r#"# HELP portman_projects The number of projects in the registry
+# TYPE portman_projects gauge
+portman_projects 3
+# HELP portman_allocated_ports The number of ports allocated to projects, including named ports
+# TYPE portman_allocated_ports gauge
+portman_allocated_ports 3
+# HELP portman_linked_ports The number of ports linked to projects
+# TYPE portman_linked_ports gauge
+portman_linked_ports 1
+# HELP portman_last_reload_timestamp_seconds When portman last reloaded caddy, in seconds since the Unix epoch
+# TYPE portman_last_reload_timestamp_seconds gauge
+portman_last_reload_timestamp_seconds 1000
+# HELP portman_project_port The port allocated to each project
+# TYPE portman_project_port gauge
+portman_project_port{project="app1"} 3001
+portman_project_port{project="app2"} 3002
+portman_project_port{project="app3"} 3003
+# HELP portman_project_linked_port The port linked to each linked project
+# TYPE portman_project_linked_port gauge
+portman_project_linked_port{project="app2"} 3000
+"#
+        );
+    }
+
     #[test]
     fn test_generate_gallery() {
         let registry = get_mocked_registry().unwrap();
         assert_eq!(
-            generate_gallery_index(&registry),
+            generate_gallery_index(&registry, &HealthCache::default(), &UsageLog::default()),
             include_str!("snapshots/gallery.html")
         );
     }
+
+    #[test]
+    fn test_generate_gallery_display_name() {
+        let mut registry = get_mocked_registry().unwrap();
+        registry
+            .set_display_name("app1", Some(String::from("<App>")))
+            .unwrap();
+        registry.set_icon("app1", Some(String::from("🚀"))).unwrap();
+        let gallery =
+            generate_gallery_index(&registry, &HealthCache::default(), &UsageLog::default());
+        assert!(gallery.contains(
+            "<h2>🚀 &lt;App&gt;</h2>\n          <p class=\"monospace\">app1.localhost</p>"
+        ));
+    }
+
+    #[test]
+    fn test_generate_gallery_color_and_icon_url() {
+        let mut registry = get_mocked_registry().unwrap();
+        registry
+            .set_icon("app1", Some(String::from("https://example.com/icon.png")))
+            .unwrap();
+        registry
+            .set_color("app1", Some(ProjectColor::Green))
+            .unwrap();
+        let gallery =
+            generate_gallery_index(&registry, &HealthCache::default(), &UsageLog::default());
+        assert!(gallery.contains(
+            "data-last-used=\"\" style=\"--accent: #3a3\">\n          <h2><img class=\"icon\" src=\"https://example.com/icon.png\" alt=\"\" /> app1</h2>"
+        ));
+    }
+
+    #[test]
+    fn test_generate_gallery_tags() {
+        let mut registry = get_mocked_registry().unwrap();
+        let deps = Unimock::new(());
+        registry
+            .set_tags(
+                &deps,
+                "app1",
+                BTreeSet::from([String::from("backend"), String::from("<work>")]),
+            )
+            .unwrap();
+        let gallery =
+            generate_gallery_index(&registry, &HealthCache::default(), &UsageLog::default());
+        assert!(gallery.contains("<p>Tags: <strong>&lt;work&gt;, backend</strong></p>"));
+        assert!(gallery.contains("data-tags=\"&lt;work&gt;,backend\""));
+        assert!(gallery.contains(
+            "\n      <div class=\"tags\">\n        <button class=\"tag\" type=\"button\" data-tag=\"&lt;work&gt;\" aria-pressed=\"false\">&lt;work&gt;</button>\n        <button class=\"tag\" type=\"button\" data-tag=\"backend\" aria-pressed=\"false\">backend</button>\n      </div>"
+        ));
+    }
+
+    #[test]
+    fn test_generate_gallery_status() {
+        let registry = get_mocked_registry().unwrap();
+        let health: HealthCache =
+            toml::from_str("[projects.app1]\nport = 3001\nstatus = 'up'\nlast_up = 1000").unwrap();
+        let gallery = generate_gallery_index(&registry, &health, &UsageLog::default());
+        assert!(gallery.contains("<p>Status: <strong class=\"status-up\">up</strong></p>"));
+        assert!(gallery.contains(
+            "data-name=\"app1\" data-port=\"3001\" data-tags=\"\" data-last-used=\"1000\""
+        ));
+        assert!(gallery
+            .contains("<p>Last used: <strong><time data-timestamp=\"1000\"></time></strong></p>"));
+    }
+
+    #[test]
+    fn test_generate_gallery_last_used() {
+        let registry = get_mocked_registry().unwrap();
+        let health: HealthCache =
+            toml::from_str("[projects.app1]\nport = 3001\nstatus = 'down'\nlast_up = 1000")
+                .unwrap();
+        let usage: UsageLog = toml::from_str("[projects]\napp1 = 2000\napp2 = 500").unwrap();
+        let gallery = generate_gallery_index(&registry, &health, &usage);
+        assert!(gallery.contains(
+            "data-name=\"app1\" data-port=\"3001\" data-tags=\"\" data-last-used=\"2000\""
+        ));
+        assert!(gallery.contains(
+            "data-name=\"app2\" data-port=\"3002\" data-tags=\"\" data-last-used=\"500\""
+        ));
+    }
 }
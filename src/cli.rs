@@ -1,13 +1,65 @@
-use clap::{Parser, Subcommand, ValueEnum};
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
 
 #[derive(ValueEnum, Clone)]
 #[cfg_attr(test, derive(Debug))]
 pub enum InitShell {
     Bash,
     Fish,
+    Nu,
+    Powershell,
     Zsh,
 }
 
+#[derive(ValueEnum, Clone)]
+#[cfg_attr(test, derive(Debug))]
+pub enum Protocol {
+    Http,
+    H2c,
+}
+
+#[derive(ValueEnum, Clone)]
+#[cfg_attr(test, derive(Debug))]
+pub enum Color {
+    Red,
+    Yellow,
+    Green,
+    Cyan,
+    Blue,
+    Magenta,
+    None,
+}
+
+#[derive(ValueEnum, Clone)]
+#[cfg_attr(test, derive(Debug))]
+pub enum GetField {
+    Port,
+    Name,
+    Directory,
+    LinkedPort,
+}
+
+#[derive(ValueEnum, Clone)]
+#[cfg_attr(test, derive(Debug))]
+pub enum ListSort {
+    Name,
+    Port,
+    Directory,
+}
+
+#[derive(ValueEnum, Clone)]
+#[cfg_attr(test, derive(Debug))]
+pub enum CompletionKind {
+    // Ports that are linked to a project
+    LinkedPorts,
+    // The active repo's port and free ports
+    LinkPorts,
+    // The names of all projects
+    Projects,
+    // The URLs of all repos
+    Repos,
+}
+
 #[derive(Subcommand)]
 #[cfg_attr(test, derive(Debug))]
 pub enum Config {
@@ -16,6 +68,38 @@ pub enum Config {
 
     /// Open the configuration file in $EDITOR
     Edit,
+
+    /// Upgrade the configuration file to the latest config format version, preserving its comments
+    Migrate,
+
+    /// Check that a configuration file is valid, exiting with a non-zero status if it isn't
+    Validate {
+        /// The configuration file to validate, which defaults to the current configuration file
+        path: Option<PathBuf>,
+    },
+
+    /// Change a setting in the configuration file, preserving its comments
+    Set {
+        /// The setting to change, using dots to separate nested settings (e.g. `defaults.no_link`)
+        key: String,
+
+        /// The new value as TOML (e.g. "[[3000, 3999]]"), or a string if it isn't valid TOML
+        value: String,
+    },
+}
+
+#[derive(Subcommand)]
+#[cfg_attr(test, derive(Debug))]
+pub enum Registry {
+    /// Accept changes made to the registry file outside of portman
+    Accept,
+
+    /// Restore the registry from a backup, by default the last version that portman saved
+    Restore {
+        /// Which backup to restore, where 1 is the last version that portman saved and higher numbers are older versions
+        #[clap(long, default_value_t = 1)]
+        index: usize,
+    },
 }
 
 #[derive(Subcommand)]
@@ -29,17 +113,323 @@ pub enum Repo {
 
     /// List all repos and their associated ports
     List,
+
+    /// Set a repo's metadata
+    Set {
+        /// The repo to modify
+        repo: String,
+
+        /// The port that projects created in the repo are linked to
+        #[clap(long)]
+        port: Option<u16>,
+
+        /// The port assigned to projects created in the repo when it is available (0 clears it)
+        #[clap(long)]
+        project_port: Option<u16>,
+
+        /// Link a port to a named port of projects created in the repo, in the format "NAME=PORT" (a port of 0 clears it)
+        #[clap(long = "link-port", value_name = "NAME=PORT", value_parser = parse_named_port)]
+        linked_ports: Vec<(String, u16)>,
+
+        /// A description of the repo (an empty string clears it)
+        #[clap(long)]
+        description: Option<String>,
+
+        /// The name of projects created in the repo when no name is provided (an empty string clears it)
+        #[clap(long, visible_alias = "name", value_name = "NAME")]
+        default_project_name: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+#[cfg_attr(test, derive(Debug))]
+pub enum Lease {
+    /// Lease a port for a tool that isn't a project and print it
+    Acquire {
+        /// How long the lease lasts before its port is reclaimed, like 30s, 15m, 1h, or 2d
+        #[clap(long, default_value = "1h", value_parser = parse_duration)]
+        ttl: u64,
+
+        /// A description of what the port is used for
+        #[clap(long)]
+        description: Option<String>,
+    },
+
+    /// Release a leased port before its lease expires
+    Release {
+        /// The leased port to release
+        port: u16,
+    },
+
+    /// List all unexpired leases
+    List,
+}
+
+#[derive(Subcommand)]
+#[cfg_attr(test, derive(Debug))]
+pub enum Domain {
+    /// Serve a project at an extra hostname in addition to its .localhost hostname
+    Add {
+        /// The name of the project
+        project_name: String,
+
+        /// The hostname to add, like api.myapp.test
+        domain: String,
+    },
+
+    /// Stop serving a project at an extra hostname
+    Remove {
+        /// The name of the project
+        project_name: String,
+
+        /// The hostname to remove
+        domain: String,
+    },
+}
+
+#[derive(Subcommand)]
+#[cfg_attr(test, derive(Debug))]
+pub enum Forward {
+    /// Make caddy forward raw TCP or UDP connections on a port to a project, for services like databases that don't speak HTTP (requires the caddy-l4 plugin)
+    Add {
+        /// The name of the project
+        project_name: String,
+
+        /// The port that caddy listens on
+        listen_port: u16,
+
+        /// Forward connections to the project's port with this name instead of its primary port
+        #[clap(long, value_name = "NAME")]
+        port_name: Option<String>,
+
+        /// Forward UDP datagrams instead of TCP connections
+        #[clap(long)]
+        udp: bool,
+    },
+
+    /// Stop forwarding a port to a project
+    Remove {
+        /// The name of the project
+        project_name: String,
+
+        /// The port that caddy listens on
+        listen_port: u16,
+
+        /// Remove the UDP forward instead of the TCP forward
+        #[clap(long)]
+        udp: bool,
+    },
+}
+
+#[derive(Subcommand)]
+#[cfg_attr(test, derive(Debug))]
+pub enum Activate {
+    /// Activate a project in an additional directory, like a git worktree or symlinked checkout
+    Add {
+        /// The directory to add, relative to the current directory
+        path: PathBuf,
+
+        /// The name of the project to modify (defaults to the active project)
+        project_name: Option<String>,
+    },
+
+    /// Stop activating a project in a directory
+    Remove {
+        /// The directory to remove, relative to the current directory
+        path: PathBuf,
+
+        /// The name of the project to modify (defaults to the active project)
+        project_name: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+#[cfg_attr(test, derive(Debug))]
+pub enum Template {
+    /// Render the configured templates into a project's directory
+    Render {
+        /// The name of the project (defaults to the active project)
+        project_name: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+#[cfg_attr(test, derive(Debug))]
+pub enum Tag {
+    /// Add a tag to a project
+    Add {
+        /// The tag to add
+        tag: String,
+
+        /// The name of the project to modify (defaults to the active project)
+        project_name: Option<String>,
+    },
+
+    /// Remove a tag from a project
+    Remove {
+        /// The tag to remove
+        tag: String,
+
+        /// The name of the project to modify (defaults to the active project)
+        project_name: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+#[cfg_attr(test, derive(Debug))]
+pub enum Set {
+    /// Set the addresses that caddy listens on for a project
+    Bind {
+        /// "loopback", "all", or a specific interface address (an empty string uses the global setting)
+        bind: String,
+
+        /// The name of the project to modify (defaults to the active project)
+        project_name: Option<String>,
+    },
+
+    /// Set the name shown for a project in the gallery
+    DisplayName {
+        /// The display name (an empty string clears it)
+        display_name: String,
+
+        /// The name of the project to modify (defaults to the active project)
+        project_name: Option<String>,
+    },
+
+    /// Set the icon, emoji, or image URL shown for a project in the gallery and the list of projects
+    Icon {
+        /// The icon (an empty string clears it)
+        icon: String,
+
+        /// The name of the project to modify (defaults to the active project)
+        project_name: Option<String>,
+    },
+
+    /// Set the color that accents a project in the gallery and the list of projects
+    Color {
+        /// The color (none clears it)
+        #[clap(value_enum)]
+        color: Color,
+
+        /// The name of the project to modify (defaults to the active project)
+        project_name: Option<String>,
+    },
+
+    /// Tune the proxy for dev servers that use websockets for hot module replacement, server-sent events, or long polling
+    #[clap(alias = "streaming")]
+    Hmr {
+        /// Whether to enable the tuning
+        #[clap(action = ArgAction::Set)]
+        enabled: bool,
+
+        /// The name of the project to modify (defaults to the active project)
+        project_name: Option<String>,
+    },
+
+    /// Serve a project at every subdomain of its hostname, like tenant.app.localhost
+    Wildcard {
+        /// Whether to enable wildcard routing
+        #[clap(action = ArgAction::Set)]
+        enabled: bool,
+
+        /// The name of the project to modify (defaults to the active project)
+        project_name: Option<String>,
+    },
+
+    /// Set the path that health checks request for a project, like /healthz
+    HealthPath {
+        /// The path (an empty string only checks that the port accepts connections)
+        health_path: String,
+
+        /// The name of the project to modify (defaults to the active project)
+        project_name: Option<String>,
+    },
+
+    /// Add Caddyfile directives from a file to a project's site blocks
+    CaddyExtra {
+        /// The file containing the directives
+        #[clap(long, value_name = "PATH", required_unless_present("clear"))]
+        file: Option<PathBuf>,
+
+        /// Remove the project's extra directives
+        #[clap(long, conflicts_with("file"))]
+        clear: bool,
+
+        /// The name of the project to modify (defaults to the active project)
+        project_name: Option<String>,
+    },
+
+    /// Set the protocol that caddy uses to proxy requests to a project
+    Protocol {
+        /// The protocol ("h2c" supports gRPC services)
+        #[clap(value_enum)]
+        protocol: Protocol,
+
+        /// The name of the project to modify (defaults to the active project)
+        project_name: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+#[cfg_attr(test, derive(Debug))]
+pub enum Service {
+    /// Install and start a launchd or systemd service that runs portman in the background
+    Install,
 }
 
 #[derive(Parser)]
 #[cfg_attr(test, derive(Debug))]
 #[clap(about, version, author)]
-pub enum Cli {
+#[allow(clippy::struct_excessive_bools)]
+pub struct Cli {
+    /// Store the registry and other state in this directory instead of the default data directory
+    #[clap(long, global = true, value_name = "PATH")]
+    pub data_dir: Option<PathBuf>,
+
+    /// Fail instead of silently adjusting names or using ports in questionable states
+    #[clap(long, global = true)]
+    pub strict: bool,
+
+    /// Don't warn about project ports that were reassigned because they were no longer valid
+    #[clap(long, short = 'q', global = true)]
+    pub quiet: bool,
+
+    /// Print how long loading, validating, and reloading took to stderr
+    #[clap(long, global = true)]
+    pub timings: bool,
+
+    /// Print structured JSON instead of text (supported by list, get, resolve, repo list, config show, cleanup, conflicts, logs, and version)
+    #[clap(long, global = true)]
+    pub json: bool,
+
+    /// Print the changes and the Caddyfile diff without saving the registry or reloading caddy (supported by create, adopt, set, delete, link, unlink, batch, cleanup, and repo delete)
+    #[clap(long, global = true)]
+    pub dry_run: bool,
+
+    /// Save changes to projects without reloading caddy until `portman reload-caddy` is run
+    #[clap(long, global = true)]
+    pub no_reload: bool,
+
+    /// Wait up to this many seconds for another portman process to release the registry lock
+    #[clap(long, global = true, value_name = "SECONDS", default_value_t = 5)]
+    pub wait: u64,
+
+    #[clap(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+#[cfg_attr(test, derive(Debug))]
+pub enum Command {
     /// Print the shell configuration command to initialize portman
     Init {
         /// Specifies the shell to use
         #[clap(value_enum)]
         shell: InitShell,
+
+        /// Export `PREFIX_PORT`, `PREFIX_PROJECT`, and `PREFIX_LINKED_PORT` instead of `PORT`, `PORTMAN_PROJECT`, and `PORTMAN_LINKED_PORT`
+        #[clap(long, value_name = "PREFIX", value_parser = parse_var_prefix)]
+        var_prefix: Option<String>,
     },
 
     /// Manage the configuration
@@ -54,8 +444,37 @@ pub enum Cli {
         /// Print the project's name, directory, and linked port in addition to its port
         #[clap(long, short = 'e')]
         extended: bool,
+
+        /// Print the port with this name instead of the project's primary port
+        #[clap(long, value_name = "NAME", conflicts_with("extended"))]
+        port_name: Option<String>,
+
+        /// Print only this field of the project
+        #[clap(long, value_enum, conflicts_with_all(["extended", "port_name"]))]
+        field: Option<GetField>,
+
+        /// Print the URL that serves the project, or the named port if --port-name is present, instead of its port
+        #[clap(long, conflicts_with_all(["extended", "field"]))]
+        url: bool,
+
+        /// Print the active project's extended details from the directory index without loading the registry (used by the shell integration)
+        #[clap(long, conflicts_with_all(["project_name", "extended", "port_name", "field", "url"]))]
+        shell_fast: bool,
+    },
+
+    /// Print the project that is active in a path or its nearest parent directory
+    Resolve {
+        /// The file or directory to resolve, relative to the current directory
+        path: PathBuf,
+
+        /// Print the project's name, directory, and linked port in addition to its port
+        #[clap(long, short = 'e')]
+        extended: bool,
     },
 
+    /// Explain which project is active in the current directory and which environment variables the shell integration exports
+    Which,
+
     /// Create a new project
     Create {
         /// The name of the project (defaults to the basename of the current directory unless --no-activate is present)
@@ -65,6 +484,31 @@ pub enum Cli {
         #[clap(long, short = 'A', requires("project_name"))]
         no_activate: bool,
 
+        /// Generate the project name from a template with {repo}, {branch}, and {dir} variables, like "{repo}-{branch}"
+        #[clap(
+            long,
+            visible_alias = "template",
+            value_name = "TEMPLATE",
+            conflicts_with("project_name")
+        )]
+        name_template: Option<String>,
+
+        /// The project's directory (defaults to the current directory)
+        #[clap(long, value_name = "PATH", conflicts_with("no_activate"))]
+        directory: Option<PathBuf>,
+
+        /// Assign this port to the project instead of allocating one
+        #[clap(long)]
+        port: Option<u16>,
+
+        /// Assign the port even if it is outside of the configured port ranges
+        #[clap(long, short = 'f', requires("port"))]
+        force: bool,
+
+        /// Link the project to this port instead of the port assigned to its repo
+        #[clap(long, value_name = "PORT", conflicts_with("no_link"))]
+        link: Option<u16>,
+
         /// Do not automatically link this project to a port based on its repo
         #[clap(long, short = 'N', conflicts_with("no_activate"))]
         no_link: bool,
@@ -72,19 +516,195 @@ pub enum Cli {
         /// Modify the project if it already exists instead of failing
         #[clap(long, short = 'o')]
         overwrite: bool,
+
+        /// Tag the project, allocating its port from the tag's port range if one is configured (can be repeated or comma-separated)
+        #[clap(
+            long = "tag",
+            short = 't',
+            visible_alias = "tags",
+            value_name = "TAG",
+            value_delimiter = ','
+        )]
+        tags: Vec<String>,
+
+        /// Allocate additional named ports for the project, like "web,api" (can be repeated or comma-separated)
+        #[clap(long = "ports", value_name = "NAME", value_delimiter = ',')]
+        ports: Vec<String>,
+
+        /// A description of the project shown in the gallery
+        #[clap(long)]
+        description: Option<String>,
+
+        /// Set an environment variable that the shell integration exports while the project is active (can be repeated)
+        #[clap(long = "env", value_name = "KEY=VALUE", value_parser = parse_env)]
+        env: Vec<(String, String)>,
+
+        /// Register the project for the current git branch so that it is only active while that branch is checked out (defaults the name to "{repo}-{branch}")
+        #[clap(long, conflicts_with("no_activate"))]
+        per_branch: bool,
+
+        /// Proxy the project to a remote URL instead of its local port
+        #[clap(long, value_name = "URL")]
+        upstream: Option<String>,
+
+        /// Set a header on requests proxied to the upstream (can be repeated)
+        #[clap(long = "header", short = 'H', value_name = "NAME: VALUE", value_parser = parse_header, requires("upstream"))]
+        headers: Vec<(String, String)>,
+
+        /// Also serve the project at every subdomain of its hostname, like tenant.app.localhost
+        #[clap(long)]
+        wildcard: bool,
+
+        /// Also activate the project in any checkout of its git repo, like worktrees and fresh clones
+        #[clap(long, conflicts_with("per_branch"))]
+        match_repo: bool,
+
+        /// Identify the project's repo using this git remote instead of origin
+        #[clap(long, value_name = "NAME")]
+        remote: Option<String>,
+    },
+
+    /// Create a new project for the dev server already running in the current directory, using the port that it is listening on
+    Adopt {
+        /// The name of the project (defaults to the basename of the current directory)
+        project_name: Option<String>,
+
+        /// The port to adopt if the server is listening on multiple ports
+        #[clap(long)]
+        port: Option<u16>,
+
+        /// Adopt the port even if it is outside of the configured port ranges
+        #[clap(long, short = 'f')]
+        force: bool,
     },
 
+    /// Modify a project's settings
+    #[clap(subcommand)]
+    Set(Set),
+
     /// Delete an existing project
     Delete {
         /// The name of the project to delete (defaults to the active project)
         project_name: Option<String>,
     },
 
+    /// Move a project to a different directory, like after its checkout was moved on disk
+    Mv {
+        /// The name of the project to move
+        project_name: String,
+
+        /// The project's new directory, relative to the current directory (defaults to the current directory)
+        directory: Option<PathBuf>,
+    },
+
+    /// Move a project out of the registry into the archive
+    Archive {
+        /// The name of the project to archive (defaults to the active project)
+        project_name: Option<String>,
+
+        /// Keep the project's port reserved so that it isn't assigned to another project
+        #[clap(long, short = 'r')]
+        reserve_port: bool,
+    },
+
+    /// Restore an archived project to the registry
+    Unarchive {
+        /// The name of the archived project to restore
+        project_name: String,
+    },
+
     /// Cleanup projects whose directory has been deleted
-    Cleanup,
+    Cleanup {
+        /// Also delete projects that haven't been used and whose directory hasn't been modified in
+        /// this long, like "30d"
+        #[clap(long, value_parser = parse_duration)]
+        stale: Option<u64>,
+
+        /// Delete stale projects without prompting
+        #[clap(long, short = 'y', requires("stale"))]
+        yes: bool,
+    },
 
     /// List all projects
-    List,
+    List {
+        /// Show whether each project's port is up, as of the last `portman health` check
+        #[clap(long, short = 's')]
+        status: bool,
+
+        /// List the archived projects instead
+        #[clap(long, short = 'a', conflicts_with("status"))]
+        archived: bool,
+
+        /// List the reserved ports instead
+        #[clap(long, conflicts_with_all(["status", "archived"]))]
+        reservations: bool,
+
+        /// Print each project's tags and description too
+        #[clap(long, short = 'e', conflicts_with("reservations"))]
+        extended: bool,
+
+        /// The order to list the projects in
+        #[clap(long, value_enum, default_value_t = ListSort::Name)]
+        sort: ListSort,
+
+        /// Only list projects with a linked port
+        #[clap(long)]
+        linked_only: bool,
+
+        /// Only list projects without a directory
+        #[clap(long)]
+        no_directory: bool,
+
+        /// Only list projects whose names match this glob pattern, like "api-*"
+        #[clap(long, value_name = "GLOB")]
+        filter: Option<String>,
+    },
+
+    /// Check whether each project's port is up and cache the results
+    Health {
+        /// Keep checking the projects' ports periodically by running the daemon
+        #[clap(long, short = 'w')]
+        watch: bool,
+
+        /// The number of seconds to wait between checks in watch mode
+        #[clap(long, default_value_t = 10, requires("watch"))]
+        interval: u64,
+    },
+
+    /// Check whether projects are up now, update the cached results, and print them
+    Status {
+        /// The name of the project to check (defaults to all projects)
+        project_name: Option<String>,
+    },
+
+    /// Print the requests that caddy has proxied to a project
+    Logs {
+        /// The name of the project (defaults to the active project)
+        project_name: Option<String>,
+
+        /// Keep printing requests as they are made
+        #[clap(long, short = 'f')]
+        follow: bool,
+
+        /// The number of past requests to print
+        #[clap(long, short = 'n', default_value_t = 10)]
+        lines: usize,
+    },
+
+    /// Browse, filter, create, delete, link, and open projects in an interactive terminal UI
+    Ui,
+
+    /// Watch the registry and project directories, deleting projects whose directories are removed
+    /// and reloading caddy when the registry changes, and periodically check the projects' health
+    Daemon {
+        /// Advertise every project's <name>.local hostname to other devices on the local network over mDNS
+        #[clap(long)]
+        mdns: bool,
+
+        /// The number of seconds to wait between health checks
+        #[clap(long, default_value_t = 10)]
+        interval: u64,
+    },
 
     /// Link a project to a port
     Link {
@@ -94,9 +714,31 @@ pub enum Cli {
         /// The name of the project to link (defaults to the active project)
         project_name: Option<String>,
 
+        /// Link the port to one of the project's named ports instead of to its main port
+        #[clap(long, value_name = "NAME")]
+        port_name: Option<String>,
+
         /// Do not remember which port the active project's repo was assigned to
         #[clap(long, short = 'S', requires("port"), conflicts_with("project_name"))]
         no_save: bool,
+
+        /// Identify the active project's repo using this git remote instead of origin
+        #[clap(long, value_name = "NAME")]
+        remote: Option<String>,
+
+        /// Interactively link each port in a range like 3000-3010 to a project
+        #[clap(long, value_name = "START-END", value_parser = parse_port_range, conflicts_with_all(["port", "project_name", "port_name", "no_save"]))]
+        range: Option<(u16, u16)>,
+    },
+
+    /// Move a project to a new port while its old port keeps serving requests
+    Handoff {
+        /// The name of the project to move (defaults to the active project)
+        project_name: Option<String>,
+
+        /// The port to move the project to (defaults to an automatically allocated port)
+        #[clap(long, value_name = "PORT")]
+        to: Option<u16>,
     },
 
     /// Unlink a port from a project
@@ -105,13 +747,258 @@ pub enum Cli {
         port: u16,
     },
 
+    /// Run create, set, delete, link, and unlink commands as a single transaction
+    Batch {
+        /// Read the commands from this file instead of stdin, one per line
+        file: Option<PathBuf>,
+    },
+
+    /// Manage the registry file
+    #[clap(subcommand)]
+    Registry(Registry),
+
     /// Manage repos
     #[clap(subcommand)]
     Repo(Repo),
 
+    /// Manage ports leased to tools that aren't projects
+    #[clap(subcommand)]
+    Lease(Lease),
+
+    /// Reserve a port for a tool that isn't a project so that it is never allocated
+    Reserve {
+        /// The port to reserve
+        port: u16,
+
+        /// A description of what the port is used for
+        #[clap(long)]
+        description: Option<String>,
+    },
+
+    /// Release a reserved port so that it can be allocated again
+    Unreserve {
+        /// The reserved port to release
+        port: u16,
+    },
+
+    /// Manage projects' extra hostnames
+    #[clap(subcommand)]
+    Domain(Domain),
+
+    /// Manage projects' raw TCP and UDP port forwards
+    #[clap(subcommand)]
+    Forward(Forward),
+
+    /// Manage projects' tags
+    #[clap(subcommand)]
+    Tag(Tag),
+
+    /// Manage the directories that activate projects
+    #[clap(subcommand)]
+    Activate(Activate),
+
+    /// Manage file templates rendered into project directories
+    #[clap(subcommand)]
+    Template(Template),
+
+    /// Forward a project's port in its devcontainer configuration
+    Devcontainer {
+        /// The name of the project to forward (defaults to the active project)
+        project_name: Option<String>,
+    },
+
+    /// Print a .env fragment with a project's port and environment variables
+    Env {
+        /// The name of the project (defaults to the active project)
+        project_name: Option<String>,
+
+        /// Merge the variables into this .env file instead of printing them, preserving unrelated variables
+        #[clap(long, value_name = "PATH")]
+        file: Option<PathBuf>,
+    },
+
+    /// Manage the background service
+    #[clap(subcommand)]
+    Service(Service),
+
+    /// Print a tmux status-line segment for the active project
+    Tmux {
+        /// Also export the active project's variables to the tmux session environment
+        #[clap(long, short = 's')]
+        set_environment: bool,
+    },
+
+    /// Find existing projects in a directory tree and offer to create a project for each one
+    Scan {
+        /// The directory to scan, relative to the current directory
+        root: PathBuf,
+
+        /// How many levels of subdirectories to scan
+        #[clap(long, default_value_t = 3)]
+        depth: usize,
+
+        /// Create every project with its proposed name without prompting
+        #[clap(long, short = 'y')]
+        yes: bool,
+    },
+
+    /// Run a command with the active project's port and environment variables set
+    Run {
+        /// The command to run and its arguments
+        #[clap(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+
+    /// Run a command in a project's directory with the project's port and environment variables set, even outside of the project's directory
+    Exec {
+        /// The name of the project
+        project_name: String,
+
+        /// The command to run and its arguments
+        #[clap(required = true, last = true)]
+        command: Vec<String>,
+    },
+
+    /// Run a command in every project's directory with the project's environment variables set
+    ExecAll {
+        /// Only run the command in projects with this tag (can be repeated)
+        #[clap(long = "tag", short = 't', value_name = "TAG")]
+        tags: Vec<String>,
+
+        /// The command to run and its arguments
+        #[clap(required = true, last = true)]
+        command: Vec<String>,
+    },
+
+    /// Print completion candidates for the shell integration
+    #[clap(hide = true)]
+    Complete {
+        /// The kind of candidates to print
+        #[clap(value_enum)]
+        kind: CompletionKind,
+    },
+
+    /// Check that every project's port is in its tags' configured port range
+    Verify,
+
+    /// Check project ports and linked ports for conflicts with the configured port ranges, reserved ports, well-known system ports, and other processes
+    Conflicts,
+
     /// Print the generated Caddyfile
     Caddyfile,
 
     /// Regenerate the Caddyfile and restart caddy
     ReloadCaddy,
+
+    /// Diagnose problems with the caddy installation, the config, and the registry
+    Doctor,
+
+    /// Print version, build, and installation details for bug reports
+    Version,
+}
+
+// Parse a header in the format "Name: value"
+fn parse_header(header: &str) -> Result<(String, String), String> {
+    let (name, value) = header
+        .split_once(':')
+        .ok_or_else(|| String::from("header must be in the format \"Name: value\""))?;
+    let name = name.trim();
+    // Header names are RFC 7230 tokens
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(char))
+    {
+        return Err(String::from(
+            "header name must not be empty and may only contain letters, digits, and !#$%&'*+-.^_`|~",
+        ));
+    }
+    Ok((name.to_owned(), value.trim().to_owned()))
+}
+
+// Parse an environment variable in the format "KEY=VALUE"
+fn parse_env(env: &str) -> Result<(String, String), String> {
+    let (key, value) = env
+        .split_once('=')
+        .ok_or_else(|| String::from("environment variable must be in the format \"KEY=VALUE\""))?;
+    let mut chars = key.chars();
+    if !chars
+        .next()
+        .is_some_and(|char| char.is_ascii_alphabetic() || char == '_')
+        || !chars.all(|char| char.is_ascii_alphanumeric() || char == '_')
+    {
+        return Err(format!(
+            "environment variable name \"{key}\" must only contain letters, digits, and underscores and must not start with a digit"
+        ));
+    }
+    if value.contains('\n') {
+        return Err(String::from(
+            "environment variable value must not contain newlines",
+        ));
+    }
+    Ok((key.to_owned(), value.to_owned()))
+}
+
+// Parse a named port in the format "NAME=PORT"
+fn parse_named_port(named_port: &str) -> Result<(String, u16), String> {
+    let (name, port) = named_port
+        .split_once('=')
+        .ok_or_else(|| String::from("named port must be in the format \"NAME=PORT\""))?;
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(String::from("port name must not be empty"));
+    }
+    let port = port.trim().parse::<u16>().map_err(|err| err.to_string())?;
+    Ok((name.to_owned(), port))
+}
+
+// Parse the prefix of the shell integration's variable names
+fn parse_var_prefix(prefix: &str) -> Result<String, String> {
+    let mut chars = prefix.chars();
+    if !chars
+        .next()
+        .is_some_and(|char| char.is_ascii_alphabetic() || char == '_')
+        || !chars.all(|char| char.is_ascii_alphanumeric() || char == '_')
+    {
+        return Err(String::from(
+            "variable prefix must only contain letters, digits, and underscores and must not start with a digit",
+        ));
+    }
+    Ok(prefix.to_owned())
+}
+
+// Parse a port range in the format "start-end"
+fn parse_port_range(range: &str) -> Result<(u16, u16), String> {
+    let (start, end) = range
+        .split_once('-')
+        .ok_or_else(|| String::from("range must be in the format \"start-end\""))?;
+    let start = start.trim().parse::<u16>().map_err(|err| err.to_string())?;
+    let end = end.trim().parse::<u16>().map_err(|err| err.to_string())?;
+    if start > end {
+        return Err(String::from(
+            "range start must not be greater than range end",
+        ));
+    }
+    Ok((start, end))
+}
+
+// Parse a duration like "90", "30s", "15m", "1h", or "2d" into a number of seconds
+fn parse_duration(duration: &str) -> Result<u64, String> {
+    let (amount, multiplier) = match duration.char_indices().last() {
+        Some((index, 's')) => (&duration[..index], 1),
+        Some((index, 'm')) => (&duration[..index], 60),
+        Some((index, 'h')) => (&duration[..index], 60 * 60),
+        Some((index, 'd')) => (&duration[..index], 60 * 60 * 24),
+        _ => (duration, 1),
+    };
+    let amount = amount
+        .trim()
+        .parse::<u64>()
+        .map_err(|err| err.to_string())?;
+    if amount == 0 {
+        return Err(String::from("duration must be greater than zero"));
+    }
+    amount
+        .checked_mul(multiplier)
+        .ok_or_else(|| String::from("duration is too long"))
 }
@@ -1,48 +1,414 @@
-use crate::dependencies::ReadFile;
+//! The user's configuration, which controls the port ranges that projects are allocated from and
+//! how caddy serves them.
+
+use crate::dependencies::{Environment, ReadFile};
 use crate::error::{ApplicationError, Result};
-use anyhow::bail;
+use anyhow::{bail, Context};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fmt::{Display, Formatter};
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
+use toml::{Table, Value};
+use toml_edit::{Document, Item, TableLike};
+
+/// The config format version that this version of portman understands
+/// Version 1 config files don't specify a version and only contain top-level settings
+pub const CONFIG_VERSION: i64 = 2;
+
+const fn default_version() -> i64 {
+    CONFIG_VERSION
+}
 
 fn default_ranges() -> Vec<(u16, u16)> {
     vec![(3000, 3999)]
 }
 
+fn default_gallery_hostname() -> String {
+    String::from("localhost")
+}
+
+fn default_tld() -> String {
+    String::from("localhost")
+}
+
+const fn default_auto_reload() -> bool {
+    true
+}
+
+const fn default_require_caddy() -> bool {
+    true
+}
+
+/// How the registry is stored on disk
+#[derive(Clone, Copy, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[cfg_attr(test, derive(Debug))]
+#[serde(rename_all = "lowercase")]
+pub enum RegistryLayout {
+    // All projects are stored in registry.toml
+    #[default]
+    Single,
+
+    // Projects are also loaded from registry.d/*.toml and saved back to the file they came from
+    Split,
+}
+
+/// What to do when all of the configured ports have been allocated
+#[derive(Clone, Copy, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[cfg_attr(test, derive(Debug))]
+#[serde(rename_all = "lowercase")]
+pub enum PortFallback {
+    // Fail to allocate a port
+    #[default]
+    None,
+
+    // Ask the OS for a free ephemeral port outside of the configured ranges
+    Ephemeral,
+}
+
+/// Whether caddy serves portman-managed sites over HTTPS
+#[derive(Clone, Copy, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[cfg_attr(test, derive(Debug))]
+#[serde(rename_all = "lowercase")]
+pub enum TlsMode {
+    // Serve sites over HTTPS with certificates from caddy's locally trusted certificate authority
+    #[default]
+    Internal,
+
+    // Serve sites over plain HTTP
+    Off,
+}
+
+/// How ports are chosen from the available ports
+#[derive(Clone, Copy, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[cfg_attr(test, derive(Debug))]
+#[serde(rename_all = "lowercase")]
+pub enum AllocationStrategy {
+    // Choose a random available port
+    #[default]
+    Random,
+
+    // Choose the lowest available port so that port numbers are predictable
+    Sequential,
+}
+
+/// The addresses that caddy listens on for portman-managed sites
+#[derive(Clone, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[cfg_attr(test, derive(Debug))]
+#[serde(try_from = "String", into = "String")]
+pub enum BindAddress {
+    // Only listen on the loopback interface
+    #[default]
+    Loopback,
+
+    // Listen on all interfaces
+    All,
+
+    // Listen on a specific address
+    Address(String),
+}
+
+impl TryFrom<String> for BindAddress {
+    type Error = ApplicationError;
+
+    fn try_from(value: String) -> Result<Self> {
+        match value.as_str() {
+            "loopback" => Ok(Self::Loopback),
+            "all" => Ok(Self::All),
+            _ if value.is_empty() || value.contains(char::is_whitespace) => {
+                Err(ApplicationError::InvalidBindAddress(value))
+            }
+            _ => Ok(Self::Address(value)),
+        }
+    }
+}
+
+impl From<BindAddress> for String {
+    fn from(value: BindAddress) -> Self {
+        value.to_string()
+    }
+}
+
+impl Display for BindAddress {
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Self::Loopback => write!(fmt, "loopback"),
+            Self::All => write!(fmt, "all"),
+            Self::Address(address) => write!(fmt, "{address}"),
+        }
+    }
+}
+
+/// Shell commands that run after projects change
+#[derive(Clone, Default, Deserialize, Serialize)]
+#[cfg_attr(test, derive(Debug))]
+pub struct Hooks {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_create: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_delete: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_link: Option<String>,
+}
+
+/// Settings applied to every new project unless the create command overrides them
+#[derive(Clone, Default, Deserialize, Serialize)]
+#[cfg_attr(test, derive(Debug, Eq, PartialEq))]
+pub struct ProjectDefaults {
+    #[serde(default)]
+    pub no_link: bool,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+/// Settings for a specific project, keyed by project name
+#[derive(Clone, Default, Deserialize, Serialize)]
+#[cfg_attr(test, derive(Debug, Eq, PartialEq))]
+pub struct ProjectOverride {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+}
+
+/// portman's configuration, loaded from `config.toml` in the data directory or the file in the
+/// `$PORTMAN_CONFIG` environment variable
 #[derive(Deserialize, Serialize)]
 #[cfg_attr(test, derive(Debug))]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Config {
+    #[serde(default = "default_version")]
+    pub version: i64,
+
     #[serde(default = "default_ranges")]
     pub ranges: Vec<(u16, u16)>,
 
     #[serde(default)]
     pub reserved: Vec<u16>,
+
+    #[serde(default)]
+    pub tag_ranges: BTreeMap<String, (u16, u16)>,
+
+    #[serde(default)]
+    pub layout: RegistryLayout,
+
+    /// The number of older backups of the registry files to keep besides the last saved version
+    #[serde(default)]
+    pub backups: usize,
+
+    #[serde(default)]
+    pub fallback: PortFallback,
+
+    #[serde(default)]
+    pub strategy: AllocationStrategy,
+
+    #[serde(default)]
+    pub bind: BindAddress,
+
+    #[serde(default = "default_gallery_hostname")]
+    pub gallery_hostname: String,
+
+    #[serde(default = "default_tld")]
+    pub tld: String,
+
+    #[serde(default)]
+    pub tls_mode: TlsMode,
+
+    /// Whether caddy streams responses and waits longer for every project, like it does for projects
+    /// with HMR tuning enabled
+    #[serde(default)]
+    pub streaming: bool,
+
+    /// Whether caddy writes each project's requests to an access log in the data directory
+    #[serde(default)]
+    pub access_logs: bool,
+
+    /// Whether caddy also serves every project at a .local hostname that `portman daemon --mdns`
+    /// advertises to other devices on the local network
+    #[serde(default)]
+    pub mdns: bool,
+
+    /// Whether changes to the registry reload caddy, instead of waiting for `portman reload-caddy`
+    #[serde(default = "default_auto_reload")]
+    pub auto_reload: bool,
+
+    /// Whether commands fail when caddy isn't installed, instead of saving the registry without
+    /// reloading caddy
+    #[serde(default = "default_require_caddy")]
+    pub require_caddy: bool,
+
+    /// Whether the shell integration creates a project when it enters the root of a git repo that
+    /// doesn't have one
+    #[serde(default)]
+    pub auto_create: bool,
+
+    /// The root Caddyfile that caddy loads, which defaults to `$HOMEBREW_PREFIX/etc/Caddyfile` or
+    /// `/etc/caddy/Caddyfile`
+    #[serde(
+        default,
+        alias = "caddyfile_path",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub caddyfile: Option<PathBuf>,
+
+    #[serde(default)]
+    pub strict: bool,
+
+    #[serde(default)]
+    pub hooks: Hooks,
+
+    /// Template files rendered into project directories, keyed by their destination relative to the
+    /// project directory
+    #[serde(default)]
+    pub templates: BTreeMap<String, String>,
+
+    #[serde(default)]
+    pub defaults: ProjectDefaults,
+
+    #[serde(default)]
+    pub project_overrides: BTreeMap<String, ProjectOverride>,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
+            version: CONFIG_VERSION,
             ranges: default_ranges(),
             reserved: vec![],
+            tag_ranges: BTreeMap::new(),
+            layout: RegistryLayout::default(),
+            backups: 0,
+            fallback: PortFallback::default(),
+            strategy: AllocationStrategy::default(),
+            bind: BindAddress::default(),
+            gallery_hostname: default_gallery_hostname(),
+            tld: default_tld(),
+            tls_mode: TlsMode::default(),
+            streaming: false,
+            access_logs: false,
+            mdns: false,
+            auto_reload: default_auto_reload(),
+            require_caddy: default_require_caddy(),
+            auto_create: false,
+            caddyfile: None,
+            strict: false,
+            hooks: Hooks::default(),
+            templates: BTreeMap::new(),
+            defaults: ProjectDefaults::default(),
+            project_overrides: BTreeMap::new(),
         }
     }
 }
 
 impl Config {
-    // Load the configuration from the file
-    // Return None if the file doesn't exist
-    pub fn load(deps: &impl ReadFile, path: &Path) -> Result<Option<Self>> {
-        deps.read_file(path)
-            .map_err(ApplicationError::InvalidConfig)?
-            .map(|config_str| Self::from_toml(&config_str))
-            .transpose()
+    /// Load the configuration from the file, merged with the base files that it extends
+    /// Return None if the file doesn't exist
+    /// Older files, including the base files, are upgraded to the current version in memory as they
+    /// are parsed. The files themselves are left alone because every command loads the config,
+    /// including read-only ones like the shell integration's prompt hook, and base files may be
+    /// shared, so only `portman config migrate` rewrites a file.
+    pub fn load(deps: &(impl Environment + ReadFile), path: &Path) -> Result<Option<Self>> {
+        Self::load_table(deps, path, &mut Vec::new())
+            .and_then(|table| table.map(Self::from_table).transpose())
+            .map_err(ApplicationError::InvalidConfig)
+    }
+
+    // Load a configuration file as a TOML table, recursively merging it over the base files that
+    // it extends
+    // extending contains the files that are already being loaded and is used to detect cycles
+    fn load_table(
+        deps: &(impl Environment + ReadFile),
+        path: &Path,
+        extending: &mut Vec<PathBuf>,
+    ) -> anyhow::Result<Option<Table>> {
+        if extending.iter().any(|extended| extended == path) {
+            bail!(
+                "Validation error: config file \"{}\" extends itself\n",
+                path.display()
+            )
+        }
+        let Some(toml_str) = deps.read_file(path)? else {
+            return Ok(None);
+        };
+        Self::parse_table(deps, path, &toml_str, extending).map(Some)
+    }
+
+    // Parse the contents of a configuration file as a TOML table, recursively merging it over the
+    // base files that it extends
+    fn parse_table(
+        deps: &(impl Environment + ReadFile),
+        path: &Path,
+        toml_str: &str,
+        extending: &mut Vec<PathBuf>,
+    ) -> anyhow::Result<Table> {
+        let mut table: Table = toml::from_str(toml_str)?;
+        upgrade_table(&mut table)?;
+
+        let extends = match table.remove("extends") {
+            Some(extends) => extends
+                .try_into::<Vec<String>>()
+                .context("Validation error: extends must be an array of paths\n")?,
+            None => vec![],
+        };
+        extending.push(path.to_owned());
+        let mut merged = Table::new();
+        for base in extends {
+            let base_path = Self::resolve_path(deps, path, &base)?;
+            let base_table = Self::load_table(deps, &base_path, extending)?.with_context(|| {
+                format!(
+                    "Validation error: extended config file \"{}\" does not exist\n",
+                    base_path.display()
+                )
+            })?;
+            merge_tables(&mut merged, base_table);
+        }
+        extending.pop();
+        merge_tables(&mut merged, table);
+        Ok(merged)
+    }
+
+    /// Parse and validate the contents of the configuration file at a path without reading it, for
+    /// example to check changes before saving them
+    pub fn parse(
+        deps: &(impl Environment + ReadFile),
+        path: &Path,
+        toml_str: &str,
+    ) -> Result<Self> {
+        Self::parse_table(deps, path, toml_str, &mut Vec::new())
+            .and_then(Self::from_table)
             .map_err(ApplicationError::InvalidConfig)
     }
 
+    /// Resolve a path referenced by a config file, like an extended config file or a template, which
+    /// can start with ~ or be relative to the config file
+    pub fn resolve_path(
+        deps: &impl Environment,
+        path: &Path,
+        base: &str,
+    ) -> anyhow::Result<PathBuf> {
+        if let Some(relative) = base.strip_prefix("~/") {
+            return Ok(PathBuf::from(deps.read_var("HOME")?).join(relative));
+        }
+        Ok(path
+            .parent()
+            .map_or_else(|| PathBuf::from(base), |parent| parent.join(base)))
+    }
+
+    // Return a new configuration from a TOML table
+    fn from_table(table: Table) -> anyhow::Result<Self> {
+        Self::validate(Value::Table(table).try_into()?)
+    }
+
     // Return a new configuration from a TOML string
+    #[cfg(test)]
     fn from_toml(toml_str: &str) -> anyhow::Result<Self> {
-        let config: Self = toml::from_str(toml_str)?;
+        let mut table: Table = toml::from_str(toml_str)?;
+        upgrade_table(&mut table)?;
+        Self::from_table(table)
+    }
 
+    // Check that the values in a configuration are valid
+    fn validate(config: Self) -> anyhow::Result<Self> {
         if config.ranges.is_empty() {
             bail!("Validation error: port ranges must not be empty\n")
         }
@@ -51,11 +417,70 @@ impl Config {
                 bail!("Validation error at port range ({start}-{end}), start must be less than range end\n")
             }
         }
+        if config.gallery_hostname.is_empty()
+            || config
+                .gallery_hostname
+                .contains(|char: char| char.is_whitespace() || char == '{' || char == '}')
+        {
+            bail!(
+                "Validation error at gallery hostname \"{}\", it must be a single hostname\n",
+                config.gallery_hostname
+            )
+        }
+        if config.tld.is_empty()
+            || config.tld.starts_with(['.', '-'])
+            || config.tld.ends_with(['.', '-'])
+            || !config.tld.chars().all(|char| {
+                char.is_ascii_lowercase() || char.is_ascii_digit() || char == '-' || char == '.'
+            })
+        {
+            bail!(
+                "Validation error at tld \"{}\", it must be a lowercase domain suffix like \"test\"\n",
+                config.tld
+            )
+        }
+        for (tag, (start, end)) in &config.tag_ranges {
+            if start >= end {
+                bail!("Validation error at port range ({start}-{end}) for tag {tag}, start must be less than range end\n")
+            }
+            // Projects with the tag can only be allocated ports that are also in the configured
+            // port ranges and aren't reserved
+            if !(*start..=*end).all(|port| config.in_ranges(port)) {
+                bail!("Validation error at port range ({start}-{end}) for tag {tag}, range must be inside of the configured port ranges\n")
+            }
+            if (*start..=*end).all(|port| config.reserved.contains(&port)) {
+                bail!("Validation error at port range ({start}-{end}) for tag {tag}, range must contain at least one port that isn't reserved\n")
+            }
+        }
+        for destination in config.templates.keys() {
+            let path = Path::new(destination);
+            if destination.is_empty()
+                || !path
+                    .components()
+                    .all(|component| matches!(component, Component::Normal(_)))
+            {
+                bail!("Validation error at template \"{destination}\", its destination must be a relative path inside of the project directory\n")
+            }
+        }
+        for (name, project_override) in &config.project_overrides {
+            if let Some(port) = project_override.port {
+                if !config.in_ranges(port) {
+                    bail!("Validation error at port {port} for project {name}, port must be in the configured port ranges\n")
+                }
+            }
+        }
 
         Ok(config)
     }
 
-    // Return an iterator of the valid ports allowed by this configuration
+    /// Determine whether a port is inside of one of the configured port ranges
+    pub fn in_ranges(&self, port: u16) -> bool {
+        self.ranges
+            .iter()
+            .any(|(start, end)| (*start..=*end).contains(&port))
+    }
+
+    /// Return an iterator of the valid ports allowed by this configuration
     pub fn get_valid_ports(&self) -> impl Iterator<Item = u16> + '_ {
         self.ranges
             .iter()
@@ -64,7 +489,115 @@ impl Config {
     }
 }
 
+// Return the format version of a config file's table, which is 1 if it doesn't specify one
+fn table_version(table: &Table) -> anyhow::Result<i64> {
+    let version = match table.get("version") {
+        Some(version) => version
+            .as_integer()
+            .context("Validation error: version must be an integer\n")?,
+        None => 1,
+    };
+    if !(1..=CONFIG_VERSION).contains(&version) {
+        bail!("Validation error at version {version}, this version of portman only supports config versions 1 through {CONFIG_VERSION}\n")
+    }
+    Ok(version)
+}
+
+// Upgrade a config file's table to the current format version in place
+fn upgrade_table(table: &mut Table) -> anyhow::Result<()> {
+    // Version 2 only added sections, so version 1 settings are still valid as-is
+    table_version(table)?;
+    table.insert(String::from("version"), Value::Integer(CONFIG_VERSION));
+    Ok(())
+}
+
+/// Return the contents of a config file upgraded to the current format version, preserving its
+/// comments and formatting, or None if it is already up to date
+pub fn migrate(toml_str: &str) -> anyhow::Result<Option<String>> {
+    let table: Table = toml::from_str(toml_str)?;
+    if table_version(&table)? == CONFIG_VERSION {
+        return Ok(None);
+    }
+    // Only the version needs to be added, and top-level keys must come before any tables
+    Ok(Some(format!(
+        "# `version` is the config format version, which portman uses to upgrade old\n# config files\nversion = {CONFIG_VERSION}\n\n{toml_str}"
+    )))
+}
+
+/// Return the contents of a config file with a setting changed, preserving its comments and
+/// formatting
+///
+/// Nested settings are identified by dotted keys, like `defaults.no_link`, and values that aren't valid
+/// TOML, like unquoted words, are treated as strings
+pub fn set_value(toml_str: &str, key: &str, value: &str) -> anyhow::Result<String> {
+    let mut document = toml_str.parse::<Document>()?;
+    let segments = key.split('.').collect::<Vec<_>>();
+    let known_keys = Table::try_from(Config::default())?;
+    if !known_keys.contains_key(segments[0])
+        && !["caddyfile", "caddyfile_path", "extends"].contains(&segments[0])
+    {
+        bail!("Validation error: {key} is not a config setting\n")
+    }
+
+    let mut value = value
+        .parse::<toml_edit::Value>()
+        .unwrap_or_else(|_| toml_edit::Value::from(value));
+    value.decor_mut().clear();
+    let Some((last, parents)) = segments.split_last() else {
+        bail!("Validation error: the key must not be empty\n")
+    };
+    let mut table: &mut dyn TableLike = document.as_table_mut();
+    for segment in parents {
+        if table.get(segment).is_none() {
+            let mut parent = toml_edit::Table::new();
+            parent.set_implicit(true);
+            table.insert(segment, Item::Table(parent));
+        }
+        table = table
+            .get_mut(segment)
+            .and_then(Item::as_table_like_mut)
+            .with_context(|| format!("Validation error: {segment} is not a table\n"))?;
+    }
+    match table.get_mut(last) {
+        // Replace the value in place to keep the comments around it
+        Some(item) => {
+            if let Some(old_value) = item.as_value() {
+                *value.decor_mut() = old_value.decor().clone();
+            }
+            *item = Item::Value(value);
+        }
+        None => {
+            table.insert(last, Item::Value(value));
+        }
+    }
+    Ok(document.to_string())
+}
+
+// Merge a table over a base table
+// Nested tables are merged recursively, arrays are appended to the base array, and other values
+// replace the base value
+fn merge_tables(base: &mut Table, overrides: Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base_table)), Value::Table(table)) => {
+                merge_tables(base_table, table);
+            }
+            (Some(Value::Array(base_array)), Value::Array(array)) => {
+                for item in array {
+                    if !base_array.contains(&item) {
+                        base_array.push(item);
+                    }
+                }
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
 impl Display for Config {
+    #[allow(clippy::too_many_lines)]
     fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
         write!(
             fmt,
@@ -88,6 +621,123 @@ impl Display for Config {
             )?;
         }
 
+        if self.layout == RegistryLayout::Split {
+            write!(fmt, "\nRegistry layout: split")?;
+        }
+
+        if self.backups > 0 {
+            write!(fmt, "\nRegistry backups: {}", self.backups)?;
+        }
+
+        if self.bind != BindAddress::Loopback {
+            write!(fmt, "\nBind address: {}", self.bind)?;
+        }
+
+        if self.gallery_hostname != default_gallery_hostname() {
+            write!(fmt, "\nGallery hostname: {}", self.gallery_hostname)?;
+        }
+
+        if self.tld != default_tld() {
+            write!(fmt, "\nTLD: {}", self.tld)?;
+        }
+
+        if self.tls_mode == TlsMode::Off {
+            write!(fmt, "\nTLS: off")?;
+        }
+
+        if self.streaming {
+            write!(fmt, "\nStreaming: enabled")?;
+        }
+
+        if self.access_logs {
+            write!(fmt, "\nAccess logs: enabled")?;
+        }
+
+        if self.mdns {
+            write!(fmt, "\nmDNS: enabled")?;
+        }
+
+        if !self.auto_reload {
+            write!(fmt, "\nAuto reload: disabled")?;
+        }
+
+        if !self.require_caddy {
+            write!(fmt, "\nRequire caddy: disabled")?;
+        }
+
+        if self.auto_create {
+            write!(fmt, "\nAuto create: enabled")?;
+        }
+
+        if let Some(caddyfile) = self.caddyfile.as_ref() {
+            write!(fmt, "\nCaddyfile: {}", caddyfile.display())?;
+        }
+
+        if self.strict {
+            write!(fmt, "\nStrict mode: enabled")?;
+        }
+
+        if self.fallback == PortFallback::Ephemeral {
+            write!(fmt, "\nFallback: ephemeral")?;
+        }
+
+        if self.strategy == AllocationStrategy::Sequential {
+            write!(fmt, "\nAllocation strategy: sequential")?;
+        }
+
+        if !self.tag_ranges.is_empty() {
+            write!(
+                fmt,
+                "\nTag port ranges: {}",
+                self.tag_ranges
+                    .iter()
+                    .map(|(tag, (start, end))| format!("{tag} {start}-{end}"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )?;
+        }
+
+        for (hook, command) in [
+            ("on_create", &self.hooks.on_create),
+            ("on_delete", &self.hooks.on_delete),
+            ("on_link", &self.hooks.on_link),
+        ] {
+            if let Some(command) = command {
+                write!(fmt, "\nHook {hook}: {command}")?;
+            }
+        }
+
+        if !self.templates.is_empty() {
+            write!(
+                fmt,
+                "\nTemplates: {}",
+                self.templates
+                    .keys()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )?;
+        }
+
+        if self.defaults.no_link {
+            write!(fmt, "\nDefault linking: disabled")?;
+        }
+
+        if !self.defaults.tags.is_empty() {
+            write!(fmt, "\nDefault tags: {}", self.defaults.tags.join(", "))?;
+        }
+
+        let port_overrides = self
+            .project_overrides
+            .iter()
+            .filter_map(|(name, project_override)| {
+                project_override.port.map(|port| format!("{name} {port}"))
+            })
+            .collect::<Vec<_>>();
+        if !port_overrides.is_empty() {
+            write!(fmt, "\nProject ports: {}", port_overrides.join(", "))?;
+        }
+
         Ok(())
     }
 }
@@ -99,8 +749,9 @@ mod tests {
     use std::{
         io::{Error, ErrorKind},
         path::PathBuf,
+        sync::Arc,
     };
-    use unimock::{matching, MockFn, Unimock};
+    use unimock::{matching, Clause, MockFn, Unimock};
 
     #[test]
     fn test_load_config() {
@@ -115,7 +766,9 @@ mod tests {
             .unwrap()
             .unwrap();
         assert_eq!(config.ranges, vec![(3000, 3999)]);
-        assert_eq!(config.reserved, vec![]);
+        assert_eq!(config.reserved, Vec::<u16>::new());
+        // The version 1 file is upgraded as it is loaded
+        assert_eq!(config.version, CONFIG_VERSION);
     }
 
     #[test]
@@ -144,11 +797,82 @@ mod tests {
         assert!(matches!(err, ApplicationError::InvalidConfig(_)));
     }
 
+    // Mock reading a set of config files, which are the only files that exist
+    fn read_configs_mock(files: &'static [(&'static str, &'static str)]) -> impl Clause {
+        dependencies::ReadFileMock
+            .each_call(matching!(_))
+            .answers_arc(Arc::new(move |_, path| {
+                files
+                    .iter()
+                    .find(|(file, _)| path == Path::new(file))
+                    .map(|(_, contents)| String::from(*contents))
+                    .ok_or_else(|| Error::from(ErrorKind::NotFound))
+            }))
+            .at_least_times(1)
+    }
+
+    #[test]
+    fn test_load_config_extends() {
+        let deps = Unimock::new((
+            read_configs_mock(&[
+                (
+                    "/config/config.toml",
+                    "extends = ['base.toml', '~/shared.toml']\nreserved = [3005]\nbind = 'all'",
+                ),
+                (
+                    "/config/base.toml",
+                    "ranges = [[4000, 4999]]\nreserved = [4000]\n[tag_ranges]\nwork = [4100, 4199]",
+                ),
+                (
+                    "/home/user/shared.toml",
+                    "reserved = [4001]\nbind = 'loopback'",
+                ),
+            ]),
+            dependencies::EnvironmentMock
+                .each_call(matching!("HOME"))
+                .answers(&|_, _| Ok(String::from("/home/user")))
+                .once(),
+        ));
+
+        let config = Config::load(&deps, &PathBuf::from("/config/config.toml"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(config.ranges, vec![(4000, 4999)]);
+        assert_eq!(config.reserved, vec![4000, 4001, 3005]);
+        assert_eq!(config.bind, BindAddress::All);
+        assert_eq!(
+            config.tag_ranges,
+            BTreeMap::from([(String::from("work"), (4100, 4199))])
+        );
+    }
+
+    #[test]
+    fn test_load_config_extends_cycle() {
+        let deps = Unimock::new(read_configs_mock(&[
+            ("/config/a.toml", "extends = ['b.toml']"),
+            ("/config/b.toml", "extends = ['a.toml']"),
+        ]));
+
+        let err = Config::load(&deps, &PathBuf::from("/config/a.toml")).unwrap_err();
+        assert!(matches!(err, ApplicationError::InvalidConfig(_)));
+    }
+
+    #[test]
+    fn test_load_config_extends_missing() {
+        let deps = Unimock::new(read_configs_mock(&[(
+            "/config/config.toml",
+            "extends = ['base.toml']",
+        )]));
+
+        let err = Config::load(&deps, &PathBuf::from("/config/config.toml")).unwrap_err();
+        assert!(matches!(err, ApplicationError::InvalidConfig(_)));
+    }
+
     #[test]
     fn test_empty_config() {
         let config = Config::from_toml("").unwrap();
         assert_eq!(config.ranges, vec![(3000, 3999)]);
-        assert_eq!(config.reserved, vec![]);
+        assert_eq!(config.reserved, Vec::<u16>::new());
     }
 
     #[test]
@@ -169,6 +893,310 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_tag_ranges() {
+        let config = Config::from_toml("[tag_ranges]\nwork = [3100, 3199]").unwrap();
+        assert_eq!(
+            config.tag_ranges,
+            BTreeMap::from([(String::from("work"), (3100, 3199))])
+        );
+    }
+
+    #[test]
+    fn test_inverted_tag_ranges() {
+        let result = Config::from_toml("[tag_ranges]\nwork = [3199, 3100]");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_tag_ranges_outside_ranges() {
+        let result =
+            Config::from_toml("ranges = [[3000, 3999]]\n[tag_ranges]\nwork = [3900, 4099]");
+        assert!(result.is_err());
+        let result = Config::from_toml(
+            "ranges = [[3000, 3999], [4000, 4999]]\n[tag_ranges]\nwork = [3900, 4099]",
+        );
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_tag_ranges_reserved() {
+        let result =
+            Config::from_toml("reserved = [3100, 3101]\n[tag_ranges]\nwork = [3100, 3101]");
+        assert!(result.is_err());
+        let result = Config::from_toml("reserved = [3100]\n[tag_ranges]\nwork = [3100, 3101]");
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_layout() {
+        assert_eq!(
+            Config::from_toml("").unwrap().layout,
+            RegistryLayout::Single
+        );
+        assert_eq!(
+            Config::from_toml("layout = 'split'").unwrap().layout,
+            RegistryLayout::Split
+        );
+        assert!(Config::from_toml("layout = 'other'").is_err());
+    }
+
+    #[test]
+    fn test_fallback() {
+        assert_eq!(Config::from_toml("").unwrap().fallback, PortFallback::None);
+        assert_eq!(
+            Config::from_toml("fallback = 'ephemeral'")
+                .unwrap()
+                .fallback,
+            PortFallback::Ephemeral
+        );
+        assert!(Config::from_toml("fallback = 'random'").is_err());
+    }
+
+    #[test]
+    fn test_streaming() {
+        assert!(!Config::from_toml("").unwrap().streaming);
+        assert!(Config::from_toml("streaming = true").unwrap().streaming);
+    }
+
+    #[test]
+    fn test_auto_reload() {
+        assert!(Config::from_toml("").unwrap().auto_reload);
+        assert!(
+            !Config::from_toml("auto_reload = false")
+                .unwrap()
+                .auto_reload
+        );
+    }
+
+    #[test]
+    fn test_require_caddy() {
+        assert!(Config::from_toml("").unwrap().require_caddy);
+        assert!(
+            !Config::from_toml("require_caddy = false")
+                .unwrap()
+                .require_caddy
+        );
+    }
+
+    #[test]
+    fn test_auto_create() {
+        assert!(!Config::from_toml("").unwrap().auto_create);
+        assert!(Config::from_toml("auto_create = true").unwrap().auto_create);
+    }
+
+    #[test]
+    fn test_mdns() {
+        assert!(!Config::from_toml("").unwrap().mdns);
+        assert!(Config::from_toml("mdns = true").unwrap().mdns);
+    }
+
+    #[test]
+    fn test_access_logs() {
+        assert!(!Config::from_toml("").unwrap().access_logs);
+        assert!(Config::from_toml("access_logs = true").unwrap().access_logs);
+    }
+
+    #[test]
+    fn test_bind() {
+        assert_eq!(Config::from_toml("").unwrap().bind, BindAddress::Loopback);
+        assert_eq!(
+            Config::from_toml("bind = 'all'").unwrap().bind,
+            BindAddress::All
+        );
+        assert_eq!(
+            Config::from_toml("bind = '192.168.1.10'").unwrap().bind,
+            BindAddress::Address(String::from("192.168.1.10"))
+        );
+        assert!(Config::from_toml("bind = '192.168.1.10 10.0.0.1'").is_err());
+    }
+
+    #[test]
+    fn test_gallery_hostname() {
+        assert_eq!(Config::from_toml("").unwrap().gallery_hostname, "localhost");
+        assert_eq!(
+            Config::from_toml("gallery_hostname = 'portman.localhost'")
+                .unwrap()
+                .gallery_hostname,
+            "portman.localhost"
+        );
+        assert!(Config::from_toml("gallery_hostname = ''").is_err());
+        assert!(Config::from_toml("gallery_hostname = 'a b'").is_err());
+    }
+
+    #[test]
+    fn test_strategy() {
+        assert_eq!(
+            Config::from_toml("").unwrap().strategy,
+            AllocationStrategy::Random
+        );
+        assert_eq!(
+            Config::from_toml("strategy = 'sequential'")
+                .unwrap()
+                .strategy,
+            AllocationStrategy::Sequential
+        );
+        assert!(Config::from_toml("strategy = 'lowest'").is_err());
+    }
+
+    #[test]
+    fn test_tld() {
+        let config = Config::from_toml("").unwrap();
+        assert_eq!(config.tld, "localhost");
+        assert_eq!(config.tls_mode, TlsMode::Internal);
+        let config = Config::from_toml("tld = 'test'\ntls_mode = 'off'").unwrap();
+        assert_eq!(config.tld, "test");
+        assert_eq!(config.tls_mode, TlsMode::Off);
+        assert!(Config::from_toml("tld = 'dev.test'").is_ok());
+        assert!(Config::from_toml("tld = ''").is_err());
+        assert!(Config::from_toml("tld = '.test'").is_err());
+        assert!(Config::from_toml("tld = 'Test'").is_err());
+        assert!(Config::from_toml("tls_mode = 'acme'").is_err());
+    }
+
+    #[test]
+    fn test_caddyfile() {
+        assert!(Config::from_toml("").unwrap().caddyfile.is_none());
+        let config = Config::from_toml("caddyfile = 'C:\\Caddy\\Caddyfile'").unwrap();
+        assert_eq!(
+            config.caddyfile,
+            Some(PathBuf::from("C:\\Caddy\\Caddyfile"))
+        );
+        assert!(config
+            .to_string()
+            .contains("\nCaddyfile: C:\\Caddy\\Caddyfile"));
+
+        assert_eq!(
+            Config::from_toml("caddyfile_path = '/etc/caddy/Caddyfile'")
+                .unwrap()
+                .caddyfile,
+            Some(PathBuf::from("/etc/caddy/Caddyfile"))
+        );
+    }
+
+    #[test]
+    fn test_version() {
+        assert_eq!(Config::from_toml("").unwrap().version, CONFIG_VERSION);
+        assert_eq!(Config::from_toml("version = 2").unwrap().version, 2);
+        assert!(Config::from_toml("version = 3").is_err());
+        assert!(Config::from_toml("version = 0").is_err());
+        assert!(Config::from_toml("version = '2'").is_err());
+    }
+
+    #[test]
+    fn test_defaults() {
+        let config =
+            Config::from_toml("version = 2\n[defaults]\nno_link = true\ntags = ['work']").unwrap();
+        assert_eq!(
+            config.defaults,
+            ProjectDefaults {
+                no_link: true,
+                tags: vec![String::from("work")],
+            }
+        );
+    }
+
+    #[test]
+    fn test_backups() {
+        assert_eq!(Config::from_toml("").unwrap().backups, 0);
+        let config = Config::from_toml("backups = 5").unwrap();
+        assert_eq!(config.backups, 5);
+        assert!(config.to_string().contains("\nRegistry backups: 5"));
+        assert!(Config::from_toml("backups = -1").is_err());
+    }
+
+    #[test]
+    fn test_templates() {
+        let config = Config::from_toml(
+            "version = 2\n[templates]\n'.env.local' = 'templates/env'\n'docker/compose.yml' = '~/compose.yml'",
+        )
+        .unwrap();
+        assert_eq!(config.templates[".env.local"], "templates/env");
+        assert!(config
+            .to_string()
+            .contains("\nTemplates: .env.local, docker/compose.yml"));
+        assert!(Config::from_toml("[templates]\n'../.env' = 'env'").is_err());
+        assert!(Config::from_toml("[templates]\n'/tmp/.env' = 'env'").is_err());
+        assert!(Config::from_toml("[templates]\n'' = 'env'").is_err());
+    }
+
+    #[test]
+    fn test_project_overrides() {
+        let config =
+            Config::from_toml("version = 2\n[project_overrides.app1]\nport = 3123").unwrap();
+        assert_eq!(config.project_overrides["app1"].port, Some(3123));
+        assert!(Config::from_toml("[project_overrides.app1]\nport = 5000").is_err());
+    }
+
+    #[test]
+    fn test_set_value() {
+        assert_eq!(
+            set_value(
+                "# Work ports\nranges = [[3000, 3099]] # Inclusive\n\n[defaults]\nno_link = false\n",
+                "ranges",
+                "[[4000, 4099], [5000, 5099]]"
+            )
+            .unwrap(),
+            "# Work ports\nranges = [[4000, 4099], [5000, 5099]] # Inclusive\n\n[defaults]\nno_link = false\n"
+        );
+        assert_eq!(
+            set_value("[defaults]\nno_link = false\n", "defaults.no_link", "true").unwrap(),
+            "[defaults]\nno_link = true\n"
+        );
+    }
+
+    #[test]
+    fn test_set_value_new() {
+        assert_eq!(
+            set_value("# Work ports\nranges = [[3000, 3099]]\n", "tld", "test").unwrap(),
+            "# Work ports\nranges = [[3000, 3099]]\ntld = \"test\"\n"
+        );
+        assert_eq!(
+            set_value("", "project_overrides.app1.port", "3001").unwrap(),
+            "[project_overrides.app1]\nport = 3001\n"
+        );
+    }
+
+    #[test]
+    fn test_set_value_invalid() {
+        assert!(set_value("", "unknown", "1").is_err());
+        assert!(set_value("", "", "1").is_err());
+        assert!(set_value("ranges = [[3000, 3099]]\n", "ranges.start", "1").is_err());
+    }
+
+    #[test]
+    fn test_migrate() {
+        assert_eq!(
+            migrate("# Work ports\nranges = [[3000, 3099]]\n").unwrap(),
+            Some(String::from("# `version` is the config format version, which portman uses to upgrade old\n# config files\nversion = 2\n\n# Work ports\nranges = [[3000, 3099]]\n"))
+        );
+        assert_eq!(
+            migrate("version = 2\nranges = [[3000, 3099]]\n").unwrap(),
+            None
+        );
+        assert!(migrate("version = 3").is_err());
+    }
+
+    #[test]
+    fn test_display_defaults() {
+        let config = Config::from_toml(
+            "[defaults]\nno_link = true\ntags = ['work', 'api']\n[project_overrides.app1]\nport = 3123",
+        )
+        .unwrap();
+        assert_eq!(
+            format!("{config}"),
+            "Allowed port ranges: 3000-3999\nDefault linking: disabled\nDefault tags: work, api\nProject ports: app1 3123",
+        );
+    }
+
+    #[test]
+    fn test_in_ranges() {
+        let config = Config::from_toml("ranges = [[3000, 3002], [4000, 4005]]").unwrap();
+        assert!(config.in_ranges(3000));
+        assert!(config.in_ranges(4005));
+        assert!(!config.in_ranges(3003));
+    }
+
     #[test]
     fn test_valid_ports() {
         let config = Config::from_toml(
@@ -193,6 +1221,18 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_display_tag_ranges() {
+        let config = Config::from_toml(
+            "ranges = [[3000, 3999]]\n[tag_ranges]\nwork = [3100, 3199]\npersonal = [3200, 3299]",
+        )
+        .unwrap();
+        assert_eq!(
+            format!("{config}"),
+            "Allowed port ranges: 3000-3999\nTag port ranges: personal 3200-3299, work 3100-3199",
+        );
+    }
+
     #[test]
     fn test_display_none_reserved() {
         let config = Config::from_toml("ranges = [[3000, 3999], [4500, 4999]]").unwrap();
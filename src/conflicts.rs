@@ -0,0 +1,242 @@
+use crate::caddy;
+use crate::config::Config;
+use crate::dependencies::{DataDir, ProbePort, ReadFile};
+use crate::error::{ApplicationError, CaddyError, Result};
+use crate::registry::Registry;
+use serde::Serialize;
+use std::fmt::Write;
+
+// A project port that conflicts with the config, the system, or another process
+#[derive(Serialize)]
+pub struct Conflict {
+    pub project: String,
+    pub port: u16,
+    // The conflicting port's role in the project, like "port", "linked port", or "api port"
+    pub role: String,
+    // Why the port conflicts, serialized as "conflict" in the JSON output
+    #[serde(rename = "conflict")]
+    pub reason: String,
+    pub suggestion: String,
+}
+
+// Explain why a port that was assigned to a project is no longer available
+fn unavailable_reason(config: &Config, port: u16) -> &'static str {
+    if config.reserved.contains(&port) {
+        "reserved in the config"
+    } else if !config.in_ranges(port) {
+        "outside of the configured port ranges"
+    } else {
+        "used by another project"
+    }
+}
+
+// Find the project ports that conflict with the configured port ranges, the reserved ports, the
+// system's well-known ports, or the ports that other processes are listening on
+pub fn find(
+    deps: &(impl DataDir + ProbePort + ReadFile),
+    config: &Config,
+    registry: &Registry,
+) -> Result<Vec<Conflict>> {
+    // Ports that became unavailable were already moved when the registry was loaded
+    let mut conflicts = registry
+        .reassignments()
+        .iter()
+        .map(|reassignment| Conflict {
+            project: reassignment.project.clone(),
+            port: reassignment.old_port,
+            role: reassignment
+                .port_name
+                .as_ref()
+                .map_or_else(|| String::from("port"), |name| format!("{name} port")),
+            reason: String::from(unavailable_reason(config, reassignment.old_port)),
+            suggestion: format!(
+                "portman moves it to port {} the next time it saves the registry",
+                reassignment.new_port
+            ),
+        })
+        .collect::<Vec<_>>();
+
+    for (name, project) in registry.iter_projects() {
+        // Fallback and pinned ports are allowed to stay outside of the configured port ranges
+        if !config.in_ranges(project.port) {
+            conflicts.push(Conflict {
+                project: name.clone(),
+                port: project.port,
+                role: String::from("port"),
+                reason: String::from("outside of the configured port ranges"),
+                suggestion: format!(
+                    "Run `portman handoff {name}` to move it to a port in the configured ranges"
+                ),
+            });
+        }
+
+        let linked_ports = project
+            .linked_port
+            .map(|port| (String::from("linked port"), port))
+            .into_iter()
+            .chain(
+                project
+                    .linked_ports
+                    .iter()
+                    .map(|(port_name, port)| (format!("{port_name} linked port"), *port)),
+            );
+        for (role, linked_port) in linked_ports {
+            let conflict = if linked_port < 1024 {
+                "a well-known system port that caddy needs elevated privileges to listen on"
+            } else if config.reserved.contains(&linked_port) {
+                "reserved in the config"
+            } else {
+                continue;
+            };
+            conflicts.push(Conflict {
+                project: name.clone(),
+                port: linked_port,
+                role,
+                reason: String::from(conflict),
+                suggestion: format!(
+                    "Run `portman unlink {linked_port}` and link the project to a different port"
+                ),
+            });
+        }
+    }
+
+    // Caddy needs to be able to listen on every linked port
+    match caddy::check_port_conflicts(deps, registry) {
+        Ok(()) => {}
+        Err(CaddyError::PortConflicts(in_use)) => conflicts.extend(in_use.into_iter().map(|(project, port)| Conflict {
+            project,
+            port,
+            role: String::from("linked port"),
+            reason: String::from("another process is listening on it"),
+            suggestion: format!(
+                "Stop the other process or run `portman unlink {port}` and link the project to a different port"
+            ),
+        })),
+        Err(err) => return Err(ApplicationError::Caddy(err)),
+    }
+
+    conflicts.sort_by(|conflict1, conflict2| {
+        (&conflict1.project, conflict1.port).cmp(&(&conflict2.project, conflict2.port))
+    });
+    Ok(conflicts)
+}
+
+// Format conflicts as a table with a row for each conflict
+pub fn format_table(conflicts: &[Conflict]) -> String {
+    let headers = ["PROJECT", "PORT", "ROLE", "CONFLICT", "SUGGESTION"];
+    let rows = conflicts
+        .iter()
+        .map(|conflict| {
+            [
+                conflict.project.clone(),
+                conflict.port.to_string(),
+                conflict.role.clone(),
+                conflict.reason.clone(),
+                conflict.suggestion.clone(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let widths = headers.map(str::len);
+    let widths = rows.iter().fold(widths, |mut widths, row| {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+        widths
+    });
+
+    let mut output = String::new();
+    for row in std::iter::once(headers.map(String::from)).chain(rows) {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        let _ = writeln!(output, "{}", line.trim_end());
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dependencies::{ProbePortMock, ReadFileMock};
+    use crate::mocks::{data_dir_mock, get_mocked_registry};
+    use std::path::PathBuf;
+    use unimock::{matching, Clause, MockFn, Unimock};
+
+    fn caddyfile_mock() -> impl Clause {
+        ReadFileMock
+            .each_call(matching!((path) if path == &PathBuf::from("/data/Caddyfile")))
+            .answers(&|_, _| Ok(String::from("http://localhost:3000 {\n}\n")))
+    }
+
+    #[test]
+    fn test_find_none() {
+        let mocked_deps = Unimock::new((data_dir_mock(), caddyfile_mock()));
+        let registry = get_mocked_registry().unwrap();
+        assert!(find(&mocked_deps, &Config::default(), &registry)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_find() {
+        let mocked_deps = Unimock::new((
+            data_dir_mock(),
+            caddyfile_mock(),
+            ProbePortMock
+                .each_call(matching!(80))
+                .answers(&|_, _| false)
+                .once(),
+        ));
+        let mut registry = get_mocked_registry().unwrap();
+        registry.link(&mocked_deps, "app1", None, 80).unwrap();
+        let config = Config {
+            reserved: vec![3000],
+            ..Default::default()
+        };
+        let conflicts = find(&mocked_deps, &config, &registry).unwrap();
+        assert_eq!(
+            conflicts
+                .iter()
+                .map(|conflict| (conflict.project.as_str(), conflict.port))
+                .collect::<Vec<_>>(),
+            vec![("app1", 80), ("app2", 3000)]
+        );
+        assert_eq!(conflicts[1].reason, "reserved in the config");
+    }
+
+    #[test]
+    fn test_find_in_use() {
+        let mocked_deps = Unimock::new((
+            data_dir_mock(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/Caddyfile")))
+                .answers(&|_, _| Ok(String::new())),
+            ProbePortMock
+                .each_call(matching!(3000))
+                .answers(&|_, _| true)
+                .once(),
+        ));
+        let registry = get_mocked_registry().unwrap();
+        let conflicts = find(&mocked_deps, &Config::default(), &registry).unwrap();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].reason, "another process is listening on it");
+    }
+
+    #[test]
+    fn test_format_table() {
+        let conflicts = vec![Conflict {
+            project: String::from("app1"),
+            port: 80,
+            role: String::from("linked port"),
+            reason: String::from("reserved in the config"),
+            suggestion: String::from("Run `portman unlink 80`"),
+        }];
+        assert_eq!(
+            format_table(&conflicts),
+            "PROJECT  PORT  ROLE         CONFLICT                SUGGESTION\napp1     80    linked port  reserved in the config  Run `portman unlink 80`\n"
+        );
+    }
+}
@@ -1,45 +1,245 @@
+//! The side effects that portman performs, like reading files and running commands, abstracted
+//! as traits so that they can be mocked in tests.
+//!
+//! Every trait is implemented by [`Impl`](entrait::Impl), so `Impl::new(())` provides the real
+//! implementations to functions that accept `deps`.
+
 #![allow(clippy::ignored_unit_patterns)]
 
 use crate::error::{ExecError, ExecResult};
-use anyhow::{Context, Result};
+pub use crate::version::Build;
+use anyhow::{bail, Context, Result};
 use entrait::entrait;
 use rand::prelude::*;
 use std::collections::HashSet;
 use std::ffi::{OsStr, OsString};
-use std::io::{stdout, IsTerminal};
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{stderr, stdin, stdout, BufRead, BufReader, IsTerminal, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket};
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 #[entrait(pub Args, mock_api=ArgsMock)]
 fn get_args(_deps: &impl std::any::Any) -> Vec<String> {
     std::env::args().collect()
 }
 
+#[entrait(pub BuildInfo, mock_api=BuildInfoMock)]
+fn get_build_info(_deps: &impl std::any::Any) -> Build {
+    Build {
+        version: env!("CARGO_PKG_VERSION"),
+        target: env!("PORTMAN_TARGET"),
+        profile: env!("PORTMAN_PROFILE"),
+        static_binary: cfg!(target_feature = "crt-static"),
+        platform: format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH),
+    }
+}
+
 #[entrait(pub CheckPath, mock_api=CheckPathMock)]
 fn path_exists(_deps: &impl std::any::Any, path: &Path) -> bool {
     path.exists()
 }
 
+#[entrait(pub CheckPortInUse, mock_api=CheckPortInUseMock)]
+fn is_port_in_use(_deps: &impl std::any::Any, port: u16) -> bool {
+    // The port is in use if another process is listening on it on any interface
+    TcpListener::bind((Ipv4Addr::UNSPECIFIED, port)).is_err()
+        || TcpListener::bind((Ipv4Addr::LOCALHOST, port)).is_err()
+}
+
+#[entrait(pub CheckWritable, mock_api=CheckWritableMock)]
+fn check_writable(_deps: &impl std::any::Any, dir: &Path) -> std::io::Result<()> {
+    // Write a probe file because permissions alone don't account for read-only filesystems
+    std::fs::create_dir_all(dir)?;
+    let probe_path = dir.join(".portman-probe");
+    std::fs::write(&probe_path, "")?;
+    std::fs::remove_file(probe_path)
+}
+
 #[entrait(pub ChoosePort, mock_api=ChoosePortMock)]
 fn choose_port(_deps: &impl std::any::Any, available_ports: &HashSet<u16>) -> Option<u16> {
     let mut rng = rand::thread_rng();
     available_ports.iter().choose(&mut rng).copied()
 }
 
+#[entrait(pub CurrentExe, mock_api=CurrentExeMock)]
+fn get_current_exe(_deps: &impl std::any::Any) -> Result<PathBuf> {
+    std::env::current_exe().context("Failed to determine the path to the portman executable")
+}
+
+#[entrait(pub CurrentTime, mock_api=CurrentTimeMock)]
+fn get_current_time(_deps: &impl std::any::Any) -> u64 {
+    // Return the number of seconds since the Unix epoch
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
 #[entrait(pub DataDir, mock_api=DataDirMock)]
-fn get_data_dir(_deps: &impl std::any::Any) -> Result<PathBuf> {
+fn get_data_dir(deps: &impl Environment) -> Result<PathBuf> {
+    if let Some(data_dir) = deps
+        .read_var("PORTMAN_DATA_DIR")
+        .ok()
+        .filter(|data_dir| !data_dir.is_empty())
+    {
+        return Ok(PathBuf::from(data_dir));
+    }
+
+    // On Linux, the default data directory is in $XDG_DATA_HOME if it is set
     let project_dirs = directories::ProjectDirs::from("com", "canac", "portman")
         .context("Failed to determine application directories")?;
     let data_dir = project_dirs.data_local_dir().to_owned();
     Ok(data_dir)
 }
 
+/// Dependencies that store the registry and other state in an explicit data directory
+///
+/// The data directory replaces the one that `$PORTMAN_DATA_DIR` or the platform determines, and
+/// everything else is delegated to the wrapped dependencies. This is how the CLI implements
+/// `--data-dir`.
+pub struct DataDirOverride<'deps, Deps> {
+    deps: &'deps Deps,
+    data_dir: PathBuf,
+}
+
+impl<'deps, Deps> DataDirOverride<'deps, Deps> {
+    pub const fn new(deps: &'deps Deps, data_dir: PathBuf) -> Self {
+        Self { deps, data_dir }
+    }
+}
+
+impl<Deps> DataDir for DataDirOverride<'_, Deps> {
+    fn get_data_dir(&self) -> Result<PathBuf> {
+        Ok(self.data_dir.clone())
+    }
+}
+
+// Implement dependency traits for DataDirOverride by forwarding them to the wrapped dependencies
+macro_rules! delegate_to_deps {
+    ($($trait:ident::$method:ident($($arg:ident: $ty:ty),*) -> $ret:ty;)*) => {
+        $(
+            impl<Deps: $trait> $trait for DataDirOverride<'_, Deps> {
+                fn $method(&self, $($arg: $ty),*) -> $ret {
+                    self.deps.$method($($arg),*)
+                }
+            }
+        )*
+    };
+}
+
+delegate_to_deps! {
+    Args::get_args() -> Vec<String>;
+    BuildInfo::get_build_info() -> Build;
+    CheckPath::path_exists(path: &Path) -> bool;
+    CheckPortInUse::is_port_in_use(port: u16) -> bool;
+    CheckWritable::check_writable(dir: &Path) -> std::io::Result<()>;
+    ChoosePort::choose_port(available_ports: &HashSet<u16>) -> Option<u16>;
+    CurrentExe::get_current_exe() -> Result<PathBuf>;
+    CurrentTime::get_current_time() -> u64;
+    Environment::read_var(var: &str) -> Result<String>;
+    EphemeralPort::get_ephemeral_port() -> Result<u16>;
+    Exec::exec(command: &mut Command) -> ExecResult<String>;
+    LanAddress::get_lan_address() -> Option<IpAddr>;
+    ListDirectory::list_directory(path: &Path) -> Result<Vec<PathBuf>>;
+    LockFile::lock_file(path: &Path, timeout: Duration) -> Result<Option<FileLock>>;
+    ProbeHttp::probe_http(port: u16, path: &str) -> bool;
+    ProbePort::probe_port(port: u16) -> bool;
+    Prompt::prompt(message: &str) -> Result<String>;
+    ReadFile::read_file(path: &Path) -> Result<Option<String>>;
+    ReadStdin::read_stdin() -> Result<String>;
+    ResolveHost::resolves_to_loopback(hostname: &str) -> bool;
+    Spawn::spawn(command: &mut Command) -> ExecResult<i32>;
+    Tty::is_tty() -> bool;
+    WalkDir::walk_dir(root: &Path, max_depth: usize) -> Result<Vec<PathBuf>>;
+    WatchPath::get_modified_time(path: &Path) -> Option<SystemTime>;
+    WorkingDirectory::get_cwd() -> Result<PathBuf>;
+    WriteFile::write_file(path: &Path, contents: &str) -> Result<()>;
+}
+
 #[entrait(pub Environment, mock_api=EnvironmentMock)]
 pub fn read_var(_deps: &impl std::any::Any, var: &str) -> Result<String> {
     let var_name = OsString::from(var);
     std::env::var(var_name).with_context(|| format!("Failed to read ${var} environment variable"))
 }
 
+#[entrait(pub EphemeralPort, mock_api=EphemeralPortMock)]
+fn get_ephemeral_port(_deps: &impl std::any::Any) -> Result<u16> {
+    // Binding to port 0 makes the OS pick a free port, which is released when the listener drops
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .context("Failed to bind to an ephemeral port")?;
+    Ok(listener
+        .local_addr()
+        .context("Failed to read the ephemeral port")?
+        .port())
+}
+
+/// Return the address that other devices on the local network can reach this machine at
+#[entrait(pub LanAddress, mock_api=LanAddressMock)]
+fn get_lan_address(_deps: &impl std::any::Any) -> Option<IpAddr> {
+    // Connecting a UDP socket doesn't send any packets, but it picks the interface that routes to
+    // the mDNS multicast group
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+    socket.connect((Ipv4Addr::new(224, 0, 0, 251), 5353)).ok()?;
+    let address = socket.local_addr().ok()?.ip();
+    (!address.is_unspecified() && !address.is_loopback()).then_some(address)
+}
+
+#[entrait(pub ListDirectory, mock_api=ListDirectoryMock)]
+fn list_directory(_deps: &impl std::any::Any, path: &Path) -> Result<Vec<PathBuf>> {
+    let entries = match std::fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(io_err) if io_err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(io_err) => {
+            return Err(io_err)
+                .with_context(|| format!("Failed to read directory at \"{}\"", path.display()))
+        }
+    };
+    entries
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()
+        .with_context(|| format!("Failed to read directory at \"{}\"", path.display()))
+}
+
+/// An exclusive lock on a file that is released when it is dropped
+#[derive(Debug, Default)]
+pub struct FileLock {
+    _file: Option<File>,
+}
+
+/// Return None if another process holds the lock for longer than the timeout
+#[entrait(pub LockFile, mock_api=LockFileMock)]
+fn lock_file(
+    _deps: &impl std::any::Any,
+    path: &Path,
+    timeout: Duration,
+) -> Result<Option<FileLock>> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory at \"{}\"", parent.display()))?;
+    }
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(path)
+        .with_context(|| format!("Failed to open lock file at \"{}\"", path.display()))?;
+    let start = Instant::now();
+    loop {
+        match file.try_lock() {
+            Ok(()) => return Ok(Some(FileLock { _file: Some(file) })),
+            Err(TryLockError::WouldBlock) if start.elapsed() < timeout => {
+                std::thread::sleep(Duration::from_millis(50));
+            }
+            Err(TryLockError::WouldBlock) => return Ok(None),
+            Err(TryLockError::Error(io_err)) => {
+                return Err(io_err)
+                    .with_context(|| format!("Failed to lock file at \"{}\"", path.display()))
+            }
+        }
+    }
+}
+
 pub enum ExecStatus {
     Success { output: String },
     Failure { output: String, code: i32 },
@@ -70,6 +270,7 @@ fn low_level_exec(
     })
 }
 
+/// Run a command and return its output, failing if it doesn't exit successfully
 pub trait Exec {
     fn exec(&self, command: &mut Command) -> ExecResult<String>;
 }
@@ -105,11 +306,67 @@ impl<T: LowLevelExec> Exec for T {
     }
 }
 
+#[entrait(pub ProbeHttp, mock_api=ProbeHttpMock)]
+fn probe_http(_deps: &impl std::any::Any, port: u16, path: &str) -> bool {
+    // The path is healthy if it responds with a successful or redirect status code
+    let address = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+    let Ok(mut stream) = TcpStream::connect_timeout(&address, Duration::from_millis(200)) else {
+        return false;
+    };
+    let timeout = Some(Duration::from_secs(2));
+    if stream.set_read_timeout(timeout).is_err() || stream.set_write_timeout(timeout).is_err() {
+        return false;
+    }
+    let request =
+        format!("GET {path} HTTP/1.0\r\nHost: localhost:{port}\r\nConnection: close\r\n\r\n");
+    if stream.write_all(request.as_bytes()).is_err() {
+        return false;
+    }
+    let mut status_line = String::new();
+    if BufReader::new(stream).read_line(&mut status_line).is_err() {
+        return false;
+    }
+    status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse::<u16>().ok())
+        .is_some_and(|code| (200..400).contains(&code))
+}
+
+#[entrait(pub ProbePort, mock_api=ProbePortMock)]
+fn probe_port(_deps: &impl std::any::Any, port: u16) -> bool {
+    let address = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+    TcpStream::connect_timeout(&address, Duration::from_millis(200)).is_ok()
+}
+
+#[entrait(pub Prompt, mock_api=PromptMock)]
+fn prompt(_deps: &impl std::any::Any, message: &str) -> Result<String> {
+    let mut stderr = stderr();
+    write!(stderr, "{message}")
+        .and_then(|()| stderr.flush())
+        .context("Failed to write prompt")?;
+    let mut answer = String::new();
+    let bytes_read = stdin()
+        .read_line(&mut answer)
+        .context("Failed to read answer from stdin")?;
+    // An empty answer means Enter was pressed, so stdin closing must not be mistaken for it
+    if bytes_read == 0 {
+        bail!("Input ended before an answer was provided");
+    }
+    Ok(answer.trim().to_owned())
+}
+
+#[entrait(pub ReadStdin, mock_api=ReadStdinMock)]
+fn read_stdin(_deps: &impl std::any::Any) -> Result<String> {
+    std::io::read_to_string(stdin()).context("Failed to read stdin")
+}
+
 #[entrait(pub LowLevelReadFile, mock_api=ReadFileMock)]
 fn low_level_read_file(_deps: &impl std::any::Any, path: &Path) -> std::io::Result<String> {
     std::fs::read_to_string(path)
 }
 
+/// Read a file, returning None if it doesn't exist
 pub trait ReadFile {
     fn read_file(&self, path: &Path) -> Result<Option<String>>;
 }
@@ -130,11 +387,73 @@ impl<T: LowLevelReadFile> ReadFile for T {
     }
 }
 
+#[entrait(pub ResolveHost, mock_api=ResolveHostMock)]
+fn resolves_to_loopback(_deps: &impl std::any::Any, hostname: &str) -> bool {
+    // The hostname resolves to this machine if every address it resolves to is a loopback address
+    (hostname, 0).to_socket_addrs().is_ok_and(|addresses| {
+        let addresses = addresses.collect::<Vec<_>>();
+        !addresses.is_empty() && addresses.iter().all(|address| address.ip().is_loopback())
+    })
+}
+
+/// Run a command with inherited stdio and return its exit code
+#[entrait(pub Spawn, mock_api=SpawnMock)]
+fn spawn(_deps: &impl std::any::Any, command: &mut Command) -> ExecResult<i32> {
+    let status = command.status().map_err(|io_err| ExecError::IO {
+        command: format_command(command),
+        io_err,
+    })?;
+    // Processes terminated by a signal don't have an exit code
+    Ok(status.code().unwrap_or(1))
+}
+
 #[entrait(pub Tty, mock_api=TtyMock)]
 fn is_tty(_deps: &impl std::any::Any) -> bool {
     stdout().is_terminal()
 }
 
+/// Return the paths of the files and directories in a directory tree, up to a maximum depth
+/// Hidden directories and dependency and build directories are listed but not descended into
+#[entrait(pub WalkDir, mock_api=WalkDirMock)]
+fn walk_dir(_deps: &impl std::any::Any, root: &Path, max_depth: usize) -> Result<Vec<PathBuf>> {
+    let mut paths = vec![];
+    let mut pending = vec![(root.to_owned(), 0)];
+    while let Some((directory, depth)) = pending.pop() {
+        let entries = match std::fs::read_dir(&directory) {
+            Ok(entries) => entries,
+            Err(io_err) if depth == 0 => {
+                return Err(io_err)
+                    .with_context(|| format!("Failed to read directory at \"{}\"", root.display()))
+            }
+            // Skip subdirectories that can't be read instead of failing the whole walk
+            Err(_) => continue,
+        };
+        for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+            let descend = depth < max_depth
+                && path.is_dir()
+                && !path
+                    .file_name()
+                    .and_then(OsStr::to_str)
+                    .is_some_and(|name| {
+                        name.starts_with('.') || name == "node_modules" || name == "target"
+                    });
+            if descend {
+                pending.push((path.clone(), depth + 1));
+            }
+            paths.push(path);
+        }
+    }
+    Ok(paths)
+}
+
+#[entrait(pub WatchPath, mock_api=WatchPathMock)]
+fn get_modified_time(_deps: &impl std::any::Any, path: &Path) -> Option<SystemTime> {
+    // Return None if the path doesn't exist
+    std::fs::metadata(path)
+        .ok()
+        .map(|metadata| metadata.modified().unwrap_or(UNIX_EPOCH))
+}
+
 #[entrait(pub WorkingDirectory, mock_api=WorkingDirectoryMock)]
 fn get_cwd(_deps: &impl std::any::Any) -> Result<PathBuf> {
     std::env::current_dir().context("Failed to get current directory")
@@ -154,6 +473,13 @@ fn write_file(_deps: &impl std::any::Any, path: &Path, contents: &str) -> Result
             parent_dir.display()
         )
     })?;
-    std::fs::write(path, contents)
+
+    // Write to a temporary file and rename it so that readers never see a partially written file
+    // Symlinks are resolved first so that the rename replaces the target instead of the link
+    let target_path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+    let mut temp_path = target_path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    std::fs::write(&temp_path, contents)
+        .and_then(|()| std::fs::rename(&temp_path, &target_path))
         .with_context(|| format!("Failed to write file at \"{}\"", path.display()))
 }
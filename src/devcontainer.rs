@@ -0,0 +1,482 @@
+use crate::dependencies::{ReadFile, WriteFile};
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Map, Value};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+// Return the path to the devcontainer configuration in a directory
+pub fn config_path(directory: &Path) -> PathBuf {
+    directory.join(".devcontainer").join("devcontainer.json")
+}
+
+// Return the label that identifies ports managed by portman in the devcontainer configuration
+fn port_label(name: &str) -> String {
+    format!("{name} (portman)")
+}
+
+// Strip // and /* */ comments from a JSONC string, leaving strings intact
+fn strip_comments(jsonc: &str) -> String {
+    let mut output = String::with_capacity(jsonc.len());
+    let mut chars = jsonc.chars().peekable();
+    let mut in_string = false;
+    while let Some(char) = chars.next() {
+        if in_string {
+            output.push(char);
+            if char == '\\' {
+                if let Some(escaped) = chars.next() {
+                    output.push(escaped);
+                }
+            } else if char == '"' {
+                in_string = false;
+            }
+            continue;
+        }
+
+        match (char, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                output.push(char);
+            }
+            ('/', Some('/')) => {
+                while chars.peek().is_some_and(|next| *next != '\n') {
+                    chars.next();
+                }
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut previous = None;
+                for next in chars.by_ref() {
+                    if previous == Some('*') && next == '/' {
+                        break;
+                    }
+                    previous = Some(next);
+                }
+            }
+            _ => output.push(char),
+        }
+    }
+    output
+}
+
+// Return the index of the first character at or after an index that isn't whitespace or part of a
+// comment
+fn skip_trivia(jsonc: &str, mut index: usize) -> usize {
+    let bytes = jsonc.as_bytes();
+    while index < bytes.len() {
+        match (bytes[index], bytes.get(index + 1)) {
+            (b' ' | b'\t' | b'\r' | b'\n', _) => index += 1,
+            (b'/', Some(b'/')) => {
+                index = jsonc[index..]
+                    .find('\n')
+                    .map_or(bytes.len(), |newline| index + newline);
+            }
+            (b'/', Some(b'*')) => {
+                index = jsonc[index + 2..]
+                    .find("*/")
+                    .map_or(bytes.len(), |close| index + 2 + close + 2);
+            }
+            _ => break,
+        }
+    }
+    index
+}
+
+// Return the index just past the end of the string that starts at an index
+fn string_end(jsonc: &str, start: usize) -> Result<usize> {
+    let bytes = jsonc.as_bytes();
+    let mut index = start + 1;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'\\' => index += 2,
+            b'"' => return Ok(index + 1),
+            _ => index += 1,
+        }
+    }
+    bail!("Unterminated string in devcontainer configuration")
+}
+
+// Return the index just past the end of the value that starts at an index
+fn value_end(jsonc: &str, start: usize) -> Result<usize> {
+    let bytes = jsonc.as_bytes();
+    match bytes.get(start) {
+        Some(b'"') => string_end(jsonc, start),
+        Some(b'{' | b'[') => {
+            let mut depth = 0;
+            let mut index = start;
+            while index < bytes.len() {
+                match bytes[index] {
+                    b'"' => {
+                        index = string_end(jsonc, index)?;
+                        continue;
+                    }
+                    b'/' => {
+                        let next = skip_trivia(jsonc, index);
+                        if next > index {
+                            index = next;
+                            continue;
+                        }
+                    }
+                    b'{' | b'[' => depth += 1,
+                    b'}' | b']' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            return Ok(index + 1);
+                        }
+                    }
+                    _ => {}
+                }
+                index += 1;
+            }
+            bail!("Unterminated value in devcontainer configuration")
+        }
+        Some(_) => Ok(jsonc[start..]
+            .find(|char: char| char.is_whitespace() || matches!(char, ',' | '}' | ']' | '/'))
+            .map_or(jsonc.len(), |end| start + end)),
+        None => bail!("Missing value in devcontainer configuration"),
+    }
+}
+
+// A property of the top-level object in a devcontainer configuration
+struct Property {
+    key: String,
+    // The position of the property's key in the configuration
+    key_start: usize,
+    // The position of the property's value in the configuration
+    value: Range<usize>,
+}
+
+// Find the properties of the top-level object in a devcontainer configuration without discarding
+// its comments or formatting
+fn top_level_properties(jsonc: &str) -> Result<Vec<Property>> {
+    let bytes = jsonc.as_bytes();
+    let mut index = skip_trivia(jsonc, 0);
+    if bytes.get(index) != Some(&b'{') {
+        bail!("Devcontainer configuration must be an object");
+    }
+    let mut properties = vec![];
+    loop {
+        index = skip_trivia(jsonc, index + 1);
+        match bytes.get(index) {
+            Some(b'}') => break,
+            Some(b'"') => {}
+            _ => bail!("Failed to parse devcontainer configuration"),
+        }
+        let key_start = index;
+        let key_end = string_end(jsonc, key_start)?;
+        let key = serde_json::from_str(&jsonc[key_start..key_end])
+            .context("Failed to parse devcontainer configuration")?;
+        index = skip_trivia(jsonc, key_end);
+        if bytes.get(index) != Some(&b':') {
+            bail!("Failed to parse devcontainer configuration");
+        }
+        let value_start = skip_trivia(jsonc, index + 1);
+        let value_end = value_end(jsonc, value_start)?;
+        properties.push(Property {
+            key,
+            key_start,
+            value: value_start..value_end,
+        });
+        index = skip_trivia(jsonc, value_end);
+        match bytes.get(index) {
+            // Allow trailing commas like VS Code does
+            Some(b',') => {}
+            Some(b'}') => break,
+            _ => bail!("Failed to parse devcontainer configuration"),
+        }
+    }
+    Ok(properties)
+}
+
+// Parse the value of a top-level property, ignoring any comments inside of it
+fn parse_value(jsonc: &str, property: &Property) -> Result<Value> {
+    serde_json::from_str(&strip_comments(&jsonc[property.value.clone()])).with_context(|| {
+        format!(
+            "Failed to parse \"{}\" in devcontainer configuration",
+            property.key
+        )
+    })
+}
+
+// Return the whitespace that indents the line containing an index
+fn line_indent(jsonc: &str, index: usize) -> &str {
+    let line_start = jsonc[..index].rfind('\n').map_or(0, |newline| newline + 1);
+    let line = &jsonc[line_start..index];
+    if line.trim().is_empty() {
+        line
+    } else {
+        "  "
+    }
+}
+
+// Serialize the forwarded ports on a single line
+fn format_forward_ports(forward_ports: &[Value]) -> String {
+    let ports = forward_ports
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    format!("[{}]", ports.join(", "))
+}
+
+// Serialize the port attributes, indenting every line after the first
+fn format_attributes(attributes: &Map<String, Value>, indent: &str) -> Result<String> {
+    let formatted = serde_json::to_string_pretty(attributes)
+        .context("Failed to serialize devcontainer configuration")?;
+    Ok(formatted.replace('\n', &format!("\n{indent}")))
+}
+
+// Return the edits that add new properties to the end of the top-level object
+fn insert_properties(
+    contents: &str,
+    properties: &[Property],
+    missing: &[(&str, String)],
+    indent: &str,
+) -> Vec<(Range<usize>, String)> {
+    let mut edits = vec![];
+    let mut inserted = missing
+        .iter()
+        .map(|(key, value)| format!("\n{indent}\"{key}\": {value}"))
+        .collect::<Vec<_>>()
+        .join(",");
+    let insert_at = if let Some(last) = properties.last() {
+        // Add the new properties after the last property and any comment on the same line
+        let after_value = skip_trivia(contents, last.value.end);
+        let has_comma = contents.as_bytes().get(after_value) == Some(&b',');
+        let comma_end = if has_comma {
+            after_value + 1
+        } else {
+            last.value.end
+        };
+        let line_end = contents[comma_end..]
+            .find('\n')
+            .map_or(contents.len(), |newline| comma_end + newline);
+        let rest_of_line = contents[comma_end..line_end].trim();
+        let insert_at = if rest_of_line.is_empty() || rest_of_line.starts_with("//") {
+            line_end
+        } else {
+            comma_end
+        };
+        if !has_comma {
+            if insert_at == last.value.end {
+                inserted.insert(0, ',');
+            } else {
+                edits.push((last.value.end..last.value.end, String::from(",")));
+            }
+        }
+        insert_at
+    } else {
+        inserted.push('\n');
+        skip_trivia(contents, 0) + 1
+    };
+    edits.push((insert_at..insert_at, inserted));
+    edits
+}
+
+// Return the devcontainer configuration updated to forward the project's port, replacing any port
+// that portman previously forwarded for the project
+// Only the "forwardPorts" and "portsAttributes" values are rewritten, so comments and formatting
+// elsewhere in the configuration are preserved
+fn update_config(contents: &str, name: &str, port: u16) -> Result<String> {
+    let properties = top_level_properties(contents)?;
+    let find_property = |key: &str| properties.iter().find(|property| property.key == key);
+    let attributes_property = find_property("portsAttributes");
+    let forward_ports_property = find_property("forwardPorts");
+
+    let original_attributes = match attributes_property {
+        Some(property) => parse_value(contents, property)?,
+        None => Value::Object(Map::new()),
+    }
+    .as_object()
+    .context("\"portsAttributes\" must be an object")?
+    .clone();
+    let mut attributes = original_attributes.clone();
+    let label = port_label(name);
+    let stale_ports = attributes
+        .iter()
+        .filter(|(_, attribute)| {
+            attribute.get("label").and_then(Value::as_str) == Some(label.as_str())
+        })
+        .map(|(port, _)| port.clone())
+        .collect::<Vec<_>>();
+    for stale_port in &stale_ports {
+        attributes.remove(stale_port);
+    }
+    attributes.insert(port.to_string(), json!({ "label": label }));
+
+    let original_forward_ports = match forward_ports_property {
+        Some(property) => parse_value(contents, property)?,
+        None => Value::Array(vec![]),
+    }
+    .as_array()
+    .context("\"forwardPorts\" must be an array")?
+    .clone();
+    let mut forward_ports = original_forward_ports.clone();
+    forward_ports.retain(|forwarded| {
+        !forwarded
+            .as_u64()
+            .is_some_and(|forwarded| stale_ports.contains(&forwarded.to_string()))
+    });
+    if !forward_ports
+        .iter()
+        .any(|forwarded| forwarded.as_u64() == Some(u64::from(port)))
+    {
+        forward_ports.push(json!(port));
+    }
+
+    let indent = properties
+        .first()
+        .map_or("  ", |property| line_indent(contents, property.key_start));
+
+    // Replace the values that changed and add the ones that are missing
+    let mut replacements = vec![];
+    let mut missing = vec![];
+    if attributes != original_attributes {
+        let formatted = format_attributes(&attributes, indent)?;
+        match attributes_property {
+            Some(property) => replacements.push((property.value.clone(), formatted)),
+            None => missing.push(("portsAttributes", formatted)),
+        }
+    }
+    if forward_ports != original_forward_ports {
+        let formatted = format_forward_ports(&forward_ports);
+        match forward_ports_property {
+            Some(property) => replacements.push((property.value.clone(), formatted)),
+            None => missing.push(("forwardPorts", formatted)),
+        }
+    }
+
+    if !missing.is_empty() {
+        replacements.extend(insert_properties(contents, &properties, &missing, indent));
+    }
+
+    // Apply the replacements from the end so that the earlier positions stay valid
+    replacements.sort_by_key(|(range, _)| std::cmp::Reverse(range.start));
+    let mut updated = contents.to_owned();
+    for (range, replacement) in replacements {
+        updated.replace_range(range, &replacement);
+    }
+    Ok(updated)
+}
+
+// Update the devcontainer configuration in a project's directory to forward the project's port
+// Return true if the configuration was changed
+pub fn sync(
+    deps: &(impl ReadFile + WriteFile),
+    directory: &Path,
+    name: &str,
+    port: u16,
+) -> Result<bool> {
+    let path = config_path(directory);
+    let Some(contents) = deps.read_file(&path)? else {
+        bail!(
+            "Devcontainer configuration at \"{}\" does not exist",
+            path.display()
+        );
+    };
+    let updated = update_config(&contents, name, port)?;
+    if updated == contents {
+        return Ok(false);
+    }
+    deps.write_file(&path, &updated)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_comments() {
+        assert_eq!(
+            strip_comments("{\n  // Comment\n  \"a\": \"//b\", /* c */ \"d\": 1\n}"),
+            "{\n  \n  \"a\": \"//b\",  \"d\": 1\n}"
+        );
+    }
+
+    #[test]
+    fn test_update_config_empty() {
+        assert_eq!(
+            update_config("{\n  \"image\": \"rust\"\n}\n", "app1", 3001).unwrap(),
+            r#"{
+  "image": "rust",
+  "portsAttributes": {
+    "3001": {
+      "label": "app1 (portman)"
+    }
+  },
+  "forwardPorts": [3001]
+}
+"#
+        );
+        assert_eq!(
+            update_config("{}", "app1", 3001).unwrap(),
+            "{\n  \"portsAttributes\": {\n    \"3001\": {\n      \"label\": \"app1 (portman)\"\n    }\n  },\n  \"forwardPorts\": [3001]\n}"
+        );
+    }
+
+    #[test]
+    fn test_update_config_replaces_stale_port() {
+        let config = r#"{
+  "forwardPorts": [5432, 3001],
+  "portsAttributes": {
+    "3001": { "label": "app1 (portman)" },
+    "5432": { "label": "postgres" }
+  }
+}"#;
+        let updated: Value =
+            serde_json::from_str(&update_config(config, "app1", 3002).unwrap()).unwrap();
+        assert_eq!(updated["forwardPorts"], json!([5432, 3002]));
+        assert_eq!(
+            updated["portsAttributes"],
+            json!({ "5432": { "label": "postgres" }, "3002": { "label": "app1 (portman)" } })
+        );
+    }
+
+    #[test]
+    fn test_update_config_unchanged() {
+        let config = "{\n  \"forwardPorts\": [3001], // Ports\n  \"portsAttributes\": { \"3001\": { \"label\": \"app1 (portman)\" } }\n}";
+        assert_eq!(update_config(config, "app1", 3001).unwrap(), config);
+    }
+
+    #[test]
+    fn test_update_config_comments() {
+        let config = r#"// For format details, see https://aka.ms/devcontainer.json
+{
+	"name": "Rust",
+	// Use 'forwardPorts' to make a list of ports inside the container available locally.
+	"forwardPorts": [5432 /* postgres */],
+	/* The image to use */
+	"image": "mcr.microsoft.com/devcontainers/rust:1", // Pinned
+}
+"#;
+        assert_eq!(
+            update_config(config, "app1", 3001).unwrap(),
+            r#"// For format details, see https://aka.ms/devcontainer.json
+{
+	"name": "Rust",
+	// Use 'forwardPorts' to make a list of ports inside the container available locally.
+	"forwardPorts": [5432, 3001],
+	/* The image to use */
+	"image": "mcr.microsoft.com/devcontainers/rust:1", // Pinned
+	"portsAttributes": {
+	  "3001": {
+	    "label": "app1 (portman)"
+	  }
+	}
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_update_config_same_line() {
+        assert_eq!(
+            update_config("{ \"image\": \"rust\" /* Image */ }", "app1", 3001).unwrap(),
+            "{ \"image\": \"rust\",\n  \"portsAttributes\": {\n    \"3001\": {\n      \"label\": \"app1 (portman)\"\n    }\n  },\n  \"forwardPorts\": [3001] /* Image */ }"
+        );
+    }
+
+    #[test]
+    fn test_update_config_invalid() {
+        assert!(update_config("[]", "app1", 3001).is_err());
+    }
+}
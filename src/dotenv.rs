@@ -0,0 +1,168 @@
+use crate::dependencies::{ReadFile, WriteFile};
+use crate::registry::Project;
+use anyhow::Result;
+use std::fmt::Write;
+use std::path::Path;
+
+// The variables that portman always manages in .env files, even if a project doesn't set them
+const MANAGED_KEYS: [&str; 3] = ["PORT", "PORTMAN_PROJECT", "PORTMAN_LINKED_PORT"];
+
+// Return the environment variables that the shell integration sets while a project is active
+pub fn variables(name: &str, project: &Project) -> Vec<(String, String)> {
+    let mut variables = vec![
+        (String::from("PORT"), project.port.to_string()),
+        (String::from("PORTMAN_PROJECT"), name.to_owned()),
+    ];
+    if let Some(linked_port) = project.linked_port {
+        variables.push((String::from("PORTMAN_LINKED_PORT"), linked_port.to_string()));
+    }
+    variables.extend(
+        project
+            .env
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone())),
+    );
+    variables
+}
+
+// Quote a value if dotenv parsers would otherwise misinterpret it
+fn format_value(value: &str) -> String {
+    if value
+        .chars()
+        .all(|char| char.is_ascii_alphanumeric() || "-_./:@,+".contains(char))
+    {
+        value.to_owned()
+    } else {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+// Return the variables formatted as the lines of a .env file
+pub fn format(variables: &[(String, String)]) -> String {
+    variables
+        .iter()
+        .fold(String::new(), |mut output, (key, value)| {
+            let _ = writeln!(output, "{key}={}", format_value(value));
+            output
+        })
+}
+
+// Return the key assigned by a line of a .env file, if any
+fn line_key(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    let line = line.strip_prefix("export ").unwrap_or(line);
+    line.split_once('=').map(|(key, _)| key.trim())
+}
+
+// Return the .env file contents with the variables set, replacing existing assignments of the
+// same keys in place and preserving unrelated lines
+fn merge(contents: &str, variables: &[(String, String)]) -> String {
+    let mut remaining = variables.iter().collect::<Vec<_>>();
+    let mut output = String::with_capacity(contents.len());
+    for line in contents.lines() {
+        let Some(key) = line_key(line) else {
+            output.push_str(line);
+            output.push('\n');
+            continue;
+        };
+        if let Some(index) = remaining.iter().position(|(name, _)| name == key) {
+            let (key, value) = remaining.remove(index);
+            let _ = writeln!(output, "{key}={}", format_value(value));
+        } else if !(variables.iter().any(|(name, _)| name == key) || MANAGED_KEYS.contains(&key)) {
+            // Keep unrelated lines but drop duplicate assignments and stale managed variables
+            output.push_str(line);
+            output.push('\n');
+        }
+    }
+    for (key, value) in remaining {
+        let _ = writeln!(output, "{key}={}", format_value(value));
+    }
+    output
+}
+
+// Merge the variables into the .env file at the path, creating it if it doesn't exist
+// Return true if the file was changed
+pub fn sync(
+    deps: &(impl ReadFile + WriteFile),
+    path: &Path,
+    variables: &[(String, String)],
+) -> Result<bool> {
+    let contents = deps.read_file(path)?.unwrap_or_default();
+    let updated = merge(&contents, variables);
+    if updated == contents {
+        return Ok(false);
+    }
+    deps.write_file(path, &updated)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app_variables() -> Vec<(String, String)> {
+        variables(
+            "app1",
+            &Project {
+                port: 3001,
+                linked_port: Some(3000),
+                ..Default::default()
+            },
+        )
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(
+            format(&app_variables()),
+            "PORT=3001\nPORTMAN_PROJECT=app1\nPORTMAN_LINKED_PORT=3000\n"
+        );
+    }
+
+    #[test]
+    fn test_format_value() {
+        assert_eq!(
+            format_value("http://localhost:3001"),
+            "http://localhost:3001"
+        );
+        assert_eq!(format_value("a \"b\""), "\"a \\\"b\\\"\"");
+        assert_eq!(format_value(""), "");
+    }
+
+    #[test]
+    fn test_merge_empty() {
+        assert_eq!(
+            merge("", &app_variables()),
+            "PORT=3001\nPORTMAN_PROJECT=app1\nPORTMAN_LINKED_PORT=3000\n"
+        );
+    }
+
+    #[test]
+    fn test_merge_preserves_unrelated() {
+        assert_eq!(
+            merge(
+                "# Local overrides\nDATABASE_URL=postgres://localhost\nexport PORT=3005\n",
+                &app_variables()
+            ),
+            "# Local overrides\nDATABASE_URL=postgres://localhost\nPORT=3001\nPORTMAN_PROJECT=app1\nPORTMAN_LINKED_PORT=3000\n"
+        );
+    }
+
+    #[test]
+    fn test_merge_removes_stale() {
+        let variables = variables(
+            "app1",
+            &Project {
+                port: 3001,
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            merge(
+                "PORT=3001\nPORT=3002\nPORTMAN_LINKED_PORT=3000\nDEBUG=1\n",
+                &variables
+            ),
+            "PORT=3001\nDEBUG=1\nPORTMAN_PROJECT=app1\n"
+        );
+    }
+}
@@ -1,17 +1,36 @@
+//! The errors that portman operations can fail with.
+
 use std::{ffi::OsString, path::PathBuf};
 use thiserror::Error;
 
+/// An error generating the Caddyfile or reloading caddy
 #[derive(Debug, Error)]
 pub enum CaddyError {
     #[error(transparent)]
     Exec(#[from] ExecError),
 
+    #[error("The generated Caddyfile is invalid:\n\n{0}")]
+    InvalidCaddyfile(ExecError),
+
+    #[error("The Caddyfile at \"{}\" does not import the portman Caddyfile", .0.display())]
+    MissingImport(PathBuf),
+
+    #[error("Caddy is not running")]
+    NotRunning,
+
     #[error(transparent)]
     Other(#[from] anyhow::Error),
+
+    #[error("Caddy cannot listen on linked ports that other processes are already using:\n{}", .0.iter().map(|(name, port)| format!("  {port} (linked to project {name})")).collect::<Vec<_>>().join("\n"))]
+    PortConflicts(Vec<(String, u16)>),
+
+    #[error("Could not locate the Caddyfile that caddy loads")]
+    UnknownCaddyfile,
 }
 
 pub type CaddyResult<T> = std::result::Result<T, CaddyError>;
 
+/// An error running an external command
 #[derive(Debug, Error)]
 pub enum ExecError {
     #[error("Command \"{}\" failed with exit code {code} and output:\n{output}", command.to_string_lossy())]
@@ -33,44 +52,202 @@ pub enum ExecError {
 
 pub type ExecResult<T> = std::result::Result<T, ExecError>;
 
+/// An error from any portman operation
 #[derive(Debug, Error)]
 pub enum ApplicationError {
+    #[error("Access logs are disabled")]
+    AccessLogsDisabled,
+
+    #[error("Multiple ports are being listened on by processes running in this directory: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "))]
+    AmbiguousListeningPorts(Vec<u16>),
+
+    #[error("Command on line {0} of the batch failed, so no changes were saved:\n\n{1}")]
+    BatchCommand(usize, Box<Self>),
+
+    #[error("Browser could not be opened:\n\n{0}")]
+    BrowserCommand(ExecError),
+
     #[error("Error reloading caddy:\n{0}")]
     Caddy(CaddyError),
 
+    #[error("{output}Port conflicts found: {count}")]
+    ConflictsFound { output: String, count: usize },
+
+    #[error("{output}{failed} of {total} checks failed")]
+    DiagnosticsFailed {
+        output: String,
+        failed: usize,
+        total: usize,
+    },
+
     #[error("Project {0} already uses the directory \"{1}\"")]
     DuplicateDirectory(String, PathBuf),
 
+    #[error("Project {1} already uses the domain {0}")]
+    DuplicateDomain(String, String),
+
+    #[error("Port {0} is already used or forwarded by project {1}")]
+    DuplicateForward(u16, String),
+
     #[error("A project already has the name {0}")]
     DuplicateProject(String),
 
+    #[error("Project {0} is already activated by the repo {1}")]
+    DuplicateRepoMatch(String, String),
+
+    #[error("Port {0} is already reserved")]
+    DuplicateReservation(u16),
+
     #[error("Editor command could not be run:\n\n{0}")]
     EditorCommand(ExecError),
 
     #[error("All available ports have been allocated already")]
     EmptyAllocator,
 
+    #[error("{output}Command failed in {} of {total} projects: {}", failed.len(), failed.join(", "))]
+    ExecAllFailed {
+        output: String,
+        failed: Vec<String>,
+        total: usize,
+    },
+
     #[error("Git command could not be run:\n\n{0}")]
     GitCommand(ExecError),
 
+    #[error("Hook command could not be run:\n\n{0}")]
+    HookCommand(ExecError),
+
+    #[error(
+        "Bind address \"{0}\" is invalid: it must be \"loopback\", \"all\", or a single address"
+    )]
+    InvalidBindAddress(String),
+
     #[error("Configuration is invalid:\n\n{0}")]
     InvalidConfig(anyhow::Error),
 
+    #[error("Domain \"{0}\" is invalid: {1}")]
+    InvalidDomain(String, &'static str),
+
+    #[error("Health path \"{0}\" is invalid: it must start with /")]
+    InvalidHealthPath(String),
+
+    #[error("Name template \"{0}\" is invalid: {1}")]
+    InvalidNameTemplate(String, String),
+
     #[error("Project name \"{0}\" is invalid: {1}")]
     InvalidProjectName(String, &'static str),
 
+    #[error("Upstream URL \"{0}\" is invalid: {1}")]
+    InvalidUpstream(String, &'static str),
+
+    #[error("Upstream header \"{0}\" is invalid: {1}")]
+    InvalidUpstreamHeader(String, &'static str),
+
+    #[error("lsof command could not be run:\n\n{0}")]
+    LsofCommand(ExecError),
+
+    #[error("mDNS is disabled")]
+    MdnsDisabled,
+
+    #[error("mDNS advertisement is not supported on this platform")]
+    MdnsUnsupported,
+
+    #[error("Some project ports are not in their tag port ranges:\n{0}")]
+    MisplacedPorts(String),
+
     #[error("Custom config file at \"{0}\" does not exist")]
     MissingCustomConfig(PathBuf),
 
     #[error("The current directory does not contain a project")]
     NoActiveProject,
 
+    #[error("Project {0} is not linked to a port")]
+    NoLinkedPort(String),
+
+    #[error("No process running in \"{}\" is listening on a matching port", .0.display())]
+    NoListeningPort(PathBuf),
+
+    #[error("Project {0} does not have a directory")]
+    NoProjectDirectory(String),
+
+    #[error("No templates are configured")]
+    NoTemplates,
+
+    #[error("{0} must be run in an interactive terminal")]
+    NotInteractive(&'static str),
+
+    #[error("Archived project {0} does not exist")]
+    NonExistentArchivedProject(String),
+
+    #[error("Port {0} is not leased")]
+    NonExistentLease(u16),
+
+    #[error("Project {0} is not activated in the directory \"{1}\"")]
+    NonExistentDirectory(String, PathBuf),
+
+    #[error("Project {0} does not have the domain {1}")]
+    NonExistentDomain(String, String),
+
+    #[error("Project {0} does not forward port {1}")]
+    NonExistentForward(String, u16),
+
+    #[error("Project {0} does not have a port named {1}")]
+    NonExistentPortName(String, String),
+
     #[error("Project {0} does not exist")]
     NonExistentProject(String),
 
     #[error("Repo {0} does not exist")]
     NonExistentRepo(String),
 
+    #[error("Port {0} is not reserved")]
+    NonExistentReservation(u16),
+
+    #[error("Project {0} does not have the tag {1}")]
+    NonExistentTag(String, String),
+
+    #[error("Port {0} is outside of the configured port ranges")]
+    OutOfRangePort(u16),
+
+    #[error("The registry is locked by another portman process (lock file \"{}\")", .0.display())]
+    RegistryLocked(PathBuf),
+
+    #[error("Port {0} is already used by project {1}")]
+    ReservedPortInUse(u16, String),
+
+    #[error("Some projects use reserved or system ports:\n{}", .0.iter().map(|(name, port)| format!("  {port} (used by project {name})")).collect::<Vec<_>>().join("\n"))]
+    ReservedPorts(Vec<(String, u16)>),
+
+    #[error("Command could not be run:\n\n{0}")]
+    RunCommand(ExecError),
+
+    #[error("Command exited with code {0}")]
+    RunCommandExit(i32),
+
+    #[error("Service command could not be run:\n\n{0}")]
+    ServiceCommand(ExecError),
+
+    #[error("Strict mode violation: {0}")]
+    StrictMode(String),
+
+    #[error("Tmux command could not be run:\n\n{0}")]
+    TmuxCommand(ExecError),
+
+    #[error("Port {0} is not available")]
+    UnavailablePort(u16),
+
+    #[error("Only create, set, delete, link, and unlink commands can be run in a batch")]
+    UnbatchableCommand,
+
+    #[error("No project is active in \"{0}\" or any of its parent directories")]
+    UnresolvedPath(PathBuf),
+
+    #[error("Background services are not supported on this platform")]
+    UnsupportedPlatform,
+
+    #[error("Data directory \"{}\" is not writable: {1}", .0.display())]
+    UnwritableDataDir(PathBuf, std::io::Error),
+
     #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
@@ -0,0 +1,103 @@
+use crate::dependencies::{ReadFile, WriteFile};
+use crate::registry::Project;
+use anyhow::{Context, Result};
+use std::path::Path;
+
+// Return the value of a file template variable, or None if the variable is unknown
+fn resolve_variable(name: &str, project: &Project, variable: &str) -> Option<String> {
+    match variable {
+        "port" => Some(project.port.to_string()),
+        "name" => Some(name.to_owned()),
+        "linked_port" => Some(
+            project
+                .linked_port
+                .map(|port| port.to_string())
+                .unwrap_or_default(),
+        ),
+        _ => None,
+    }
+}
+
+// Substitute a project's values for the {{variables}} in a file template
+// Unknown variables are left as-is so that templates can contain other templating syntax
+fn render(template: &str, name: &str, project: &Project) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut remaining = template;
+    while let Some(start) = remaining.find("{{") {
+        output.push_str(&remaining[..start]);
+        let placeholder = &remaining[start..];
+        let value = placeholder.find("}}").and_then(|end| {
+            resolve_variable(name, project, placeholder[2..end].trim()).map(|value| (value, end))
+        });
+        if let Some((value, end)) = value {
+            output.push_str(&value);
+            remaining = &placeholder[end + 2..];
+        } else {
+            output.push_str("{{");
+            remaining = &placeholder[2..];
+        }
+    }
+    output.push_str(remaining);
+    output
+}
+
+// Render the template file at the template path into the destination file for a project
+// Return true if the destination file was changed
+pub fn sync(
+    deps: &(impl ReadFile + WriteFile),
+    template_path: &Path,
+    destination: &Path,
+    name: &str,
+    project: &Project,
+) -> Result<bool> {
+    let template = deps.read_file(template_path)?.with_context(|| {
+        format!(
+            "Template file \"{}\" does not exist",
+            template_path.display()
+        )
+    })?;
+    let rendered = render(&template, name, project);
+    if deps.read_file(destination)?.as_ref() == Some(&rendered) {
+        return Ok(false);
+    }
+    deps.write_file(destination, &rendered)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let project = Project {
+            port: 3001,
+            linked_port: Some(3000),
+            ..Default::default()
+        };
+        assert_eq!(
+            render(
+                "services:\n  {{ name }}:\n    ports:\n      - \"{{port}}:{{linked_port}}\"\n",
+                "app1",
+                &project
+            ),
+            "services:\n  app1:\n    ports:\n      - \"3001:3000\"\n"
+        );
+    }
+
+    #[test]
+    fn test_render_unknown() {
+        let project = Project {
+            port: 3001,
+            ..Default::default()
+        };
+        assert_eq!(
+            render(
+                "PORT={{port}}\nLINKED={{linked_port}}\nFORMAT={{.Names}} {{",
+                "app1",
+                &project
+            ),
+            "PORT=3001\nLINKED=\nFORMAT={{.Names}} {{"
+        );
+    }
+}
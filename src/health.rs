@@ -0,0 +1,220 @@
+use crate::dependencies::{CurrentTime, DataDir, ProbeHttp, ProbePort, ReadFile, WriteFile};
+use crate::registry::{Project, Registry};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+
+#[derive(Clone, Copy, Deserialize, Serialize)]
+#[cfg_attr(test, derive(Debug, Eq, PartialEq))]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Up,
+    Down,
+}
+
+impl Display for Status {
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        write!(
+            fmt,
+            "{}",
+            match self {
+                Self::Up => "up",
+                Self::Down => "down",
+            }
+        )
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+#[cfg_attr(test, derive(Debug))]
+struct ProjectHealth {
+    port: u16,
+    status: Status,
+    // When the project was last found up, in seconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_up: Option<u64>,
+}
+
+// The most recent status of each project's port, persisted so that status displays don't need to
+// probe every port themselves
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(test, derive(Debug))]
+pub struct HealthCache {
+    #[serde(default)]
+    projects: BTreeMap<String, ProjectHealth>,
+}
+
+// Return the path to the health cache file
+fn cache_path(deps: &impl DataDir) -> Result<PathBuf> {
+    Ok(deps.get_data_dir()?.join("health.toml"))
+}
+
+// Request a project's health path if it has one, or otherwise check whether its port accepts
+// connections
+fn probe_status(deps: &(impl ProbeHttp + ProbePort), project: &Project) -> Status {
+    let up = project.health_path.as_ref().map_or_else(
+        || deps.probe_port(project.port),
+        |health_path| deps.probe_http(project.port, health_path),
+    );
+    if up {
+        Status::Up
+    } else {
+        Status::Down
+    }
+}
+
+// Probe a project and return its health, carrying over when it was last up from its previous
+// health if it is down now
+fn probe_health(
+    deps: &(impl CurrentTime + ProbeHttp + ProbePort),
+    project: &Project,
+    previous: Option<&ProjectHealth>,
+) -> ProjectHealth {
+    let status = probe_status(deps, project);
+    let last_up = match status {
+        Status::Up => Some(deps.get_current_time()),
+        Status::Down => previous.and_then(|health| health.last_up),
+    };
+    ProjectHealth {
+        port: project.port,
+        status,
+        last_up,
+    }
+}
+
+impl HealthCache {
+    // Load the health cache from the data directory
+    // A missing or unparseable cache is treated as empty because it can always be regenerated
+    pub fn load(deps: &(impl DataDir + ReadFile)) -> Result<Self> {
+        Ok(deps
+            .read_file(&cache_path(deps)?)?
+            .and_then(|cache_str| toml::from_str(&cache_str).ok())
+            .unwrap_or_default())
+    }
+
+    // Probe every project in the registry and replace the cached results
+    pub fn probe(
+        &mut self,
+        deps: &(impl CurrentTime + ProbeHttp + ProbePort),
+        registry: &Registry,
+    ) {
+        let previous = std::mem::take(&mut self.projects);
+        self.projects = registry
+            .iter_projects()
+            .map(|(name, project)| {
+                (
+                    name.clone(),
+                    probe_health(deps, project, previous.get(name)),
+                )
+            })
+            .collect();
+    }
+
+    // Probe a single project, record its status, and return it
+    pub fn probe_project(
+        &mut self,
+        deps: &(impl CurrentTime + ProbeHttp + ProbePort),
+        name: &str,
+        project: &Project,
+    ) -> Status {
+        let health = probe_health(deps, project, self.projects.get(name));
+        let status = health.status;
+        self.projects.insert(name.to_owned(), health);
+        status
+    }
+
+    // Save the health cache to the data directory
+    pub fn save(&self, deps: &(impl DataDir + WriteFile)) -> Result<()> {
+        let cache_str = toml::to_string(self).context("Failed to serialize health cache")?;
+        deps.write_file(&cache_path(deps)?, &cache_str)
+            .context("Failed to save health cache")
+    }
+
+    // Get the cached status of a project, if any
+    // The status is ignored if the project's port has changed since it was probed
+    pub fn get(&self, name: &str, project: &Project) -> Option<Status> {
+        self.projects
+            .get(name)
+            .filter(|health| health.port == project.port)
+            .map(|health| health.status)
+    }
+
+    // Get when a project was last found up, in seconds since the Unix epoch, if ever
+    pub fn last_up(&self, name: &str) -> Option<u64> {
+        self.projects.get(name).and_then(|health| health.last_up)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dependencies::{ProbeHttpMock, ProbePortMock, ReadFileMock};
+    use crate::mocks::{current_time_mock, data_dir_mock, get_mocked_registry};
+    use unimock::{matching, MockFn, Unimock};
+
+    #[test]
+    fn test_probe() {
+        let registry = get_mocked_registry().unwrap();
+        let deps = Unimock::new((
+            current_time_mock(1000),
+            ProbePortMock
+                .each_call(matching!(_))
+                .answers(&|_, port| port == 3002)
+                .n_times(3),
+        ));
+        let mut cache: HealthCache =
+            toml::from_str("[projects.app1]\nport = 3001\nstatus = 'up'\nlast_up = 500").unwrap();
+        cache.probe(&deps, &registry);
+        assert_eq!(
+            cache.get("app1", registry.get("app1").unwrap()),
+            Some(Status::Down)
+        );
+        assert_eq!(cache.last_up("app1"), Some(500));
+        assert_eq!(
+            cache.get("app2", registry.get("app2").unwrap()),
+            Some(Status::Up)
+        );
+        assert_eq!(cache.last_up("app2"), Some(1000));
+        assert_eq!(cache.last_up("app3"), None);
+    }
+
+    #[test]
+    fn test_probe_project_health_path() {
+        let mut registry = get_mocked_registry().unwrap();
+        let project = registry
+            .set_health_path("app1", Some(String::from("/healthz")))
+            .unwrap();
+        let deps = Unimock::new(
+            ProbeHttpMock
+                .each_call(matching!(3001, "/healthz"))
+                .returns(false)
+                .once(),
+        );
+        let mut cache = HealthCache::default();
+        assert_eq!(cache.probe_project(&deps, "app1", &project), Status::Down);
+        assert_eq!(cache.get("app1", &project), Some(Status::Down));
+    }
+
+    #[test]
+    fn test_get_port_changed() {
+        let registry = get_mocked_registry().unwrap();
+        let cache: HealthCache =
+            toml::from_str("[projects.app1]\nport = 3100\nstatus = 'up'").unwrap();
+        assert!(cache.get("app1", registry.get("app1").unwrap()).is_none());
+    }
+
+    #[test]
+    fn test_load_invalid() {
+        let deps = Unimock::new((
+            data_dir_mock(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/health.toml")))
+                .answers(&|_, _| Ok(String::from(";")))
+                .once(),
+        ));
+        let cache = HealthCache::load(&deps).unwrap();
+        assert!(cache.projects.is_empty());
+    }
+}
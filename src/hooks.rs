@@ -0,0 +1,44 @@
+use crate::config::Hooks;
+use crate::dependencies::Exec;
+use crate::dotenv;
+use crate::error::ExecResult;
+use crate::registry::Project;
+use std::process::Command;
+
+// A change to a project that can trigger a hook
+#[derive(Clone, Copy)]
+#[cfg_attr(test, derive(Debug, Eq, PartialEq))]
+pub enum Hook {
+    Create,
+    Delete,
+    Link,
+}
+
+// A project change whose hook runs after the registry is saved
+pub struct HookEvent {
+    pub hook: Hook,
+    pub name: String,
+    pub project: Project,
+}
+
+// Run the command configured for an event's hook, if any, with the project's variables set
+pub fn run(deps: &impl Exec, hooks: &Hooks, event: &HookEvent) -> ExecResult<()> {
+    let command = match event.hook {
+        Hook::Create => hooks.on_create.as_ref(),
+        Hook::Delete => hooks.on_delete.as_ref(),
+        Hook::Link => hooks.on_link.as_ref(),
+    };
+    let Some(command) = command else {
+        return Ok(());
+    };
+    let mut shell = Command::new("sh");
+    shell
+        .arg("-c")
+        .arg(command)
+        .envs(dotenv::variables(&event.name, &event.project));
+    if event.project.linked_port.is_none() {
+        shell.env_remove("PORTMAN_LINKED_PORT");
+    }
+    deps.exec(&mut shell)?;
+    Ok(())
+}
@@ -0,0 +1,180 @@
+use crate::dependencies::DataDir;
+use crate::registry::{Project, Registry};
+use crate::repo;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+// A project in the directory index
+#[derive(Deserialize, Serialize)]
+#[cfg_attr(test, derive(Debug, Eq, PartialEq))]
+pub struct IndexEntry {
+    pub name: String,
+    pub port: u16,
+    pub linked_port: Option<u16>,
+    pub branch: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+}
+
+impl IndexEntry {
+    // Create the index entry for a project
+    pub fn new(name: &str, project: &Project) -> Self {
+        Self {
+            name: name.to_owned(),
+            port: project.port,
+            linked_port: project.linked_port,
+            branch: project.branch.clone(),
+            env: project.env.clone(),
+        }
+    }
+}
+
+// The directory index maps project directories to their projects so that the shell integration and
+// external tools can find the project in a directory without loading the whole registry and config
+// A directory can contain several per-branch projects, so each directory maps to a list of entries
+#[derive(Default, Deserialize, Serialize)]
+pub struct DirectoryIndex {
+    #[serde(flatten)]
+    entries: BTreeMap<String, Vec<IndexEntry>>,
+    // The projects that are activated by their repo, keyed by canonical repo URL
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    repos: BTreeMap<String, IndexEntry>,
+}
+
+impl DirectoryIndex {
+    // Return the path to the index file
+    pub fn path(deps: &impl DataDir) -> Result<PathBuf> {
+        Ok(deps.get_data_dir()?.join("index.toml"))
+    }
+
+    // Build the index of the projects in a registry that have a directory
+    pub fn from_registry(registry: &Registry) -> Self {
+        let mut entries: BTreeMap<String, Vec<IndexEntry>> = BTreeMap::new();
+        let mut repos = BTreeMap::new();
+        for (name, project) in registry.iter_projects() {
+            let entry = || IndexEntry::new(name, project);
+            if let Some(repo) = project.repo.as_ref() {
+                repos.insert(repo::canonicalize(repo), entry());
+            }
+            for directory in project
+                .activation_directories()
+                .filter_map(|directory| directory.to_str())
+            {
+                entries
+                    .entry(directory.to_owned())
+                    .or_default()
+                    .push(entry());
+            }
+        }
+        Self { entries, repos }
+    }
+
+    // Parse the index from the contents of the index file
+    pub fn from_toml(contents: &str) -> Result<Self> {
+        toml::from_str(contents).context("Failed to deserialize directory index")
+    }
+
+    // Serialize the index to the contents of the index file
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string(self).context("Failed to serialize directory index")
+    }
+
+    // Find the project in a directory
+    // The current branch is only resolved if the directory contains per-branch projects
+    pub fn get(
+        &self,
+        directory: &Path,
+        current_branch: impl FnOnce() -> Option<String>,
+    ) -> Option<&IndexEntry> {
+        let entries = self.entries.get(directory.to_str()?)?;
+        let branch = if entries.iter().any(|entry| entry.branch.is_some()) {
+            current_branch()
+        } else {
+            None
+        };
+        entries
+            .iter()
+            .find(|entry| entry.branch.is_some() && entry.branch == branch)
+            .or_else(|| entries.iter().find(|entry| entry.branch.is_none()))
+    }
+
+    // Determine whether any projects are activated by their repo
+    pub fn has_repos(&self) -> bool {
+        !self.repos.is_empty()
+    }
+
+    // Find the project that is activated by a repo
+    pub fn get_repo(&self, url: &str) -> Option<&IndexEntry> {
+        self.repos.get(&repo::canonicalize(url))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mocks::get_mocked_registry;
+
+    #[test]
+    fn test_to_toml() {
+        let registry = get_mocked_registry().unwrap();
+        assert_eq!(
+            DirectoryIndex::from_registry(&registry).to_toml().unwrap(),
+            "[[\"/projects/app3\"]]\nname = \"app3\"\nport = 3003\n"
+        );
+    }
+
+    #[test]
+    fn test_get() {
+        let index = DirectoryIndex::from_toml(
+            "[['/projects/app1']]\nname = 'app1'\nport = 3001\nlinked_port = 3000\n",
+        )
+        .unwrap();
+        assert_eq!(
+            index.get(&PathBuf::from("/projects/app1"), || unreachable!()),
+            Some(&IndexEntry {
+                name: String::from("app1"),
+                port: 3001,
+                linked_port: Some(3000),
+                branch: None,
+                env: BTreeMap::new(),
+            })
+        );
+        assert!(index
+            .get(&PathBuf::from("/projects/app2"), || unreachable!())
+            .is_none());
+    }
+
+    #[test]
+    fn test_get_branch() {
+        let index = DirectoryIndex::from_toml(
+            "[['/projects/app']]\nname = 'app'\nport = 3001\n\n[['/projects/app']]\nname = 'app-feature'\nport = 3002\nbranch = 'feature'\n",
+        )
+        .unwrap();
+        let get_name = |branch: Option<&str>| {
+            index
+                .get(&PathBuf::from("/projects/app"), || branch.map(String::from))
+                .map(|entry| entry.name.as_str())
+        };
+        assert_eq!(get_name(Some("feature")), Some("app-feature"));
+        assert_eq!(get_name(Some("main")), Some("app"));
+        assert_eq!(get_name(None), Some("app"));
+    }
+
+    #[test]
+    fn test_get_repo() {
+        let index = DirectoryIndex::from_toml(
+            "[['/projects/app']]\nname = 'app'\nport = 3001\n\n[repos.'github.com/user/app']\nname = 'app'\nport = 3001\n",
+        )
+        .unwrap();
+        assert!(index.has_repos());
+        assert_eq!(
+            index
+                .get_repo("git@github.com:user/app.git")
+                .map(|entry| entry.name.as_str()),
+            Some("app")
+        );
+        assert!(index.get_repo("git@github.com:user/app2.git").is_none());
+    }
+}
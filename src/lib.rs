@@ -0,0 +1,70 @@
+//! portman allocates ports to local development projects and configures caddy to serve each
+//! project at its own hostname.
+//!
+//! Besides the `portman` binary, the crate exposes the registry, configuration, and port
+//! allocator so that other tools can allocate and query ports without running `portman`.
+//! Operations with side effects accept `deps`, which [`Impl::new(())`](Impl) provides.
+//!
+//! ```no_run
+//! use portman::config::Config;
+//! use portman::dependencies::DataDir;
+//! use portman::registry::Registry;
+//! use portman::Impl;
+//!
+//! # fn main() -> portman::error::Result<()> {
+//! let deps = Impl::new(());
+//! let config_path = deps.get_data_dir()?.join("config.toml");
+//! let config = Config::load(&deps, &config_path)?.unwrap_or_default();
+//! let registry = Registry::load(&deps, &config)?;
+//! for (name, project) in registry.iter_projects() {
+//!     println!("{name}: {}", project.port);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+#![warn(clippy::str_to_string, clippy::pedantic, clippy::nursery)]
+#![allow(
+    clippy::missing_errors_doc,
+    clippy::missing_panics_doc,
+    clippy::module_name_repetitions,
+    clippy::must_use_candidate,
+    clippy::return_self_not_must_use
+)]
+
+mod adopt;
+pub mod allocator;
+mod app;
+mod archive;
+mod batch;
+mod caddy;
+mod cli;
+pub mod config;
+mod conflicts;
+pub mod dependencies;
+mod devcontainer;
+mod dotenv;
+pub mod error;
+mod file_template;
+mod health;
+mod hooks;
+mod index;
+mod logs;
+mod mdns;
+#[cfg(test)]
+mod mocks;
+pub mod registry;
+mod repo;
+mod scan;
+mod service;
+mod shell;
+mod template;
+mod timings;
+mod tmux;
+mod ui;
+mod usage;
+mod version;
+mod watch;
+
+pub use app::run_cli;
+pub use entrait::Impl;
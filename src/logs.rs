@@ -0,0 +1,184 @@
+use crate::dependencies::{DataDir, ReadFile};
+use anyhow::Result;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// Return the directory that caddy writes the projects' access logs to
+pub fn log_dir(deps: &impl DataDir) -> Result<PathBuf> {
+    Ok(deps.get_data_dir()?.join("logs"))
+}
+
+// Return the path of a project's access log in the log directory
+pub fn log_file(log_dir: &Path, name: &str) -> PathBuf {
+    log_dir.join(format!("{name}.log"))
+}
+
+// Return the path of the access log that caddy writes a project's requests to
+pub fn log_path(deps: &impl DataDir, name: &str) -> Result<PathBuf> {
+    Ok(log_file(&log_dir(deps)?, name))
+}
+
+// Format a Unix timestamp as a UTC date and time
+#[allow(clippy::cast_possible_truncation)]
+fn format_timestamp(timestamp: f64) -> String {
+    let seconds = timestamp.floor() as i64;
+    let (days, time) = (seconds.div_euclid(86_400), seconds.rem_euclid(86_400));
+
+    // Convert the days since the Unix epoch into a civil date
+    let shifted = days + 719_468;
+    let era = shifted.div_euclid(146_097);
+    let day_of_era = shifted.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+// Format a line of caddy's JSON access log as a summary of the request
+// Lines that aren't access log entries are returned as-is
+pub fn format_entry(line: &str) -> String {
+    let Ok(entry) = serde_json::from_str::<Value>(line) else {
+        return line.to_owned();
+    };
+    let request = &entry["request"];
+    let (Some(method), Some(host), Some(uri)) = (
+        request["method"].as_str(),
+        request["host"].as_str(),
+        request["uri"].as_str(),
+    ) else {
+        return line.to_owned();
+    };
+    let timestamp = entry["ts"]
+        .as_f64()
+        .map(|timestamp| format!("[{}] ", format_timestamp(timestamp)))
+        .unwrap_or_default();
+    let status = entry["status"]
+        .as_u64()
+        .map(|status| format!(" {status}"))
+        .unwrap_or_default();
+    let duration = entry["duration"]
+        .as_f64()
+        .map(|duration| format!(" ({:.0}ms)", duration * 1000.0))
+        .unwrap_or_default();
+    format!("{timestamp}{method} {host}{uri}{status}{duration}")
+}
+
+// Return the last lines of a project's access log
+pub fn tail(deps: &(impl DataDir + ReadFile), name: &str, lines: usize) -> Result<Vec<String>> {
+    let log = deps.read_file(&log_path(deps, name)?)?.unwrap_or_default();
+    let entries = log.lines().collect::<Vec<_>>();
+    Ok(entries[entries.len().saturating_sub(lines)..]
+        .iter()
+        .map(|line| (*line).to_owned())
+        .collect())
+}
+
+// Print the requests that caddy logs for a project as they are made until the process is stopped
+// Errors are printed instead of returned so that a single failure doesn't stop following
+pub fn follow(deps: &(impl DataDir + ReadFile), name: &str, raw: bool) -> ! {
+    let mut printed = None;
+    loop {
+        match log_path(deps, name).and_then(|path| deps.read_file(&path)) {
+            Ok(log) => {
+                let log = log.unwrap_or_default();
+                // Only print the lines that were added since the last poll, starting over if caddy
+                // rolled the log
+                let start = match printed {
+                    Some(printed) if log.is_char_boundary(printed) => printed,
+                    Some(_) => 0,
+                    None => log.len(),
+                };
+                let added = &log[start..];
+                // Wait for partially written lines to be completed
+                let complete = added.rfind('\n').map_or(0, |index| index + 1);
+                for line in added[..complete].lines() {
+                    println!(
+                        "{}",
+                        if raw {
+                            line.to_owned()
+                        } else {
+                            format_entry(line)
+                        }
+                    );
+                }
+                printed = Some(start + complete);
+            }
+            Err(err) => eprintln!("{err}"),
+        }
+        std::thread::sleep(Duration::from_secs(1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dependencies::ReadFileMock;
+    use crate::mocks::data_dir_mock;
+    use unimock::{matching, MockFn, Unimock};
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0.0), "1970-01-01 00:00:00");
+        assert_eq!(format_timestamp(1_709_251_199.75), "2024-02-29 23:59:59");
+        assert_eq!(format_timestamp(1_792_108_800.0), "2026-10-16 00:00:00");
+    }
+
+    #[test]
+    fn test_format_entry() {
+        assert_eq!(
+            format_entry(
+                r#"{"level":"info","ts":1792108800.5,"logger":"http.log.access.log0","msg":"handled request","request":{"remote_ip":"127.0.0.1","proto":"HTTP/2.0","method":"GET","host":"app1.localhost","uri":"/api/users?page=2"},"duration":0.0123,"size":512,"status":200}"#
+            ),
+            "[2026-10-16 00:00:00] GET app1.localhost/api/users?page=2 200 (12ms)"
+        );
+    }
+
+    #[test]
+    fn test_format_entry_unknown() {
+        assert_eq!(format_entry("not json"), "not json");
+        assert_eq!(format_entry(r#"{"msg":"other"}"#), r#"{"msg":"other"}"#);
+    }
+
+    #[test]
+    fn test_tail() {
+        let mocked_deps = Unimock::new((
+            data_dir_mock(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/logs/app1.log")))
+                .answers(&|_, _| Ok(String::from("one\ntwo\nthree\n")))
+                .n_times(2),
+        ));
+        assert_eq!(tail(&mocked_deps, "app1", 2).unwrap(), vec!["two", "three"]);
+        assert_eq!(
+            tail(&mocked_deps, "app1", 10).unwrap(),
+            vec!["one", "two", "three"]
+        );
+    }
+
+    #[test]
+    fn test_tail_missing() {
+        let mocked_deps = Unimock::new((
+            data_dir_mock(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/logs/app1.log")))
+                .answers(&|_, _| Err(std::io::Error::from(std::io::ErrorKind::NotFound)))
+                .once(),
+        ));
+        assert!(tail(&mocked_deps, "app1", 10).unwrap().is_empty());
+    }
+}
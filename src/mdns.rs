@@ -0,0 +1,200 @@
+use crate::dependencies::LanAddress;
+use crate::registry::Registry;
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::IpAddr;
+use std::process::{Child, Command, Stdio};
+
+// Return the hostname that a project is advertised at over mDNS
+pub fn hostname(name: &str) -> String {
+    format!("{name}.local")
+}
+
+// Return the names of the projects to advertise
+pub fn project_names(registry: &Registry) -> BTreeSet<String> {
+    registry
+        .iter_projects()
+        .map(|(name, _)| name.clone())
+        .collect()
+}
+
+// The mDNS responder that advertises hostnames on the current operating system
+#[derive(Clone, Copy)]
+pub enum Responder {
+    // The dns-sd command that is built into macOS
+    DnsSd,
+    // The avahi-publish command from Avahi, which most Linux distributions include
+    Avahi,
+}
+
+impl Responder {
+    // Return the mDNS responder for the current operating system
+    pub const fn current() -> Option<Self> {
+        if cfg!(target_os = "macos") {
+            Some(Self::DnsSd)
+        } else if cfg!(target_os = "linux") {
+            Some(Self::Avahi)
+        } else {
+            None
+        }
+    }
+
+    // Return the command that advertises a project's hostname at an address until it is killed
+    fn advertise_command(self, name: &str, address: IpAddr) -> Command {
+        let hostname = hostname(name);
+        match self {
+            Self::DnsSd => {
+                // Registering a proxy service also publishes the address record for its host
+                let mut command = Command::new("dns-sd");
+                command
+                    .args(["-P", name, "_http._tcp", "local", "80", &hostname])
+                    .arg(address.to_string());
+                command
+            }
+            Self::Avahi => {
+                let mut command = Command::new("avahi-publish");
+                command
+                    .args(["--address", "--no-reverse", &hostname])
+                    .arg(address.to_string());
+                command
+            }
+        }
+    }
+}
+
+// Advertises projects' hostnames over mDNS by running a responder process for each project
+pub struct Advertiser {
+    responder: Responder,
+    // The address that the running processes advertise
+    address: Option<IpAddr>,
+    processes: BTreeMap<String, Child>,
+}
+
+impl Advertiser {
+    pub const fn new(responder: Responder) -> Self {
+        Self {
+            responder,
+            address: None,
+            processes: BTreeMap::new(),
+        }
+    }
+
+    // Advertise the projects at this machine's current address on the local network, starting and
+    // stopping processes as projects are created and deleted and restarting them all when the
+    // address changes
+    pub fn sync(&mut self, deps: &impl LanAddress, names: &BTreeSet<String>) -> Result<()> {
+        let address = deps.get_lan_address();
+        if address != self.address {
+            self.stop_all();
+            self.address = address;
+        }
+        let Some(address) = address else {
+            // Nothing can be advertised until the machine is connected to a network
+            return Ok(());
+        };
+
+        let removed = self
+            .processes
+            .keys()
+            .filter(|name| !names.contains(*name))
+            .cloned()
+            .collect::<Vec<_>>();
+        for name in removed {
+            if let Some(process) = self.processes.remove(&name) {
+                stop(process);
+            }
+        }
+
+        for name in names {
+            if self.processes.contains_key(name) {
+                continue;
+            }
+            let mut command = self.responder.advertise_command(name, address);
+            let process = command
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+                .with_context(|| {
+                    format!(
+                        "Failed to run {} to advertise {}",
+                        command.get_program().to_string_lossy(),
+                        hostname(name)
+                    )
+                })?;
+            self.processes.insert(name.clone(), process);
+        }
+        Ok(())
+    }
+
+    // Stop advertising every project
+    fn stop_all(&mut self) {
+        for process in std::mem::take(&mut self.processes).into_values() {
+            stop(process);
+        }
+    }
+}
+
+impl Drop for Advertiser {
+    fn drop(&mut self) {
+        self.stop_all();
+    }
+}
+
+// Kill a responder process, which withdraws its advertisement
+fn stop(mut process: Child) {
+    let _ = process.kill();
+    let _ = process.wait();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mocks::get_mocked_registry;
+    use std::net::Ipv4Addr;
+
+    fn args(command: &Command) -> Vec<String> {
+        command
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_project_names() {
+        let registry = get_mocked_registry().unwrap();
+        assert_eq!(
+            project_names(&registry).into_iter().collect::<Vec<_>>(),
+            vec!["app1", "app2", "app3"]
+        );
+    }
+
+    #[test]
+    fn test_advertise_command_dns_sd() {
+        let command =
+            Responder::DnsSd.advertise_command("app1", IpAddr::V4(Ipv4Addr::new(192, 168, 1, 5)));
+        assert_eq!(command.get_program(), "dns-sd");
+        assert_eq!(
+            args(&command),
+            vec![
+                "-P",
+                "app1",
+                "_http._tcp",
+                "local",
+                "80",
+                "app1.local",
+                "192.168.1.5"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_advertise_command_avahi() {
+        let command =
+            Responder::Avahi.advertise_command("app1", IpAddr::V4(Ipv4Addr::new(192, 168, 1, 5)));
+        assert_eq!(command.get_program(), "avahi-publish");
+        assert_eq!(
+            args(&command),
+            vec!["--address", "--no-reverse", "app1.local", "192.168.1.5"]
+        );
+    }
+}
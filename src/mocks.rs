@@ -1,7 +1,8 @@
 use crate::allocator::PortAllocator;
-use crate::config::Config;
+use crate::config::{Config, RegistryLayout};
 use crate::dependencies::{
-    ArgsMock, ChoosePortMock, DataDirMock, EnvironmentMock, ExecMock, ExecStatus, ReadFileMock,
+    ArgsMock, CheckPortInUseMock, ChoosePortMock, CurrentTimeMock, DataDirMock, EnvironmentMock,
+    ExecMock, ExecStatus, FileLock, LockFileMock, ReadFileMock, ReadStdinMock, ResolveHostMock,
     TtyMock, WorkingDirectoryMock, WriteFileMock,
 };
 use crate::error::Result;
@@ -15,14 +16,18 @@ pub fn args_mock(args: &str) -> impl Clause {
     ArgsMock
         .each_call(matching!())
         .returns(args.split(' ').map(String::from).collect::<Vec<_>>())
-        .once()
+        .at_least_times(1)
 }
 
 pub fn choose_port_mock() -> impl Clause {
-    ChoosePortMock
-        .each_call(matching!(_))
-        .answers(&|_, available_ports| available_ports.iter().min().copied())
-        .at_least_times(1)
+    (
+        ChoosePortMock
+            .each_call(matching!(_))
+            .answers(&|_, available_ports| available_ports.iter().min().copied())
+            .at_least_times(1),
+        // No processes outside of portman are using any ports
+        CheckPortInUseMock.each_call(matching!(_)).returns(false),
+    )
 }
 
 pub fn cwd_mock(project: &str) -> impl Clause {
@@ -33,6 +38,13 @@ pub fn cwd_mock(project: &str) -> impl Clause {
         .at_least_times(1)
 }
 
+pub fn current_time_mock(now: u64) -> impl Clause {
+    CurrentTimeMock
+        .each_call(matching!())
+        .returns(now)
+        .at_least_times(1)
+}
+
 pub fn data_dir_mock() -> impl Clause {
     DataDirMock
         .each_call(matching!())
@@ -61,13 +73,39 @@ pub fn exec_git_mock(project: &str) -> impl Clause {
         .at_least_times(1)
 }
 
+pub fn lock_registry_mock() -> impl Clause {
+    LockFileMock
+        .each_call(matching!((path, _) if path == &PathBuf::from("/data/registry.toml.lock")))
+        .answers(&|_, _, _| Ok(Some(FileLock::default())))
+}
+
 pub fn read_registry_mock(contents: Option<&str>) -> impl Clause {
     let result = contents
         .unwrap_or(include_str!("fixtures/registry.toml"))
         .to_owned();
+    (
+        ReadFileMock
+            .each_call(matching!((path) if path == &PathBuf::from("/data/registry.toml")))
+            .answers_arc(Arc::new(move |_, _| Ok(result.clone())))
+            .once(),
+        ReadFileMock
+            .each_call(matching!((path) if path == &PathBuf::from("/data/registry.toml.checksum")))
+            .answers(&|_, _| Err(std::io::Error::from(std::io::ErrorKind::NotFound)))
+            .once(),
+    )
+}
+
+pub fn read_health_mock() -> impl Clause {
     ReadFileMock
-        .each_call(matching!((path) if path == &PathBuf::from("/data/registry.toml")))
-        .answers_arc(Arc::new(move |_, _| Ok(result.clone())))
+        .each_call(matching!((path) if path == &PathBuf::from("/data/health.toml")))
+        .answers(&|_, _| Err(std::io::Error::from(std::io::ErrorKind::NotFound)))
+        .once()
+}
+
+pub fn read_usage_mock() -> impl Clause {
+    ReadFileMock
+        .each_call(matching!((path) if path == &PathBuf::from("/data/usage.toml")))
+        .answers(&|_, _| Err(std::io::Error::from(std::io::ErrorKind::NotFound)))
         .once()
 }
 
@@ -82,6 +120,18 @@ pub fn read_var_mock() -> impl Clause {
     })
 }
 
+pub fn read_stdin_mock(input: &str) -> impl Clause {
+    let input = input.to_owned();
+    ReadStdinMock
+        .each_call(matching!())
+        .answers_arc(Arc::new(move |_| Ok(input.clone())))
+        .once()
+}
+
+pub fn resolve_host_mock(resolves: bool) -> impl Clause {
+    ResolveHostMock.each_call(matching!(_)).returns(resolves)
+}
+
 pub fn tty_mock(is_tty: bool) -> impl Clause {
     TtyMock
         .each_call(matching!())
@@ -90,15 +140,37 @@ pub fn tty_mock(is_tty: bool) -> impl Clause {
 }
 
 pub fn write_file_mock() -> impl Clause {
+    (
+        WriteFileMock
+            .each_call(matching!(_))
+            .answers(&|_, _, _| Ok(()))
+            .at_least_times(1),
+        // The metrics record when caddy was reloaded
+        CurrentTimeMock.each_call(matching!()).returns(1000_u64),
+    )
+}
+
+pub fn write_caddyfile_mock() -> impl Clause {
+    (
+        WriteFileMock
+            .each_call(matching!((path, _) if path == &PathBuf::from("/homebrew/etc/Caddyfile") || path == &PathBuf::from("/data/Caddyfile") || path == &PathBuf::from("/data/Caddyfile.validate") || path == &PathBuf::from("/data/gallery_www/index.html") || path == &PathBuf::from("/data/gallery_www/metrics") || path == &PathBuf::from("/data/gallery_www/projects.json") || path == &PathBuf::from("/data/Caddyfile.checksum")))
+            .answers(&|_, _, _| Ok(()))
+            .at_least_times(1),
+        // The metrics record when caddy was reloaded
+        CurrentTimeMock.each_call(matching!()).returns(1000_u64),
+    )
+}
+
+pub fn write_checksum_mock() -> impl Clause {
     WriteFileMock
-        .each_call(matching!(_))
+        .each_call(matching!((path, _) if path == &PathBuf::from("/data/registry.toml.1") || path == &PathBuf::from("/data/registry.toml.checksum")))
         .answers(&|_, _, _| Ok(()))
         .at_least_times(1)
 }
 
-pub fn write_caddyfile_mock() -> impl Clause {
+pub fn write_index_mock() -> impl Clause {
     WriteFileMock
-        .each_call(matching!((path, _) if path == &PathBuf::from("/homebrew/etc/Caddyfile") || path == &PathBuf::from("/data/Caddyfile") || path == &PathBuf::from("/data/gallery_www/index.html")))
+        .each_call(matching!((path, _) if path == &PathBuf::from("/data/index.toml")))
         .answers(&|_, _, _| Ok(()))
         .at_least_times(1)
 }
@@ -114,5 +186,5 @@ pub fn get_mocked_registry() -> Result<Registry> {
     let mocked_deps = Unimock::new((data_dir_mock(), read_registry_mock(None)));
     let config = Config::default();
     let allocator = PortAllocator::new(config.get_valid_ports());
-    Registry::new(&mocked_deps, allocator)
+    Registry::new(&mocked_deps, allocator, RegistryLayout::Single)
 }
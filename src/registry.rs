@@ -1,781 +1,4378 @@
-use crate::caddy::reload;
-use crate::dependencies::{ChoosePort, DataDir, Exec, ReadFile, WorkingDirectory, WriteFile};
-use crate::error::{ApplicationError, Result};
+//! The registry of projects and the ports allocated to them, which is stored in `registry.toml`
+//! in the data directory.
+
+use crate::caddy;
+use crate::config::{BindAddress, Config, RegistryLayout, TlsMode};
+use crate::dependencies::{
+    CheckPortInUse, ChoosePort, CurrentTime, DataDir, EphemeralPort, Exec, FileLock, ListDirectory,
+    ReadFile, WorkingDirectory, WriteFile,
+};
+use crate::error::{ApplicationError, CaddyResult, Result};
+use crate::index::DirectoryIndex;
+use crate::repo;
+use crate::template::branch_name;
+use crate::timings;
 use crate::{allocator::PortAllocator, dependencies::Environment};
 use anyhow::Context;
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeMap, HashSet};
-use std::path::PathBuf;
+use std::collections::{btree_map::Entry, BTreeMap, BTreeSet, HashSet};
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+
+/// A remote server that a project proxies to instead of a local port
+#[derive(Clone, Deserialize, Eq, PartialEq, Serialize)]
+#[cfg_attr(test, derive(Debug))]
+pub struct Upstream {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+}
+
+impl Upstream {
+    /// Make sure that the upstream can be safely inserted into the Caddyfile, returning the upstream
+    /// with its URL normalized
+    pub fn validate(self) -> Result<Self> {
+        let url = normalize_upstream_url(&self.url)
+            .map_err(|reason| ApplicationError::InvalidUpstream(self.url.clone(), reason))?;
+        for (name, value) in &self.headers {
+            if !is_header_name(name) {
+                return Err(ApplicationError::InvalidUpstreamHeader(
+                    name.clone(),
+                    "header names may only contain letters, digits, and !#$%&'*+-.^_`|~",
+                ));
+            }
+            if value.chars().any(char::is_control) {
+                return Err(ApplicationError::InvalidUpstreamHeader(
+                    name.clone(),
+                    "header values must not contain control characters like newlines",
+                ));
+            }
+        }
+        Ok(Self { url, ..self })
+    }
+}
+
+/// Determine whether a header name only contains the token characters that RFC 7230 allows
+pub fn is_header_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(char))
+}
+
+// Normalize an upstream URL to scheme://host[:port], because caddy doesn't allow paths in upstreams
+fn normalize_upstream_url(url: &str) -> std::result::Result<String, &'static str> {
+    let (scheme, authority) = url
+        .split_once("://")
+        .filter(|(scheme, _)| matches!(*scheme, "http" | "https"))
+        .ok_or("it must start with http:// or https://")?;
+    let authority = authority.strip_suffix('/').unwrap_or(authority);
+    if authority.contains(['/', '?', '#']) {
+        return Err("it must not contain a path, query, or fragment");
+    }
+    let port = if let Some(address) = authority.strip_prefix('[') {
+        // IPv6 addresses are wrapped in brackets
+        let (address, port) = address
+            .split_once(']')
+            .ok_or("it must close the IPv6 address with ]")?;
+        if address.is_empty()
+            || !address
+                .chars()
+                .all(|char| char.is_ascii_hexdigit() || matches!(char, ':' | '.'))
+        {
+            return Err("it must contain a valid IPv6 address");
+        }
+        port
+    } else {
+        let host_end = authority.find(':').unwrap_or(authority.len());
+        let host = &authority[..host_end];
+        if host.is_empty()
+            || !host
+                .chars()
+                .all(|char| char.is_ascii_alphanumeric() || matches!(char, '-' | '.'))
+        {
+            return Err("it must contain a hostname with only letters, digits, -, and .");
+        }
+        &authority[host_end..]
+    };
+    let valid_port = port.is_empty()
+        || port
+            .strip_prefix(':')
+            .and_then(|port| port.parse::<u16>().ok())
+            .is_some_and(|port| port != 0);
+    if !valid_port {
+        return Err("it must have a port between 1 and 65535");
+    }
+    Ok(format!("{scheme}://{authority}"))
+}
+
+/// The protocol that caddy uses to communicate with a project's upstream
+#[derive(Clone, Copy, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[cfg_attr(test, derive(Debug))]
+#[serde(rename_all = "lowercase")]
+pub enum UpstreamProtocol {
+    // HTTP/1.1
+    #[default]
+    Http,
+
+    // HTTP/2 over cleartext, used by gRPC services
+    H2c,
+}
+
+impl UpstreamProtocol {
+    // Determine whether the protocol is the default HTTP protocol
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    fn is_http(&self) -> bool {
+        *self == Self::Http
+    }
+}
+
+/// A color that distinguishes a project in the gallery and in the list of projects
+#[derive(Clone, Copy, Deserialize, Eq, PartialEq, Serialize)]
+#[cfg_attr(test, derive(Debug))]
+#[serde(rename_all = "lowercase")]
+pub enum ProjectColor {
+    Red,
+    Yellow,
+    Green,
+    Cyan,
+    Blue,
+    Magenta,
+}
+
+impl ProjectColor {
+    /// Return the CSS color of the project's card in the gallery
+    pub const fn css(self) -> &'static str {
+        match self {
+            Self::Red => "#d33",
+            Self::Yellow => "#db0",
+            Self::Green => "#3a3",
+            Self::Cyan => "#2ab",
+            Self::Blue => "#36d",
+            Self::Magenta => "#c3c",
+        }
+    }
+
+    /// Return the ANSI escape sequence that colors the project in a terminal
+    pub const fn ansi(self) -> &'static str {
+        match self {
+            Self::Red => "\x1b[31m",
+            Self::Yellow => "\x1b[33m",
+            Self::Green => "\x1b[32m",
+            Self::Cyan => "\x1b[36m",
+            Self::Blue => "\x1b[34m",
+            Self::Magenta => "\x1b[35m",
+        }
+    }
+}
+
+/// Determine whether a project's icon is the URL of an image instead of an emoji
+pub fn is_icon_url(icon: &str) -> bool {
+    icon.starts_with("https://") || icon.starts_with("http://")
+}
+
+/// The transport protocol of a port that caddy forwards without proxying HTTP
+#[derive(Clone, Copy, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[cfg_attr(test, derive(Debug))]
+#[serde(rename_all = "lowercase")]
+pub enum ForwardProtocol {
+    #[default]
+    Tcp,
+    Udp,
+}
 
+impl ForwardProtocol {
+    // Determine whether the protocol is the default TCP protocol
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    fn is_tcp(&self) -> bool {
+        *self == Self::Tcp
+    }
+}
+
+impl Display for ForwardProtocol {
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Self::Tcp => write!(fmt, "tcp"),
+            Self::Udp => write!(fmt, "udp"),
+        }
+    }
+}
+
+/// A raw TCP or UDP port that caddy forwards to one of a project's ports, for services like
+/// databases that don't speak HTTP
 #[derive(Clone, Deserialize, Serialize)]
 #[cfg_attr(test, derive(Debug, Eq, PartialEq))]
+pub struct PortForward {
+    /// The port that caddy listens on
+    pub listen_port: u16,
+    /// The named port that connections are forwarded to, or None to forward them to the project's
+    /// primary port
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port_name: Option<String>,
+    #[serde(default, skip_serializing_if = "ForwardProtocol::is_tcp")]
+    pub protocol: ForwardProtocol,
+}
+
+/// The order to list projects in
+#[derive(Clone, Copy, Default)]
+pub enum ProjectOrder {
+    #[default]
+    Name,
+    Port,
+    // Projects without a directory are listed last
+    Directory,
+}
+
+impl ProjectOrder {
+    /// Sort projects that are already sorted by name, keeping projects with the same key in name order
+    pub fn sort(self, projects: &mut [(&String, &Project)]) {
+        match self {
+            Self::Name => {}
+            Self::Port => projects.sort_by_key(|(_, project)| project.port),
+            Self::Directory => projects.sort_by(|(_, project1), (_, project2)| {
+                (project1.directory.is_none(), &project1.directory)
+                    .cmp(&(project2.directory.is_none(), &project2.directory))
+            }),
+        }
+    }
+}
+
+/// Criteria that projects must meet to be listed
+#[derive(Default)]
+pub struct ProjectFilter {
+    /// A glob pattern that project names must match
+    pub pattern: Option<String>,
+    pub linked_only: bool,
+    pub no_directory: bool,
+}
+
+impl ProjectFilter {
+    /// Determine whether a project meets all of the criteria
+    pub fn matches(&self, name: &str, project: &Project) -> bool {
+        self.pattern
+            .as_ref()
+            .is_none_or(|pattern| glob_matches(pattern, name))
+            && (!self.linked_only || project.linked_port.is_some())
+            && (!self.no_directory || project.directory.is_none())
+    }
+}
+
+// Determine whether a name matches a glob pattern, where * matches any sequence of characters and ?
+// matches any single character
+fn glob_matches(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+    let (mut pattern_index, mut name_index) = (0, 0);
+    // The position of the last * and the name position that it matched up to
+    let mut backtrack = None;
+    while name_index < name.len() {
+        match pattern.get(pattern_index) {
+            Some('*') => {
+                backtrack = Some((pattern_index, name_index));
+                pattern_index += 1;
+            }
+            Some(char) if *char == '?' || *char == name[name_index] => {
+                pattern_index += 1;
+                name_index += 1;
+            }
+            _ => {
+                // Let the last * match one more character and try again
+                let Some((star_index, star_name_index)) = backtrack else {
+                    return false;
+                };
+                pattern_index = star_index + 1;
+                name_index = star_name_index + 1;
+                backtrack = Some((star_index, name_index));
+            }
+        }
+    }
+    pattern[pattern_index..].iter().all(|char| *char == '*')
+}
+
+#[derive(Clone, Default, Deserialize, Serialize)]
+#[cfg_attr(test, derive(Debug, Eq, PartialEq))]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Project {
     pub port: u16,
+    /// Whether the port was allocated by the fallback from outside of the configured port ranges
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub out_of_range: bool,
+    /// Whether the port was adopted from a running server and is kept even if it is outside of the
+    /// configured port ranges
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
+    /// Additional ports that the project owns, keyed by name, which are proxied at
+    /// `{port_name}.{project_name}.localhost`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub ports: BTreeMap<String, u16>,
     pub directory: Option<PathBuf>,
+    /// Additional directories that also activate the project, like git worktrees or symlinked
+    /// checkouts
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub directories: Vec<PathBuf>,
     pub linked_port: Option<u16>,
+    /// Ports linked to the project's named ports, keyed by port name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub linked_ports: BTreeMap<String, u16>,
+    pub display_name: Option<String>,
+    pub icon: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<ProjectColor>,
+    pub description: Option<String>,
+    pub upstream: Option<Upstream>,
+    #[serde(default, skip_serializing_if = "UpstreamProtocol::is_http")]
+    pub upstream_protocol: UpstreamProtocol,
+    /// Whether to tune the proxy for dev servers with hot module replacement websockets
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub hmr: bool,
+    /// Whether caddy also serves the project at every subdomain of its hostname, like
+    /// `{tenant}.{project_name}.localhost`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub wildcard: bool,
+    /// The path that health checks request instead of only checking that the port accepts
+    /// connections
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health_path: Option<String>,
+    /// Caddyfile directives that are added to the project's site blocks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub caddy_extra: Option<String>,
+    /// The addresses that caddy listens on for the project, overriding the global bind setting
+    pub bind: Option<BindAddress>,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub tags: BTreeSet<String>,
+    /// Extra hostnames that caddy serves the project at in addition to {name}.localhost
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub domains: BTreeSet<String>,
+    /// Raw TCP and UDP ports that caddy forwards to the project's ports
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub forwards: Vec<PortForward>,
+    /// Environment variables that the shell integration exports while the project is active
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// The git branch that the project is active for, if it is a per-branch project
+    pub branch: Option<String>,
+    /// The git remote URL of the repo whose checkouts activate the project, in addition to its
+    /// directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo: Option<String>,
+    /// The registry.d file that the project was loaded from, if any
+    #[serde(skip)]
+    pub group: Option<String>,
+}
+
+impl Project {
+    /// Iterate over every directory that activates the project, starting with its primary directory
+    pub fn activation_directories(&self) -> impl Iterator<Item = &PathBuf> {
+        self.directory.iter().chain(&self.directories)
+    }
+
+    /// Iterate over every port linked to the project, including the ports linked to its named ports
+    pub fn iter_linked_ports(&self) -> impl Iterator<Item = u16> + '_ {
+        self.linked_port
+            .into_iter()
+            .chain(self.linked_ports.values().copied())
+    }
+
+    /// Return the port that a forwarded port forwards connections to
+    pub fn forward_target(&self, forward: &PortForward) -> Option<u16> {
+        forward
+            .port_name
+            .as_ref()
+            .map_or(Some(self.port), |port_name| {
+                self.ports.get(port_name).copied()
+            })
+    }
+}
+
+/// A repo's port and optional metadata
+#[derive(Clone, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[cfg_attr(test, derive(Debug))]
+#[serde(from = "RepoData", into = "RepoData")]
+pub struct RepoEntry {
+    /// The port that projects created in the repo are linked to
+    pub port: u16,
+    /// The port assigned to projects created in the repo when it is available
+    pub project_port: Option<u16>,
+    /// The ports that projects created in the repo link to their named ports, keyed by port name
+    pub linked_ports: BTreeMap<String, u16>,
+    pub description: Option<String>,
+    /// The name of projects created in the repo when no name is provided
+    pub default_project_name: Option<String>,
+}
+
+// A repo as it is stored in the registry, which is just its port if it has no metadata
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum RepoData {
+    Port(u16),
+    Detailed {
+        port: u16,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        project_port: Option<u16>,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        linked_ports: BTreeMap<String, u16>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        description: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        default_project_name: Option<String>,
+    },
+}
+
+impl From<RepoData> for RepoEntry {
+    fn from(value: RepoData) -> Self {
+        match value {
+            RepoData::Port(port) => Self {
+                port,
+                ..Default::default()
+            },
+            RepoData::Detailed {
+                port,
+                project_port,
+                linked_ports,
+                description,
+                default_project_name,
+            } => Self {
+                port,
+                project_port,
+                linked_ports,
+                description,
+                default_project_name,
+            },
+        }
+    }
 }
 
-// The port registry data that will be serialized and deserialized in the database
+impl From<RepoEntry> for RepoData {
+    fn from(value: RepoEntry) -> Self {
+        if value.project_port.is_none()
+            && value.linked_ports.is_empty()
+            && value.description.is_none()
+            && value.default_project_name.is_none()
+        {
+            Self::Port(value.port)
+        } else {
+            Self::Detailed {
+                port: value.port,
+                project_port: value.project_port,
+                linked_ports: value.linked_ports,
+                description: value.description,
+                default_project_name: value.default_project_name,
+            }
+        }
+    }
+}
+
+/// Changes to a repo's port and metadata
+/// A project port of 0 clears the repo's project port, and a linked port of 0 clears the named
+/// port's linked port
+#[derive(Default)]
+pub struct RepoUpdate {
+    pub port: Option<u16>,
+    pub project_port: Option<u16>,
+    /// Named ports and the ports to link to them
+    pub linked_ports: Vec<(String, u16)>,
+    pub description: Option<String>,
+    pub default_project_name: Option<String>,
+}
+
+/// A port allocated to an external tool until it is released or expires
+#[derive(Clone, Deserialize, Serialize)]
+#[cfg_attr(test, derive(Debug, Eq, PartialEq))]
+pub struct Lease {
+    pub port: u16,
+    /// The time that the lease expires, in seconds since the Unix epoch
+    pub expires: u64,
+    pub description: Option<String>,
+}
+
+/// A port claimed by a tool that isn't a project, like a database container, which is never
+/// allocated
+#[derive(Clone, Deserialize, Serialize)]
+#[cfg_attr(test, derive(Debug, Eq, PartialEq))]
+pub struct Reservation {
+    pub port: u16,
+    pub description: Option<String>,
+}
+
+/// The port registry data that will be serialized and deserialized in the database
 #[derive(Clone, Default, Deserialize, Serialize)]
 pub struct RegistryData {
     pub projects: BTreeMap<String, Project>,
     #[serde(default)]
-    pub repos: BTreeMap<String, u16>,
+    pub repos: BTreeMap<String, RepoEntry>,
+    /// The ports that archived projects kept, which aren't allocated to other projects
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub archived_ports: BTreeMap<String, u16>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub leases: Vec<Lease>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reservations: Vec<Reservation>,
+}
+
+// The data stored in a registry.d file, which can only contain projects
+#[derive(Default, Deserialize, Serialize)]
+struct GroupData {
+    #[serde(default)]
+    projects: BTreeMap<String, Project>,
+}
+
+/// A port that was reassigned while loading the registry because it was no longer valid, for
+/// example because it is outside of the configured port ranges
+#[derive(Clone)]
+#[cfg_attr(test, derive(Debug, Eq, PartialEq))]
+pub struct PortReassignment {
+    pub project: String,
+    /// The name of the reassigned named port, or None if the project's primary port was reassigned
+    pub port_name: Option<String>,
+    pub old_port: u16,
+    pub new_port: u16,
+}
+
+impl Display for PortReassignment {
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        match &self.port_name {
+            Some(port_name) => write!(fmt, "{}'s {port_name} port", self.project)?,
+            None => write!(fmt, "{}", self.project)?,
+        }
+        write!(fmt, " moved from {} to {}", self.old_port, self.new_port)
+    }
 }
 
+/// The projects that portman manages and the ports allocated to them
+///
+/// Changes are only written to disk and applied to caddy when the registry is saved.
 #[cfg_attr(test, derive(Debug))]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Registry {
     store_path: PathBuf,
     projects: BTreeMap<String, Project>,
-    repos: BTreeMap<String, u16>,
+    repos: BTreeMap<String, RepoEntry>,
+    archived_ports: BTreeMap<String, u16>,
+    leases: Vec<Lease>,
     allocator: PortAllocator,
+    reservations: Vec<Reservation>,
+    groups: BTreeSet<String>,
+    // The number of older backups of the registry files to keep besides the last saved version
+    backups: usize,
+    bind: BindAddress,
+    gallery_hostname: String,
+    tld: String,
+    tls_mode: TlsMode,
+    // Whether caddy streams responses for every project instead of only projects with HMR tuning
+    streaming: bool,
+    // Whether caddy writes each project's requests to an access log
+    access_logs: bool,
+    // Whether caddy also serves every project at a .local hostname for other devices on the network
+    mdns: bool,
+    // Whether saving the registry reloads caddy
+    auto_reload: bool,
+    // Whether saving the registry fails when caddy isn't installed
+    require_caddy: bool,
+    // The root Caddyfile that caddy loads, if it was configured
+    caddyfile: Option<PathBuf>,
+    // The ports that new projects are assigned instead of allocated ports, keyed by project name
+    port_overrides: BTreeMap<String, u16>,
+    // The ports that were reassigned while loading the registry
+    reassignments: Vec<PortReassignment>,
+    modified_externally: bool,
     dirty: bool,
+    // Whether saving and reloading caddy are skipped so that changes can be previewed
+    dry_run: bool,
+    // Held until the registry is dropped so that other processes can't modify it in the meantime
+    lock: Option<FileLock>,
 }
 
-impl Registry {
-    // Create a new registry
-    pub fn new(
-        deps: &(impl ChoosePort + DataDir + Environment + ReadFile),
-        port_allocator: PortAllocator,
-    ) -> Result<Self> {
-        let store_path = deps.get_data_dir()?.join(PathBuf::from("registry.toml"));
-        let registry_data = deps
-            .read_file(&store_path)
-            .context("Failed to load registry")?
-            .map(|registry_str| {
-                toml::from_str::<RegistryData>(&registry_str).with_context(|| {
-                    format!(
-                        "Failed to deserialize project registry at \"{}\"",
-                        store_path.display()
-                    )
-                })
-            })
-            .transpose()?
-            .unwrap_or_default();
+/// What saving the registry did
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SaveOutcome {
+    /// Nothing was saved because the registry didn't change or changes are only being previewed
+    Unchanged,
 
-        let mut allocator = port_allocator;
-        let mut linked_ports = HashSet::new();
-        for project in registry_data.projects.values() {
-            if let Some(linked_port) = project.linked_port {
-                // Prevent projects from using this port
-                allocator.discard(linked_port);
-            };
-        }
+    /// The registry was saved, but caddy wasn't reloaded because reloading is disabled
+    Saved,
 
-        let mut dirty = false;
-        let mut directories: HashSet<PathBuf> = HashSet::new();
+    /// The registry was saved and caddy was reloaded
+    Reloaded,
 
-        // Validate all ports in the registry against the config and regenerate
-        // invalid ones as necessary
-        let projects = registry_data
-            .projects
-            .into_iter()
-            .map(|(name, old_project)| {
-                Self::validate_name(&name)?;
+    /// The registry was saved, but caddy wasn't reloaded because it isn't installed and isn't
+    /// required
+    CaddyMissing,
+}
 
-                let mut old_project = old_project;
+/// Return the FNV-1a hash of a file's contents as a hex string
+/// This only needs to detect modifications, so a cryptographic hash isn't necessary
+pub fn checksum(contents: &str) -> String {
+    let hash = contents
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+        });
+    format!("{hash:016x}")
+}
 
-                if let Some(linked_port) = old_project.linked_port {
-                    if !linked_ports.insert(linked_port) {
-                        old_project.linked_port = None;
-                        dirty = true;
-                    }
-                }
+impl Registry {
+    /// Return the path to the registry file
+    pub fn store_path(deps: &impl DataDir) -> Result<PathBuf> {
+        Ok(deps.get_data_dir()?.join(PathBuf::from("registry.toml")))
+    }
 
-                if let Some(directory) = old_project.directory.as_ref() {
-                    if !directories.insert(directory.clone()) {
-                        old_project.directory = None;
-                        dirty = true;
-                    }
-                }
+    /// Return the path to the file that portman locks while it is using the registry
+    pub fn lock_path(deps: &impl DataDir) -> Result<PathBuf> {
+        Ok(Self::store_path(deps)?.with_file_name("registry.toml.lock"))
+    }
 
-                let existing_port = old_project.port;
-                allocator.allocate(deps, Some(existing_port)).map(|port| {
-                    if port != existing_port {
-                        dirty = true;
-                    }
-                    (
-                        name,
-                        Project {
-                            port,
-                            ..old_project
-                        },
-                    )
-                })
-            })
-            .collect::<Result<BTreeMap<_, _>>>()?;
-        let registry = Self {
-            store_path,
-            projects,
-            repos: registry_data.repos,
-            allocator,
-            dirty,
-        };
-        Ok(registry)
+    // Return the path to the checksum of the registry file that portman last wrote
+    fn checksum_path(store_path: &Path) -> PathBuf {
+        store_path.with_file_name("registry.toml.checksum")
     }
 
-    // Save a port registry to the file
-    pub fn save(
-        &self,
-        deps: &(impl DataDir + Environment + Exec + ReadFile + WriteFile),
+    // Return the path to a backup of a registry file, where backup 1 is the version that portman
+    // saved most recently and higher indexes are older versions
+    fn backup_path(path: &Path, index: usize) -> PathBuf {
+        let mut file_name = path.file_name().unwrap_or_default().to_owned();
+        file_name.push(format!(".{index}"));
+        path.with_file_name(file_name)
+    }
+
+    // Back up the contents of a registry file that portman saved, shifting its older backups and
+    // keeping at most `backups` of them besides the new backup
+    fn write_backup(
+        deps: &(impl ReadFile + WriteFile),
+        path: &Path,
+        contents: &str,
+        backups: usize,
     ) -> Result<()> {
-        if !self.dirty {
-            return Ok(());
+        for index in (1..=backups).rev() {
+            let backup = deps
+                .read_file(&Self::backup_path(path, index))
+                .context("Failed to load registry backup")?;
+            if let Some(backup) = backup {
+                deps.write_file(&Self::backup_path(path, index + 1), &backup)
+                    .context("Failed to save registry backup")?;
+            }
         }
-
-        let registry = RegistryData {
-            projects: self.projects.clone(),
-            repos: self.repos.clone(),
-        };
-        let registry_str =
-            toml::to_string(&registry).context("Failed to serialize project registry")?;
-        deps.write_file(&self.store_path, &registry_str)
-            .context("Failed to save registry")?;
-        reload(deps, self).map_err(ApplicationError::Caddy)
+        deps.write_file(&Self::backup_path(path, 1), contents)
+            .context("Failed to save registry backup")?;
+        Ok(())
     }
 
-    // Get a project from the registry
-    pub fn get(&self, name: &str) -> Option<&Project> {
-        self.projects.get(name)
+    // Write the checksum of the registry file contents
+    fn write_checksum(deps: &impl WriteFile, store_path: &Path, contents: &str) -> Result<()> {
+        deps.write_file(&Self::checksum_path(store_path), &checksum(contents))
+            .context("Failed to save registry checksum")?;
+        Ok(())
     }
 
-    // Create a new project and return it
-    pub fn create(
-        &mut self,
-        deps: &impl ChoosePort,
-        name: &str,
-        directory: Option<PathBuf>,
-        linked_port: Option<u16>,
-    ) -> Result<Project> {
-        Self::validate_name(name)?;
+    // Return the registry file and, in the split layout, the registry.d files
+    fn registry_files(
+        deps: &(impl DataDir + ListDirectory),
+        layout: RegistryLayout,
+    ) -> Result<Vec<PathBuf>> {
+        let store_path = Self::store_path(deps)?;
+        let mut paths = match layout {
+            RegistryLayout::Single => vec![],
+            RegistryLayout::Split => Self::group_paths(deps, &store_path)?
+                .into_values()
+                .collect(),
+        };
+        paths.insert(0, store_path);
+        Ok(paths)
+    }
 
-        if self.projects.contains_key(name) {
-            return Err(ApplicationError::DuplicateProject(name.to_owned()));
+    /// Accept changes made to the registry files outside of portman by backing them up and updating
+    /// the registry checksum as if portman had saved them
+    pub fn accept_changes(
+        deps: &(impl DataDir + ListDirectory + ReadFile + WriteFile),
+        layout: RegistryLayout,
+        backups: usize,
+    ) -> Result<()> {
+        let store_path = Self::store_path(deps)?;
+        for path in Self::registry_files(deps, layout)? {
+            let contents = deps
+                .read_file(&path)
+                .context("Failed to load registry")?
+                .unwrap_or_default();
+            Self::write_backup(deps, &path, &contents, backups)?;
+            if path == store_path {
+                Self::write_checksum(deps, &store_path, &contents)?;
+            }
         }
+        Ok(())
+    }
 
-        if let Some(directory) = directory.as_ref() {
-            if let Some((name, _)) = self
-                .projects
-                .iter()
-                .find(|(_, project)| project.directory.as_ref() == Some(directory))
-            {
-                return Err(ApplicationError::DuplicateDirectory(
-                    name.clone(),
-                    directory.clone(),
-                ));
+    /// Replace the registry files with one of their backups, where backup 1 is the version that
+    /// portman saved most recently
+    pub fn restore_backup(
+        deps: &(impl DataDir + ListDirectory + ReadFile + WriteFile),
+        layout: RegistryLayout,
+        index: usize,
+    ) -> Result<()> {
+        let store_path = Self::store_path(deps)?;
+        let backup_path = Self::backup_path(&store_path, index);
+        let contents = deps
+            .read_file(&backup_path)
+            .context("Failed to load registry backup")?
+            .with_context(|| {
+                format!("No registry backup exists at \"{}\"", backup_path.display())
+            })?;
+        deps.write_file(&store_path, &contents)
+            .context("Failed to save registry")?;
+        Self::write_checksum(deps, &store_path, &contents)?;
+
+        // registry.d files without the backup were created after it, so they are left alone
+        if layout == RegistryLayout::Split {
+            for path in Self::group_paths(deps, &store_path)?.into_values() {
+                let backup = deps
+                    .read_file(&Self::backup_path(&path, index))
+                    .context("Failed to load registry backup")?;
+                if let Some(contents) = backup {
+                    deps.write_file(&path, &contents)
+                        .context("Failed to save registry group")?;
+                }
             }
         }
+        Ok(())
+    }
 
-        if let Some(port) = linked_port {
-            self.allocator.discard(port);
-        }
+    /// Set the number of older backups of the registry files to keep
+    pub fn with_backups(self, backups: usize) -> Self {
+        Self { backups, ..self }
+    }
 
-        let port = self.allocator.allocate(deps, None)?;
-        let mut new_project = Project {
-            port,
-            directory,
-            linked_port: None,
-        };
-        self.projects.insert(name.to_owned(), new_project.clone());
+    /// Set the addresses that caddy listens on for projects without their own bind setting
+    pub fn with_bind(self, bind: BindAddress) -> Self {
+        Self { bind, ..self }
+    }
 
-        if let Some(port) = linked_port {
-            self.link(deps, name, port)?;
-            new_project.linked_port = Some(port);
+    /// Hold a lock on the registry until the registry is dropped
+    pub fn with_lock(self, lock: FileLock) -> Self {
+        Self {
+            lock: Some(lock),
+            ..self
         }
+    }
 
-        self.dirty = true;
+    /// Skip saving the registry and reloading caddy
+    pub fn with_dry_run(self, dry_run: bool) -> Self {
+        Self { dry_run, ..self }
+    }
 
-        Ok(new_project)
+    /// Determine whether saving the registry and reloading caddy are skipped
+    pub const fn is_dry_run(&self) -> bool {
+        self.dry_run
     }
 
-    // Update a project and return the updated project
-    pub fn update(&mut self, name: &str, directory: Option<PathBuf>) -> Result<Project> {
-        let project = self
-            .projects
-            .get_mut(name)
-            .ok_or_else(|| ApplicationError::NonExistentProject(String::from(name)))?;
-        if project.directory != directory {
-            project.directory = directory;
-            self.dirty = true;
+    /// Release the registry lock early for commands that don't save the registry but run other
+    /// commands that may need it, like other portman invocations
+    pub fn unlock(&mut self) {
+        self.lock = None;
+    }
+
+    /// Set the hostname that serves the gallery
+    pub fn with_gallery_hostname(self, gallery_hostname: String) -> Self {
+        Self {
+            gallery_hostname,
+            ..self
         }
-        Ok(project.clone())
     }
 
-    // Delete a project and return the deleted project and its names
-    pub fn delete(&mut self, name: &str) -> Result<Project> {
-        let project = self
-            .projects
-            .remove(name)
-            .ok_or_else(|| ApplicationError::NonExistentProject(String::from(name)))?;
-        self.dirty = true;
-        Ok(project)
+    /// Return the hostname that serves the gallery
+    pub fn gallery_hostname(&self) -> &str {
+        &self.gallery_hostname
     }
 
-    // Delete multiple projects and return the deleted projects and their names
-    pub fn delete_many(&mut self, project_names: Vec<String>) -> Result<Vec<(String, Project)>> {
-        let deleted_projects: Vec<(String, Project)> = project_names
-            .into_iter()
-            .map(|name| {
-                let project = self
-                    .projects
-                    .remove(&name)
-                    .ok_or_else(|| ApplicationError::NonExistentProject(name.clone()))?;
-                Ok((name, project))
-            })
-            .collect::<Result<Vec<_>>>()?;
-        if !deleted_projects.is_empty() {
-            self.dirty = true;
-        }
-        Ok(deleted_projects)
+    /// Set the top-level domain that projects are served under
+    pub fn with_tld(self, tld: String) -> Self {
+        Self { tld, ..self }
     }
 
-    // Iterate over all projects with their names
-    pub fn iter_projects(&self) -> impl Iterator<Item = (&String, &Project)> {
-        self.projects.iter()
+    /// Set the root Caddyfile that caddy loads
+    pub fn with_caddyfile(self, caddyfile: Option<PathBuf>) -> Self {
+        Self { caddyfile, ..self }
     }
 
-    // Link a port to a project
-    pub fn link(
-        &mut self,
-        deps: &impl ChoosePort,
-        project_name: &str,
-        linked_port: u16,
-    ) -> Result<()> {
-        if !self.projects.contains_key(project_name) {
-            return Err(ApplicationError::NonExistentProject(String::from(
-                project_name,
-            )));
+    /// Return the root Caddyfile that caddy loads, if it was configured
+    pub fn caddyfile(&self) -> Option<&Path> {
+        self.caddyfile.as_deref()
+    }
+
+    /// Set the ports that new projects with specific names are assigned
+    pub fn with_port_overrides(self, port_overrides: BTreeMap<String, u16>) -> Self {
+        Self {
+            port_overrides,
+            ..self
         }
+    }
 
-        for (name, project) in &mut self.projects {
-            if project.port == linked_port {
-                // Take the port from the project so that it can be used by the linked port
-                project.port = self.allocator.allocate(deps, None)?;
-                self.dirty = true;
-            }
-            if name == project_name {
-                if project.linked_port != Some(linked_port) {
-                    // Link the port to the new project
-                    project.linked_port = Some(linked_port);
-                    self.dirty = true;
-                }
-            } else if project.linked_port == Some(linked_port) {
-                // Unlink the port from the previous project
-                project.linked_port = None;
-                self.dirty = true;
-            }
+    /// Set whether caddy serves projects over HTTPS
+    pub fn with_tls_mode(self, tls_mode: TlsMode) -> Self {
+        Self { tls_mode, ..self }
+    }
+
+    /// Set whether caddy streams responses for every project
+    pub fn with_streaming(self, streaming: bool) -> Self {
+        Self { streaming, ..self }
+    }
+
+    /// Determine whether caddy streams responses and waits longer for a project, either because HMR
+    /// tuning is enabled for it or because streaming is enabled for every project
+    pub const fn streams(&self, project: &Project) -> bool {
+        project.hmr || self.streaming
+    }
+
+    /// Set whether caddy writes each project's requests to an access log
+    pub fn with_access_logs(self, access_logs: bool) -> Self {
+        Self {
+            access_logs,
+            ..self
         }
+    }
 
-        Ok(())
+    /// Return whether caddy writes each project's requests to an access log
+    pub const fn access_logs(&self) -> bool {
+        self.access_logs
     }
 
-    // Unlink the port linked to a project and return the name of the project it was linked to
-    pub fn unlink(&mut self, port: u16) -> Option<String> {
-        for (name, project) in &mut self.projects {
-            if project.linked_port == Some(port) {
-                project.linked_port = None;
-                self.dirty = true;
-                return Some(name.clone());
-            }
+    /// Set whether caddy also serves every project at a .local hostname
+    pub fn with_mdns(self, mdns: bool) -> Self {
+        Self { mdns, ..self }
+    }
+
+    /// Return whether caddy also serves every project at a .local hostname
+    pub const fn mdns(&self) -> bool {
+        self.mdns
+    }
+
+    /// Set whether saving the registry reloads caddy
+    pub fn with_auto_reload(self, auto_reload: bool) -> Self {
+        Self {
+            auto_reload,
+            ..self
         }
-        None
     }
 
-    // Get the port associated with a repo
-    pub fn get_repo_port(&self, repo: &str) -> Result<u16> {
-        self.repos
-            .get(repo)
-            .copied()
-            .ok_or_else(|| ApplicationError::NonExistentRepo(repo.to_owned()))
+    /// Return whether changes to the registry reload caddy
+    pub const fn auto_reload(&self) -> bool {
+        self.auto_reload
     }
 
-    // Get the port associated with a repo
-    pub fn set_repo_port(&mut self, repo: String, port: u16) {
-        if self.repos.insert(repo, port) != Some(port) {
-            self.dirty = true;
+    /// Set whether saving the registry fails when caddy isn't installed
+    pub fn with_require_caddy(self, require_caddy: bool) -> Self {
+        Self {
+            require_caddy,
+            ..self
         }
     }
 
-    // Delete a repo's port association
-    pub fn delete_repo(&mut self, repo: &str) -> Result<u16> {
-        let deleted_repo = self.repos.remove(repo);
-        if deleted_repo.is_some() {
-            self.dirty = true;
+    /// Return the hostname that serves a project
+    pub fn hostname(&self, name: &str) -> String {
+        format!("{name}.{}", self.tld)
+    }
+
+    // Return the scheme of the URLs that serve projects
+    const fn scheme(&self) -> &'static str {
+        match self.tls_mode {
+            TlsMode::Internal => "https",
+            TlsMode::Off => "http",
         }
-        deleted_repo.ok_or_else(|| ApplicationError::NonExistentRepo(repo.to_owned()))
     }
 
-    // Delete a repo's port association
-    pub fn iter_repos(&self) -> impl Iterator<Item = (&String, &u16)> {
-        self.repos.iter()
+    /// Return the URL that serves a project
+    pub fn url(&self, name: &str) -> String {
+        format!("{}://{}", self.scheme(), self.hostname(name))
     }
 
-    // Find and return the project that matches the current working directory, if any
-    pub fn match_cwd(&self, deps: &impl WorkingDirectory) -> Result<Option<(&String, &Project)>> {
-        let cwd = deps.get_cwd()?;
-        Ok(self.iter_projects().find(|(_, project)| {
-            project
-                .directory
-                .as_ref()
-                .map_or(false, |directory| directory == &cwd)
-        }))
+    /// Return the URL that a project should be reached at, which is its linked port if it has one
+    /// and its hostname otherwise
+    pub fn project_url(&self, name: &str, project: &Project) -> String {
+        project.linked_port.map_or_else(
+            || self.url(name),
+            |linked_port| format!("http://localhost:{linked_port}"),
+        )
     }
 
-    // Normalize a potential project name by stripping out invalid characters
-    pub fn normalize_name(name: &str) -> String {
-        let mut normalized = name
-            .chars()
-            .map(|char| {
-                if char.is_ascii_alphanumeric() || char == '-' {
-                    char
+    /// Return the URL that serves one of a project's named ports
+    pub fn port_url(&self, name: &str, port_name: &str) -> String {
+        format!("{}://{port_name}.{}", self.scheme(), self.hostname(name))
+    }
+
+    /// Return the top-level domain that projects are served under
+    pub fn tld(&self) -> &str {
+        &self.tld
+    }
+
+    /// Return whether caddy serves projects over HTTPS
+    pub const fn tls_mode(&self) -> TlsMode {
+        self.tls_mode
+    }
+
+    /// Return the addresses that caddy listens on for projects without their own bind setting
+    pub const fn default_bind(&self) -> &BindAddress {
+        &self.bind
+    }
+
+    /// Return the addresses that caddy listens on for a project
+    pub fn bind_for<'registry>(
+        &'registry self,
+        project: &'registry Project,
+    ) -> &'registry BindAddress {
+        project.bind.as_ref().unwrap_or(&self.bind)
+    }
+
+    /// Determine whether the registry file was modified outside of portman since portman last
+    /// wrote it
+    pub const fn modified_externally(&self) -> bool {
+        self.modified_externally
+    }
+
+    /// Return the ports that were reassigned while loading the registry because they were no longer
+    /// valid
+    pub fn reassignments(&self) -> &[PortReassignment] {
+        &self.reassignments
+    }
+
+    // Return the path to the directory containing the registry.d files
+    fn group_dir(store_path: &Path) -> PathBuf {
+        store_path.with_file_name("registry.d")
+    }
+
+    // Return the paths to the registry.d files, keyed by the file stem
+    fn group_paths(
+        deps: &impl ListDirectory,
+        store_path: &Path,
+    ) -> Result<BTreeMap<String, PathBuf>> {
+        Ok(deps
+            .list_directory(&Self::group_dir(store_path))?
+            .into_iter()
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == "toml")
+            })
+            .filter_map(|path| {
+                let group = path.file_stem()?.to_str()?.to_owned();
+                Some((group, path))
+            })
+            .collect())
+    }
+
+    // Load the projects from the registry.d files, keyed by the file stem
+    fn load_groups(
+        deps: &(impl ListDirectory + ReadFile),
+        store_path: &Path,
+    ) -> Result<BTreeMap<String, GroupData>> {
+        Self::group_paths(deps, store_path)?
+            .into_iter()
+            .map(|(group, path)| {
+                let group_str = deps
+                    .read_file(&path)
+                    .context("Failed to load registry group")?
+                    .unwrap_or_default();
+                let group_data = toml::from_str::<GroupData>(&group_str).with_context(|| {
+                    format!(
+                        "Failed to deserialize project registry group at \"{}\"",
+                        path.display()
+                    )
+                })?;
+                Ok((group, group_data))
+            })
+            .collect()
+    }
+
+    /// Load the registry with every setting from the configuration applied, like the CLI does
+    pub fn load(
+        deps: &(impl CheckPortInUse
+              + ChoosePort
+              + CurrentTime
+              + DataDir
+              + Environment
+              + EphemeralPort
+              + ListDirectory
+              + ReadFile),
+        config: &Config,
+    ) -> Result<Self> {
+        let port_allocator = PortAllocator::new(config.get_valid_ports())
+            .with_tag_ranges(config.tag_ranges.clone())
+            .with_fallback(config.fallback)
+            .with_strategy(config.strategy);
+        Ok(Self::new(deps, port_allocator, config.layout)?
+            .with_backups(config.backups)
+            .with_bind(config.bind.clone())
+            .with_gallery_hostname(config.gallery_hostname.clone())
+            .with_tld(config.tld.clone())
+            .with_tls_mode(config.tls_mode)
+            .with_streaming(config.streaming)
+            .with_access_logs(config.access_logs)
+            .with_mdns(config.mdns)
+            .with_auto_reload(config.auto_reload)
+            .with_require_caddy(config.require_caddy)
+            .with_caddyfile(config.caddyfile.clone())
+            .with_port_overrides(
+                config
+                    .project_overrides
+                    .iter()
+                    .filter_map(|(name, project_override)| {
+                        project_override.port.map(|port| (name.clone(), port))
+                    })
+                    .collect(),
+            ))
+    }
+
+    /// Create a new registry
+    #[allow(clippy::too_many_lines)]
+    pub fn new(
+        deps: &(impl CheckPortInUse
+              + ChoosePort
+              + CurrentTime
+              + DataDir
+              + Environment
+              + EphemeralPort
+              + ListDirectory
+              + ReadFile),
+        port_allocator: PortAllocator,
+        layout: RegistryLayout,
+    ) -> Result<Self> {
+        let store_path = Self::store_path(deps)?;
+        let registry_str = deps
+            .read_file(&store_path)
+            .context("Failed to load registry")?;
+        let modified_externally = deps
+            .read_file(&Self::checksum_path(&store_path))
+            .context("Failed to load registry checksum")?
+            .is_some_and(|expected| {
+                expected.trim() != checksum(registry_str.as_deref().unwrap_or_default())
+            });
+        let mut registry_data = registry_str
+            .map(|registry_str| {
+                toml::from_str::<RegistryData>(&registry_str).with_context(|| {
+                    format!(
+                        "Failed to deserialize project registry at \"{}\"",
+                        store_path.display()
+                    )
+                })
+            })
+            .transpose()?
+            .unwrap_or_default();
+
+        let mut groups = BTreeSet::new();
+        if layout == RegistryLayout::Split {
+            for (group, group_data) in Self::load_groups(deps, &store_path)? {
+                for (name, project) in group_data.projects {
+                    if registry_data.projects.contains_key(&name) {
+                        return Err(ApplicationError::DuplicateProject(name));
+                    }
+                    let project = Project {
+                        group: Some(group.clone()),
+                        ..project
+                    };
+                    registry_data.projects.insert(name, project);
+                }
+                groups.insert(group);
+            }
+        }
+
+        let mut allocator = port_allocator;
+        let mut linked_ports = HashSet::new();
+        for project in registry_data.projects.values() {
+            for linked_port in project.iter_linked_ports() {
+                // Prevent projects from using this port
+                allocator.discard(linked_port);
+            }
+        }
+        for port in registry_data.archived_ports.values() {
+            allocator.discard(*port);
+        }
+        for reservation in &registry_data.reservations {
+            allocator.discard(reservation.port);
+        }
+
+        let mut dirty = false;
+
+        // Reclaim the ports of expired leases
+        if !registry_data.leases.is_empty() {
+            let now = deps.get_current_time();
+            let lease_count = registry_data.leases.len();
+            registry_data.leases.retain(|lease| lease.expires > now);
+            dirty |= registry_data.leases.len() != lease_count;
+        }
+        for lease in &registry_data.leases {
+            allocator.discard(lease.port);
+        }
+        // Merge repos that were saved with different URLs for the same repo
+        let repo_count = registry_data.repos.len();
+        let repos = Self::merge_duplicate_repos(std::mem::take(&mut registry_data.repos));
+        dirty |= repos.len() != repo_count;
+
+        let mut directories: HashSet<(PathBuf, Option<String>)> = HashSet::new();
+        let mut matched_repos = HashSet::new();
+        let mut reassignments = Vec::new();
+
+        // Validate all ports in the registry against the config and regenerate
+        // invalid ones as necessary
+        let timer = timings::start("validate registry");
+        let projects = registry_data
+            .projects
+            .into_iter()
+            .map(|(name, old_project)| {
+                Self::validate_name(&name)?;
+
+                let mut old_project = old_project;
+
+                if let Some(linked_port) = old_project.linked_port {
+                    if !linked_ports.insert(linked_port) {
+                        old_project.linked_port = None;
+                        dirty = true;
+                    }
+                }
+                let linked_port_count = old_project.linked_ports.len();
+                old_project
+                    .linked_ports
+                    .retain(|_, linked_port| linked_ports.insert(*linked_port));
+                dirty |= old_project.linked_ports.len() != linked_port_count;
+
+                if let Some(directory) = old_project.directory.as_ref() {
+                    if !directories.insert((directory.clone(), old_project.branch.clone())) {
+                        old_project.directory = None;
+                        dirty = true;
+                    }
+                }
+                let directory_count = old_project.directories.len();
+                old_project.directories.retain(|directory| {
+                    directories.insert((directory.clone(), old_project.branch.clone()))
+                });
+                dirty |= old_project.directories.len() != directory_count;
+
+                // The upstream is inserted into the Caddyfile, so a hand-edited upstream must be
+                // rejected before it can break or inject directives into the Caddyfile
+                if let Some(upstream) = old_project.upstream.take() {
+                    let validated = upstream.clone().validate()?;
+                    dirty |= validated != upstream;
+                    old_project.upstream = Some(validated);
+                }
+
+                if let Some(repo) = old_project.repo.as_ref() {
+                    if !matched_repos.insert(repo::canonicalize(repo)) {
+                        old_project.repo = None;
+                        dirty = true;
+                    }
+                }
+
+                // Out-of-range ports keep their port as long as the fallback is still enabled, and
+                // pinned ports keep their port as long as no other project claimed it
+                let existing_port = old_project.port;
+                let port = if (old_project.out_of_range && allocator.claim_fallback(existing_port))
+                    || (old_project.pinned && allocator.claim_pinned(existing_port))
+                {
+                    existing_port
                 } else {
-                    '-'
+                    allocator.allocate_tagged(deps, Some(existing_port), &old_project.tags)?
+                };
+                let out_of_range = allocator.is_fallback(port);
+                let pinned = old_project.pinned && port == existing_port;
+                if port != existing_port {
+                    reassignments.push(PortReassignment {
+                        project: name.clone(),
+                        port_name: None,
+                        old_port: existing_port,
+                        new_port: port,
+                    });
+                }
+                if port != existing_port
+                    || out_of_range != old_project.out_of_range
+                    || pinned != old_project.pinned
+                {
+                    dirty = true;
+                }
+
+                let mut ports = BTreeMap::new();
+                for (port_name, existing_port) in std::mem::take(&mut old_project.ports) {
+                    Self::validate_name(&port_name)?;
+                    let port =
+                        allocator.allocate_tagged(deps, Some(existing_port), &old_project.tags)?;
+                    if port != existing_port {
+                        reassignments.push(PortReassignment {
+                            project: name.clone(),
+                            port_name: Some(port_name.clone()),
+                            old_port: existing_port,
+                            new_port: port,
+                        });
+                        dirty = true;
+                    }
+                    ports.insert(port_name, port);
                 }
+
+                Ok((
+                    name,
+                    Project {
+                        port,
+                        out_of_range,
+                        pinned,
+                        ports,
+                        ..old_project
+                    },
+                ))
             })
-            .skip_while(|char| char == &'-')
-            .fold(String::with_capacity(name.len()), |mut result, char| {
-                // Remove adjacent dashes
-                if !(result.ends_with('-') && char == '-') {
-                    result.push(char.to_ascii_lowercase());
+            .collect::<Result<BTreeMap<_, _>>>()?;
+        drop(timer);
+        let registry = Self {
+            store_path,
+            projects,
+            repos,
+            archived_ports: registry_data.archived_ports,
+            leases: registry_data.leases,
+            reservations: registry_data.reservations,
+            allocator,
+            groups,
+            backups: 0,
+            bind: BindAddress::default(),
+            gallery_hostname: String::from("localhost"),
+            tld: String::from("localhost"),
+            tls_mode: TlsMode::default(),
+            streaming: false,
+            access_logs: false,
+            mdns: false,
+            auto_reload: true,
+            require_caddy: true,
+            caddyfile: None,
+            port_overrides: BTreeMap::new(),
+            reassignments,
+            modified_externally,
+            dirty,
+            dry_run: false,
+            lock: None,
+        };
+        Ok(registry)
+    }
+
+    // Merge the repos whose URLs refer to the same repo, keeping the first repo's URL and port and
+    // filling in its missing metadata from the duplicates
+    fn merge_duplicate_repos(repos: BTreeMap<String, RepoEntry>) -> BTreeMap<String, RepoEntry> {
+        let mut merged: BTreeMap<String, (String, RepoEntry)> = BTreeMap::new();
+        for (url, entry) in repos {
+            match merged.entry(repo::canonicalize(&url)) {
+                Entry::Vacant(vacant) => {
+                    vacant.insert((url, entry));
                 }
-                result
-            });
-        normalized.truncate(63);
-        if normalized.ends_with('-') {
-            normalized.pop();
+                Entry::Occupied(mut occupied) => {
+                    let (_, existing) = occupied.get_mut();
+                    existing.project_port = existing.project_port.or(entry.project_port);
+                    if existing.linked_ports.is_empty() {
+                        existing.linked_ports = entry.linked_ports;
+                    }
+                    existing.description = existing.description.take().or(entry.description);
+                    existing.default_project_name = existing
+                        .default_project_name
+                        .take()
+                        .or(entry.default_project_name);
+                }
+            }
         }
-        normalized
+        merged.into_values().collect()
     }
 
-    // Validate a project name
-    fn validate_name(name: &str) -> Result<()> {
-        if name.is_empty() {
-            return Err(ApplicationError::InvalidProjectName(
-                String::from(name),
-                "must not be empty",
-            ));
+    /// Save a port registry to the file and reload caddy if caddy is reloaded automatically
+    pub fn save(
+        &self,
+        deps: &(impl CurrentTime + DataDir + Environment + Exec + ReadFile + WriteFile),
+    ) -> Result<SaveOutcome> {
+        if !self.dirty || self.dry_run {
+            return Ok(SaveOutcome::Unchanged);
         }
-        if name.len() > 63 {
-            return Err(ApplicationError::InvalidProjectName(
-                String::from(name),
-                "must not exceed 63 characters",
-            ));
+
+        // Prepare the Caddyfile before saving anything so that changes that caddy would reject are
+        // never saved and the Caddyfile is only generated and validated once
+        let caddyfile = if self.auto_reload {
+            self.allow_missing_caddy(caddy::prepare(deps, self))?
+        } else {
+            None
+        };
+
+        let timer = timings::start("save registry");
+        // Save each project back to the file that it was loaded from
+        let mut projects = BTreeMap::new();
+        let mut groups = self
+            .groups
+            .iter()
+            .map(|group| (group, GroupData::default()))
+            .collect::<BTreeMap<_, _>>();
+        for (name, project) in &self.projects {
+            match project
+                .group
+                .as_ref()
+                .and_then(|group| groups.get_mut(group))
+            {
+                Some(group_data) => group_data.projects.insert(name.clone(), project.clone()),
+                None => projects.insert(name.clone(), project.clone()),
+            };
         }
-        if name.starts_with('-') || name.ends_with('-') {
-            return Err(ApplicationError::InvalidProjectName(
-                String::from(name),
-                "must not start or end with a dash",
-            ));
+
+        let registry = RegistryData {
+            projects,
+            repos: self.repos.clone(),
+            archived_ports: self.archived_ports.clone(),
+            leases: self.leases.clone(),
+            reservations: self.reservations.clone(),
+        };
+        let registry_str =
+            toml::to_string(&registry).context("Failed to serialize project registry")?;
+        deps.write_file(&self.store_path, &registry_str)
+            .context("Failed to save registry")?;
+        Self::write_backup(deps, &self.store_path, &registry_str, self.backups)?;
+        Self::write_checksum(deps, &self.store_path, &registry_str)?;
+        for (group, group_data) in groups {
+            let group_str = toml::to_string(&group_data)
+                .context("Failed to serialize project registry group")?;
+            let group_path = Self::group_dir(&self.store_path).join(format!("{group}.toml"));
+            deps.write_file(&group_path, &group_str)
+                .context("Failed to save registry group")?;
+            Self::write_backup(deps, &group_path, &group_str, self.backups)?;
         }
-        if name.contains("--") {
-            return Err(ApplicationError::InvalidProjectName(
-                String::from(name),
-                "must not contain consecutive dashes",
-            ));
+        self.write_index(deps)?;
+        drop(timer);
+        if !self.auto_reload {
+            return Ok(SaveOutcome::Saved);
         }
-        if name
-            .chars()
-            .any(|char| !(char.is_ascii_lowercase() || char.is_numeric() || char == '-'))
+        let reloaded = match caddyfile {
+            Some(caddyfile) => self
+                .allow_missing_caddy(caddy::reload_prepared(deps, self, &caddyfile, false))?
+                .is_some(),
+            None => false,
+        };
+        Ok(if reloaded {
+            SaveOutcome::Reloaded
+        } else {
+            SaveOutcome::CaddyMissing
+        })
+    }
+
+    // Convert a failure to run caddy because it isn't installed into None if caddy isn't required,
+    // so that portman can keep track of ports on machines without caddy
+    fn allow_missing_caddy<T>(&self, result: CaddyResult<T>) -> Result<Option<T>> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(err) if !self.require_caddy && caddy::is_not_installed(&err) => Ok(None),
+            Err(err) => Err(ApplicationError::Caddy(err)),
+        }
+    }
+
+    /// Write the directory index for the registry
+    pub fn write_index(&self, deps: &(impl DataDir + WriteFile)) -> Result<()> {
+        deps.write_file(
+            &DirectoryIndex::path(deps)?,
+            &DirectoryIndex::from_registry(self).to_toml()?,
+        )
+        .context("Failed to save directory index")?;
+        Ok(())
+    }
+
+    /// Get a project from the registry
+    pub fn get(&self, name: &str) -> Option<&Project> {
+        self.projects.get(name)
+    }
+
+    /// Create a new project and return it
+    /// An explicit port or the project's port override is used instead of allocating one, but it
+    /// must be available and in the configured port ranges unless it was allowed with `allow_port`
+    #[allow(clippy::too_many_arguments)]
+    pub fn create(
+        &mut self,
+        deps: &(impl CheckPortInUse + ChoosePort + EphemeralPort),
+        name: &str,
+        port: Option<u16>,
+        directory: Option<PathBuf>,
+        linked_port: Option<u16>,
+        tags: BTreeSet<String>,
+        branch: Option<String>,
+    ) -> Result<Project> {
+        Self::validate_name(name)?;
+
+        if self.projects.contains_key(name) {
+            return Err(ApplicationError::DuplicateProject(name.to_owned()));
+        }
+
+        if let Some(directory) = directory.as_ref() {
+            if let Some(name) = self.find_directory_owner(directory, branch.as_ref()) {
+                return Err(ApplicationError::DuplicateDirectory(
+                    name.clone(),
+                    directory.clone(),
+                ));
+            }
+        }
+
+        if let Some(port) = linked_port {
+            self.allocator.discard(port);
+        }
+
+        let port = port.or_else(|| self.port_overrides.get(name).copied());
+        let port = match port {
+            Some(port) if self.allocator.is_available(port, &tags) => {
+                self.allocator.discard(port);
+                port
+            }
+            Some(port) if self.allocator.is_valid(port) => {
+                return Err(ApplicationError::UnavailablePort(port))
+            }
+            Some(port) => return Err(ApplicationError::OutOfRangePort(port)),
+            None => self.allocator.allocate_tagged(deps, None, &tags)?,
+        };
+        let mut new_project = Project {
+            port,
+            out_of_range: self.allocator.is_fallback(port),
+            pinned: self.allocator.is_pinned(port),
+            directory,
+            tags,
+            branch,
+            ..Default::default()
+        };
+        self.projects.insert(name.to_owned(), new_project.clone());
+
+        if let Some(port) = linked_port {
+            self.link(deps, name, None, port)?;
+            new_project.linked_port = Some(port);
+        }
+
+        self.dirty = true;
+
+        Ok(new_project)
+    }
+
+    /// Allow the next project created with an explicit port to use a port outside of the configured
+    /// port ranges, which the project will be pinned to
+    pub fn allow_port(&mut self, port: u16) -> Result<()> {
+        if self.allocator.is_valid(port) {
+            return Ok(());
+        }
+        let in_use = self.projects.values().any(|project| {
+            project
+                .iter_linked_ports()
+                .any(|linked_port| linked_port == port)
+                || project.ports.values().any(|named_port| *named_port == port)
+        }) || self
+            .reservations
+            .iter()
+            .any(|reservation| reservation.port == port);
+        if in_use || !self.allocator.pin(port) {
+            return Err(ApplicationError::UnavailablePort(port));
+        }
+        Ok(())
+    }
+
+    /// Create a new project for a server that is already listening on a port and return it
+    /// Forcing allows ports outside of the configured port ranges, which the project is pinned to
+    pub fn adopt(
+        &mut self,
+        deps: &(impl CheckPortInUse + ChoosePort + EphemeralPort),
+        name: &str,
+        port: u16,
+        directory: Option<PathBuf>,
+        force: bool,
+    ) -> Result<Project> {
+        if force {
+            self.allow_port(port)?;
+        }
+        self.create(
+            deps,
+            name,
+            Some(port),
+            directory,
+            None,
+            BTreeSet::new(),
+            None,
+        )
+    }
+
+    /// Allocate ports for a project's named ports that it doesn't already have and return the
+    /// updated project
+    pub fn add_named_ports(
+        &mut self,
+        deps: &(impl CheckPortInUse + ChoosePort + EphemeralPort),
+        name: &str,
+        port_names: Vec<String>,
+    ) -> Result<Project> {
+        for port_name in &port_names {
+            Self::validate_name(port_name)?;
+        }
+        let project = self
+            .projects
+            .get_mut(name)
+            .ok_or_else(|| ApplicationError::NonExistentProject(String::from(name)))?;
+        for port_name in port_names {
+            if !project.ports.contains_key(&port_name) {
+                let port = self.allocator.allocate_tagged(deps, None, &project.tags)?;
+                project.ports.insert(port_name, port);
+                self.dirty = true;
+            }
+        }
+        Ok(project.clone())
+    }
+
+    /// Add an extra hostname to a project and return the updated project
+    pub fn add_domain(&mut self, name: &str, domain: String) -> Result<Project> {
+        Self::validate_domain(&domain)?;
+        if let Some((owner, _)) = self
+            .projects
+            .iter()
+            .find(|(_, project)| project.domains.contains(&domain))
         {
-            return Err(ApplicationError::InvalidProjectName(
+            return Err(ApplicationError::DuplicateDomain(domain, owner.clone()));
+        }
+        let project = self
+            .projects
+            .get_mut(name)
+            .ok_or_else(|| ApplicationError::NonExistentProject(String::from(name)))?;
+        project.domains.insert(domain);
+        self.dirty = true;
+        Ok(project.clone())
+    }
+
+    /// Remove an extra hostname from a project and return the updated project
+    pub fn remove_domain(&mut self, name: &str, domain: &str) -> Result<Project> {
+        let project = self
+            .projects
+            .get_mut(name)
+            .ok_or_else(|| ApplicationError::NonExistentProject(String::from(name)))?;
+        if !project.domains.remove(domain) {
+            return Err(ApplicationError::NonExistentDomain(
                 String::from(name),
-                "must only contain lowercase alphanumeric characters and dashes",
+                String::from(domain),
             ));
         }
-        Ok(())
+        self.dirty = true;
+        Ok(project.clone())
+    }
+
+    /// Forward a raw TCP or UDP port to one of a project's ports and return the updated project
+    pub fn add_forward(&mut self, name: &str, forward: PortForward) -> Result<Project> {
+        let project = self
+            .projects
+            .get(name)
+            .ok_or_else(|| ApplicationError::NonExistentProject(String::from(name)))?;
+        if let Some(port_name) = forward.port_name.as_ref() {
+            if !project.ports.contains_key(port_name) {
+                return Err(ApplicationError::NonExistentPortName(
+                    String::from(name),
+                    port_name.clone(),
+                ));
+            }
+        }
+        // caddy can't listen on a port that a project's server or one of its linked ports uses
+        let listen_port = forward.listen_port;
+        if let Some((owner, _)) = self.projects.iter().find(|(_, project)| {
+            project.port == listen_port
+                || project.ports.values().any(|port| *port == listen_port)
+                || (forward.protocol == ForwardProtocol::Tcp
+                    && project.iter_linked_ports().any(|port| port == listen_port))
+                || project.forwards.iter().any(|existing| {
+                    existing.listen_port == listen_port && existing.protocol == forward.protocol
+                })
+        }) {
+            return Err(ApplicationError::DuplicateForward(
+                listen_port,
+                owner.clone(),
+            ));
+        }
+        let project = self
+            .projects
+            .get_mut(name)
+            .ok_or_else(|| ApplicationError::NonExistentProject(String::from(name)))?;
+        project.forwards.push(forward);
+        project.forwards.sort_by_key(|forward| forward.listen_port);
+        self.dirty = true;
+        Ok(project.clone())
+    }
+
+    /// Stop forwarding a raw TCP or UDP port to a project and return the updated project
+    pub fn remove_forward(
+        &mut self,
+        name: &str,
+        listen_port: u16,
+        protocol: ForwardProtocol,
+    ) -> Result<Project> {
+        let project = self
+            .projects
+            .get_mut(name)
+            .ok_or_else(|| ApplicationError::NonExistentProject(String::from(name)))?;
+        let forward_count = project.forwards.len();
+        project
+            .forwards
+            .retain(|forward| forward.listen_port != listen_port || forward.protocol != protocol);
+        if project.forwards.len() == forward_count {
+            return Err(ApplicationError::NonExistentForward(
+                String::from(name),
+                listen_port,
+            ));
+        }
+        self.dirty = true;
+        Ok(project.clone())
+    }
+
+    // Find the project that is activated in a directory for a branch, if any
+    fn find_directory_owner(&self, directory: &Path, branch: Option<&String>) -> Option<&String> {
+        self.projects
+            .iter()
+            .find(|(_, project)| {
+                project.branch.as_ref() == branch
+                    && project
+                        .activation_directories()
+                        .any(|existing| existing == directory)
+            })
+            .map(|(name, _)| name)
+    }
+
+    /// Activate a project in an additional directory and return the updated project
+    pub fn add_directory(&mut self, name: &str, directory: PathBuf) -> Result<Project> {
+        let branch = self
+            .projects
+            .get(name)
+            .ok_or_else(|| ApplicationError::NonExistentProject(String::from(name)))?
+            .branch
+            .clone();
+        if let Some(owner) = self.find_directory_owner(&directory, branch.as_ref()) {
+            return Err(ApplicationError::DuplicateDirectory(
+                owner.clone(),
+                directory,
+            ));
+        }
+        let project = self
+            .projects
+            .get_mut(name)
+            .ok_or_else(|| ApplicationError::NonExistentProject(String::from(name)))?;
+        if project.directory.is_none() {
+            project.directory = Some(directory);
+        } else {
+            project.directories.push(directory);
+        }
+        self.dirty = true;
+        Ok(project.clone())
+    }
+
+    /// Move a project's primary directory and return the updated project
+    /// Moving a project to one of its additional directories promotes that directory
+    pub fn move_directory(&mut self, name: &str, directory: PathBuf) -> Result<Project> {
+        let branch = self
+            .projects
+            .get(name)
+            .ok_or_else(|| ApplicationError::NonExistentProject(String::from(name)))?
+            .branch
+            .clone();
+        if let Some(owner) = self
+            .find_directory_owner(&directory, branch.as_ref())
+            .filter(|owner| *owner != name)
+        {
+            return Err(ApplicationError::DuplicateDirectory(
+                owner.clone(),
+                directory,
+            ));
+        }
+        let project = self
+            .projects
+            .get_mut(name)
+            .ok_or_else(|| ApplicationError::NonExistentProject(String::from(name)))?;
+        if project.directory.as_ref() != Some(&directory) {
+            project
+                .directories
+                .retain(|existing| existing != &directory);
+            project.directory = Some(directory);
+            self.dirty = true;
+        }
+        Ok(project.clone())
+    }
+
+    /// Stop activating a project in a directory and return the updated project
+    /// Removing the project's primary directory promotes its first additional directory
+    pub fn remove_directory(&mut self, name: &str, directory: &Path) -> Result<Project> {
+        let project = self
+            .projects
+            .get_mut(name)
+            .ok_or_else(|| ApplicationError::NonExistentProject(String::from(name)))?;
+        if project.directory.as_deref() == Some(directory) {
+            project.directory = if project.directories.is_empty() {
+                None
+            } else {
+                Some(project.directories.remove(0))
+            };
+        } else if let Some(index) = project
+            .directories
+            .iter()
+            .position(|existing| existing == directory)
+        {
+            project.directories.remove(index);
+        } else {
+            return Err(ApplicationError::NonExistentDirectory(
+                String::from(name),
+                directory.to_owned(),
+            ));
+        }
+        self.dirty = true;
+        Ok(project.clone())
+    }
+
+    /// Update a project and return the updated project
+    pub fn update(&mut self, name: &str, directory: Option<PathBuf>) -> Result<Project> {
+        let project = self
+            .projects
+            .get_mut(name)
+            .ok_or_else(|| ApplicationError::NonExistentProject(String::from(name)))?;
+        if project.directory != directory {
+            project.directory = directory;
+            self.dirty = true;
+        }
+        Ok(project.clone())
+    }
+
+    /// Set the Caddyfile directives that are added to a project's site blocks and return the
+    /// updated project
+    pub fn set_caddy_extra(&mut self, name: &str, caddy_extra: Option<String>) -> Result<Project> {
+        let project = self
+            .projects
+            .get_mut(name)
+            .ok_or_else(|| ApplicationError::NonExistentProject(String::from(name)))?;
+        if project.caddy_extra != caddy_extra {
+            project.caddy_extra = caddy_extra;
+            self.dirty = true;
+        }
+        Ok(project.clone())
+    }
+
+    /// Set a project's description and return the updated project
+    pub fn set_description(&mut self, name: &str, description: Option<String>) -> Result<Project> {
+        let project = self
+            .projects
+            .get_mut(name)
+            .ok_or_else(|| ApplicationError::NonExistentProject(String::from(name)))?;
+        if project.description != description {
+            project.description = description;
+            self.dirty = true;
+        }
+        Ok(project.clone())
+    }
+
+    /// Add environment variables to a project, replacing existing variables with the same name, and
+    /// return the updated project
+    pub fn extend_env(
+        &mut self,
+        name: &str,
+        env: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Project> {
+        let project = self
+            .projects
+            .get_mut(name)
+            .ok_or_else(|| ApplicationError::NonExistentProject(String::from(name)))?;
+        for (key, value) in env {
+            if project.env.get(&key) != Some(&value) {
+                project.env.insert(key, value);
+                self.dirty = true;
+            }
+        }
+        Ok(project.clone())
+    }
+
+    /// Set a project's display name and return the updated project
+    pub fn set_display_name(
+        &mut self,
+        name: &str,
+        display_name: Option<String>,
+    ) -> Result<Project> {
+        let project = self
+            .projects
+            .get_mut(name)
+            .ok_or_else(|| ApplicationError::NonExistentProject(String::from(name)))?;
+        if project.display_name != display_name {
+            project.display_name = display_name;
+            self.dirty = true;
+        }
+        Ok(project.clone())
+    }
+
+    /// Set a project's icon and return the updated project
+    pub fn set_icon(&mut self, name: &str, icon: Option<String>) -> Result<Project> {
+        let project = self
+            .projects
+            .get_mut(name)
+            .ok_or_else(|| ApplicationError::NonExistentProject(String::from(name)))?;
+        if project.icon != icon {
+            project.icon = icon;
+            self.dirty = true;
+        }
+        Ok(project.clone())
+    }
+
+    /// Set a project's color and return the updated project
+    pub fn set_color(&mut self, name: &str, color: Option<ProjectColor>) -> Result<Project> {
+        let project = self
+            .projects
+            .get_mut(name)
+            .ok_or_else(|| ApplicationError::NonExistentProject(String::from(name)))?;
+        if project.color != color {
+            project.color = color;
+            self.dirty = true;
+        }
+        Ok(project.clone())
+    }
+
+    /// Set the path that health checks request for a project and return the updated project
+    pub fn set_health_path(&mut self, name: &str, health_path: Option<String>) -> Result<Project> {
+        if let Some(health_path) = health_path.as_ref() {
+            if !health_path.starts_with('/') {
+                return Err(ApplicationError::InvalidHealthPath(health_path.clone()));
+            }
+        }
+        let project = self
+            .projects
+            .get_mut(name)
+            .ok_or_else(|| ApplicationError::NonExistentProject(String::from(name)))?;
+        if project.health_path != health_path {
+            project.health_path = health_path;
+            self.dirty = true;
+        }
+        Ok(project.clone())
+    }
+
+    /// Set the addresses that caddy listens on for a project and return the updated project
+    pub fn set_bind(&mut self, name: &str, bind: Option<BindAddress>) -> Result<Project> {
+        let project = self
+            .projects
+            .get_mut(name)
+            .ok_or_else(|| ApplicationError::NonExistentProject(String::from(name)))?;
+        if project.bind != bind {
+            project.bind = bind;
+            self.dirty = true;
+        }
+        Ok(project.clone())
+    }
+
+    /// Set the protocol that caddy uses to communicate with a project and return the updated project
+    pub fn set_upstream_protocol(
+        &mut self,
+        name: &str,
+        upstream_protocol: UpstreamProtocol,
+    ) -> Result<Project> {
+        let project = self
+            .projects
+            .get_mut(name)
+            .ok_or_else(|| ApplicationError::NonExistentProject(String::from(name)))?;
+        if project.upstream_protocol != upstream_protocol {
+            project.upstream_protocol = upstream_protocol;
+            self.dirty = true;
+        }
+        Ok(project.clone())
+    }
+
+    /// Set whether the proxy is tuned for hot module replacement and return the updated project
+    pub fn set_hmr(&mut self, name: &str, hmr: bool) -> Result<Project> {
+        let project = self
+            .projects
+            .get_mut(name)
+            .ok_or_else(|| ApplicationError::NonExistentProject(String::from(name)))?;
+        if project.hmr != hmr {
+            project.hmr = hmr;
+            self.dirty = true;
+        }
+        Ok(project.clone())
+    }
+
+    /// Enable or disable wildcard subdomain routing for a project and return the updated project
+    pub fn set_wildcard(&mut self, name: &str, wildcard: bool) -> Result<Project> {
+        let project = self
+            .projects
+            .get_mut(name)
+            .ok_or_else(|| ApplicationError::NonExistentProject(String::from(name)))?;
+        if project.wildcard != wildcard {
+            project.wildcard = wildcard;
+            self.dirty = true;
+        }
+        Ok(project.clone())
+    }
+
+    /// Set the repo whose checkouts activate a project and return the updated project
+    pub fn set_repo_match(&mut self, name: &str, repo: Option<String>) -> Result<Project> {
+        if let Some(repo) = repo.as_ref() {
+            if let Some((other, _)) = self
+                .match_repo(repo)
+                .filter(|(other, _)| other.as_str() != name)
+            {
+                return Err(ApplicationError::DuplicateRepoMatch(
+                    other.clone(),
+                    repo.clone(),
+                ));
+            }
+        }
+
+        let project = self
+            .projects
+            .get_mut(name)
+            .ok_or_else(|| ApplicationError::NonExistentProject(String::from(name)))?;
+        if project.repo != repo {
+            project.repo = repo;
+            self.dirty = true;
+        }
+        Ok(project.clone())
+    }
+
+    /// Set a project's remote upstream and return the updated project
+    pub fn set_upstream(&mut self, name: &str, upstream: Option<Upstream>) -> Result<Project> {
+        let upstream = upstream.map(Upstream::validate).transpose()?;
+
+        let project = self
+            .projects
+            .get_mut(name)
+            .ok_or_else(|| ApplicationError::NonExistentProject(String::from(name)))?;
+        if project.upstream != upstream {
+            project.upstream = upstream;
+            self.dirty = true;
+        }
+        Ok(project.clone())
+    }
+
+    /// Set a project's tags and return the updated project
+    /// The project is moved to a new port if its current port is outside its new tags' port range
+    pub fn set_tags(
+        &mut self,
+        deps: &(impl CheckPortInUse + ChoosePort + EphemeralPort),
+        name: &str,
+        tags: BTreeSet<String>,
+    ) -> Result<Project> {
+        let project = self
+            .projects
+            .get_mut(name)
+            .ok_or_else(|| ApplicationError::NonExistentProject(String::from(name)))?;
+        if project.tags != tags {
+            if !self.allocator.permits(project.port, &tags) {
+                project.port = self.allocator.allocate_tagged(deps, None, &tags)?;
+                project.out_of_range = self.allocator.is_fallback(project.port);
+            }
+            project.tags = tags;
+            self.dirty = true;
+        }
+        Ok(project.clone())
+    }
+
+    /// Add a tag to a project and return the updated project
+    pub fn add_tag(
+        &mut self,
+        deps: &(impl CheckPortInUse + ChoosePort + EphemeralPort),
+        name: &str,
+        tag: String,
+    ) -> Result<Project> {
+        let mut tags = self.get_project_tags(name)?;
+        tags.insert(tag);
+        self.set_tags(deps, name, tags)
+    }
+
+    /// Remove a tag from a project and return the updated project
+    pub fn remove_tag(
+        &mut self,
+        deps: &(impl CheckPortInUse + ChoosePort + EphemeralPort),
+        name: &str,
+        tag: &str,
+    ) -> Result<Project> {
+        let mut tags = self.get_project_tags(name)?;
+        if !tags.remove(tag) {
+            return Err(ApplicationError::NonExistentTag(
+                String::from(name),
+                String::from(tag),
+            ));
+        }
+        self.set_tags(deps, name, tags)
+    }
+
+    fn get_project_tags(&self, name: &str) -> Result<BTreeSet<String>> {
+        self.projects
+            .get(name)
+            .map(|project| project.tags.clone())
+            .ok_or_else(|| ApplicationError::NonExistentProject(String::from(name)))
+    }
+
+    /// Return the tag and port range that a project's port should be in, if any
+    pub fn tag_range(&self, project: &Project) -> Option<(&String, (u16, u16))> {
+        self.allocator.tag_range(&project.tags)
+    }
+
+    /// Iterate over the projects whose ports are not allowed by their tags' port ranges
+    pub fn iter_misplaced_projects(&self) -> impl Iterator<Item = (&String, &Project)> {
+        self.projects
+            .iter()
+            .filter(|(_, project)| !self.allocator.permits(project.port, &project.tags))
+    }
+
+    /// Delete a project and return the deleted project and its names
+    pub fn delete(&mut self, name: &str) -> Result<Project> {
+        let project = self
+            .projects
+            .remove(name)
+            .ok_or_else(|| ApplicationError::NonExistentProject(String::from(name)))?;
+        self.dirty = true;
+        Ok(project)
+    }
+
+    /// Remove a project from the registry so that it can be archived and return the removed project
+    /// If `reserve_port` is true, its port will not be allocated to other projects until it is
+    /// unarchived
+    pub fn archive(&mut self, name: &str, reserve_port: bool) -> Result<Project> {
+        let project = self.delete(name)?;
+        if reserve_port {
+            self.archived_ports.insert(name.to_owned(), project.port);
+        }
+        Ok(project)
+    }
+
+    /// Restore an archived project to the registry and return the restored project
+    /// The project keeps its port if it was reserved or is still available
+    pub fn unarchive(
+        &mut self,
+        deps: &(impl CheckPortInUse + ChoosePort + EphemeralPort),
+        name: &str,
+        project: Project,
+    ) -> Result<Project> {
+        if self.projects.contains_key(name) {
+            return Err(ApplicationError::DuplicateProject(name.to_owned()));
+        }
+        for directory in project.activation_directories() {
+            if let Some(name) = self.find_directory_owner(directory, project.branch.as_ref()) {
+                return Err(ApplicationError::DuplicateDirectory(
+                    name.clone(),
+                    directory.clone(),
+                ));
+            }
+        }
+
+        let port = if self.archived_ports.remove(name) == Some(project.port) {
+            project.port
+        } else {
+            self.allocator
+                .allocate_tagged(deps, Some(project.port), &project.tags)?
+        };
+        let linked_port = project.linked_port;
+        let mut restored_project = Project {
+            port,
+            out_of_range: self.allocator.is_fallback(port),
+            linked_port: None,
+            linked_ports: BTreeMap::new(),
+            group: None,
+            ..project
+        };
+        self.projects
+            .insert(name.to_owned(), restored_project.clone());
+
+        // Only restore the linked port if no other project has linked it since
+        if let Some(linked_port) = linked_port {
+            if !self
+                .projects
+                .values()
+                .any(|project| project.iter_linked_ports().any(|port| port == linked_port))
+            {
+                self.link(deps, name, None, linked_port)?;
+                restored_project = self.projects[name].clone();
+            }
+        }
+
+        self.dirty = true;
+        Ok(restored_project)
+    }
+
+    /// Delete multiple projects and return the deleted projects and their names
+    pub fn delete_many(&mut self, project_names: Vec<String>) -> Result<Vec<(String, Project)>> {
+        let deleted_projects: Vec<(String, Project)> = project_names
+            .into_iter()
+            .map(|name| {
+                let project = self
+                    .projects
+                    .remove(&name)
+                    .ok_or_else(|| ApplicationError::NonExistentProject(name.clone()))?;
+                Ok((name, project))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if !deleted_projects.is_empty() {
+            self.dirty = true;
+        }
+        Ok(deleted_projects)
+    }
+
+    /// Iterate over all projects with their names
+    pub fn iter_projects(&self) -> impl Iterator<Item = (&String, &Project)> {
+        self.projects.iter()
+    }
+
+    /// Iterate over the projects that match a filter with their names
+    pub fn iter_filtered_projects<'a>(
+        &'a self,
+        filter: &'a ProjectFilter,
+    ) -> impl Iterator<Item = (&'a String, &'a Project)> {
+        self.iter_projects()
+            .filter(|(name, project)| filter.matches(name, project))
+    }
+
+    /// Return the projects that match a filter with their names in the specified order
+    pub fn sorted_projects<'a>(
+        &'a self,
+        filter: &'a ProjectFilter,
+        order: ProjectOrder,
+    ) -> Vec<(&'a String, &'a Project)> {
+        let mut projects = self.iter_filtered_projects(filter).collect::<Vec<_>>();
+        order.sort(&mut projects);
+        projects
+    }
+
+    /// Link a port to a project, or to one of its named ports if a port name is provided
+    /// A project can have a linked port for its primary port and for each of its named ports
+    pub fn link(
+        &mut self,
+        deps: &(impl CheckPortInUse + ChoosePort + EphemeralPort),
+        project_name: &str,
+        port_name: Option<&str>,
+        linked_port: u16,
+    ) -> Result<()> {
+        let project = self
+            .projects
+            .get(project_name)
+            .ok_or_else(|| ApplicationError::NonExistentProject(String::from(project_name)))?;
+        if let Some(port_name) = port_name {
+            if !project.ports.contains_key(port_name) {
+                return Err(ApplicationError::NonExistentPortName(
+                    project_name.to_owned(),
+                    port_name.to_owned(),
+                ));
+            }
+        }
+
+        for (name, project) in &mut self.projects {
+            if project.port == linked_port {
+                // Take the port from the project so that it can be used by the linked port
+                project.port = self.allocator.allocate_tagged(deps, None, &project.tags)?;
+                project.out_of_range = self.allocator.is_fallback(project.port);
+                self.dirty = true;
+            }
+
+            // Unlink the port from its previous project or named port
+            let is_target = name == project_name;
+            if project.linked_port == Some(linked_port) && !(is_target && port_name.is_none()) {
+                project.linked_port = None;
+                self.dirty = true;
+            }
+            let linked_port_count = project.linked_ports.len();
+            project.linked_ports.retain(|name, port| {
+                *port != linked_port || (is_target && port_name == Some(name.as_str()))
+            });
+            self.dirty |= project.linked_ports.len() != linked_port_count;
+
+            if is_target {
+                // Link the port to the new project or named port
+                let previous = match port_name {
+                    Some(port_name) => project
+                        .linked_ports
+                        .insert(port_name.to_owned(), linked_port),
+                    None => project.linked_port.replace(linked_port),
+                };
+                self.dirty |= previous != Some(linked_port);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Move a project to a new port and link its old port to it so that a new server can start on
+    /// the new port while the old server keeps serving requests through the linked port
+    /// Return the old port and the updated project
+    pub fn handoff(
+        &mut self,
+        deps: &(impl CheckPortInUse + ChoosePort + EphemeralPort),
+        name: &str,
+        new_port: Option<u16>,
+    ) -> Result<(u16, Project)> {
+        let project = self
+            .projects
+            .get_mut(name)
+            .ok_or_else(|| ApplicationError::NonExistentProject(String::from(name)))?;
+        let old_port = project.port;
+        project.port = match new_port {
+            Some(port) if self.allocator.is_available(port, &project.tags) => {
+                self.allocator.discard(port);
+                port
+            }
+            Some(port) => return Err(ApplicationError::UnavailablePort(port)),
+            None => self.allocator.allocate_tagged(deps, None, &project.tags)?,
+        };
+        project.out_of_range = self.allocator.is_fallback(project.port);
+        project.linked_port = Some(old_port);
+        self.dirty = true;
+        Ok((old_port, project.clone()))
+    }
+
+    /// Unlink the port linked to a project or one of its named ports and return the name of the
+    /// project it was linked to
+    pub fn unlink(&mut self, port: u16) -> Option<String> {
+        for (name, project) in &mut self.projects {
+            let linked_port_count = project.linked_ports.len();
+            project
+                .linked_ports
+                .retain(|_, linked_port| *linked_port != port);
+            if project.linked_port == Some(port) {
+                project.linked_port = None;
+            } else if project.linked_ports.len() == linked_port_count {
+                continue;
+            }
+            self.dirty = true;
+            return Some(name.clone());
+        }
+        None
+    }
+
+    // Find the key of the repo with the same canonical URL as a repo URL
+    fn find_repo_key(&self, repo: &str) -> Option<String> {
+        self.repos
+            .keys()
+            .find(|key| repo::same_repo(key, repo))
+            .cloned()
+    }
+
+    /// Get a repo's port and metadata
+    pub fn get_repo(&self, repo: &str) -> Result<&RepoEntry> {
+        self.find_repo_key(repo)
+            .and_then(|key| self.repos.get(&key))
+            .ok_or_else(|| ApplicationError::NonExistentRepo(repo.to_owned()))
+    }
+
+    /// Determine whether a port can be assigned to a new project with the provided tags
+    pub fn is_port_available(&self, port: u16, tags: &BTreeSet<String>) -> bool {
+        self.allocator.is_available(port, tags)
+    }
+
+    /// Get the port associated with a repo
+    pub fn get_repo_port(&self, repo: &str) -> Result<u16> {
+        self.get_repo(repo).map(|entry| entry.port)
+    }
+
+    /// Set the port associated with a repo, preserving its metadata
+    pub fn set_repo_port(&mut self, repo: String, port: u16) {
+        let repo = self.find_repo_key(&repo).unwrap_or(repo);
+        match self.repos.get_mut(&repo) {
+            Some(entry) if entry.port == port => {}
+            Some(entry) => {
+                entry.port = port;
+                self.dirty = true;
+            }
+            None => {
+                self.repos.insert(
+                    repo,
+                    RepoEntry {
+                        port,
+                        ..Default::default()
+                    },
+                );
+                self.dirty = true;
+            }
+        }
+    }
+
+    /// Update a repo's port and metadata and return the updated repo
+    /// Values that are None are left unchanged and metadata that is empty is cleared
+    pub fn set_repo_metadata(&mut self, repo: &str, update: RepoUpdate) -> Result<RepoEntry> {
+        let RepoUpdate {
+            port,
+            project_port,
+            linked_ports,
+            description,
+            default_project_name,
+        } = update;
+        for (port_name, _) in &linked_ports {
+            Self::validate_name(port_name)?;
+        }
+        if let Some(port) = project_port.filter(|port| *port != 0) {
+            if !self.allocator.is_valid(port) {
+                return Err(ApplicationError::OutOfRangePort(port));
+            }
+        }
+        let entry = self
+            .find_repo_key(repo)
+            .and_then(|key| self.repos.get_mut(&key))
+            .ok_or_else(|| ApplicationError::NonExistentRepo(repo.to_owned()))?;
+        let mut updated = entry.clone();
+        if let Some(port) = port {
+            updated.port = port;
+        }
+        if let Some(port) = project_port {
+            updated.project_port = Some(port).filter(|port| *port != 0);
+        }
+        for (port_name, port) in linked_ports {
+            if port == 0 {
+                updated.linked_ports.remove(&port_name);
+            } else {
+                updated.linked_ports.insert(port_name, port);
+            }
+        }
+        if let Some(description) = description {
+            updated.description = Some(description).filter(|value| !value.is_empty());
+        }
+        if let Some(name) = default_project_name {
+            updated.default_project_name = if name.is_empty() {
+                None
+            } else {
+                Self::validate_name(&name)?;
+                Some(name)
+            };
+        }
+        if *entry != updated {
+            *entry = updated.clone();
+            self.dirty = true;
+        }
+        Ok(updated)
+    }
+
+    /// Delete a repo's port association
+    pub fn delete_repo(&mut self, repo: &str) -> Result<u16> {
+        let deleted_repo = self
+            .find_repo_key(repo)
+            .and_then(|key| self.repos.remove(&key));
+        if deleted_repo.is_some() {
+            self.dirty = true;
+        }
+        deleted_repo
+            .map(|entry| entry.port)
+            .ok_or_else(|| ApplicationError::NonExistentRepo(repo.to_owned()))
+    }
+
+    /// Iterate over all repos with their ports and metadata
+    pub fn iter_repos(&self) -> impl Iterator<Item = (&String, &RepoEntry)> {
+        self.repos.iter()
+    }
+
+    /// Lease a port that won't be allocated to projects or other leases until it is released or
+    /// expires, and return the new lease
+    pub fn acquire_lease(
+        &mut self,
+        deps: &(impl CheckPortInUse + ChoosePort + EphemeralPort),
+        expires: u64,
+        description: Option<String>,
+    ) -> Result<Lease> {
+        let lease = Lease {
+            port: self.allocator.allocate(deps, None)?,
+            expires,
+            description,
+        };
+        self.leases.push(lease.clone());
+        self.dirty = true;
+        Ok(lease)
+    }
+
+    /// Release a leased port and return the released lease
+    pub fn release_lease(&mut self, port: u16) -> Result<Lease> {
+        let index = self
+            .leases
+            .iter()
+            .position(|lease| lease.port == port)
+            .ok_or(ApplicationError::NonExistentLease(port))?;
+        self.dirty = true;
+        Ok(self.leases.remove(index))
+    }
+
+    /// Reserve a port so that it is never allocated, and return the new reservation
+    pub fn reserve(&mut self, port: u16, description: Option<String>) -> Result<Reservation> {
+        if self
+            .reservations
+            .iter()
+            .any(|reservation| reservation.port == port)
+        {
+            return Err(ApplicationError::DuplicateReservation(port));
+        }
+        if let Some((name, _)) = self.projects.iter().find(|(_, project)| {
+            project.port == port
+                || project
+                    .iter_linked_ports()
+                    .any(|linked_port| linked_port == port)
+                || project.ports.values().any(|named_port| *named_port == port)
+        }) {
+            return Err(ApplicationError::ReservedPortInUse(port, name.clone()));
+        }
+        self.allocator.discard(port);
+        let reservation = Reservation { port, description };
+        self.reservations.push(reservation.clone());
+        self.reservations
+            .sort_by_key(|reservation| reservation.port);
+        self.dirty = true;
+        Ok(reservation)
+    }
+
+    /// Release a reserved port and return the removed reservation
+    pub fn unreserve(&mut self, port: u16) -> Result<Reservation> {
+        let index = self
+            .reservations
+            .iter()
+            .position(|reservation| reservation.port == port)
+            .ok_or(ApplicationError::NonExistentReservation(port))?;
+        self.dirty = true;
+        Ok(self.reservations.remove(index))
+    }
+
+    /// Iterate over all reserved ports
+    pub fn iter_reservations(&self) -> impl Iterator<Item = &Reservation> {
+        self.reservations.iter()
+    }
+
+    /// Return the lowest ports that could be assigned to a new untagged project
+    pub fn free_ports(&self, count: usize) -> Vec<u16> {
+        self.allocator.lowest_available(count)
+    }
+
+    /// Iterate over all unexpired leases
+    pub fn iter_leases(&self) -> impl Iterator<Item = &Lease> {
+        self.leases.iter()
+    }
+
+    /// Find and return the project that matches the current working directory, if any
+    /// Per-branch projects only match when their branch is checked out
+    pub fn match_cwd(
+        &self,
+        deps: &(impl Exec + WorkingDirectory),
+    ) -> Result<Option<(&String, &Project)>> {
+        let cwd = deps.get_cwd()?;
+        Ok(self
+            .match_directory(&cwd, || branch_name(deps).ok())
+            .or_else(|| self.match_checkout(deps, &cwd)))
+    }
+
+    /// Find and return the project that is activated by the repo checked out in the current
+    /// directory, if any
+    /// git is only run if at least one project is activated by its repo
+    pub fn match_checkout(&self, deps: &impl Exec, cwd: &Path) -> Option<(&String, &Project)> {
+        if self.projects.values().all(|project| project.repo.is_none()) {
+            return None;
+        }
+        let url = repo::get_checkout_repo(deps, cwd)?;
+        self.match_repo(&url)
+    }
+
+    /// Find and return the project that is activated by a repo, if any
+    pub fn match_repo(&self, url: &str) -> Option<(&String, &Project)> {
+        self.iter_projects().find(|(_, project)| {
+            project
+                .repo
+                .as_ref()
+                .is_some_and(|repo| repo::same_repo(repo, url))
+        })
+    }
+
+    /// Find and return the project that would be active in a directory, if any
+    /// The current branch is only resolved if the directory contains per-branch projects
+    pub fn match_directory(
+        &self,
+        directory: &Path,
+        current_branch: impl FnOnce() -> Option<String>,
+    ) -> Option<(&String, &Project)> {
+        let matches = self
+            .iter_projects()
+            .filter(|(_, project)| {
+                project
+                    .activation_directories()
+                    .any(|existing| existing == directory)
+            })
+            .collect::<Vec<_>>();
+        let branch = if matches.iter().any(|(_, project)| project.branch.is_some()) {
+            current_branch()
+        } else {
+            None
+        };
+        matches
+            .iter()
+            .find(|(_, project)| project.branch.is_some() && project.branch == branch)
+            .or_else(|| matches.iter().find(|(_, project)| project.branch.is_none()))
+            .copied()
+    }
+
+    /// Normalize a potential project name by stripping out invalid characters
+    pub fn normalize_name(name: &str) -> String {
+        let mut normalized = name
+            .chars()
+            .map(|char| {
+                if char.is_ascii_alphanumeric() || char == '-' {
+                    char
+                } else {
+                    '-'
+                }
+            })
+            .skip_while(|char| char == &'-')
+            .fold(String::with_capacity(name.len()), |mut result, char| {
+                // Remove adjacent dashes
+                if !(result.ends_with('-') && char == '-') {
+                    result.push(char.to_ascii_lowercase());
+                }
+                result
+            });
+        normalized.truncate(63);
+        if normalized.ends_with('-') {
+            normalized.pop();
+        }
+        normalized
+    }
+
+    // Validate a domain
+    fn validate_domain(domain: &str) -> Result<()> {
+        if domain.len() > 253 {
+            return Err(ApplicationError::InvalidDomain(
+                String::from(domain),
+                "must not exceed 253 characters",
+            ));
+        }
+        for label in domain.split('.') {
+            if label.is_empty() || label.len() > 63 {
+                return Err(ApplicationError::InvalidDomain(
+                    String::from(domain),
+                    "each dot-separated part must contain 1 to 63 characters",
+                ));
+            }
+            if label.starts_with('-') || label.ends_with('-') {
+                return Err(ApplicationError::InvalidDomain(
+                    String::from(domain),
+                    "each dot-separated part must not start or end with a dash",
+                ));
+            }
+            if label
+                .chars()
+                .any(|char| !(char.is_ascii_lowercase() || char.is_ascii_digit() || char == '-'))
+            {
+                return Err(ApplicationError::InvalidDomain(
+                    String::from(domain),
+                    "must only contain lowercase alphanumeric characters, dashes, and dots",
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // Validate a project name
+    fn validate_name(name: &str) -> Result<()> {
+        if name.is_empty() {
+            return Err(ApplicationError::InvalidProjectName(
+                String::from(name),
+                "must not be empty",
+            ));
+        }
+        if name.len() > 63 {
+            return Err(ApplicationError::InvalidProjectName(
+                String::from(name),
+                "must not exceed 63 characters",
+            ));
+        }
+        if name.starts_with('-') || name.ends_with('-') {
+            return Err(ApplicationError::InvalidProjectName(
+                String::from(name),
+                "must not start or end with a dash",
+            ));
+        }
+        if name.contains("--") {
+            return Err(ApplicationError::InvalidProjectName(
+                String::from(name),
+                "must not contain consecutive dashes",
+            ));
+        }
+        if name
+            .chars()
+            .any(|char| !(char.is_ascii_lowercase() || char.is_numeric() || char == '-'))
+        {
+            return Err(ApplicationError::InvalidProjectName(
+                String::from(name),
+                "must only contain lowercase alphanumeric characters and dashes",
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::config::{Config, PortFallback};
+    use crate::dependencies::{self, ReadFileMock};
+    use crate::error::CaddyError;
+    use crate::mocks::{
+        choose_port_mock, current_time_mock, cwd_mock, data_dir_mock, get_mocked_registry,
+        read_health_mock, read_registry_mock, read_usage_mock, read_var_mock, write_checksum_mock,
+        write_file_mock, write_index_mock,
+    };
+    use anyhow::bail;
+    use std::io::{Error, ErrorKind};
+    use std::sync::Arc;
+    use unimock::{matching, Clause, MockFn, Unimock};
+
+    fn read_caddyfile_mock() -> impl Clause {
+        ReadFileMock
+            .each_call(matching!((path) if path == &PathBuf::from("/homebrew/etc/Caddyfile")))
+            .answers(&|_, _| Err(Error::from(ErrorKind::NotFound)))
+            .once()
+    }
+
+    fn get_mocked_registry_with_tag_ranges() -> Registry {
+        let mocked_deps = Unimock::new((data_dir_mock(), read_registry_mock(None)));
+        let allocator = PortAllocator::new(Config::default().get_valid_ports())
+            .with_tag_ranges(BTreeMap::from([(String::from("work"), (3100, 3199))]));
+        Registry::new(&mocked_deps, allocator, RegistryLayout::Single).unwrap()
+    }
+
+    #[test]
+    fn test_glob_matches() {
+        assert!(glob_matches("app1", "app1"));
+        assert!(glob_matches("app*", "app1"));
+        assert!(glob_matches("*", ""));
+        assert!(glob_matches("a*p*1", "app-api1"));
+        assert!(glob_matches("app?", "app2"));
+        assert!(!glob_matches("app?", "app"));
+        assert!(!glob_matches("api-*", "app1"));
+        assert!(!glob_matches("*1", "app2"));
+    }
+
+    #[test]
+    fn test_sorted_projects() {
+        let registry = get_mocked_registry().unwrap();
+        let names = |projects: Vec<(&String, &Project)>| {
+            projects
+                .into_iter()
+                .map(|(name, _)| name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(registry.sorted_projects(&ProjectFilter::default(), ProjectOrder::Directory)),
+            vec!["app3", "app1", "app2"]
+        );
+        assert_eq!(
+            names(registry.sorted_projects(
+                &ProjectFilter {
+                    pattern: Some(String::from("app*")),
+                    no_directory: true,
+                    ..Default::default()
+                },
+                ProjectOrder::Port
+            )),
+            vec!["app1", "app2"]
+        );
+    }
+
+    #[test]
+    fn test_load() {
+        let config = Config::default();
+        let mocked_deps = Unimock::new((data_dir_mock(), read_registry_mock(None)));
+        let allocator = PortAllocator::new(config.get_valid_ports());
+        let registry = Registry::new(&mocked_deps, allocator, RegistryLayout::Single).unwrap();
+        assert_eq!(registry.projects.len(), 3);
+        assert_eq!(registry.repos.len(), 1);
+        assert!(!registry.dirty);
+    }
+
+    #[test]
+    fn test_load_invalid() {
+        let config = Config::default();
+        let mocked_deps = Unimock::new((data_dir_mock(), read_registry_mock(Some(";"))));
+        let allocator = PortAllocator::new(config.get_valid_ports());
+        let err = Registry::new(&mocked_deps, allocator, RegistryLayout::Single).unwrap_err();
+        assert!(matches!(err, ApplicationError::Other(_)));
+    }
+
+    #[test]
+    fn test_load_invalid_name() {
+        let config = Config::default();
+        let mocked_deps = Unimock::new((
+            data_dir_mock(),
+            read_registry_mock(Some("projects.App1 = { port = 3001 }")),
+        ));
+        let allocator = PortAllocator::new(config.get_valid_ports());
+        let err = Registry::new(&mocked_deps, allocator, RegistryLayout::Single).unwrap_err();
+        assert!(matches!(err, ApplicationError::InvalidProjectName(name, _) if name == "App1"));
+    }
+
+    #[test]
+    fn test_load_invalid_upstream() {
+        let config = Config::default();
+        let mocked_deps = Unimock::new((
+            data_dir_mock(),
+            read_registry_mock(Some(
+                "[projects.app1]\nport = 3001\nupstream = { url = 'https://example.com/api' }",
+            )),
+        ));
+        let allocator = PortAllocator::new(config.get_valid_ports());
+        let err = Registry::new(&mocked_deps, allocator, RegistryLayout::Single).unwrap_err();
+        assert!(
+            matches!(err, ApplicationError::InvalidUpstream(url, _) if url == "https://example.com/api")
+        );
+
+        let mocked_deps = Unimock::new((
+            data_dir_mock(),
+            read_registry_mock(Some(
+                "[projects.app1]\nport = 3001\nupstream = { url = 'https://example.com', headers = { 'X-Token }' = 'a' } }",
+            )),
+        ));
+        let allocator = PortAllocator::new(config.get_valid_ports());
+        let err = Registry::new(&mocked_deps, allocator, RegistryLayout::Single).unwrap_err();
+        assert!(
+            matches!(err, ApplicationError::InvalidUpstreamHeader(name, _) if name == "X-Token }")
+        );
+    }
+
+    #[test]
+    fn test_load_duplicate_directory() {
+        let config = Config::default();
+        let mocked_deps = Unimock::new((
+            data_dir_mock(),
+            read_registry_mock(Some(
+                "[projects.app1]
+port = 3001
+directory = '/projects/app'
+
+[projects.app2]
+port = 3002
+directory = '/projects/app'",
+            )),
+        ));
+        let allocator = PortAllocator::new(config.get_valid_ports());
+        let registry = Registry::new(&mocked_deps, allocator, RegistryLayout::Single).unwrap();
+        assert!(registry.get("app1").unwrap().directory.is_some());
+        assert!(registry.get("app2").unwrap().directory.is_none());
+        assert!(registry.dirty);
+    }
+
+    #[test]
+    fn test_load_duplicate_repos() {
+        let config = Config::default();
+        let mocked_deps = Unimock::new((
+            data_dir_mock(),
+            read_registry_mock(Some(
+                "[projects]
+
+[repos]
+'git@github.com:user/app.git' = 3001
+
+[repos.'https://github.com/user/app.git']
+port = 3002
+description = 'App'
+
+[repos.'https://github.com/user/app2.git']
+port = 3003",
+            )),
+        ));
+        let allocator = PortAllocator::new(config.get_valid_ports());
+        let registry = Registry::new(&mocked_deps, allocator, RegistryLayout::Single).unwrap();
+        assert_eq!(registry.repos.len(), 2);
+        let entry = registry
+            .get_repo("https://github.com/user/app.git")
+            .unwrap();
+        assert_eq!(entry.port, 3001);
+        assert_eq!(entry.description.as_deref(), Some("App"));
+        assert!(registry.repos.contains_key("git@github.com:user/app.git"));
+        assert!(registry.dirty);
+    }
+
+    #[test]
+    fn test_load_duplicate_repo_match() {
+        let config = Config::default();
+        let mocked_deps = Unimock::new((
+            data_dir_mock(),
+            read_registry_mock(Some(
+                "[projects.app1]
+port = 3001
+repo = 'git@github.com:user/app.git'
+
+[projects.app2]
+port = 3002
+repo = 'https://github.com/user/app.git'",
+            )),
+        ));
+        let allocator = PortAllocator::new(config.get_valid_ports());
+        let registry = Registry::new(&mocked_deps, allocator, RegistryLayout::Single).unwrap();
+        assert!(registry.get("app1").unwrap().repo.is_some());
+        assert!(registry.get("app2").unwrap().repo.is_none());
+        assert!(registry.dirty);
+    }
+
+    #[test]
+    fn test_load_duplicate_directories() {
+        let config = Config::default();
+        let mocked_deps = Unimock::new((
+            data_dir_mock(),
+            read_registry_mock(Some(
+                "[projects.app1]
+port = 3001
+directory = '/projects/app'
+directories = ['/projects/app-review']
+
+[projects.app2]
+port = 3002
+directory = '/projects/app2'
+directories = ['/projects/app', '/projects/app2-review']",
+            )),
+        ));
+        let allocator = PortAllocator::new(config.get_valid_ports());
+        let registry = Registry::new(&mocked_deps, allocator, RegistryLayout::Single).unwrap();
+        assert_eq!(
+            registry.get("app2").unwrap().directories,
+            vec![PathBuf::from("/projects/app2-review")]
+        );
+        assert!(registry.dirty);
+    }
+
+    #[test]
+    fn test_load_duplicate_linked() {
+        let config = Config::default();
+        let mocked_deps = Unimock::new((
+            data_dir_mock(),
+            read_registry_mock(Some(
+                "[projects.app1]
+port = 3001
+linked_port = 3000
+
+[projects.app2]
+port = 3002
+linked_port = 3000",
+            )),
+        ));
+        let allocator = PortAllocator::new(config.get_valid_ports());
+        let registry = Registry::new(&mocked_deps, allocator, RegistryLayout::Single).unwrap();
+        assert!(registry.get("app1").unwrap().linked_port.is_some());
+        assert!(registry.get("app2").unwrap().linked_port.is_none());
+        assert!(registry.dirty);
+    }
+
+    #[test]
+    fn test_load_reallocates_for_linked() {
+        let config = Config::default();
+        let mocked_deps = Unimock::new((
+            choose_port_mock(),
+            data_dir_mock(),
+            read_registry_mock(Some("projects.app1 = { port = 3001, linked_port = 3001 }")),
+        ));
+        let allocator = PortAllocator::new(config.get_valid_ports());
+        let registry = Registry::new(&mocked_deps, allocator, RegistryLayout::Single).unwrap();
+        assert_eq!(registry.projects.get("app1").unwrap().port, 3000);
+        assert!(registry.dirty);
+    }
+
+    #[test]
+    fn test_load_reallocates_named_ports() {
+        let config = Config::default();
+        let mocked_deps = Unimock::new((
+            choose_port_mock(),
+            data_dir_mock(),
+            read_registry_mock(Some(
+                "projects.app1 = { port = 3001, ports = { api = 3001, web = 3002 } }",
+            )),
+        ));
+        let allocator = PortAllocator::new(config.get_valid_ports());
+        let registry = Registry::new(&mocked_deps, allocator, RegistryLayout::Single).unwrap();
+        let project = registry.projects.get("app1").unwrap();
+        assert_eq!(project.port, 3001);
+        assert_eq!(
+            project.ports,
+            BTreeMap::from([(String::from("api"), 3000), (String::from("web"), 3002)])
+        );
+        assert!(registry.dirty);
+        assert_eq!(
+            registry.reassignments(),
+            [PortReassignment {
+                project: String::from("app1"),
+                port_name: Some(String::from("api")),
+                old_port: 3001,
+                new_port: 3000,
+            }]
+        );
+        assert_eq!(
+            registry.reassignments()[0].to_string(),
+            "app1's api port moved from 3001 to 3000"
+        );
+    }
+
+    fn read_checksum_mock(checksum: String) -> impl Clause {
+        ReadFileMock
+            .each_call(matching!((path) if path == &PathBuf::from("/data/registry.toml.checksum")))
+            .answers_arc(Arc::new(move |_, _| Ok(checksum.clone())))
+            .once()
+    }
+
+    fn read_fixture_mock() -> impl Clause {
+        ReadFileMock
+            .each_call(matching!((path) if path == &PathBuf::from("/data/registry.toml")))
+            .answers(&|_, _| Ok(include_str!("fixtures/registry.toml").to_owned()))
+            .once()
+    }
+
+    #[test]
+    fn test_checksum() {
+        assert_eq!(checksum(""), "cbf29ce484222325");
+        assert_eq!(checksum("a"), "af63dc4c8601ec8c");
+    }
+
+    #[test]
+    fn test_load_checksum_mismatch() {
+        let mocked_deps = Unimock::new((
+            data_dir_mock(),
+            read_fixture_mock(),
+            read_checksum_mock(String::from("0000000000000000\n")),
+        ));
+        let allocator = PortAllocator::new(Config::default().get_valid_ports());
+        let registry = Registry::new(&mocked_deps, allocator, RegistryLayout::Single).unwrap();
+        assert!(registry.modified_externally());
+    }
+
+    #[test]
+    fn test_load_checksum_match() {
+        let mocked_deps = Unimock::new((
+            data_dir_mock(),
+            read_fixture_mock(),
+            read_checksum_mock(checksum(include_str!("fixtures/registry.toml"))),
+        ));
+        let allocator = PortAllocator::new(Config::default().get_valid_ports());
+        let registry = Registry::new(&mocked_deps, allocator, RegistryLayout::Single).unwrap();
+        assert!(!registry.modified_externally());
+    }
+
+    #[test]
+    fn test_load_out_of_range() {
+        let mocked_deps = Unimock::new((
+            data_dir_mock(),
+            read_registry_mock(Some(
+                "projects.app1 = { port = 49152, out_of_range = true }",
+            )),
+        ));
+        let allocator = PortAllocator::new(Config::default().get_valid_ports())
+            .with_fallback(PortFallback::Ephemeral);
+        let registry = Registry::new(&mocked_deps, allocator, RegistryLayout::Single).unwrap();
+        let project = registry.projects.get("app1").unwrap();
+        assert_eq!(project.port, 49152);
+        assert!(project.out_of_range);
+        assert!(!registry.dirty);
+        assert!(registry.reassignments().is_empty());
+    }
+
+    #[test]
+    fn test_load_pinned() {
+        let mocked_deps = Unimock::new((
+            data_dir_mock(),
+            read_registry_mock(Some(
+                "projects.app1 = { port = 8080, pinned = true }\nprojects.app2 = { port = 8080, pinned = true }",
+            )),
+            choose_port_mock(),
+        ));
+        let allocator = PortAllocator::new(Config::default().get_valid_ports());
+        let registry = Registry::new(&mocked_deps, allocator, RegistryLayout::Single).unwrap();
+        let app1 = registry.projects.get("app1").unwrap();
+        assert_eq!(app1.port, 8080);
+        assert!(app1.pinned);
+        let app2 = registry.projects.get("app2").unwrap();
+        assert_ne!(app2.port, 8080);
+        assert!(!app2.pinned);
+        assert!(registry.dirty);
+    }
+
+    #[test]
+    fn test_load_out_of_range_no_fallback() {
+        let mocked_deps = Unimock::new((
+            choose_port_mock(),
+            data_dir_mock(),
+            read_registry_mock(Some(
+                "projects.app1 = { port = 49152, out_of_range = true }",
+            )),
+        ));
+        let allocator = PortAllocator::new(Config::default().get_valid_ports());
+        let registry = Registry::new(&mocked_deps, allocator, RegistryLayout::Single).unwrap();
+        let project = registry.projects.get("app1").unwrap();
+        assert_eq!(project.port, 3000);
+        assert!(!project.out_of_range);
+        assert!(registry.dirty);
+    }
+
+    #[test]
+    fn test_load_normalizes() {
+        let config = Config {
+            ranges: vec![(4000, 4999)],
+            ..Default::default()
+        };
+        let mocked_deps = Unimock::new((
+            choose_port_mock(),
+            data_dir_mock(),
+            read_registry_mock(None),
+        ));
+        let allocator = PortAllocator::new(config.get_valid_ports());
+        let registry = Registry::new(&mocked_deps, allocator, RegistryLayout::Single).unwrap();
+        assert_eq!(registry.get("app1").unwrap().port, 4000);
+        assert_eq!(registry.get("app2").unwrap().port, 4001);
+        assert_eq!(registry.get("app3").unwrap().port, 4002);
+        assert!(registry.dirty);
+        assert_eq!(
+            registry
+                .reassignments()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec![
+                "app1 moved from 3001 to 4000",
+                "app2 moved from 3002 to 4001",
+                "app3 moved from 3003 to 4002",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_save_clean() {
+        let mocked_deps = Unimock::new(());
+        let registry = get_mocked_registry().unwrap();
+        assert!(!registry.dirty);
+        registry.save(&mocked_deps).unwrap();
+    }
+
+    #[test]
+    fn test_save_caddy_read_failure() {
+        let mocked_deps = Unimock::new((
+            data_dir_mock(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/homebrew/etc/Caddyfile")))
+                .answers(&|_, _| Err(Error::from(ErrorKind::PermissionDenied)))
+                .once(),
+            read_var_mock(),
+            dependencies::WriteFileMock
+                .each_call(matching!(_))
+                .answers(&|_, _, _| Ok(()))
+                .at_least_times(1),
+        ));
+        let mut registry = get_mocked_registry().unwrap();
+        registry.dirty = true;
+        let err = registry.save(&mocked_deps).unwrap_err();
+        assert!(matches!(err, ApplicationError::Caddy(_)));
+    }
+
+    #[test]
+    fn test_save_caddy_write_portman_caddyfile_failure() {
+        let mocked_deps = Unimock::new((
+            data_dir_mock(),
+            dependencies::WriteFileMock
+                .each_call(matching!((path, _) if path == &PathBuf::from("/data/registry.toml") || path == &PathBuf::from("/data/registry.toml.1") || path == &PathBuf::from("/data/registry.toml.checksum") || path == &PathBuf::from("/data/index.toml")))
+                .answers(&|_, _, _| Ok(()))
+                .n_times(4),
+            dependencies::WriteFileMock
+                .each_call(matching!((path, _) if path == &PathBuf::from("/data/Caddyfile")))
+                .answers(&|_, _, _| bail!("Error writing"))
+                .once(),
+        ));
+        let mut registry = get_mocked_registry().unwrap();
+        registry.dirty = true;
+        let err = registry.save(&mocked_deps).unwrap_err();
+        assert!(matches!(err, ApplicationError::Caddy(_)));
+    }
+
+    #[test]
+    fn test_save_rotates_backups() {
+        let mocked_deps = Unimock::new((
+            data_dir_mock(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/registry.toml.1")))
+                .answers(&|_, _| Ok(String::from("previous")))
+                .once(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/registry.toml.2")))
+                .answers(&|_, _| Ok(String::from("older")))
+                .once(),
+            dependencies::WriteFileMock
+                .each_call(matching!((path, contents) if path == &PathBuf::from("/data/registry.toml.3") && contents == &String::from("older")))
+                .answers(&|_, _, _| Ok(()))
+                .once(),
+            dependencies::WriteFileMock
+                .each_call(matching!((path, contents) if path == &PathBuf::from("/data/registry.toml.2") && contents == &String::from("previous")))
+                .answers(&|_, _, _| Ok(()))
+                .once(),
+            dependencies::WriteFileMock
+                .each_call(matching!((path, contents) if path == &PathBuf::from("/data/registry.toml.1") && contents.contains("[projects.app1]")))
+                .answers(&|_, _, _| Ok(()))
+                .once(),
+            dependencies::WriteFileMock
+                .each_call(matching!((path, _) if path == &PathBuf::from("/data/registry.toml") || path == &PathBuf::from("/data/registry.toml.checksum") || path == &PathBuf::from("/data/index.toml")))
+                .answers(&|_, _, _| Ok(()))
+                .n_times(3),
+            dependencies::WriteFileMock
+                .each_call(matching!((path, _) if path == &PathBuf::from("/data/Caddyfile")))
+                .answers(&|_, _, _| bail!("Error writing"))
+                .once(),
+        ));
+        let mut registry = get_mocked_registry().unwrap().with_backups(2);
+        registry.dirty = true;
+        let err = registry.save(&mocked_deps).unwrap_err();
+        assert!(matches!(err, ApplicationError::Caddy(_)));
+    }
+
+    #[test]
+    fn test_save_caddy_write_root_caddyfile_failure() {
+        let mocked_deps = Unimock::new((
+            data_dir_mock(),
+            read_caddyfile_mock(),
+            read_var_mock(),
+            dependencies::WriteFileMock
+                .each_call(matching!((path, _) if path == &PathBuf::from("/data/registry.toml") || path == &PathBuf::from("/data/registry.toml.1") || path == &PathBuf::from("/data/registry.toml.checksum") || path == &PathBuf::from("/data/index.toml") || path == &PathBuf::from("/data/Caddyfile")))
+                .answers(&|_, _, _| Ok(()))
+                .n_times(5),
+            dependencies::WriteFileMock
+                .each_call(
+                    matching!((path, _) if path == &PathBuf::from("/homebrew/etc/Caddyfile")),
+                )
+                .answers(&|_, _, _| bail!("Error writing"))
+                .once(),
+        ));
+        let mut registry = get_mocked_registry().unwrap();
+        registry.dirty = true;
+        let err = registry.save(&mocked_deps).unwrap_err();
+        assert!(matches!(err, ApplicationError::Caddy(_)));
+    }
+
+    #[test]
+    fn test_save_caddy_exec_failure() {
+        let mocked_deps = Unimock::new((
+            data_dir_mock(),
+            read_caddyfile_mock(),
+            read_health_mock(),
+            read_usage_mock(),
+            read_var_mock(),
+            write_file_mock(),
+            dependencies::ExecMock
+                .each_call(matching!((command) if command.get_program() == "caddy"))
+                .answers(&|_, _| Err(Error::from(ErrorKind::NotFound)))
+                .once(),
+        ));
+        let mut registry = get_mocked_registry().unwrap();
+        registry.dirty = true;
+        let err = registry.save(&mocked_deps).unwrap_err();
+        assert!(matches!(err, ApplicationError::Caddy(_)));
+    }
+
+    #[test]
+    fn test_save_caddy_not_required() {
+        let mocked_deps = Unimock::new((
+            data_dir_mock(),
+            read_caddyfile_mock(),
+            read_health_mock(),
+            read_usage_mock(),
+            read_var_mock(),
+            write_file_mock(),
+            dependencies::ExecMock
+                .each_call(matching!((command) if command.get_program() == "caddy"))
+                .answers(&|_, _| Err(Error::from(ErrorKind::NotFound)))
+                .once(),
+        ));
+        let mut registry = get_mocked_registry().unwrap().with_require_caddy(false);
+        registry.dirty = true;
+        assert_eq!(
+            registry.save(&mocked_deps).unwrap(),
+            SaveOutcome::CaddyMissing
+        );
+    }
+
+    #[test]
+    fn test_save_caddy_not_required_validation() {
+        // The registry is still saved when caddy can't validate the Caddyfile
+        let mocked_deps = Unimock::new((
+            data_dir_mock(),
+            write_checksum_mock(),
+            write_index_mock(),
+            dependencies::WriteFileMock
+                .each_call(matching!((path, _) if path == &PathBuf::from("/data/registry.toml") || path == &PathBuf::from("/data/Caddyfile.validate")))
+                .answers(&|_, _, _| Ok(()))
+                .n_times(2),
+            dependencies::ExecMock
+                .each_call(matching!((command) if command.get_program() == "caddy"))
+                .answers(&|_, _| Err(Error::from(ErrorKind::NotFound)))
+                .once(),
+        ));
+        let mut registry = get_mocked_registry().unwrap().with_require_caddy(false);
+        registry
+            .set_caddy_extra("app1", Some(String::from("encode gzip")))
+            .unwrap();
+        assert_eq!(
+            registry.save(&mocked_deps).unwrap(),
+            SaveOutcome::CaddyMissing
+        );
+    }
+
+    #[test]
+    fn test_save_invalid_caddyfile() {
+        // Nothing except the Caddyfile that caddy validates is written
+        let mocked_deps = Unimock::new((
+            data_dir_mock(),
+            dependencies::WriteFileMock
+                .each_call(
+                    matching!((path, _) if path == &PathBuf::from("/data/Caddyfile.validate")),
+                )
+                .answers(&|_, _, _| Ok(()))
+                .once(),
+            dependencies::ExecMock
+                .each_call(matching!((command) if command.get_program() == "caddy"))
+                .answers(&|_, _| {
+                    Ok(dependencies::ExecStatus::Failure {
+                        output: String::from("Error: unrecognized directive: invalid_directive\n"),
+                        code: 1,
+                    })
+                })
+                .once(),
+        ));
+        let mut registry = get_mocked_registry().unwrap();
+        registry
+            .set_caddy_extra("app1", Some(String::from("invalid_directive\n")))
+            .unwrap();
+        let err = registry.save(&mocked_deps).unwrap_err();
+        assert!(matches!(
+            err,
+            ApplicationError::Caddy(CaddyError::InvalidCaddyfile(_))
+        ));
+    }
+
+    #[test]
+    fn test_get() {
+        let registry = get_mocked_registry().unwrap();
+        assert_eq!(registry.get("app1").unwrap().port, 3001);
+        assert!(registry.get("app4").is_none());
+    }
+
+    #[test]
+    fn test_create() {
+        let mocked_deps = Unimock::new(choose_port_mock());
+        let mut registry = get_mocked_registry().unwrap();
+        registry
+            .create(
+                &mocked_deps,
+                "app4",
+                None,
+                None,
+                None,
+                BTreeSet::new(),
+                None,
+            )
+            .unwrap();
+        assert!(registry.get("app4").is_some());
+        assert!(registry.dirty);
+    }
+
+    #[test]
+    fn test_create_invalid_name() {
+        let mocked_deps = Unimock::new(());
+        let mut registry = get_mocked_registry().unwrap();
+        let err = registry
+            .create(
+                &mocked_deps,
+                "App3",
+                None,
+                None,
+                None,
+                BTreeSet::new(),
+                None,
+            )
+            .unwrap_err();
+        assert!(matches!(err, ApplicationError::InvalidProjectName(_, _)));
+        assert!(!registry.dirty);
+    }
+
+    #[test]
+    fn test_create_duplicate_name() {
+        let mocked_deps = Unimock::new(());
+        let mut registry = get_mocked_registry().unwrap();
+        let err = registry
+            .create(
+                &mocked_deps,
+                "app3",
+                None,
+                None,
+                None,
+                BTreeSet::new(),
+                None,
+            )
+            .unwrap_err();
+        assert!(matches!(err, ApplicationError::DuplicateProject(_)));
+        assert!(!registry.dirty);
+    }
+
+    #[test]
+    fn test_create_linked_port() {
+        let mocked_deps = Unimock::new(choose_port_mock());
+        let mut registry = get_mocked_registry().unwrap();
+        registry
+            .create(
+                &mocked_deps,
+                "app4",
+                None,
+                None,
+                Some(3100),
+                BTreeSet::new(),
+                None,
+            )
+            .unwrap();
+        assert_eq!(registry.get("app4").unwrap().linked_port.unwrap(), 3100);
+        assert!(registry.dirty);
+    }
+
+    #[test]
+    fn test_create_linked_port_reallocates_previous() {
+        let mocked_deps = Unimock::new(choose_port_mock());
+        let mut registry = get_mocked_registry().unwrap();
+        registry
+            .create(
+                &mocked_deps,
+                "app4",
+                None,
+                None,
+                Some(3001),
+                BTreeSet::new(),
+                None,
+            )
+            .unwrap();
+        assert_eq!(registry.get("app1").unwrap().port, 3005);
+        assert!(registry.dirty);
+    }
+
+    #[test]
+    fn test_create_linked_port_discards_current() {
+        let mocked_deps = Unimock::new(choose_port_mock());
+        let mut registry = get_mocked_registry().unwrap();
+        let project = registry
+            .create(
+                &mocked_deps,
+                "app4",
+                None,
+                None,
+                Some(3004),
+                BTreeSet::new(),
+                None,
+            )
+            .unwrap();
+        assert_eq!(project.port, 3005);
+        assert_eq!(registry.get("app4").unwrap().port, 3005);
+        assert!(registry.dirty);
+    }
+
+    #[test]
+    fn test_create_duplicate_directory() {
+        let mocked_deps = Unimock::new(());
+        let mut registry = get_mocked_registry().unwrap();
+        let result = registry.create(
+            &mocked_deps,
+            "app4",
+            None,
+            Some(PathBuf::from("/projects/app3")),
+            None,
+            BTreeSet::new(),
+            None,
+        );
+        assert!(matches!(
+            result,
+            Err(ApplicationError::DuplicateDirectory(_, _)),
+        ));
+        assert!(!registry.dirty);
+    }
+
+    #[test]
+    fn test_update() {
+        let mut registry = get_mocked_registry().unwrap();
+        let path = PathBuf::from("/projects/app2");
+        assert_eq!(
+            registry
+                .update("app2", Some(path.clone()))
+                .unwrap()
+                .directory
+                .unwrap(),
+            path,
+        );
+        assert_eq!(
+            registry.get("app2").unwrap().directory.as_ref().unwrap(),
+            &path,
+        );
+        assert!(registry.dirty);
+    }
+
+    #[test]
+    fn test_update_same() {
+        let mut registry = get_mocked_registry().unwrap();
+        let path = PathBuf::from("/projects/app3");
+        assert_eq!(
+            registry
+                .update("app3", Some(path.clone()))
+                .unwrap()
+                .directory
+                .unwrap(),
+            path,
+        );
+        assert_eq!(
+            registry.get("app3").unwrap().directory.as_ref().unwrap(),
+            &path,
+        );
+        assert!(!registry.dirty);
+    }
+
+    #[test]
+    fn test_update_nonexistent() {
+        let mut registry = get_mocked_registry().unwrap();
+        let err = registry.update("app4", None).unwrap_err();
+        assert!(matches!(err, ApplicationError::NonExistentProject(_)));
+    }
+
+    #[test]
+    fn test_set_display_name() {
+        let mut registry = get_mocked_registry().unwrap();
+        let project = registry
+            .set_display_name("app1", Some(String::from("App 1")))
+            .unwrap();
+        assert_eq!(project.display_name.unwrap(), "App 1");
+        assert!(registry.dirty);
+    }
+
+    #[test]
+    fn test_set_icon_same() {
+        let mut registry = get_mocked_registry().unwrap();
+        registry.set_icon("app1", None).unwrap();
+        assert!(!registry.dirty);
+    }
+
+    #[test]
+    fn test_set_color() {
+        let mut registry = get_mocked_registry().unwrap();
+        let project = registry
+            .set_color("app1", Some(ProjectColor::Blue))
+            .unwrap();
+        assert_eq!(project.color, Some(ProjectColor::Blue));
+        assert!(registry.dirty);
+    }
+
+    #[test]
+    fn test_is_icon_url() {
+        assert!(is_icon_url("https://example.com/icon.png"));
+        assert!(is_icon_url("http://localhost:3001/favicon.ico"));
+        assert!(!is_icon_url("🚀"));
+    }
+
+    #[test]
+    fn test_bind_for() {
+        let mut registry = get_mocked_registry().unwrap().with_bind(BindAddress::All);
+        registry
+            .set_bind("app1", Some(BindAddress::Loopback))
+            .unwrap();
+        assert!(registry.dirty);
+        let app1 = registry.get("app1").unwrap();
+        let app2 = registry.get("app2").unwrap();
+        assert_eq!(registry.bind_for(app1), &BindAddress::Loopback);
+        assert_eq!(registry.bind_for(app2), &BindAddress::All);
+    }
+
+    #[test]
+    fn test_create_explicit_port() {
+        let mocked_deps = Unimock::new(());
+        let mut registry = get_mocked_registry().unwrap();
+        let project = registry
+            .create(
+                &mocked_deps,
+                "app4",
+                Some(3500),
+                None,
+                None,
+                BTreeSet::new(),
+                None,
+            )
+            .unwrap();
+        assert_eq!(project.port, 3500);
+        assert!(!registry.allocator.is_available(3500, &BTreeSet::new()));
+    }
+
+    #[test]
+    fn test_create_port_override() {
+        let mocked_deps = Unimock::new(());
+        let mut registry = get_mocked_registry()
+            .unwrap()
+            .with_port_overrides(BTreeMap::from([(String::from("app4"), 3123)]));
+        let project = registry
+            .create(
+                &mocked_deps,
+                "app4",
+                None,
+                None,
+                None,
+                BTreeSet::new(),
+                None,
+            )
+            .unwrap();
+        assert_eq!(project.port, 3123);
+    }
+
+    #[test]
+    fn test_create_out_of_range_port() {
+        let mocked_deps = Unimock::new(());
+        let mut registry = get_mocked_registry().unwrap();
+        let create = |registry: &mut Registry| {
+            registry.create(
+                &mocked_deps,
+                "app4",
+                Some(8080),
+                None,
+                None,
+                BTreeSet::new(),
+                None,
+            )
+        };
+        assert!(matches!(
+            create(&mut registry),
+            Err(ApplicationError::OutOfRangePort(8080)),
+        ));
+        registry.allow_port(8080).unwrap();
+        let project = create(&mut registry).unwrap();
+        assert_eq!(project.port, 8080);
+        assert!(project.pinned);
+        assert!(matches!(
+            registry.allow_port(8080),
+            Err(ApplicationError::UnavailablePort(8080)),
+        ));
+    }
+
+    #[test]
+    fn test_create_unavailable_port() {
+        let mocked_deps = Unimock::new(());
+        let mut registry = get_mocked_registry().unwrap();
+        let err = registry
+            .create(
+                &mocked_deps,
+                "app4",
+                Some(3001),
+                None,
+                None,
+                BTreeSet::new(),
+                None,
+            )
+            .unwrap_err();
+        assert!(matches!(err, ApplicationError::UnavailablePort(3001)));
+        assert!(!registry.dirty);
+    }
+
+    #[test]
+    fn test_adopt() {
+        let mocked_deps = Unimock::new(());
+        let mut registry = get_mocked_registry().unwrap();
+        let project = registry
+            .adopt(
+                &mocked_deps,
+                "app4",
+                3500,
+                Some(PathBuf::from("/projects/app4")),
+                false,
+            )
+            .unwrap();
+        assert_eq!(project.port, 3500);
+        assert!(!project.pinned);
+        assert!(matches!(
+            registry.adopt(&mocked_deps, "app5", 8080, None, false),
+            Err(ApplicationError::OutOfRangePort(8080)),
+        ));
+    }
+
+    #[test]
+    fn test_adopt_force() {
+        let mocked_deps = Unimock::new(());
+        let mut registry = get_mocked_registry().unwrap();
+        let project = registry
+            .adopt(&mocked_deps, "app4", 8080, None, true)
+            .unwrap();
+        assert_eq!(project.port, 8080);
+        assert!(project.pinned);
+        assert!(registry.dirty);
+        assert!(matches!(
+            registry.adopt(&mocked_deps, "app5", 8080, None, true),
+            Err(ApplicationError::UnavailablePort(8080)),
+        ));
+        assert!(matches!(
+            registry.adopt(&mocked_deps, "app5", 3001, None, true),
+            Err(ApplicationError::UnavailablePort(3001)),
+        ));
+    }
+
+    #[test]
+    fn test_extend_env() {
+        let mut registry = get_mocked_registry().unwrap();
+        let project = registry
+            .extend_env(
+                "app1",
+                [(String::from("API_URL"), String::from("http://api"))],
+            )
+            .unwrap();
+        assert_eq!(project.env["API_URL"], "http://api");
+        assert!(registry.dirty);
+    }
+
+    #[test]
+    fn test_create_tagged() {
+        let mocked_deps = Unimock::new(choose_port_mock());
+        let mut registry = get_mocked_registry_with_tag_ranges();
+        let tags = BTreeSet::from([String::from("work")]);
+        let project = registry
+            .create(&mocked_deps, "app4", None, None, None, tags.clone(), None)
+            .unwrap();
+        assert_eq!(project.port, 3100);
+        assert_eq!(project.tags, tags);
+    }
+
+    #[test]
+    fn test_set_tags() {
+        let mocked_deps = Unimock::new(choose_port_mock());
+        let mut registry = get_mocked_registry_with_tag_ranges();
+        let project = registry
+            .set_tags(&mocked_deps, "app1", BTreeSet::from([String::from("work")]))
+            .unwrap();
+        assert_eq!(project.port, 3100);
+        assert!(registry.dirty);
+        assert_eq!(registry.iter_misplaced_projects().count(), 0);
+    }
+
+    #[test]
+    fn test_add_remove_tag() {
+        let mocked_deps = Unimock::new(choose_port_mock());
+        let mut registry = get_mocked_registry_with_tag_ranges();
+        let project = registry
+            .add_tag(&mocked_deps, "app1", String::from("work"))
+            .unwrap();
+        assert_eq!(project.tags, BTreeSet::from([String::from("work")]));
+        assert_eq!(project.port, 3100);
+        let project = registry.remove_tag(&mocked_deps, "app1", "work").unwrap();
+        assert!(project.tags.is_empty());
+        assert!(!(3100..=3199).contains(&project.port));
+        assert!(matches!(
+            registry.remove_tag(&mocked_deps, "app1", "work"),
+            Err(ApplicationError::NonExistentTag(name, tag)) if name == "app1" && tag == "work",
+        ));
+        assert!(matches!(
+            registry.add_tag(&mocked_deps, "app4", String::from("work")),
+            Err(ApplicationError::NonExistentProject(_)),
+        ));
+    }
+
+    #[test]
+    fn test_misplaced_projects() {
+        let mocked_deps = Unimock::new((
+            data_dir_mock(),
+            read_registry_mock(Some(
+                "[projects.app1]\nport = 3001\ntags = ['work']\n\n[projects.app2]\nport = 3150\n",
+            )),
+        ));
+        let allocator = PortAllocator::new(3000..=3999)
+            .with_tag_ranges(BTreeMap::from([(String::from("work"), (3100, 3199))]));
+        let registry = Registry::new(&mocked_deps, allocator, RegistryLayout::Single).unwrap();
+        assert_eq!(
+            registry
+                .iter_misplaced_projects()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["app1", "app2"]
+        );
+    }
+
+    fn list_groups_mock() -> impl Clause {
+        dependencies::ListDirectoryMock
+            .each_call(matching!((path) if path == &PathBuf::from("/data/registry.d")))
+            .answers(&|_, _| {
+                Ok(vec![
+                    PathBuf::from("/data/registry.d/work.toml"),
+                    PathBuf::from("/data/registry.d/README.md"),
+                ])
+            })
+            .once()
+    }
+
+    fn read_group_mock(contents: &'static str) -> impl Clause {
+        ReadFileMock
+            .each_call(matching!((path) if path == &PathBuf::from("/data/registry.d/work.toml")))
+            .answers_arc(std::sync::Arc::new(move |_, _| Ok(contents.to_owned())))
+            .once()
+    }
+
+    #[test]
+    fn test_load_split() {
+        let mocked_deps = Unimock::new((
+            data_dir_mock(),
+            read_registry_mock(None),
+            list_groups_mock(),
+            read_group_mock("[projects.app4]\nport = 3100\n"),
+        ));
+        let allocator = PortAllocator::new(Config::default().get_valid_ports());
+        let registry = Registry::new(&mocked_deps, allocator, RegistryLayout::Split).unwrap();
+        assert_eq!(
+            registry.get("app4").unwrap().group,
+            Some(String::from("work"))
+        );
+        assert_eq!(registry.get("app1").unwrap().group, None);
+        assert!(!registry.dirty);
+    }
+
+    #[test]
+    fn test_load_split_duplicate() {
+        let mocked_deps = Unimock::new((
+            data_dir_mock(),
+            read_registry_mock(None),
+            list_groups_mock(),
+            read_group_mock("[projects.app1]\nport = 3100\n"),
+        ));
+        let allocator = PortAllocator::new(Config::default().get_valid_ports());
+        let err = Registry::new(&mocked_deps, allocator, RegistryLayout::Split).unwrap_err();
+        assert!(matches!(err, ApplicationError::DuplicateProject(name) if name == "app1"));
+    }
+
+    #[test]
+    fn test_save_split() {
+        let mocked_deps = Unimock::new((
+            data_dir_mock(),
+            read_registry_mock(None),
+            list_groups_mock(),
+            read_group_mock("[projects.app4]\nport = 3100\n"),
+            dependencies::WriteFileMock
+                .each_call(matching!((path, contents) if path == &PathBuf::from("/data/registry.toml") && !contents.contains("app4")))
+                .answers(&|_, _, _| Ok(()))
+                .once(),
+            write_checksum_mock(),
+            dependencies::WriteFileMock
+                .each_call(matching!((path, contents) if (path == &PathBuf::from("/data/registry.d/work.toml") || path == &PathBuf::from("/data/registry.d/work.toml.1")) && contents == &String::from("[projects.app4]\nport = 3100\n")))
+                .answers(&|_, _, _| Ok(()))
+                .n_times(2),
+            dependencies::WriteFileMock
+                .each_call(matching!((path, _) if path == &PathBuf::from("/data/index.toml")))
+                .answers(&|_, _, _| Ok(()))
+                .once(),
+            dependencies::WriteFileMock
+                .each_call(matching!((path, _) if path == &PathBuf::from("/data/Caddyfile")))
+                .answers(&|_, _, _| bail!("Error writing"))
+                .once(),
+        ));
+        let allocator = PortAllocator::new(Config::default().get_valid_ports());
+        let mut registry = Registry::new(&mocked_deps, allocator, RegistryLayout::Split).unwrap();
+        registry.dirty = true;
+        let err = registry.save(&mocked_deps).unwrap_err();
+        assert!(matches!(err, ApplicationError::Caddy(_)));
+    }
+
+    #[test]
+    fn test_accept_changes_split() {
+        let mocked_deps = Unimock::new((
+            data_dir_mock(),
+            list_groups_mock(),
+            read_group_mock("[projects.app4]\nport = 3100\n"),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/registry.toml")))
+                .answers(&|_, _| Ok(String::from("[projects]\n")))
+                .once(),
+            dependencies::WriteFileMock
+                .each_call(matching!((path, contents) if path == &PathBuf::from("/data/registry.toml.1") && contents == &String::from("[projects]\n")))
+                .answers(&|_, _, _| Ok(()))
+                .once(),
+            dependencies::WriteFileMock
+                .each_call(matching!((path, contents) if path == &PathBuf::from("/data/registry.toml.checksum") && contents == &checksum("[projects]\n")))
+                .answers(&|_, _, _| Ok(()))
+                .once(),
+            dependencies::WriteFileMock
+                .each_call(matching!((path, contents) if path == &PathBuf::from("/data/registry.d/work.toml.1") && contents == &String::from("[projects.app4]\nport = 3100\n")))
+                .answers(&|_, _, _| Ok(()))
+                .once(),
+        ));
+        Registry::accept_changes(&mocked_deps, RegistryLayout::Split, 0).unwrap();
+    }
+
+    #[test]
+    fn test_restore_backup_split() {
+        let mocked_deps = Unimock::new((
+            data_dir_mock(),
+            dependencies::ListDirectoryMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/registry.d")))
+                .answers(&|_, _| {
+                    Ok(vec![
+                        PathBuf::from("/data/registry.d/home.toml"),
+                        PathBuf::from("/data/registry.d/work.toml"),
+                    ])
+                })
+                .once(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/registry.toml.2")))
+                .answers(&|_, _| Ok(String::from("[projects]\n")))
+                .once(),
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/registry.d/work.toml.2")))
+                .answers(&|_, _| Ok(String::from("[projects.app4]\nport = 3100\n")))
+                .once(),
+            // The home group was created after the backup
+            ReadFileMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/registry.d/home.toml.2")))
+                .answers(&|_, _| Err(Error::from(ErrorKind::NotFound)))
+                .once(),
+            dependencies::WriteFileMock
+                .each_call(matching!((path, contents) if path == &PathBuf::from("/data/registry.toml") && contents == &String::from("[projects]\n")))
+                .answers(&|_, _, _| Ok(()))
+                .once(),
+            dependencies::WriteFileMock
+                .each_call(matching!((path, contents) if path == &PathBuf::from("/data/registry.toml.checksum") && contents == &checksum("[projects]\n")))
+                .answers(&|_, _, _| Ok(()))
+                .once(),
+            dependencies::WriteFileMock
+                .each_call(matching!((path, contents) if path == &PathBuf::from("/data/registry.d/work.toml") && contents == &String::from("[projects.app4]\nport = 3100\n")))
+                .answers(&|_, _, _| Ok(()))
+                .once(),
+        ));
+        Registry::restore_backup(&mocked_deps, RegistryLayout::Split, 2).unwrap();
+    }
+
+    #[test]
+    fn test_set_upstream() {
+        let mut registry = get_mocked_registry().unwrap();
+        let upstream = Upstream {
+            url: String::from("https://staging.example.com"),
+            headers: BTreeMap::new(),
+        };
+        let project = registry
+            .set_upstream("app1", Some(upstream.clone()))
+            .unwrap();
+        assert_eq!(project.upstream.unwrap(), upstream);
+        assert!(registry.dirty);
+    }
+
+    #[test]
+    fn test_set_upstream_invalid() {
+        let mut registry = get_mocked_registry().unwrap();
+        let upstream = Upstream {
+            url: String::from("staging.example.com"),
+            headers: BTreeMap::new(),
+        };
+        let err = registry.set_upstream("app1", Some(upstream)).unwrap_err();
+        assert!(matches!(err, ApplicationError::InvalidUpstream(_, _)));
+        assert!(!registry.dirty);
+    }
+
+    #[test]
+    fn test_set_upstream_normalizes_url() {
+        let mut registry = get_mocked_registry().unwrap();
+        let upstream = Upstream {
+            url: String::from("https://staging.example.com:8443/"),
+            headers: BTreeMap::new(),
+        };
+        let project = registry.set_upstream("app1", Some(upstream)).unwrap();
+        assert_eq!(
+            project.upstream.unwrap().url,
+            "https://staging.example.com:8443"
+        );
+    }
+
+    #[test]
+    fn test_normalize_upstream_url() {
+        assert_eq!(
+            normalize_upstream_url("http://[::1]:8080").unwrap(),
+            "http://[::1]:8080"
+        );
+        assert!(normalize_upstream_url("https://staging.example.com/api").is_err());
+        assert!(normalize_upstream_url("https://staging.example.com?a=b").is_err());
+        assert!(normalize_upstream_url("https://user@example.com").is_err());
+        assert!(normalize_upstream_url("https://example.com {\n}").is_err());
+        assert!(normalize_upstream_url("https://example.com:0").is_err());
+        assert!(normalize_upstream_url("https://example.com:99999").is_err());
+        assert!(normalize_upstream_url("https://").is_err());
+        assert!(normalize_upstream_url("ftp://example.com").is_err());
     }
-}
 
-#[cfg(test)]
-pub mod tests {
-    use super::*;
-    use crate::config::Config;
-    use crate::dependencies::{self, ReadFileMock};
-    use crate::mocks::{
-        choose_port_mock, cwd_mock, data_dir_mock, get_mocked_registry, read_registry_mock,
-        read_var_mock, write_file_mock,
-    };
-    use anyhow::bail;
-    use std::io::{Error, ErrorKind};
-    use unimock::{matching, Clause, MockFn, Unimock};
+    #[test]
+    fn test_set_upstream_invalid_header() {
+        let mut registry = get_mocked_registry().unwrap();
+        for (name, value) in [("X-Token {", "value"), ("X-Token", "a\nreverse_proxy evil")] {
+            let upstream = Upstream {
+                url: String::from("https://staging.example.com"),
+                headers: std::iter::once((String::from(name), String::from(value))).collect(),
+            };
+            let err = registry.set_upstream("app1", Some(upstream)).unwrap_err();
+            assert!(matches!(err, ApplicationError::InvalidUpstreamHeader(_, _)));
+        }
+        assert!(!registry.dirty);
+    }
 
-    fn read_caddyfile_mock() -> impl Clause {
-        ReadFileMock
-            .each_call(matching!((path) if path == &PathBuf::from("/homebrew/etc/Caddyfile")))
-            .answers(&|_, _| Err(Error::from(ErrorKind::NotFound)))
-            .once()
+    #[test]
+    fn test_is_header_name() {
+        assert!(is_header_name("X-Forwarded-For"));
+        assert!(is_header_name("x_custom!"));
+        assert!(!is_header_name(""));
+        assert!(!is_header_name("X Token"));
+        assert!(!is_header_name("X-Token\n"));
+        assert!(!is_header_name("{header}"));
     }
 
     #[test]
-    fn test_load() {
-        let config = Config::default();
-        let mocked_deps = Unimock::new((data_dir_mock(), read_registry_mock(None)));
-        let allocator = PortAllocator::new(config.get_valid_ports());
-        let registry = Registry::new(&mocked_deps, allocator).unwrap();
-        assert_eq!(registry.projects.len(), 3);
-        assert_eq!(registry.repos.len(), 1);
-        assert!(!registry.dirty);
+    fn test_delete() {
+        let mut registry = get_mocked_registry().unwrap();
+        registry.delete("app2").unwrap();
+        assert!(registry.get("app2").is_none());
     }
 
     #[test]
-    fn test_load_invalid() {
-        let config = Config::default();
-        let mocked_deps = Unimock::new((data_dir_mock(), read_registry_mock(Some(";"))));
-        let allocator = PortAllocator::new(config.get_valid_ports());
-        let err = Registry::new(&mocked_deps, allocator).unwrap_err();
-        assert!(matches!(err, ApplicationError::Other(_)));
+    fn test_delete_nonexistent() {
+        let mut registry = get_mocked_registry().unwrap();
+        let err = registry.delete("app4").unwrap_err();
+        assert!(matches!(err, ApplicationError::NonExistentProject(_)));
     }
 
     #[test]
-    fn test_load_invalid_name() {
-        let config = Config::default();
-        let mocked_deps = Unimock::new((
-            data_dir_mock(),
-            read_registry_mock(Some("projects.App1 = { port = 3001 }")),
-        ));
-        let allocator = PortAllocator::new(config.get_valid_ports());
-        let err = Registry::new(&mocked_deps, allocator).unwrap_err();
-        assert!(matches!(err, ApplicationError::InvalidProjectName(name, _) if name == "App1"));
+    fn test_archive_reserve_port() {
+        let mocked_deps = Unimock::new(());
+        let mut registry = get_mocked_registry().unwrap();
+        let project = registry.archive("app3", true).unwrap();
+        assert_eq!(project.port, 3003);
+        assert!(registry.get("app3").is_none());
+        assert!(registry.dirty);
+        assert_eq!(registry.archived_ports.get("app3"), Some(&3003));
+
+        let restored = registry.unarchive(&mocked_deps, "app3", project).unwrap();
+        assert_eq!(restored.port, 3003);
+        assert_eq!(restored.directory, Some(PathBuf::from("/projects/app3")));
+        assert!(registry.archived_ports.is_empty());
     }
 
     #[test]
-    fn test_load_duplicate_directory() {
-        let config = Config::default();
+    fn test_load_archived_ports() {
         let mocked_deps = Unimock::new((
+            choose_port_mock(),
             data_dir_mock(),
             read_registry_mock(Some(
-                "[projects.app1]
-port = 3001
-directory = '/projects/app'
-
-[projects.app2]
-port = 3002
-directory = '/projects/app'",
+                "projects.app1 = { port = 3000 }\narchived_ports = { app2 = 3000 }",
             )),
         ));
-        let allocator = PortAllocator::new(config.get_valid_ports());
-        let registry = Registry::new(&mocked_deps, allocator).unwrap();
-        assert!(registry.get("app1").unwrap().directory.is_some());
-        assert!(registry.get("app2").unwrap().directory.is_none());
+        let allocator = PortAllocator::new(Config::default().get_valid_ports());
+        let registry = Registry::new(&mocked_deps, allocator, RegistryLayout::Single).unwrap();
+        assert_eq!(registry.projects.get("app1").unwrap().port, 3001);
         assert!(registry.dirty);
     }
 
     #[test]
-    fn test_load_duplicate_linked() {
-        let config = Config::default();
+    fn test_load_leases() {
         let mocked_deps = Unimock::new((
+            choose_port_mock(),
+            current_time_mock(1000),
             data_dir_mock(),
             read_registry_mock(Some(
-                "[projects.app1]
-port = 3001
-linked_port = 3000
-
-[projects.app2]
-port = 3002
-linked_port = 3000",
+                "projects.app1 = { port = 3000 }\nleases = [{ port = 3000, expires = 2000 }, { port = 3005, expires = 500 }]",
             )),
         ));
-        let allocator = PortAllocator::new(config.get_valid_ports());
-        let registry = Registry::new(&mocked_deps, allocator).unwrap();
-        assert!(registry.get("app1").unwrap().linked_port.is_some());
-        assert!(registry.get("app2").unwrap().linked_port.is_none());
+        let allocator = PortAllocator::new(Config::default().get_valid_ports());
+        let registry = Registry::new(&mocked_deps, allocator, RegistryLayout::Single).unwrap();
+        assert_eq!(registry.projects.get("app1").unwrap().port, 3001);
+        assert_eq!(
+            registry
+                .iter_leases()
+                .map(|lease| lease.port)
+                .collect::<Vec<_>>(),
+            vec![3000]
+        );
         assert!(registry.dirty);
     }
 
     #[test]
-    fn test_load_reallocates_for_linked() {
-        let config = Config::default();
-        let mocked_deps = Unimock::new((
-            choose_port_mock(),
-            data_dir_mock(),
-            read_registry_mock(Some("projects.app1 = { port = 3001, linked_port = 3001 }")),
-        ));
-        let allocator = PortAllocator::new(config.get_valid_ports());
-        let registry = Registry::new(&mocked_deps, allocator).unwrap();
-        assert_eq!(registry.projects.get("app1").unwrap().port, 3000);
+    fn test_acquire_lease() {
+        let mocked_deps = Unimock::new(choose_port_mock());
+        let mut registry = get_mocked_registry().unwrap();
+        let lease = registry
+            .acquire_lease(&mocked_deps, 2000, Some(String::from("tests")))
+            .unwrap();
+        assert_eq!(
+            lease,
+            Lease {
+                port: 3004,
+                expires: 2000,
+                description: Some(String::from("tests")),
+            }
+        );
         assert!(registry.dirty);
+        assert!(!registry.allocator.is_available(3004, &BTreeSet::new()));
     }
 
     #[test]
-    fn test_load_normalizes() {
-        let config = Config {
-            ranges: vec![(4000, 4999)],
-            ..Default::default()
-        };
+    fn test_release_lease() {
+        let mocked_deps = Unimock::new(choose_port_mock());
+        let mut registry = get_mocked_registry().unwrap();
+        registry.acquire_lease(&mocked_deps, 2000, None).unwrap();
+        assert_eq!(registry.release_lease(3004).unwrap().port, 3004);
+        assert_eq!(registry.iter_leases().count(), 0);
+        assert!(matches!(
+            registry.release_lease(3004),
+            Err(ApplicationError::NonExistentLease(3004)),
+        ));
+    }
+
+    #[test]
+    fn test_load_reservations() {
         let mocked_deps = Unimock::new((
             choose_port_mock(),
             data_dir_mock(),
-            read_registry_mock(None),
+            read_registry_mock(Some(
+                "projects.app1 = { port = 3000 }\nreservations = [{ port = 3000, description = 'postgres' }]",
+            )),
         ));
-        let allocator = PortAllocator::new(config.get_valid_ports());
-        let registry = Registry::new(&mocked_deps, allocator).unwrap();
-        assert_eq!(registry.get("app1").unwrap().port, 4000);
-        assert_eq!(registry.get("app2").unwrap().port, 4001);
-        assert_eq!(registry.get("app3").unwrap().port, 4002);
+        let allocator = PortAllocator::new(Config::default().get_valid_ports());
+        let registry = Registry::new(&mocked_deps, allocator, RegistryLayout::Single).unwrap();
+        assert_eq!(registry.projects.get("app1").unwrap().port, 3001);
+        assert_eq!(
+            registry.iter_reservations().collect::<Vec<_>>(),
+            vec![&Reservation {
+                port: 3000,
+                description: Some(String::from("postgres")),
+            }]
+        );
         assert!(registry.dirty);
     }
 
     #[test]
-    fn test_save_clean() {
-        let mocked_deps = Unimock::new(());
-        let registry = get_mocked_registry().unwrap();
-        assert!(!registry.dirty);
-        registry.save(&mocked_deps).unwrap();
+    fn test_reserve() {
+        let mocked_deps = Unimock::new(choose_port_mock());
+        let mut registry = get_mocked_registry().unwrap();
+        assert_eq!(
+            registry
+                .reserve(3004, Some(String::from("postgres")))
+                .unwrap(),
+            Reservation {
+                port: 3004,
+                description: Some(String::from("postgres")),
+            }
+        );
+        assert!(matches!(
+            registry.reserve(3004, None),
+            Err(ApplicationError::DuplicateReservation(3004)),
+        ));
+        assert!(matches!(
+            registry.reserve(3000, None),
+            Err(ApplicationError::ReservedPortInUse(3000, name)) if name == "app2",
+        ));
+        assert!(matches!(
+            registry.reserve(3001, None),
+            Err(ApplicationError::ReservedPortInUse(3001, name)) if name == "app1",
+        ));
+        let project = registry
+            .create(
+                &mocked_deps,
+                "app4",
+                None,
+                None,
+                None,
+                BTreeSet::new(),
+                None,
+            )
+            .unwrap();
+        assert_eq!(project.port, 3005);
+        assert!(registry.dirty);
     }
 
     #[test]
-    fn test_save_caddy_read_failure() {
-        let mocked_deps = Unimock::new((
-            data_dir_mock(),
-            ReadFileMock
-                .each_call(matching!((path) if path == &PathBuf::from("/homebrew/etc/Caddyfile")))
-                .answers(&|_, _| Err(Error::from(ErrorKind::PermissionDenied)))
-                .once(),
-            read_var_mock(),
-            write_file_mock(),
-        ));
-        let mut registry = get_mocked_registry().unwrap();
-        registry.dirty = true;
-        let err = registry.save(&mocked_deps).unwrap_err();
-        assert!(matches!(err, ApplicationError::Caddy(_)));
+    fn test_save_dry_run() {
+        let mocked_deps = Unimock::new(());
+        let mut registry = get_mocked_registry().unwrap().with_dry_run(true);
+        registry.delete("app1").unwrap();
+        assert!(registry.dirty);
+        registry.save(&mocked_deps).unwrap();
     }
 
     #[test]
-    fn test_save_caddy_write_portman_caddyfile_failure() {
+    fn test_save_no_reload() {
         let mocked_deps = Unimock::new((
             data_dir_mock(),
+            write_checksum_mock(),
+            write_index_mock(),
             dependencies::WriteFileMock
                 .each_call(matching!((path, _) if path == &PathBuf::from("/data/registry.toml")))
                 .answers(&|_, _, _| Ok(()))
                 .once(),
-            dependencies::WriteFileMock
-                .each_call(matching!((path, _) if path == &PathBuf::from("/data/Caddyfile")))
-                .answers(&|_, _, _| bail!("Error writing"))
-                .once(),
         ));
-        let mut registry = get_mocked_registry().unwrap();
-        registry.dirty = true;
-        let err = registry.save(&mocked_deps).unwrap_err();
-        assert!(matches!(err, ApplicationError::Caddy(_)));
+        let mut registry = get_mocked_registry().unwrap().with_auto_reload(false);
+        registry.delete("app1").unwrap();
+        registry.save(&mocked_deps).unwrap();
     }
 
     #[test]
-    fn test_save_caddy_write_root_caddyfile_failure() {
-        let mocked_deps = Unimock::new((
-            data_dir_mock(),
-            read_caddyfile_mock(),
-            read_var_mock(),
-            dependencies::WriteFileMock
-                .each_call(matching!((path, _) if path == &PathBuf::from("/data/registry.toml") || path == &PathBuf::from("/data/Caddyfile")))
-                .answers(&|_, _, _| Ok(()))
-                .n_times(2),
-            dependencies::WriteFileMock
-                .each_call(
-                    matching!((path, _) if path == &PathBuf::from("/homebrew/etc/Caddyfile")),
-                )
-                .answers(&|_, _, _| bail!("Error writing"))
-                .once(),
-        ));
+    fn test_unreserve() {
         let mut registry = get_mocked_registry().unwrap();
-        registry.dirty = true;
-        let err = registry.save(&mocked_deps).unwrap_err();
-        assert!(matches!(err, ApplicationError::Caddy(_)));
+        registry.reserve(5432, None).unwrap();
+        assert_eq!(registry.unreserve(5432).unwrap().port, 5432);
+        assert_eq!(registry.iter_reservations().count(), 0);
+        assert!(matches!(
+            registry.unreserve(5432),
+            Err(ApplicationError::NonExistentReservation(5432)),
+        ));
     }
 
     #[test]
-    fn test_save_caddy_exec_failure() {
-        let mocked_deps = Unimock::new((
-            data_dir_mock(),
-            read_caddyfile_mock(),
-            read_var_mock(),
-            write_file_mock(),
-            dependencies::ExecMock
-                .each_call(matching!((command) if command.get_program() == "caddy"))
-                .answers(&|_, _| Err(Error::from(ErrorKind::NotFound)))
-                .once(),
-        ));
+    fn test_unarchive_duplicate() {
+        let mocked_deps = Unimock::new(());
         let mut registry = get_mocked_registry().unwrap();
-        registry.dirty = true;
-        let err = registry.save(&mocked_deps).unwrap_err();
-        assert!(matches!(err, ApplicationError::Caddy(_)));
+        let project = registry.get("app1").unwrap().clone();
+        assert!(matches!(
+            registry.unarchive(&mocked_deps, "app1", project),
+            Err(ApplicationError::DuplicateProject(_)),
+        ));
     }
 
     #[test]
-    fn test_get() {
-        let registry = get_mocked_registry().unwrap();
-        assert_eq!(registry.get("app1").unwrap().port, 3001);
-        assert!(registry.get("app4").is_none());
+    fn test_delete_many() {
+        let mut registry = get_mocked_registry().unwrap();
+        let deleted_projects = registry
+            .delete_many(vec![String::from("app1"), String::from("app2")])
+            .unwrap()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            deleted_projects,
+            vec![String::from("app1"), String::from("app2")],
+        );
+        assert_eq!(registry.projects.keys().collect::<Vec<_>>(), vec!["app3"]);
     }
 
     #[test]
-    fn test_create() {
-        let mocked_deps = Unimock::new(choose_port_mock());
+    fn test_delete_many_none() {
         let mut registry = get_mocked_registry().unwrap();
-        registry.create(&mocked_deps, "app4", None, None).unwrap();
-        assert!(registry.get("app4").is_some());
-        assert!(registry.dirty);
+        assert!(registry.delete_many(vec![]).unwrap().is_empty());
+        assert!(!registry.dirty);
     }
 
     #[test]
-    fn test_create_invalid_name() {
-        let mocked_deps = Unimock::new(());
+    fn test_handoff() {
+        let mocked_deps = Unimock::new(choose_port_mock());
         let mut registry = get_mocked_registry().unwrap();
-        let err = registry
-            .create(&mocked_deps, "App3", None, None)
-            .unwrap_err();
-        assert!(matches!(err, ApplicationError::InvalidProjectName(_, _)));
-        assert!(!registry.dirty);
+        let (old_port, project) = registry.handoff(&mocked_deps, "app1", None).unwrap();
+        assert_eq!(old_port, 3001);
+        assert_eq!(project.port, 3004);
+        assert_eq!(project.linked_port, Some(3001));
+        assert!(registry.dirty);
     }
 
     #[test]
-    fn test_create_duplicate_name() {
+    fn test_handoff_to() {
         let mocked_deps = Unimock::new(());
         let mut registry = get_mocked_registry().unwrap();
-        let err = registry
-            .create(&mocked_deps, "app3", None, None)
-            .unwrap_err();
-        assert!(matches!(err, ApplicationError::DuplicateProject(_)));
-        assert!(!registry.dirty);
+        let (_, project) = registry.handoff(&mocked_deps, "app1", Some(3010)).unwrap();
+        assert_eq!(project.port, 3010);
+        assert!(matches!(
+            registry.handoff(&mocked_deps, "app3", Some(3010)),
+            Err(ApplicationError::UnavailablePort(3010)),
+        ));
     }
 
     #[test]
-    fn test_create_linked_port() {
+    fn test_add_named_ports() {
         let mocked_deps = Unimock::new(choose_port_mock());
         let mut registry = get_mocked_registry().unwrap();
-        registry
-            .create(&mocked_deps, "app4", None, Some(3100))
+        let project = registry
+            .add_named_ports(&mocked_deps, "app1", vec![String::from("api")])
             .unwrap();
-        assert_eq!(registry.get("app4").unwrap().linked_port.unwrap(), 3100);
+        assert_eq!(project.port, 3001);
+        assert_eq!(project.ports, BTreeMap::from([(String::from("api"), 3004)]));
         assert!(registry.dirty);
+
+        // Existing named ports keep their port
+        let project = registry
+            .add_named_ports(
+                &mocked_deps,
+                "app1",
+                vec![String::from("api"), String::from("web")],
+            )
+            .unwrap();
+        assert_eq!(
+            project.ports,
+            BTreeMap::from([(String::from("api"), 3004), (String::from("web"), 3005)])
+        );
     }
 
     #[test]
-    fn test_create_linked_port_reallocates_previous() {
-        let mocked_deps = Unimock::new(choose_port_mock());
+    fn test_add_named_ports_invalid_name() {
+        let mocked_deps = Unimock::new(());
         let mut registry = get_mocked_registry().unwrap();
-        registry
-            .create(&mocked_deps, "app4", None, Some(3001))
-            .unwrap();
-        assert_eq!(registry.get("app1").unwrap().port, 3005);
-        assert!(registry.dirty);
+        assert!(matches!(
+            registry.add_named_ports(&mocked_deps, "app1", vec![String::from("API")]),
+            Err(ApplicationError::InvalidProjectName(_, _)),
+        ));
     }
 
     #[test]
-    fn test_create_linked_port_discards_current() {
-        let mocked_deps = Unimock::new(choose_port_mock());
+    fn test_add_domain() {
         let mut registry = get_mocked_registry().unwrap();
         let project = registry
-            .create(&mocked_deps, "app4", None, Some(3004))
+            .add_domain("app1", String::from("app1.test"))
             .unwrap();
-        assert_eq!(project.port, 3005);
-        assert_eq!(registry.get("app4").unwrap().port, 3005);
+        assert_eq!(project.domains, BTreeSet::from([String::from("app1.test")]));
         assert!(registry.dirty);
+        assert!(matches!(
+            registry.add_domain("app2", String::from("app1.test")),
+            Err(ApplicationError::DuplicateDomain(domain, owner)) if domain == "app1.test" && owner == "app1",
+        ));
     }
 
     #[test]
-    fn test_create_duplicate_directory() {
-        let mocked_deps = Unimock::new(());
+    fn test_add_domain_invalid() {
         let mut registry = get_mocked_registry().unwrap();
-        let result = registry.create(
-            &mocked_deps,
-            "app4",
-            Some(PathBuf::from("/projects/app3")),
-            None,
-        );
+        for domain in ["", "app1..test", "-app1.test", "App1.test", "app1.test/"] {
+            assert!(matches!(
+                registry.add_domain("app1", String::from(domain)),
+                Err(ApplicationError::InvalidDomain(_, _)),
+            ));
+        }
+    }
+
+    #[test]
+    fn test_remove_domain() {
+        let mut registry = get_mocked_registry().unwrap();
+        registry
+            .add_domain("app1", String::from("app1.test"))
+            .unwrap();
+        let project = registry.remove_domain("app1", "app1.test").unwrap();
+        assert!(project.domains.is_empty());
         assert!(matches!(
-            result,
-            Err(ApplicationError::DuplicateDirectory(_, _)),
+            registry.remove_domain("app1", "app1.test"),
+            Err(ApplicationError::NonExistentDomain(_, _)),
         ));
-        assert!(!registry.dirty);
     }
 
     #[test]
-    fn test_update() {
+    fn test_add_forward() {
         let mut registry = get_mocked_registry().unwrap();
-        let path = PathBuf::from("/projects/app2");
-        assert_eq!(
-            registry
-                .update("app2", Some(path.clone()))
-                .unwrap()
-                .directory
-                .unwrap(),
-            path,
-        );
-        assert_eq!(
-            registry.get("app2").unwrap().directory.as_ref().unwrap(),
-            &path,
-        );
+        let forward = PortForward {
+            listen_port: 5432,
+            port_name: None,
+            protocol: ForwardProtocol::Tcp,
+        };
+        let project = registry.add_forward("app1", forward.clone()).unwrap();
+        assert_eq!(project.forwards, vec![forward.clone()]);
+        assert_eq!(project.forward_target(&forward), Some(3001));
         assert!(registry.dirty);
+        assert!(matches!(
+            registry.add_forward("app2", forward.clone()),
+            Err(ApplicationError::DuplicateForward(5432, owner)) if owner == "app1",
+        ));
+        // The same port can be forwarded for a different protocol
+        registry
+            .add_forward(
+                "app2",
+                PortForward {
+                    protocol: ForwardProtocol::Udp,
+                    ..forward
+                },
+            )
+            .unwrap();
     }
 
     #[test]
-    fn test_update_same() {
+    fn test_add_forward_conflicts() {
         let mut registry = get_mocked_registry().unwrap();
-        let path = PathBuf::from("/projects/app3");
-        assert_eq!(
-            registry
-                .update("app3", Some(path.clone()))
-                .unwrap()
-                .directory
-                .unwrap(),
-            path,
-        );
-        assert_eq!(
-            registry.get("app3").unwrap().directory.as_ref().unwrap(),
-            &path,
-        );
-        assert!(!registry.dirty);
+        for (listen_port, owner) in [(3002, "app2"), (3000, "app2")] {
+            assert!(matches!(
+                registry.add_forward(
+                    "app1",
+                    PortForward {
+                        listen_port,
+                        port_name: None,
+                        protocol: ForwardProtocol::Tcp,
+                    }
+                ),
+                Err(ApplicationError::DuplicateForward(port, existing_owner)) if port == listen_port && existing_owner == owner,
+            ));
+        }
+        assert!(matches!(
+            registry.add_forward(
+                "app1",
+                PortForward {
+                    listen_port: 5432,
+                    port_name: Some(String::from("db")),
+                    protocol: ForwardProtocol::Tcp,
+                }
+            ),
+            Err(ApplicationError::NonExistentPortName(_, _)),
+        ));
     }
 
     #[test]
-    fn test_update_nonexistent() {
+    fn test_remove_forward() {
         let mut registry = get_mocked_registry().unwrap();
-        let err = registry.update("app4", None).unwrap_err();
-        assert!(matches!(err, ApplicationError::NonExistentProject(_)));
+        registry
+            .add_forward(
+                "app1",
+                PortForward {
+                    listen_port: 5432,
+                    port_name: None,
+                    protocol: ForwardProtocol::Tcp,
+                },
+            )
+            .unwrap();
+        assert!(matches!(
+            registry.remove_forward("app1", 5432, ForwardProtocol::Udp),
+            Err(ApplicationError::NonExistentForward(_, 5432)),
+        ));
+        let project = registry
+            .remove_forward("app1", 5432, ForwardProtocol::Tcp)
+            .unwrap();
+        assert!(project.forwards.is_empty());
     }
 
     #[test]
-    fn test_delete() {
+    fn test_add_directory() {
         let mut registry = get_mocked_registry().unwrap();
-        registry.delete("app2").unwrap();
-        assert!(registry.get("app2").is_none());
+        let review = PathBuf::from("/projects/app3-review");
+        let project = registry.add_directory("app3", review.clone()).unwrap();
+        assert_eq!(project.directories, vec![review.clone()]);
+        assert!(registry.dirty);
+        assert_eq!(
+            registry.match_directory(&review, || None).unwrap().0,
+            "app3"
+        );
+
+        // Projects without a directory get a primary directory
+        let project = registry
+            .add_directory("app1", PathBuf::from("/projects/app1"))
+            .unwrap();
+        assert_eq!(project.directory, Some(PathBuf::from("/projects/app1")));
+        assert!(project.directories.is_empty());
+
+        assert!(matches!(
+            registry.add_directory("app2", review),
+            Err(ApplicationError::DuplicateDirectory(owner, _)) if owner == "app3",
+        ));
     }
 
     #[test]
-    fn test_delete_nonexistent() {
+    fn test_move_directory() {
         let mut registry = get_mocked_registry().unwrap();
-        let err = registry.delete("app4").unwrap_err();
-        assert!(matches!(err, ApplicationError::NonExistentProject(_)));
+        let moved = PathBuf::from("/projects/app3-moved");
+        let project = registry.move_directory("app3", moved.clone()).unwrap();
+        assert_eq!(project.directory, Some(moved.clone()));
+        assert!(registry.dirty);
+        assert!(registry
+            .match_directory(Path::new("/projects/app3"), || None)
+            .is_none());
+
+        // Moving to the current directory doesn't change anything
+        registry.dirty = false;
+        registry.move_directory("app3", moved.clone()).unwrap();
+        assert!(!registry.dirty);
+
+        assert!(matches!(
+            registry.move_directory("app1", moved),
+            Err(ApplicationError::DuplicateDirectory(owner, _)) if owner == "app3",
+        ));
     }
 
     #[test]
-    fn test_delete_many() {
+    fn test_move_directory_additional() {
         let mut registry = get_mocked_registry().unwrap();
-        let deleted_projects = registry
-            .delete_many(vec![String::from("app1"), String::from("app2")])
-            .unwrap()
-            .into_iter()
-            .map(|(name, _)| name)
-            .collect::<Vec<_>>();
-        assert_eq!(
-            deleted_projects,
-            vec![String::from("app1"), String::from("app2")],
-        );
-        assert_eq!(registry.projects.keys().collect::<Vec<_>>(), vec!["app3"]);
+        let review = PathBuf::from("/projects/app3-review");
+        registry.add_directory("app3", review.clone()).unwrap();
+        let project = registry.move_directory("app3", review.clone()).unwrap();
+        assert_eq!(project.directory, Some(review));
+        assert!(project.directories.is_empty());
     }
 
     #[test]
-    fn test_delete_many_none() {
+    fn test_remove_directory() {
         let mut registry = get_mocked_registry().unwrap();
-        assert!(registry.delete_many(vec![]).unwrap().is_empty());
-        assert!(!registry.dirty);
+        let review = PathBuf::from("/projects/app3-review");
+        registry.add_directory("app3", review.clone()).unwrap();
+        let project = registry
+            .remove_directory("app3", Path::new("/projects/app3"))
+            .unwrap();
+        assert_eq!(project.directory, Some(review));
+        assert!(project.directories.is_empty());
+        assert!(matches!(
+            registry.remove_directory("app3", Path::new("/projects/app3")),
+            Err(ApplicationError::NonExistentDirectory(_, _)),
+        ));
     }
 
     #[test]
     fn test_link_create() {
         let mocked_deps = Unimock::new(());
         let mut registry = get_mocked_registry().unwrap();
-        registry.link(&mocked_deps, "app2", 3005).unwrap();
+        registry.link(&mocked_deps, "app2", None, 3005).unwrap();
         assert_eq!(registry.get("app2").unwrap().linked_port.unwrap(), 3005);
         assert!(registry.dirty);
     }
@@ -784,7 +4381,7 @@ linked_port = 3000",
     fn test_link_change() {
         let mocked_deps = Unimock::new(());
         let mut registry = get_mocked_registry().unwrap();
-        registry.link(&mocked_deps, "app2", 3100).unwrap();
+        registry.link(&mocked_deps, "app2", None, 3100).unwrap();
         assert_eq!(registry.get("app2").unwrap().linked_port.unwrap(), 3100);
         assert!(registry.dirty);
     }
@@ -793,7 +4390,7 @@ linked_port = 3000",
     fn test_link_move() {
         let mocked_deps = Unimock::new(());
         let mut registry = get_mocked_registry().unwrap();
-        registry.link(&mocked_deps, "app3", 3000).unwrap();
+        registry.link(&mocked_deps, "app3", None, 3000).unwrap();
         assert!(registry.get("app2").unwrap().linked_port.is_none());
         assert_eq!(registry.get("app3").unwrap().linked_port.unwrap(), 3000);
         assert!(registry.dirty);
@@ -803,7 +4400,7 @@ linked_port = 3000",
     fn test_link_noop() {
         let mocked_deps = Unimock::new(());
         let mut registry = get_mocked_registry().unwrap();
-        registry.link(&mocked_deps, "app2", 3000).unwrap();
+        registry.link(&mocked_deps, "app2", None, 3000).unwrap();
         assert!(!registry.dirty);
     }
 
@@ -811,7 +4408,7 @@ linked_port = 3000",
     fn test_link_nonexistent() {
         let mocked_deps = Unimock::new(());
         let mut registry = get_mocked_registry().unwrap();
-        let err = registry.link(&mocked_deps, "app4", 3004).unwrap_err();
+        let err = registry.link(&mocked_deps, "app4", None, 3004).unwrap_err();
         assert!(matches!(err, ApplicationError::NonExistentProject(_)));
         assert!(!registry.dirty);
     }
@@ -820,7 +4417,7 @@ linked_port = 3000",
     fn test_link_reallocates_previous() {
         let mocked_deps = Unimock::new(choose_port_mock());
         let mut registry = get_mocked_registry().unwrap();
-        registry.link(&mocked_deps, "app2", 3001).unwrap();
+        registry.link(&mocked_deps, "app2", None, 3001).unwrap();
         assert_ne!(registry.get("app1").unwrap().port, 3001);
         assert_eq!(registry.get("app2").unwrap().linked_port.unwrap(), 3001);
         assert!(registry.dirty);
@@ -830,7 +4427,7 @@ linked_port = 3000",
     fn test_link_reallocates_self() {
         let mocked_deps = Unimock::new(choose_port_mock());
         let mut registry = get_mocked_registry().unwrap();
-        registry.link(&mocked_deps, "app1", 3001).unwrap();
+        registry.link(&mocked_deps, "app1", None, 3001).unwrap();
         assert_ne!(registry.get("app1").unwrap().port, 3001);
         assert_eq!(registry.get("app1").unwrap().linked_port.unwrap(), 3001);
         assert!(registry.dirty);
@@ -843,6 +4440,77 @@ linked_port = 3000",
         assert!(registry.dirty);
     }
 
+    #[test]
+    fn test_link_named_port() {
+        let mocked_deps = Unimock::new(());
+        let mut registry = get_mocked_registry().unwrap();
+        registry
+            .projects
+            .get_mut("app2")
+            .unwrap()
+            .ports
+            .insert(String::from("api"), 3005);
+        registry
+            .link(&mocked_deps, "app2", Some("api"), 8000)
+            .unwrap();
+        let project = registry.get("app2").unwrap();
+        assert_eq!(project.linked_port.unwrap(), 3000);
+        assert_eq!(project.linked_ports.get("api").copied(), Some(8000));
+        assert!(registry.dirty);
+    }
+
+    #[test]
+    fn test_link_named_port_move() {
+        let mocked_deps = Unimock::new(());
+        let mut registry = get_mocked_registry().unwrap();
+        registry
+            .projects
+            .get_mut("app2")
+            .unwrap()
+            .ports
+            .insert(String::from("api"), 3005);
+        registry
+            .link(&mocked_deps, "app2", Some("api"), 3000)
+            .unwrap();
+        let project = registry.get("app2").unwrap();
+        assert!(project.linked_port.is_none());
+        assert_eq!(project.linked_ports.get("api").copied(), Some(3000));
+
+        registry.link(&mocked_deps, "app3", None, 3000).unwrap();
+        assert!(registry.get("app2").unwrap().linked_ports.is_empty());
+        assert_eq!(registry.get("app3").unwrap().linked_port.unwrap(), 3000);
+    }
+
+    #[test]
+    fn test_link_nonexistent_port_name() {
+        let mocked_deps = Unimock::new(());
+        let mut registry = get_mocked_registry().unwrap();
+        let err = registry
+            .link(&mocked_deps, "app2", Some("api"), 8000)
+            .unwrap_err();
+        assert!(matches!(err, ApplicationError::NonExistentPortName(_, _)));
+        assert!(!registry.dirty);
+    }
+
+    #[test]
+    fn test_unlink_named_port() {
+        let mocked_deps = Unimock::new(());
+        let mut registry = get_mocked_registry().unwrap();
+        registry
+            .projects
+            .get_mut("app2")
+            .unwrap()
+            .ports
+            .insert(String::from("api"), 3005);
+        registry
+            .link(&mocked_deps, "app2", Some("api"), 8000)
+            .unwrap();
+        assert_eq!(registry.unlink(8000).unwrap(), String::from("app2"));
+        let project = registry.get("app2").unwrap();
+        assert!(project.linked_ports.is_empty());
+        assert_eq!(project.linked_port.unwrap(), 3000);
+    }
+
     #[test]
     fn test_unlink_not_linked() {
         let mut registry = get_mocked_registry().unwrap();
@@ -861,6 +4529,17 @@ linked_port = 3000",
         );
     }
 
+    #[test]
+    fn test_get_repo_port_equivalent_url() {
+        let registry = get_mocked_registry().unwrap();
+        assert_eq!(
+            registry
+                .get_repo_port("git@github.com:user/app3.git")
+                .unwrap(),
+            3004
+        );
+    }
+
     #[test]
     fn test_get_repo_port_nonexistent() {
         let registry = get_mocked_registry().unwrap();
@@ -876,7 +4555,7 @@ linked_port = 3000",
         let repo = "https://github.com/user/project.git";
         registry.set_repo_port(repo.to_owned(), 3005);
         assert!(registry.dirty);
-        assert_eq!(registry.repos.get(repo).unwrap(), &3005);
+        assert_eq!(registry.repos.get(repo).unwrap().port, 3005);
     }
 
     #[test]
@@ -885,7 +4564,201 @@ linked_port = 3000",
         let repo = "https://github.com/user/app3.git";
         registry.set_repo_port(repo.to_owned(), 3004);
         assert!(!registry.dirty);
-        assert_eq!(registry.repos.get(repo).unwrap(), &3004);
+        assert_eq!(registry.repos.get(repo).unwrap().port, 3004);
+    }
+
+    #[test]
+    fn test_set_repo_port_equivalent_url() {
+        let mut registry = get_mocked_registry().unwrap();
+        registry.set_repo_port(String::from("git@github.com:user/app3.git"), 3005);
+        assert!(registry.dirty);
+        assert_eq!(registry.repos.len(), 1);
+        assert_eq!(
+            registry
+                .repos
+                .get("https://github.com/user/app3.git")
+                .unwrap()
+                .port,
+            3005
+        );
+    }
+
+    #[test]
+    fn test_set_repo_match() {
+        let mut registry = get_mocked_registry().unwrap();
+        let project = registry
+            .set_repo_match("app1", Some(String::from("git@github.com:user/app1.git")))
+            .unwrap();
+        assert_eq!(
+            project.repo.as_deref(),
+            Some("git@github.com:user/app1.git")
+        );
+        assert!(registry.dirty);
+        assert_eq!(
+            registry
+                .match_repo("https://github.com/user/app1")
+                .unwrap()
+                .0,
+            "app1"
+        );
+        assert!(registry
+            .match_repo("https://github.com/user/app2.git")
+            .is_none());
+
+        let err = registry
+            .set_repo_match(
+                "app2",
+                Some(String::from("https://github.com/user/app1.git")),
+            )
+            .unwrap_err();
+        assert!(matches!(err, ApplicationError::DuplicateRepoMatch(name, _) if name == "app1"));
+    }
+
+    #[test]
+    fn test_set_repo_metadata() {
+        let mut registry = get_mocked_registry().unwrap();
+        let repo = "https://github.com/user/app3.git";
+        let entry = registry
+            .set_repo_metadata(
+                repo,
+                RepoUpdate {
+                    description: Some(String::from("The app")),
+                    default_project_name: Some(String::from("app")),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(entry.description.unwrap(), "The app");
+        assert_eq!(entry.default_project_name.unwrap(), "app");
+        assert!(registry.dirty);
+
+        // Setting the port preserves the metadata
+        registry.set_repo_port(repo.to_owned(), 3005);
+        let entry = registry.get_repo(repo).unwrap();
+        assert_eq!(entry.port, 3005);
+        assert_eq!(entry.description.as_deref(), Some("The app"));
+
+        let entry = registry
+            .set_repo_metadata(
+                repo,
+                RepoUpdate {
+                    description: Some(String::new()),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert!(entry.description.is_none());
+        assert_eq!(entry.default_project_name.unwrap(), "app");
+    }
+
+    #[test]
+    fn test_set_repo_metadata_ports() {
+        let mut registry = get_mocked_registry().unwrap();
+        let repo = "git@github.com:user/app3.git";
+        let entry = registry
+            .set_repo_metadata(
+                repo,
+                RepoUpdate {
+                    port: Some(3010),
+                    project_port: Some(3500),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(entry.port, 3010);
+        assert_eq!(entry.project_port, Some(3500));
+        assert_eq!(registry.get_repo_port(repo).unwrap(), 3010);
+
+        let entry = registry
+            .set_repo_metadata(
+                repo,
+                RepoUpdate {
+                    project_port: Some(0),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(entry.port, 3010);
+        assert!(entry.project_port.is_none());
+    }
+
+    #[test]
+    fn test_set_repo_metadata_linked_ports() {
+        let mut registry = get_mocked_registry().unwrap();
+        let repo = "git@github.com:user/app3.git";
+        let entry = registry
+            .set_repo_metadata(
+                repo,
+                RepoUpdate {
+                    linked_ports: vec![(String::from("api"), 8000), (String::from("web"), 3000)],
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            entry.linked_ports.into_iter().collect::<Vec<_>>(),
+            vec![(String::from("api"), 8000), (String::from("web"), 3000)]
+        );
+
+        let entry = registry
+            .set_repo_metadata(
+                repo,
+                RepoUpdate {
+                    linked_ports: vec![(String::from("web"), 0)],
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            entry.linked_ports.into_iter().collect::<Vec<_>>(),
+            vec![(String::from("api"), 8000)]
+        );
+    }
+
+    #[test]
+    fn test_set_repo_metadata_invalid_port() {
+        let mut registry = get_mocked_registry().unwrap();
+        let err = registry
+            .set_repo_metadata(
+                "https://github.com/user/app3.git",
+                RepoUpdate {
+                    project_port: Some(80),
+                    ..Default::default()
+                },
+            )
+            .unwrap_err();
+        assert!(matches!(err, ApplicationError::OutOfRangePort(80)));
+        assert!(!registry.dirty);
+    }
+
+    #[test]
+    fn test_set_repo_metadata_invalid_name() {
+        let mut registry = get_mocked_registry().unwrap();
+        let err = registry
+            .set_repo_metadata(
+                "https://github.com/user/app3.git",
+                RepoUpdate {
+                    default_project_name: Some(String::from("app_3")),
+                    ..Default::default()
+                },
+            )
+            .unwrap_err();
+        assert!(matches!(err, ApplicationError::InvalidProjectName(_, _)));
+        assert!(!registry.dirty);
+    }
+
+    #[test]
+    fn test_repo_serialization() {
+        let data: RegistryData = toml::from_str(
+            "[projects]\n\n[repos]\n\"a\" = 3000\n\n[repos.b]\nport = 3001\ndescription = \"B\"\n",
+        )
+        .unwrap();
+        assert_eq!(data.repos["a"].port, 3000);
+        assert_eq!(data.repos["b"].description.as_deref(), Some("B"));
+        assert_eq!(
+            toml::to_string(&data).unwrap(),
+            "[projects]\n\n[repos]\na = 3000\n\n[repos.b]\nport = 3001\ndescription = \"B\"\n"
+        );
     }
 
     #[test]
@@ -912,6 +4785,38 @@ linked_port = 3000",
         assert_eq!(registry.match_cwd(&mocked_deps).unwrap().unwrap().0, "app3");
     }
 
+    #[test]
+    fn test_match_cwd_branch() {
+        let mut registry = get_mocked_registry().unwrap();
+        registry
+            .create(
+                &Unimock::new(choose_port_mock()),
+                "app3-feature",
+                None,
+                Some(PathBuf::from("/projects/app3")),
+                None,
+                BTreeSet::new(),
+                Some(String::from("feature")),
+            )
+            .unwrap();
+        let match_branch = |branch: &'static str| {
+            let mocked_deps = Unimock::new((
+                cwd_mock("app3"),
+                dependencies::ExecMock
+                    .each_call(matching!((command) if command.get_program() == "git"))
+                    .answers_arc(Arc::new(move |_, _| {
+                        Ok(dependencies::ExecStatus::Success {
+                            output: format!("{branch}\n"),
+                        })
+                    }))
+                    .once(),
+            ));
+            registry.match_cwd(&mocked_deps).unwrap().unwrap().0.clone()
+        };
+        assert_eq!(match_branch("feature"), "app3-feature");
+        assert_eq!(match_branch("main"), "app3");
+    }
+
     #[test]
     fn test_normalize_name() {
         assert_eq!(
@@ -0,0 +1,267 @@
+use crate::dependencies::Exec;
+use crate::error::{ApplicationError, Result};
+use std::path::Path;
+use std::process::Command;
+
+// Return the URL of a git remote in the current directory
+fn remote_url(deps: &impl Exec, remote: &str) -> Result<String> {
+    let url = deps
+        .exec(Command::new("git").args(["remote", "get-url", remote]))
+        .map_err(ApplicationError::GitCommand)?;
+    Ok(url.trim_end().to_owned())
+}
+
+// Return the name of the remote that the current branch tracks, if any
+// "@{upstream}" is git revision syntax, not a format string
+#[allow(clippy::literal_string_with_formatting_args)]
+fn upstream_remote(deps: &impl Exec) -> Option<String> {
+    let upstream = deps
+        .exec(Command::new("git").args([
+            "rev-parse",
+            "--abbrev-ref",
+            "--symbolic-full-name",
+            "@{upstream}",
+        ]))
+        .ok()?;
+    upstream
+        .trim()
+        .split_once('/')
+        .map(|(remote, _)| remote.to_owned())
+}
+
+// Return the name of the first remote configured in the current directory, if any
+fn first_remote(deps: &impl Exec) -> Option<String> {
+    deps.exec(Command::new("git").arg("remote"))
+        .ok()?
+        .lines()
+        .map(str::trim)
+        .find(|remote| !remote.is_empty())
+        .map(str::to_owned)
+}
+
+// Return the URL of the git repo in the current directory
+// If no remote is provided, use the origin remote, falling back to the remote that the current
+// branch tracks and then to the first configured remote
+pub fn get_active_repo(deps: &impl Exec, remote: Option<&str>) -> Result<String> {
+    if let Some(remote) = remote {
+        return remote_url(deps, remote);
+    }
+    let err = match remote_url(deps, "origin") {
+        Ok(url) => return Ok(url),
+        Err(err) => err,
+    };
+    upstream_remote(deps)
+        .or_else(|| first_remote(deps))
+        .filter(|remote| remote != "origin")
+        .map_or(Err(err), |remote| remote_url(deps, &remote))
+}
+
+// Return the URL of the git repo checked out in the current directory if the current directory is
+// the root of the checkout
+pub fn get_checkout_repo(deps: &impl Exec, cwd: &Path) -> Option<String> {
+    let root = deps
+        .exec(Command::new("git").args(["rev-parse", "--show-toplevel"]))
+        .ok()?;
+    if Path::new(root.trim_end()) != cwd {
+        return None;
+    }
+    get_active_repo(deps, None).ok()
+}
+
+// Normalize a git remote URL so that the different URLs that refer to the same repo are equal
+// For example, git@github.com:user/app.git and https://github.com/User/app normalize to
+// github.com/user/app
+pub fn canonicalize(url: &str) -> String {
+    let url = url.trim().trim_end_matches('/');
+
+    // Strip the protocol
+    let (url, has_protocol) = match url.split_once("://") {
+        Some((_, rest)) => (rest, true),
+        None => (url, false),
+    };
+
+    // Strip the user
+    let url = match url.split_once('@') {
+        Some((user, rest)) if !user.contains('/') => rest,
+        _ => url,
+    };
+
+    // The scp-like syntax separates the host from the path with a colon instead of a slash
+    let url = match url.find(':') {
+        Some(index) if !has_protocol && !url[..index].contains('/') => {
+            format!("{}/{}", &url[..index], &url[index + 1..])
+        }
+        _ => url.to_owned(),
+    };
+
+    let url = url.trim_end_matches('/');
+    url.strip_suffix(".git")
+        .unwrap_or(url)
+        .trim_end_matches('/')
+        .to_lowercase()
+}
+
+// Determine whether two git remote URLs refer to the same repo
+pub fn same_repo(url1: &str, url2: &str) -> bool {
+    canonicalize(url1) == canonicalize(url2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dependencies::{ExecMock, ExecStatus};
+    use std::sync::Arc;
+    use unimock::{matching, Clause, MockFn, Unimock};
+
+    // Mock a git command that fails unless it prints output
+    fn git_mock(args: &'static [&'static str], output: Option<&'static str>) -> impl Clause {
+        ExecMock
+            .each_call(matching!((command) if command.get_program() == "git" && command.get_args().eq(args.iter().copied())))
+            .answers_arc(Arc::new(move |_, _| {
+                Ok(output.map_or_else(
+                    || ExecStatus::Failure {
+                        output: String::from("error: No such remote"),
+                        code: 2,
+                    },
+                    |output| ExecStatus::Success {
+                        output: output.to_owned(),
+                    },
+                ))
+            }))
+            .once()
+    }
+
+    #[test]
+    fn test_get_active_repo_origin() {
+        let mocked_deps = Unimock::new(git_mock(
+            &["remote", "get-url", "origin"],
+            Some("git@github.com:user/app.git\n"),
+        ));
+        assert_eq!(
+            get_active_repo(&mocked_deps, None).unwrap(),
+            "git@github.com:user/app.git"
+        );
+    }
+
+    #[test]
+    fn test_get_active_repo_remote() {
+        let mocked_deps = Unimock::new(git_mock(
+            &["remote", "get-url", "fork"],
+            Some("git@github.com:fork/app.git\n"),
+        ));
+        assert_eq!(
+            get_active_repo(&mocked_deps, Some("fork")).unwrap(),
+            "git@github.com:fork/app.git"
+        );
+    }
+
+    #[test]
+    fn test_get_active_repo_upstream() {
+        let mocked_deps = Unimock::new((
+            git_mock(&["remote", "get-url", "origin"], None),
+            git_mock(
+                &[
+                    "rev-parse",
+                    "--abbrev-ref",
+                    "--symbolic-full-name",
+                    "@{upstream}",
+                ],
+                Some("upstream/main\n"),
+            ),
+            git_mock(
+                &["remote", "get-url", "upstream"],
+                Some("git@github.com:user/app.git\n"),
+            ),
+        ));
+        assert_eq!(
+            get_active_repo(&mocked_deps, None).unwrap(),
+            "git@github.com:user/app.git"
+        );
+    }
+
+    #[test]
+    fn test_get_active_repo_first_remote() {
+        let mocked_deps = Unimock::new((
+            git_mock(&["remote", "get-url", "origin"], None),
+            git_mock(
+                &[
+                    "rev-parse",
+                    "--abbrev-ref",
+                    "--symbolic-full-name",
+                    "@{upstream}",
+                ],
+                None,
+            ),
+            git_mock(&["remote"], Some("github\nfork\n")),
+            git_mock(
+                &["remote", "get-url", "github"],
+                Some("git@github.com:user/app.git\n"),
+            ),
+        ));
+        assert_eq!(
+            get_active_repo(&mocked_deps, None).unwrap(),
+            "git@github.com:user/app.git"
+        );
+    }
+
+    #[test]
+    fn test_get_active_repo_no_remotes() {
+        let mocked_deps = Unimock::new((
+            git_mock(&["remote", "get-url", "origin"], None),
+            git_mock(
+                &[
+                    "rev-parse",
+                    "--abbrev-ref",
+                    "--symbolic-full-name",
+                    "@{upstream}",
+                ],
+                None,
+            ),
+            git_mock(&["remote"], Some("")),
+        ));
+        assert!(matches!(
+            get_active_repo(&mocked_deps, None).unwrap_err(),
+            ApplicationError::GitCommand(_)
+        ));
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        for url in [
+            "https://github.com/user/app.git",
+            "https://github.com/user/app",
+            "https://github.com/user/app/",
+            "https://user@github.com/user/app.git",
+            "http://github.com/User/App.git",
+            "git@github.com:user/app.git",
+            "git@github.com:user/app",
+            "ssh://git@github.com/user/app.git",
+            "git://github.com/user/app.git",
+            "github.com:user/app.git\n",
+        ] {
+            assert_eq!(canonicalize(url), "github.com/user/app", "{url}");
+        }
+    }
+
+    #[test]
+    fn test_canonicalize_local() {
+        assert_eq!(canonicalize("/projects/app.git"), "/projects/app");
+        assert_eq!(canonicalize("file:///projects/app.git"), "/projects/app");
+    }
+
+    #[test]
+    fn test_same_repo() {
+        assert!(same_repo(
+            "git@github.com:user/app.git",
+            "https://github.com/user/app.git"
+        ));
+        assert!(!same_repo(
+            "git@github.com:user/app.git",
+            "git@github.com:user/app2.git"
+        ));
+        assert!(!same_repo(
+            "git@github.com:user/app.git",
+            "git@gitlab.com:user/app.git"
+        ));
+    }
+}
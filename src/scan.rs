@@ -0,0 +1,53 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+// The files and directories whose presence marks a directory as a project
+const PROJECT_MARKERS: [&str; 3] = [".git", "package.json", "Cargo.toml"];
+
+// Return the project directories among the paths found while walking a directory tree, in sorted
+// order
+// Directories nested inside another project, like packages in a monorepo, are not included
+pub fn find_projects(paths: &[PathBuf]) -> Vec<PathBuf> {
+    let candidates = paths
+        .iter()
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| PROJECT_MARKERS.contains(&name))
+        })
+        .filter_map(|path| path.parent().map(Path::to_path_buf))
+        .collect::<BTreeSet<_>>();
+    candidates
+        .iter()
+        .filter(|directory| {
+            !directory
+                .ancestors()
+                .skip(1)
+                .any(|ancestor| candidates.contains(ancestor))
+        })
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_projects() {
+        let paths = [
+            "/code/api/.git",
+            "/code/api/Cargo.toml",
+            "/code/api/src",
+            "/code/notes",
+            "/code/notes/todo.md",
+            "/code/web/package.json",
+            "/code/web/packages/ui/package.json",
+        ]
+        .map(PathBuf::from);
+        assert_eq!(
+            find_projects(&paths),
+            vec![PathBuf::from("/code/api"), PathBuf::from("/code/web")]
+        );
+    }
+}
@@ -0,0 +1,222 @@
+use crate::dependencies::{CurrentExe, Environment, Exec, WriteFile};
+use crate::error::{ApplicationError, Result};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// The arguments that the service passes to portman to run the daemon
+const SERVICE_ARGS: [&str; 1] = ["daemon"];
+
+// The environment variables that the service inherits from the installing shell
+const SERVICE_VARS: [&str; 4] = [
+    "PATH",
+    "HOMEBREW_PREFIX",
+    "PORTMAN_CONFIG",
+    "PORTMAN_DATA_DIR",
+];
+
+const LAUNCHD_LABEL: &str = "com.canac.portman";
+
+#[derive(Clone, Copy)]
+#[cfg_attr(test, derive(Debug))]
+pub enum Platform {
+    Launchd,
+    Systemd,
+}
+
+impl Platform {
+    // Return the service manager for the current operating system
+    pub const fn current() -> Option<Self> {
+        if cfg!(target_os = "macos") {
+            Some(Self::Launchd)
+        } else if cfg!(target_os = "linux") {
+            Some(Self::Systemd)
+        } else {
+            None
+        }
+    }
+
+    // Return the path to the service file
+    fn service_path(self, deps: &impl Environment) -> Result<PathBuf> {
+        let home = PathBuf::from(deps.read_var("HOME")?);
+        Ok(match self {
+            Self::Launchd => home
+                .join("Library")
+                .join("LaunchAgents")
+                .join(format!("{LAUNCHD_LABEL}.plist")),
+            Self::Systemd => deps
+                .read_var("XDG_CONFIG_HOME")
+                .map_or_else(|_| home.join(".config"), PathBuf::from)
+                .join("systemd")
+                .join("user")
+                .join("portman.service"),
+        })
+    }
+
+    // Return the command that unloads a previously installed service, if the service manager
+    // refuses to load a service that is already loaded
+    fn deactivate_command(self, service_path: &Path) -> Option<Command> {
+        match self {
+            Self::Launchd => {
+                let mut command = Command::new("launchctl");
+                command.arg("unload").arg(service_path);
+                Some(command)
+            }
+            Self::Systemd => None,
+        }
+    }
+
+    // Return the commands that load and start the service, restarting it if it is already running
+    fn activate_commands(self, service_path: &Path) -> Vec<Command> {
+        match self {
+            Self::Launchd => {
+                let mut command = Command::new("launchctl");
+                command.args(["load", "-w"]).arg(service_path);
+                vec![command]
+            }
+            Self::Systemd => {
+                // Reload the units so that systemd picks up changes to an existing unit
+                let mut reload = Command::new("systemctl");
+                reload.args(["--user", "daemon-reload"]);
+                let mut enable = Command::new("systemctl");
+                enable.args(["--user", "enable", "portman.service"]);
+                let mut restart = Command::new("systemctl");
+                restart.args(["--user", "restart", "portman.service"]);
+                vec![reload, enable, restart]
+            }
+        }
+    }
+}
+
+// Escape a string for use in an XML document
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+// Escape a string for use in a quoted systemd unit setting
+fn escape_systemd(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('%', "%%")
+}
+
+// Return the contents of the launchd plist
+fn generate_launchd_plist(exe: &Path, env: &[(&str, String)]) -> String {
+    let arguments = std::iter::once(exe.display().to_string())
+        .chain(SERVICE_ARGS.iter().map(|arg| (*arg).to_owned()))
+        .fold(String::new(), |mut output, arg| {
+            let _ = write!(output, "\n    <string>{}</string>", escape_xml(&arg));
+            output
+        });
+    let env = env.iter().fold(String::new(), |mut output, (name, value)| {
+        let _ = write!(
+            output,
+            "\n    <key>{name}</key>\n    <string>{}</string>",
+            escape_xml(value)
+        );
+        output
+    });
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+  <key>Label</key>
+  <string>{LAUNCHD_LABEL}</string>
+  <key>ProgramArguments</key>
+  <array>{arguments}
+  </array>
+  <key>EnvironmentVariables</key>
+  <dict>{env}
+  </dict>
+  <key>RunAtLoad</key>
+  <true/>
+  <key>KeepAlive</key>
+  <true/>
+</dict>
+</plist>
+"#
+    )
+}
+
+// Return the contents of the systemd user unit
+fn generate_systemd_unit(exe: &Path, env: &[(&str, String)]) -> String {
+    let env = env.iter().fold(String::new(), |mut output, (name, value)| {
+        let _ = write!(output, "\nEnvironment=\"{name}={}\"", escape_systemd(value));
+        output
+    });
+    format!(
+        "[Unit]\nDescription=portman background watcher\n\n[Service]\nExecStart=\"{}\" {}{env}\nRestart=on-failure\n\n[Install]\nWantedBy=default.target\n",
+        escape_systemd(&exe.display().to_string()),
+        SERVICE_ARGS.join(" ")
+    )
+}
+
+// Write the service file and activate the service, returning the path to the service file
+pub fn install(
+    deps: &(impl CurrentExe + Environment + Exec + WriteFile),
+    platform: Platform,
+) -> Result<PathBuf> {
+    let exe = deps.get_current_exe()?;
+    let env = SERVICE_VARS
+        .iter()
+        .filter_map(|name| deps.read_var(name).ok().map(|value| (*name, value)))
+        .collect::<Vec<_>>();
+    let contents = match platform {
+        Platform::Launchd => generate_launchd_plist(&exe, &env),
+        Platform::Systemd => generate_systemd_unit(&exe, &env),
+    };
+
+    let service_path = platform.service_path(deps)?;
+    deps.write_file(&service_path, &contents)?;
+    if let Some(mut command) = platform.deactivate_command(&service_path) {
+        // The service isn't loaded if this is the first time that it is installed
+        let _ = deps.exec(&mut command);
+    }
+    for mut command in platform.activate_commands(&service_path) {
+        deps.exec(&mut command)
+            .map_err(ApplicationError::ServiceCommand)?;
+    }
+    Ok(service_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env() -> Vec<(&'static str, String)> {
+        vec![
+            ("PATH", String::from("/usr/bin:/bin")),
+            ("HOMEBREW_PREFIX", String::from("/homebrew")),
+        ]
+    }
+
+    #[test]
+    fn test_launchd_plist() {
+        assert_eq!(
+            generate_launchd_plist(&PathBuf::from("/bin/portman"), &env()),
+            include_str!("snapshots/com.canac.portman.plist")
+        );
+    }
+
+    #[test]
+    fn test_escape_systemd() {
+        assert_eq!(
+            escape_systemd("100% \"quoted\" C:\\path\nline"),
+            "100%% \\\"quoted\\\" C:\\\\path\\nline"
+        );
+    }
+
+    #[test]
+    fn test_systemd_unit() {
+        assert_eq!(
+            generate_systemd_unit(&PathBuf::from("/bin/portman"), &env()),
+            include_str!("snapshots/portman.service")
+        );
+    }
+}
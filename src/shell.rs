@@ -0,0 +1,75 @@
+use crate::cli::InitShell;
+
+// Return the names of the variables that the shell integration exports for the active project
+// A prefix replaces the PORTMAN prefix, so the prefix MYAPP exports MYAPP_PORT, MYAPP_PROJECT, and
+// MYAPP_LINKED_PORT
+fn var_names(var_prefix: Option<&str>) -> [String; 3] {
+    var_prefix.map_or_else(
+        || {
+            [
+                String::from("PORT"),
+                String::from("PORTMAN_PROJECT"),
+                String::from("PORTMAN_LINKED_PORT"),
+            ]
+        },
+        |prefix| {
+            [
+                format!("{prefix}_PORT"),
+                format!("{prefix}_PROJECT"),
+                format!("{prefix}_LINKED_PORT"),
+            ]
+        },
+    )
+}
+
+// Generate the script that enables the shell integration for a shell
+pub fn init_script(shell: &InitShell, var_prefix: Option<&str>) -> String {
+    let template = match shell {
+        InitShell::Bash => include_str!("./shells/init.bash"),
+        InitShell::Fish => include_str!("./shells/init.fish"),
+        InitShell::Nu => include_str!("./shells/init.nu"),
+        InitShell::Powershell => include_str!("./shells/init.ps1"),
+        InitShell::Zsh => include_str!("./shells/init.zsh"),
+    };
+    let [port_var, project_var, linked_port_var] = var_names(var_prefix);
+    template
+        .replace("{{port_var}}", &port_var)
+        .replace("{{project_var}}", &project_var)
+        .replace("{{linked_port_var}}", &linked_port_var)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_init_script() {
+        for shell in [
+            InitShell::Bash,
+            InitShell::Fish,
+            InitShell::Nu,
+            InitShell::Powershell,
+            InitShell::Zsh,
+        ] {
+            let script = init_script(&shell, None);
+            assert!(script.contains("PORTMAN_PROJECT"));
+            assert!(!script.contains("{{"));
+        }
+    }
+
+    #[test]
+    fn test_init_script_var_prefix() {
+        let script = init_script(&InitShell::Fish, Some("MYAPP"));
+        assert!(script.contains("set -e MYAPP_PORT MYAPP_PROJECT MYAPP_LINKED_PORT"));
+        assert!(script.contains("set -gx MYAPP_PORT $lines[1]"));
+        assert!(!script.contains("PORTMAN_PROJECT"));
+    }
+
+    #[test]
+    fn test_init_script_var_prefix_nu() {
+        let script = init_script(&InitShell::Nu, Some("MYAPP"));
+        assert!(script.contains("['MYAPP_PORT' 'MYAPP_PROJECT' 'MYAPP_LINKED_PORT']"));
+        assert!(script.contains("$env.MYAPP_PORT = ($lines | get 0)"));
+        assert!(!script.contains("PORTMAN_PROJECT"));
+    }
+}
@@ -1,25 +1,71 @@
 __portman_sync_env() {
-    unset PORT PORTMAN_PROJECT PORTMAN_LINKED_PORT
-    lines=$(command portman get --extended 2> /dev/null || true)
+    unset {{port_var}} {{project_var}} {{linked_port_var}} $__PORTMAN_ENV_KEYS
+    __PORTMAN_ENV_KEYS=
+    local lines linked_port env_line
+    lines=$(command portman get --shell-fast 2> /dev/null || true)
     if [[ -n "$lines" ]]; then
-        PORT=$(echo "$lines" | awk 'NR==1')
-        PORTMAN_PROJECT=$(echo "$lines" | awk 'NR==2')
-        PORTMAN_LINKED_PORT=$(echo "$lines" | awk 'NR==4')
-        export PORT PORTMAN_PROJECT
-        if [[ -n "$PORTMAN_LINKED_PORT" ]]; then
-            export PORTMAN_LINKED_PORT
+        {{port_var}}=$(echo "$lines" | awk 'NR==1')
+        {{project_var}}=$(echo "$lines" | awk 'NR==2')
+        export {{port_var}} {{project_var}}
+        # Leave {{linked_port_var}} unset instead of empty if the project isn't linked
+        linked_port=$(echo "$lines" | awk 'NR==4')
+        if [[ -n "$linked_port" ]]; then
+            export {{linked_port_var}}="$linked_port"
         fi
+        # The remaining lines are the project's environment variables
+        while IFS= read -r env_line; do
+            if [[ -n "$env_line" ]]; then
+                export "$env_line"
+                __PORTMAN_ENV_KEYS="$__PORTMAN_ENV_KEYS ${env_line%%=*}"
+            fi
+        done < <(echo "$lines" | awk 'NR>4')
     fi
 }
 
 portman() {
+    local portman_status
     command portman "$@"
     portman_status=$?
     __portman_sync_env
     return $portman_status
 }
 
-if [[ -z "$PROMPT_COMMAND" ]]; then
+# Complete linked ports for `portman unlink`, free ports for `portman link`, and project names and
+# repo URLs for the commands that take them, falling back to the generated completions for
+# everything else
+__portman_complete() {
+    local kind=
+    case "$COMP_CWORD ${COMP_WORDS[1]} ${COMP_WORDS[2]}" in
+        "2 unlink "*) kind=linked-ports ;;
+        "2 link "*) kind=link-ports ;;
+        "2 get "* | "2 delete "* | "2 archive "* | "2 handoff "* | "2 devcontainer "* | "2 env "* | "2 exec "* | "3 link "*)
+            kind=projects ;;
+        "3 domain add" | "3 domain remove" | "3 template render") kind=projects ;;
+        "3 repo delete" | "3 repo set") kind=repos ;;
+    esac
+    if [[ -n "$kind" ]]; then
+        COMPREPLY=($(compgen -W "$(command portman complete "$kind" 2> /dev/null)" -- "${COMP_WORDS[COMP_CWORD]}"))
+        return
+    fi
+    # Load the generated completions if bash-completion hasn't loaded them yet
+    if ! declare -F _portman > /dev/null && declare -F __load_completion > /dev/null; then
+        __load_completion portman
+        complete -F __portman_complete -o bashdefault -o default portman
+    fi
+    if declare -F _portman > /dev/null; then
+        _portman "$@"
+    fi
+}
+complete -F __portman_complete -o bashdefault -o default portman
+
+# Sync before every prompt, which also covers cd, pushd, and popd because bash has no directory
+# change hook
+# Bash 5.1 and newer allow PROMPT_COMMAND to be an array, which must stay an array
+if [[ "$(declare -p PROMPT_COMMAND 2> /dev/null)" == "declare -a"* ]]; then
+    if [[ " ${PROMPT_COMMAND[*]} " != *" __portman_sync_env "* ]]; then
+        PROMPT_COMMAND=(__portman_sync_env "${PROMPT_COMMAND[@]}")
+    fi
+elif [[ -z "$PROMPT_COMMAND" ]]; then
     PROMPT_COMMAND="__portman_sync_env"
 elif [[ "$PROMPT_COMMAND" != *"__portman_sync_env"* ]]; then
     PROMPT_COMMAND="__portman_sync_env;$PROMPT_COMMAND"
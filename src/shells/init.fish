@@ -1,10 +1,19 @@
 function __portman_sync_env
-    set -e PORT PORTMAN_PROJECT PORTMAN_LINKED_PORT
-    if set lines (command portman get --extended 2> /dev/null)
-        set -gx PORT $lines[1]
-        set -gx PORTMAN_PROJECT $lines[2]
+    set -e {{port_var}} {{project_var}} {{linked_port_var}} $__portman_env_keys
+    set -g __portman_env_keys
+    if set lines (command portman get --shell-fast 2> /dev/null)
+        set -gx {{port_var}} $lines[1]
+        set -gx {{project_var}} $lines[2]
         if test -n $lines[4]
-            set -gx PORTMAN_LINKED_PORT $lines[4]
+            set -gx {{linked_port_var}} $lines[4]
+        end
+        # The remaining lines are the project's environment variables
+        if test (count $lines) -gt 4
+            for env_line in $lines[5..-1]
+                set -l env_var (string split -m 1 = $env_line)
+                set -gx $env_var[1] $env_var[2]
+                set -a __portman_env_keys $env_var[1]
+            end
         end
     end
 end
@@ -27,3 +36,12 @@ function __portman_preexec_hook --on-event fish_preexec
     # Without clearing the cd hook, the cd hook and prompt hook would both sync the environment
     functions -e __portman_cd_hook
 end
+
+# Complete linked ports for `portman unlink`, free ports for `portman link`, and project names and
+# repo URLs for the commands that take them
+complete -c portman -n '__fish_seen_subcommand_from unlink' -f -a '(command portman complete linked-ports 2> /dev/null)'
+complete -c portman -n '__fish_seen_subcommand_from link' -f -a '(command portman complete link-ports 2> /dev/null)'
+complete -c portman -n '__fish_seen_subcommand_from get delete archive handoff devcontainer env exec; and not __fish_seen_subcommand_from repo' -f -a '(command portman complete projects 2> /dev/null)'
+complete -c portman -n '__fish_seen_subcommand_from domain; and __fish_seen_subcommand_from add remove' -f -a '(command portman complete projects 2> /dev/null)'
+complete -c portman -n '__fish_seen_subcommand_from template; and __fish_seen_subcommand_from render' -f -a '(command portman complete projects 2> /dev/null)'
+complete -c portman -n '__fish_seen_subcommand_from repo; and __fish_seen_subcommand_from delete set' -f -a '(command portman complete repos 2> /dev/null)'
@@ -0,0 +1,44 @@
+$global:__PortmanEnvKeys = @()
+
+# Resolve the portman executable once so that the wrapper function below doesn't call itself
+$global:__PortmanExecutable = (Get-Command portman -CommandType Application | Select-Object -First 1).Source
+
+function global:__portman_sync_env {
+    foreach ($key in @('{{port_var}}', '{{project_var}}', '{{linked_port_var}}') + $global:__PortmanEnvKeys) {
+        Remove-Item -Path "Env:$key" -ErrorAction SilentlyContinue
+    }
+    $global:__PortmanEnvKeys = @()
+    $lines = @(& $global:__PortmanExecutable get --shell-fast 2> $null)
+    if ($LASTEXITCODE -eq 0 -and $lines.Count -ge 2) {
+        $env:{{port_var}} = $lines[0]
+        $env:{{project_var}} = $lines[1]
+        # Leave {{linked_port_var}} unset instead of empty if the project isn't linked
+        if ($lines.Count -ge 4 -and $lines[3]) {
+            $env:{{linked_port_var}} = $lines[3]
+        }
+        # The remaining lines are the project's environment variables
+        foreach ($line in $lines | Select-Object -Skip 4) {
+            $key, $value = $line -split '=', 2
+            if ($key) {
+                Set-Item -Path "Env:$key" -Value $value
+                $global:__PortmanEnvKeys += $key
+            }
+        }
+    }
+}
+
+function global:portman {
+    & $global:__PortmanExecutable @args
+    $portmanStatus = $LASTEXITCODE
+    __portman_sync_env
+    $global:LASTEXITCODE = $portmanStatus
+}
+
+# Sync before every prompt, preserving the exit code of the last command and the existing prompt
+$global:__PortmanOriginalPrompt = $function:prompt
+function global:prompt {
+    $lastExitCode = $global:LASTEXITCODE
+    __portman_sync_env
+    $global:LASTEXITCODE = $lastExitCode
+    & $global:__PortmanOriginalPrompt
+}
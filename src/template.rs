@@ -0,0 +1,146 @@
+use crate::dependencies::{Exec, WorkingDirectory};
+use crate::error::{ApplicationError, Result};
+use crate::repo;
+use anyhow::Context;
+use std::path::Path;
+use std::process::Command;
+
+// Return the name of the git repo in the current directory, based on its remote URL
+fn repo_name(deps: &impl Exec) -> Result<String> {
+    let url = repo::get_active_repo(deps, None)?;
+    let url = url.trim_end_matches('/');
+    let name = url.rsplit(['/', ':']).next().unwrap_or(url);
+    Ok(name.strip_suffix(".git").unwrap_or(name).to_owned())
+}
+
+// Return the name of the git branch checked out in the current directory
+pub fn branch_name(deps: &impl Exec) -> Result<String> {
+    read_branch_name(deps, Command::new("git").args(["branch", "--show-current"]))
+}
+
+// Return the name of the git branch checked out in a directory other than the current directory
+pub fn branch_name_in(deps: &impl Exec, directory: &Path) -> Result<String> {
+    read_branch_name(
+        deps,
+        Command::new("git")
+            .arg("-C")
+            .arg(directory)
+            .args(["branch", "--show-current"]),
+    )
+}
+
+// Run a git command that prints a branch name and return the branch
+fn read_branch_name(deps: &impl Exec, command: &mut Command) -> Result<String> {
+    let branch = deps.exec(command).map_err(ApplicationError::GitCommand)?;
+    let branch = branch.trim();
+    if branch.is_empty() {
+        return Err(anyhow::anyhow!("The current directory is not on a git branch").into());
+    }
+    Ok(branch.to_owned())
+}
+
+// Resolve the value of a template variable
+fn resolve_variable(
+    deps: &(impl Exec + WorkingDirectory),
+    template: &str,
+    variable: &str,
+) -> Result<String> {
+    match variable {
+        "repo" => repo_name(deps),
+        "branch" => branch_name(deps),
+        "dir" => {
+            let directory = deps.get_cwd()?;
+            Ok(directory
+                .file_name()
+                .and_then(|name| name.to_str())
+                .context("Failed to extract directory basename")?
+                .to_owned())
+        }
+        _ => Err(ApplicationError::InvalidNameTemplate(
+            template.to_owned(),
+            format!("unknown variable {{{variable}}}"),
+        )),
+    }
+}
+
+// Render a project name template like "{repo}-{branch}", replacing each variable with its value
+pub fn render_name_template(
+    deps: &(impl Exec + WorkingDirectory),
+    template: &str,
+) -> Result<String> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut remaining = template;
+    while let Some(start) = remaining.find('{') {
+        rendered.push_str(&remaining[..start]);
+        let Some(length) = remaining[start..].find('}') else {
+            return Err(ApplicationError::InvalidNameTemplate(
+                template.to_owned(),
+                String::from("unclosed {"),
+            ));
+        };
+        let variable = &remaining[start + 1..start + length];
+        rendered.push_str(&resolve_variable(deps, template, variable)?);
+        remaining = &remaining[start + length + 1..];
+    }
+    rendered.push_str(remaining);
+    Ok(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dependencies::{ExecMock, ExecStatus};
+    use crate::mocks::cwd_mock;
+    use unimock::{matching, Clause, MockFn, Unimock};
+
+    fn exec_git_mock(args: &'static [&'static str], output: &'static str) -> impl Clause {
+        ExecMock
+            .each_call(matching!((command) if command.get_program() == "git" && command.get_args().eq(args.iter().copied())))
+            .answers_arc(std::sync::Arc::new(move |_, _| {
+                Ok(ExecStatus::Success {
+                    output: output.to_owned(),
+                })
+            }))
+            .once()
+    }
+
+    #[test]
+    fn test_render() {
+        let deps = Unimock::new((
+            exec_git_mock(
+                &["remote", "get-url", "origin"],
+                "git@github.com:user/app.git\n",
+            ),
+            exec_git_mock(&["branch", "--show-current"], "feature/login\n"),
+            cwd_mock("app-review"),
+        ));
+        assert_eq!(
+            render_name_template(&deps, "{repo}-{branch} ({dir})").unwrap(),
+            "app-feature/login (app-review)"
+        );
+    }
+
+    #[test]
+    fn test_render_unknown_variable() {
+        let deps = Unimock::new(());
+        assert!(matches!(
+            render_name_template(&deps, "{repo-{branch}"),
+            Err(ApplicationError::InvalidNameTemplate(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_render_unclosed() {
+        let deps = Unimock::new(());
+        assert!(matches!(
+            render_name_template(&deps, "app-{branch"),
+            Err(ApplicationError::InvalidNameTemplate(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_render_detached() {
+        let deps = Unimock::new(exec_git_mock(&["branch", "--show-current"], "\n"));
+        assert!(render_name_template(&deps, "{branch}").is_err());
+    }
+}
@@ -0,0 +1,78 @@
+use std::cell::RefCell;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+thread_local! {
+    // The phases that have finished and how long each one took, or None if timings are disabled
+    static PHASES: RefCell<Option<Vec<(&'static str, Duration)>>> = const { RefCell::new(None) };
+}
+
+// Measures how long a phase takes and records it when dropped
+pub struct Timer {
+    phase: &'static str,
+    start: Instant,
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed();
+        PHASES.with(|phases| {
+            if let Some(phases) = phases.borrow_mut().as_mut() {
+                phases.push((self.phase, elapsed));
+            }
+        });
+    }
+}
+
+// Start recording how long each phase takes
+pub fn enable() {
+    PHASES.with(|phases| *phases.borrow_mut() = Some(Vec::new()));
+}
+
+// Start timing a phase, which finishes when the returned timer is dropped
+#[must_use]
+pub fn start(phase: &'static str) -> Timer {
+    Timer {
+        phase,
+        start: Instant::now(),
+    }
+}
+
+// Return a report of how long each phase took, or None if timings are disabled
+pub fn report() -> Option<String> {
+    PHASES.with(|phases| {
+        phases.borrow().as_ref().map(|phases| {
+            phases.iter().fold(
+                String::from("Timings:\n"),
+                |mut output, (phase, elapsed)| {
+                    let _ = writeln!(output, "  {phase}: {elapsed:.2?}");
+                    output
+                },
+            )
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disabled() {
+        drop(start("phase"));
+        assert!(report().is_none());
+    }
+
+    #[test]
+    fn test_report() {
+        enable();
+        drop(start("load registry"));
+        drop(start("reload caddy"));
+        let report = report().unwrap();
+        let lines = report.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "Timings:");
+        assert!(lines[1].starts_with("  load registry: "));
+        assert!(lines[2].starts_with("  reload caddy: "));
+    }
+}
@@ -0,0 +1,48 @@
+use crate::dependencies::Exec;
+use crate::error::ExecResult;
+use crate::registry::Project;
+use std::process::Command;
+
+// Return the tmux status-line segment for a project
+pub fn format_segment(name: &str, project: &Project) -> String {
+    let linked_port = project
+        .linked_port
+        .map(|port| format!("->{port}"))
+        .unwrap_or_default();
+    format!("{name}:{}{linked_port}", project.port)
+}
+
+// Set or unset a variable in the tmux session environment
+fn set_variable(deps: &impl Exec, name: &str, value: Option<&str>) -> ExecResult<()> {
+    let mut command = Command::new("tmux");
+    command.arg("set-environment");
+    match value {
+        Some(value) => command.args([name, value]),
+        None => command.args(["-u", name]),
+    };
+    deps.exec(&mut command)?;
+    Ok(())
+}
+
+// Update the tmux session environment so that new panes inherit the project's variables
+pub fn set_environment(deps: &impl Exec, project: Option<(&String, &Project)>) -> ExecResult<()> {
+    set_variable(
+        deps,
+        "PORT",
+        project
+            .map(|(_, project)| project.port.to_string())
+            .as_deref(),
+    )?;
+    set_variable(
+        deps,
+        "PORTMAN_PROJECT",
+        project.map(|(name, _)| name.as_str()),
+    )?;
+    set_variable(
+        deps,
+        "PORTMAN_LINKED_PORT",
+        project
+            .and_then(|(_, project)| project.linked_port.map(|port| port.to_string()))
+            .as_deref(),
+    )
+}
@@ -0,0 +1,453 @@
+use crate::error::Result;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use crossterm::ExecutableCommand;
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::{Frame, Terminal};
+use std::io::stdout;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+// How often the projects and their port statuses are reloaded
+const REFRESH_INTERVAL: Duration = Duration::from_secs(2);
+
+// A project as it is shown in the UI
+#[cfg_attr(test, derive(Debug, Eq, PartialEq))]
+pub struct ProjectRow {
+    pub name: String,
+    pub port: u16,
+    pub linked_port: Option<u16>,
+    pub directory: Option<PathBuf>,
+    pub tags: Vec<String>,
+    // Whether a process is accepting connections on the project's port
+    pub up: bool,
+}
+
+// A change to the projects that the user requested
+#[cfg_attr(test, derive(Debug, Eq, PartialEq))]
+pub enum Action {
+    // Create a project in the current directory, named after the directory if the name is empty
+    Create(String),
+    Delete(String),
+    Link(String, u16),
+    Open(String),
+}
+
+// What keyboard input is currently being used for
+#[derive(Default)]
+#[cfg_attr(test, derive(Debug, Eq, PartialEq))]
+enum Mode {
+    #[default]
+    Browse,
+    Filter,
+    Create(String),
+    Link(String),
+    ConfirmDelete,
+}
+
+// The state of the UI
+#[derive(Default)]
+pub struct App {
+    rows: Vec<ProjectRow>,
+    filter: String,
+    mode: Mode,
+    selected: usize,
+    // The result of the last action
+    message: Option<String>,
+    quit: bool,
+}
+
+impl App {
+    pub fn new(rows: Vec<ProjectRow>) -> Self {
+        Self {
+            rows,
+            ..Self::default()
+        }
+    }
+
+    // Replace the projects, keeping the selection in bounds
+    fn set_rows(&mut self, rows: Vec<ProjectRow>) {
+        self.rows = rows;
+        self.selected = self
+            .selected
+            .min(self.visible_rows().len().saturating_sub(1));
+    }
+
+    // Return the projects whose name or tags contain the filter
+    fn visible_rows(&self) -> Vec<&ProjectRow> {
+        let filter = self.filter.to_lowercase();
+        self.rows
+            .iter()
+            .filter(|row| {
+                row.name.to_lowercase().contains(&filter)
+                    || row
+                        .tags
+                        .iter()
+                        .any(|tag| tag.to_lowercase().contains(&filter))
+            })
+            .collect()
+    }
+
+    // Return the name of the selected project
+    fn selected_name(&self) -> Option<String> {
+        self.visible_rows()
+            .get(self.selected)
+            .map(|row| row.name.clone())
+    }
+
+    // Handle a key press and return the action that it requested, if any
+    fn handle_key(&mut self, key: KeyEvent) -> Option<Action> {
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            self.quit = true;
+            return None;
+        }
+
+        match std::mem::take(&mut self.mode) {
+            Mode::Browse => {
+                self.message = None;
+                match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+                    KeyCode::Up | KeyCode::Char('k') => {
+                        self.selected = self.selected.saturating_sub(1);
+                    }
+                    KeyCode::Down | KeyCode::Char('j') => {
+                        let last = self.visible_rows().len().saturating_sub(1);
+                        self.selected = (self.selected + 1).min(last);
+                    }
+                    KeyCode::Char('/') => self.mode = Mode::Filter,
+                    KeyCode::Char('c') => self.mode = Mode::Create(String::new()),
+                    KeyCode::Char('d') if self.selected_name().is_some() => {
+                        self.mode = Mode::ConfirmDelete;
+                    }
+                    KeyCode::Char('l') if self.selected_name().is_some() => {
+                        self.mode = Mode::Link(String::new());
+                    }
+                    KeyCode::Char('o') | KeyCode::Enter => {
+                        return self.selected_name().map(Action::Open);
+                    }
+                    _ => {}
+                }
+            }
+            Mode::Filter => match key.code {
+                KeyCode::Enter => {}
+                KeyCode::Esc => {
+                    self.filter.clear();
+                    self.selected = 0;
+                }
+                KeyCode::Backspace => {
+                    self.filter.pop();
+                    self.selected = 0;
+                    self.mode = Mode::Filter;
+                }
+                KeyCode::Char(char) => {
+                    self.filter.push(char);
+                    self.selected = 0;
+                    self.mode = Mode::Filter;
+                }
+                _ => self.mode = Mode::Filter,
+            },
+            Mode::Create(mut input) => match key.code {
+                KeyCode::Enter => return Some(Action::Create(input)),
+                KeyCode::Esc => {}
+                KeyCode::Backspace => {
+                    input.pop();
+                    self.mode = Mode::Create(input);
+                }
+                KeyCode::Char(char) => {
+                    input.push(char);
+                    self.mode = Mode::Create(input);
+                }
+                _ => self.mode = Mode::Create(input),
+            },
+            Mode::Link(mut input) => match key.code {
+                KeyCode::Enter => match input.parse() {
+                    Ok(port) => return self.selected_name().map(|name| Action::Link(name, port)),
+                    Err(_) => self.message = Some(format!("Invalid port \"{input}\"")),
+                },
+                KeyCode::Esc => {}
+                KeyCode::Backspace => {
+                    input.pop();
+                    self.mode = Mode::Link(input);
+                }
+                KeyCode::Char(char) if char.is_ascii_digit() => {
+                    input.push(char);
+                    self.mode = Mode::Link(input);
+                }
+                _ => self.mode = Mode::Link(input),
+            },
+            Mode::ConfirmDelete => {
+                if key.code == KeyCode::Char('y') {
+                    return self.selected_name().map(Action::Delete);
+                }
+            }
+        }
+        None
+    }
+
+    // Return the line shown below the projects
+    fn status_line(&self) -> String {
+        match &self.mode {
+            Mode::Browse => self.message.clone().unwrap_or_else(|| {
+                String::from("↑/↓ select  / filter  c create  d delete  l link  o open  q quit")
+            }),
+            Mode::Filter => format!("Filter: {}_", self.filter),
+            Mode::Create(input) => {
+                format!("New project name (empty for the directory name): {input}_")
+            }
+            Mode::Link(input) => format!(
+                "Port to link to {}: {input}_",
+                self.selected_name().unwrap_or_default()
+            ),
+            Mode::ConfirmDelete => format!(
+                "Delete project {}? [y/N]",
+                self.selected_name().unwrap_or_default()
+            ),
+        }
+    }
+
+    // Draw the UI
+    fn render(&self, frame: &mut Frame) {
+        let [list_area, status_area] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.size());
+
+        let rows = self.visible_rows();
+        let title = if self.filter.is_empty() {
+            format!(" portman projects ({}) ", rows.len())
+        } else {
+            format!(
+                " portman projects ({} of {}) matching \"{}\" ",
+                rows.len(),
+                self.rows.len(),
+                self.filter
+            )
+        };
+        let items = rows
+            .iter()
+            .map(|row| {
+                let mut spans = vec![
+                    if row.up {
+                        Span::styled("● ", Style::default().fg(Color::Green))
+                    } else {
+                        Span::styled("○ ", Style::default().fg(Color::DarkGray))
+                    },
+                    Span::styled(
+                        row.name.clone(),
+                        Style::default().add_modifier(Modifier::BOLD),
+                    ),
+                    Span::raw(format!(" :{}", row.port)),
+                ];
+                if let Some(linked_port) = row.linked_port {
+                    spans.push(Span::raw(format!(" -> :{linked_port}")));
+                }
+                for tag in &row.tags {
+                    spans.push(Span::styled(
+                        format!(" #{tag}"),
+                        Style::default().fg(Color::Cyan),
+                    ));
+                }
+                if let Some(directory) = &row.directory {
+                    spans.push(Span::styled(
+                        format!(" ({})", directory.display()),
+                        Style::default().add_modifier(Modifier::DIM),
+                    ));
+                }
+                ListItem::new(Line::from(spans))
+            })
+            .collect::<Vec<_>>();
+        let list = List::new(items)
+            .block(Block::bordered().title(title))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        let mut state = ListState::default().with_selected(Some(self.selected));
+        frame.render_stateful_widget(list, list_area, &mut state);
+        frame.render_widget(Paragraph::new(self.status_line()), status_area);
+    }
+}
+
+// Draw the UI and handle key presses until the user quits
+fn event_loop(
+    app: &mut App,
+    refresh: &mut impl FnMut() -> Result<Vec<ProjectRow>>,
+    perform: &mut impl FnMut(Action) -> Result<String>,
+) -> anyhow::Result<()> {
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
+    let mut last_refresh = Instant::now();
+    while !app.quit {
+        terminal.draw(|frame| app.render(frame))?;
+        let timeout = REFRESH_INTERVAL.saturating_sub(last_refresh.elapsed());
+        if event::poll(timeout)? {
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            let Some(action) = app.handle_key(key) else {
+                continue;
+            };
+            app.message = Some(match perform(action) {
+                Ok(message) => message,
+                Err(err) => err.to_string(),
+            });
+        }
+        // Reload after every action and periodically so that port statuses stay current
+        match refresh() {
+            Ok(rows) => app.set_rows(rows),
+            Err(err) => app.message = Some(err.to_string()),
+        }
+        last_refresh = Instant::now();
+    }
+    Ok(())
+}
+
+// Run the UI in the terminal until the user quits
+// refresh loads the latest projects, and perform applies an action and returns a description of
+// its result
+pub fn run(
+    mut refresh: impl FnMut() -> Result<Vec<ProjectRow>>,
+    mut perform: impl FnMut(Action) -> Result<String>,
+) -> anyhow::Result<()> {
+    let mut app = App::new(refresh()?);
+    enable_raw_mode()?;
+    stdout().execute(EnterAlternateScreen)?;
+    let result = event_loop(&mut app, &mut refresh, &mut perform);
+    // Restore the terminal even if the UI failed
+    disable_raw_mode()?;
+    stdout().execute(LeaveAlternateScreen)?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_app() -> App {
+        App::new(vec![
+            ProjectRow {
+                name: String::from("app1"),
+                port: 3001,
+                linked_port: None,
+                directory: None,
+                tags: vec![String::from("work")],
+                up: true,
+            },
+            ProjectRow {
+                name: String::from("app2"),
+                port: 3002,
+                linked_port: Some(3000),
+                directory: None,
+                tags: vec![],
+                up: false,
+            },
+        ])
+    }
+
+    fn press(app: &mut App, keys: &[KeyCode]) -> Vec<Action> {
+        keys.iter()
+            .filter_map(|code| app.handle_key(KeyEvent::from(*code)))
+            .collect()
+    }
+
+    #[test]
+    fn test_select() {
+        let mut app = get_app();
+        assert!(press(&mut app, &[KeyCode::Down, KeyCode::Down]).is_empty());
+        assert_eq!(app.selected_name(), Some(String::from("app2")));
+        assert_eq!(
+            press(&mut app, &[KeyCode::Up, KeyCode::Enter]),
+            vec![Action::Open(String::from("app1"))]
+        );
+    }
+
+    #[test]
+    fn test_filter() {
+        let mut app = get_app();
+        press(
+            &mut app,
+            &[KeyCode::Char('/'), KeyCode::Char('2'), KeyCode::Enter],
+        );
+        assert_eq!(app.mode, Mode::Browse);
+        assert_eq!(app.selected_name(), Some(String::from("app2")));
+
+        press(&mut app, &[KeyCode::Char('/'), KeyCode::Esc]);
+        assert_eq!(app.visible_rows().len(), 2);
+
+        app.filter = String::from("WORK");
+        assert_eq!(app.selected_name(), Some(String::from("app1")));
+        assert_eq!(app.visible_rows().len(), 1);
+    }
+
+    #[test]
+    fn test_create() {
+        let mut app = get_app();
+        assert_eq!(
+            press(
+                &mut app,
+                &[
+                    KeyCode::Char('c'),
+                    KeyCode::Char('a'),
+                    KeyCode::Char('x'),
+                    KeyCode::Backspace,
+                    KeyCode::Char('b'),
+                    KeyCode::Enter,
+                ]
+            ),
+            vec![Action::Create(String::from("ab"))]
+        );
+        assert_eq!(app.mode, Mode::Browse);
+    }
+
+    #[test]
+    fn test_link() {
+        let mut app = get_app();
+        assert_eq!(
+            press(
+                &mut app,
+                &[
+                    KeyCode::Char('l'),
+                    KeyCode::Char('3'),
+                    KeyCode::Char('x'),
+                    KeyCode::Char('0'),
+                    KeyCode::Enter,
+                ]
+            ),
+            vec![Action::Link(String::from("app1"), 30)]
+        );
+
+        press(&mut app, &[KeyCode::Char('l'), KeyCode::Enter]);
+        assert_eq!(app.message, Some(String::from("Invalid port \"\"")));
+    }
+
+    #[test]
+    fn test_delete() {
+        let mut app = get_app();
+        assert!(press(&mut app, &[KeyCode::Char('d'), KeyCode::Char('n')]).is_empty());
+        assert_eq!(
+            press(&mut app, &[KeyCode::Char('d'), KeyCode::Char('y')]),
+            vec![Action::Delete(String::from("app1"))]
+        );
+    }
+
+    #[test]
+    fn test_quit() {
+        let mut app = get_app();
+        press(&mut app, &[KeyCode::Char('/'), KeyCode::Char('q')]);
+        assert!(!app.quit);
+        press(&mut app, &[KeyCode::Enter, KeyCode::Char('q')]);
+        assert!(app.quit);
+    }
+
+    #[test]
+    fn test_set_rows() {
+        let mut app = get_app();
+        press(&mut app, &[KeyCode::Down]);
+        app.set_rows(vec![]);
+        assert_eq!(app.selected, 0);
+        assert_eq!(app.selected_name(), None);
+    }
+}
@@ -0,0 +1,85 @@
+use crate::dependencies::{DataDir, ReadFile, WriteFile};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+// The minimum number of seconds between recorded uses of a project, so that the shell integration
+// doesn't rewrite the usage log at every prompt
+const RECORD_INTERVAL: u64 = 60;
+
+// When each project was last used, persisted separately from the registry so that recording uses
+// doesn't modify the registry
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(test, derive(Debug))]
+pub struct UsageLog {
+    // The time that each project was last used, in seconds since the Unix epoch
+    #[serde(default)]
+    projects: BTreeMap<String, u64>,
+}
+
+// Return the path to the usage log file
+fn log_path(deps: &impl DataDir) -> Result<PathBuf> {
+    Ok(deps.get_data_dir()?.join("usage.toml"))
+}
+
+impl UsageLog {
+    // Load the usage log from the data directory
+    // A missing or unparseable log is treated as empty because usage is only informational
+    pub fn load(deps: &(impl DataDir + ReadFile)) -> Result<Self> {
+        Ok(deps
+            .read_file(&log_path(deps)?)?
+            .and_then(|log_str| toml::from_str(&log_str).ok())
+            .unwrap_or_default())
+    }
+
+    // Save the usage log to the data directory
+    pub fn save(&self, deps: &(impl DataDir + WriteFile)) -> Result<()> {
+        let log_str = toml::to_string(self).context("Failed to serialize usage log")?;
+        deps.write_file(&log_path(deps)?, &log_str)
+            .context("Failed to save usage log")
+    }
+
+    // Record that a project was used at a time
+    // Return true if the log changed and needs to be saved
+    pub fn record(&mut self, name: &str, now: u64) -> bool {
+        match self.projects.get(name) {
+            Some(last_used) if now.saturating_sub(*last_used) < RECORD_INTERVAL => false,
+            _ => {
+                self.projects.insert(name.to_owned(), now);
+                true
+            }
+        }
+    }
+
+    // Get when a project was last used, in seconds since the Unix epoch, if ever
+    pub fn last_used(&self, name: &str) -> Option<u64> {
+        self.projects.get(name).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let mut usage = UsageLog::default();
+        assert!(usage.record("app1", 1000));
+        assert!(!usage.record("app1", 1030));
+        assert_eq!(usage.last_used("app1"), Some(1000));
+        assert!(usage.record("app1", 1060));
+        assert_eq!(usage.last_used("app1"), Some(1060));
+        assert_eq!(usage.last_used("app2"), None);
+    }
+
+    #[test]
+    fn test_serialize() {
+        let mut usage = UsageLog::default();
+        usage.record("app1", 1000);
+        assert_eq!(
+            toml::to_string(&usage).unwrap(),
+            "[projects]\napp1 = 1000\n"
+        );
+    }
+}
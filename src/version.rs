@@ -0,0 +1,46 @@
+use serde::Serialize;
+use std::fmt::{self, Display, Formatter};
+use std::path::PathBuf;
+
+// Information about how the portman binary was built
+#[derive(Clone, Serialize)]
+#[cfg_attr(test, derive(Debug))]
+pub struct Build {
+    pub version: &'static str,
+    pub target: &'static str,
+    pub profile: &'static str,
+    pub static_binary: bool,
+    pub platform: String,
+}
+
+// Everything support scripts need to know about a portman installation
+#[derive(Serialize)]
+pub struct VersionInfo {
+    #[serde(flatten)]
+    pub build: Build,
+    pub data_dir: PathBuf,
+    pub config_path: PathBuf,
+    pub caddy_version: Option<String>,
+}
+
+impl Display for VersionInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let build = &self.build;
+        writeln!(f, "portman {}", build.version)?;
+        writeln!(
+            f,
+            "build: {} ({}{})",
+            build.target,
+            build.profile,
+            if build.static_binary { ", static" } else { "" }
+        )?;
+        writeln!(f, "platform: {}", build.platform)?;
+        writeln!(f, "data directory: {}", self.data_dir.display())?;
+        writeln!(f, "config file: {}", self.config_path.display())?;
+        writeln!(
+            f,
+            "caddy: {}",
+            self.caddy_version.as_deref().unwrap_or("not found")
+        )
+    }
+}
@@ -0,0 +1,101 @@
+use crate::dependencies::{DataDir, WatchPath};
+use crate::registry::Registry;
+use anyhow::Result;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+// The number of consecutive polls that a watched value must remain unchanged before a change is
+// reported, so that a burst of edits only triggers a single reload
+const DEBOUNCE_POLLS: u32 = 2;
+
+// Tracks a polled value and reports when it changes and has since settled
+struct Debouncer<T> {
+    last_value: Option<T>,
+    stable_polls: u32,
+}
+
+impl<T> Default for Debouncer<T> {
+    fn default() -> Self {
+        Self {
+            last_value: None,
+            stable_polls: 0,
+        }
+    }
+}
+
+impl<T: PartialEq> Debouncer<T> {
+    // Record the latest value and return true if it changed and has since settled
+    fn poll(&mut self, value: T) -> bool {
+        if self.last_value.as_ref() != Some(&value) {
+            self.last_value = Some(value);
+            self.stable_polls = 0;
+            return false;
+        }
+
+        if self.stable_polls < DEBOUNCE_POLLS {
+            self.stable_polls += 1;
+            return self.stable_polls == DEBOUNCE_POLLS;
+        }
+        false
+    }
+}
+
+// Polls the registry file's modification time and whether the project directories exist
+#[derive(Default)]
+pub struct DaemonWatcher {
+    state: Debouncer<(Option<SystemTime>, Vec<bool>)>,
+}
+
+impl DaemonWatcher {
+    // Return true if the registry file was modified or a directory appeared or disappeared and
+    // the changes have since settled
+    pub fn poll(
+        &mut self,
+        deps: &(impl DataDir + WatchPath),
+        directories: &[PathBuf],
+    ) -> Result<bool> {
+        let modified = deps.get_modified_time(&Registry::store_path(deps)?);
+        let directories_exist = directories
+            .iter()
+            .map(|directory| deps.get_modified_time(directory).is_some())
+            .collect();
+        Ok(self.state.poll((modified, directories_exist)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dependencies::WatchPathMock;
+    use crate::mocks::data_dir_mock;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use unimock::{matching, MockFn, Unimock};
+
+    #[test]
+    fn test_daemon_poll_directory_removed() {
+        // The project directory is deleted before the third poll
+        let polls = Arc::new(AtomicUsize::new(0));
+        let deps = Unimock::new((
+            data_dir_mock(),
+            WatchPathMock
+                .each_call(matching!((path) if path == &PathBuf::from("/data/registry.toml")))
+                .returns(Some(SystemTime::UNIX_EPOCH))
+                .n_times(6),
+            WatchPathMock
+                .each_call(matching!((path) if path == &PathBuf::from("/projects/app3")))
+                .answers_arc(Arc::new(move |_, _| {
+                    let poll = polls.fetch_add(1, Ordering::SeqCst);
+                    (poll < 2).then_some(SystemTime::UNIX_EPOCH)
+                }))
+                .n_times(6),
+        ));
+
+        let mut watcher = DaemonWatcher::default();
+        let directories = [PathBuf::from("/projects/app3")];
+        let results = (0..6)
+            .map(|_| watcher.poll(&deps, &directories).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(results, vec![false, false, false, false, true, false]);
+    }
+}